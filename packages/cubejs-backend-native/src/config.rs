@@ -5,6 +5,7 @@ use cubesql::{
     config::{Config, ConfigObj, CubeServices},
     mysql::SqlAuthService,
     schema::SchemaService,
+    CubeError,
 };
 
 #[derive(Clone)]
@@ -23,9 +24,12 @@ impl NodeConfig {
         Self { config }
     }
 
-    pub async fn configure(&self, transport: Arc<NodeBridgeTransport>) -> CubeServices {
+    pub async fn configure(
+        &self,
+        transport: Arc<NodeBridgeTransport>,
+    ) -> Result<CubeServices, CubeError> {
         let injector = self.config.injector();
-        self.config.configure_injector().await;
+        self.config.configure_injector().await?;
 
         injector
             .register_typed::<dyn SchemaService, _, _, _>(async move |_| transport)
@@ -37,6 +41,6 @@ impl NodeConfig {
             })
             .await;
 
-        self.config.cube_services().await
+        Ok(self.config.cube_services().await)
     }
 }
@@ -38,7 +38,7 @@ fn register_interface(mut cx: FunctionContext) -> JsResult<JsPromise> {
         channel.settle_with(deferred, move |cx| Ok(cx.undefined()));
 
         runtime.block_on(async move {
-            let services = config.configure(Arc::new(transport)).await;
+            let services = config.configure(Arc::new(transport)).await.unwrap();
             track_event("Cube SQL Start".to_string(), HashMap::new()).await;
             services.wait_processing_loops().await.unwrap();
         });
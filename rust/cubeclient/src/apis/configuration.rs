@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use reqwest::{self};
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 
@@ -10,6 +12,7 @@ pub struct ConfigurationImpl<T> {
     pub oauth_access_token: Option<String>,
     pub bearer_access_token: Option<String>,
     pub api_key: Option<ApiKey>,
+    pub load_polling: LoadPolling,
     // TODO: take an oauth2 token source, similar to the go one
 }
 
@@ -23,6 +26,37 @@ pub struct ApiKey {
     pub key: String,
 }
 
+/// How `/v1/load` is retried while Cube.js answers with `Continue wait`.
+#[derive(Debug, Clone)]
+pub struct LoadPolling {
+    /// Delay before the first retry.
+    pub initial_interval: Duration,
+    /// Upper bound for the delay between retries.
+    pub max_interval: Duration,
+    /// Factor the delay grows by after each retry.
+    pub multiplier: f64,
+    /// Time the whole load, including retries, may take. `None` waits indefinitely.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for LoadPolling {
+    fn default() -> Self {
+        LoadPolling {
+            initial_interval: Duration::from_millis(200),
+            max_interval: Duration::from_secs(2),
+            multiplier: 2.0,
+            timeout: None,
+        }
+    }
+}
+
+impl LoadPolling {
+    /// Delay before the retry which follows one made after `interval`.
+    pub fn next_interval(&self, interval: Duration) -> Duration {
+        std::cmp::min(interval.mul_f64(self.multiplier), self.max_interval)
+    }
+}
+
 impl Configuration {
     pub fn new(client: ClientWithMiddleware) -> Configuration {
         Configuration {
@@ -33,6 +67,7 @@ impl Configuration {
             oauth_access_token: None,
             bearer_access_token: None,
             api_key: None,
+            load_polling: LoadPolling::default(),
         }
     }
 }
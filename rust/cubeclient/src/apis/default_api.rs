@@ -1,5 +1,8 @@
+use std::future::Future;
+
 use log::{debug, error};
 use reqwest;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
 use super::{configuration, Error};
@@ -23,11 +26,72 @@ pub enum MetaV1Error {
 pub async fn load_v1(
     configuration: &configuration::Configuration,
     v1_load_request: Option<crate::models::V1LoadRequest>,
+) -> Result<crate::models::V1LoadResponse, Error<LoadV1Error>> {
+    load_v1_with_cancellation(configuration, v1_load_request, CancellationToken::new()).await
+}
+
+/// Same as `load_v1`, but stops waiting for the result once `cancellation` is cancelled, e.g.
+/// when the client which issued the query disconnects.
+pub async fn load_v1_with_cancellation(
+    configuration: &configuration::Configuration,
+    v1_load_request: Option<crate::models::V1LoadRequest>,
+    cancellation: CancellationToken,
+) -> Result<crate::models::V1LoadResponse, Error<LoadV1Error>> {
+    with_load_limits(
+        configuration,
+        cancellation,
+        poll_load(configuration, &v1_load_request),
+    )
+    .await
+}
+
+/// Loads several queries with one `multi` request, `results` of the response follow the order
+/// of `queries`.
+pub async fn load_v1_batch(
+    configuration: &configuration::Configuration,
+    queries: Vec<crate::models::V1LoadRequestQuery>,
+    cancellation: CancellationToken,
+) -> Result<crate::models::V1LoadResponse, Error<LoadV1Error>> {
+    let v1_load_request = crate::models::V1LoadBatchRequest::new(queries);
+
+    with_load_limits(
+        configuration,
+        cancellation,
+        poll_load(configuration, &v1_load_request),
+    )
+    .await
+}
+
+async fn with_load_limits(
+    configuration: &configuration::Configuration,
+    cancellation: CancellationToken,
+    load: impl Future<Output = Result<crate::models::V1LoadResponse, Error<LoadV1Error>>>,
+) -> Result<crate::models::V1LoadResponse, Error<LoadV1Error>> {
+    let load = async {
+        match configuration.load_polling.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, load).await {
+                Ok(result) => result,
+                Err(_) => Err(Error::Timeout(timeout)),
+            },
+            None => load.await,
+        }
+    };
+
+    tokio::select! {
+        result = load => result,
+        _ = cancellation.cancelled() => Err(Error::Cancelled),
+    }
+}
+
+async fn poll_load<T: serde::Serialize>(
+    configuration: &configuration::Configuration,
+    v1_load_request: &T,
 ) -> Result<crate::models::V1LoadResponse, Error<LoadV1Error>> {
     let local_var_client = &configuration.client;
 
     let request_id = Uuid::new_v4().to_string();
     let mut span_counter: u32 = 1;
+    let mut retry_interval = configuration.load_polling.initial_interval;
 
     loop {
         let local_var_uri_str = format!("{}/v1/load", configuration.base_path);
@@ -42,7 +106,7 @@ pub async fn load_v1(
         if let Some(ref local_var_token) = configuration.bearer_access_token {
             local_var_req_builder = local_var_req_builder.bearer_auth(local_var_token.to_owned());
         };
-        local_var_req_builder = local_var_req_builder.json(&v1_load_request);
+        local_var_req_builder = local_var_req_builder.json(v1_load_request);
 
         local_var_req_builder = local_var_req_builder.header(
             "x-request-id",
@@ -68,10 +132,12 @@ pub async fn load_v1(
             if let Ok(res) = response_err {
                 if res.error.to_lowercase() == "continue wait".to_string() {
                     debug!(
-                        "[client] load - retrying request (continue wait) requestId: {}, span: {}",
-                        request_id, span_counter
+                        "[client] load - retrying request (continue wait) requestId: {}, span: {}, in: {:?}",
+                        request_id, span_counter, retry_interval
                     );
 
+                    tokio::time::sleep(retry_interval).await;
+                    retry_interval = configuration.load_polling.next_interval(retry_interval);
                     span_counter = span_counter + 1;
 
                     continue;
@@ -151,12 +217,15 @@ mod tests {
         atomic::{AtomicU32, Ordering},
         Arc,
     };
+    use std::time::Duration;
     use wiremock::{
-        matchers::{method, path},
+        matchers::{body_partial_json, method, path},
         Mock, MockServer, Respond, ResponseTemplate,
     };
 
     use crate::apis::configuration::Configuration;
+    use crate::models::{V1LoadRequestQuery, V1LoadResponse, V1LoadResult, V1LoadResultAnnotation};
+    use serde_json::json;
 
     use super::*;
 
@@ -230,4 +299,92 @@ mod tests {
             Err(e) => panic!("must be successful, {:?}", e),
         };
     }
+
+    async fn continue_wait_server() -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/load"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(r#"{"error":"Continue wait"}"#),
+            )
+            .mount(&server)
+            .await;
+
+        server
+    }
+
+    #[tokio::test]
+    async fn test_load_timeout() {
+        let server = continue_wait_server().await;
+
+        let mut configuration = Configuration::default();
+        configuration.base_path = server.uri();
+        configuration.load_polling.initial_interval = Duration::from_millis(10);
+        configuration.load_polling.timeout = Some(Duration::from_millis(300));
+
+        match load_v1(&configuration, None).await {
+            Err(Error::Timeout(t)) => assert_eq!(t, Duration::from_millis(300)),
+            r => panic!("must time out, {:?}", r),
+        };
+
+        let requests = server.received_requests().await.unwrap();
+        assert!(requests.len() > 1);
+        assert!(requests.len() < 30, "retries must back off");
+    }
+
+    #[tokio::test]
+    async fn test_load_cancellation() {
+        let server = continue_wait_server().await;
+
+        let mut configuration = Configuration::default();
+        configuration.base_path = server.uri();
+
+        let cancellation = CancellationToken::new();
+        let cancel = cancellation.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            cancel.cancel();
+        });
+
+        match load_v1_with_cancellation(&configuration, None, cancellation).await {
+            Err(Error::Cancelled) => {}
+            r => panic!("must be cancelled, {:?}", r),
+        };
+    }
+
+    #[tokio::test]
+    async fn test_load_batch() {
+        let server = MockServer::start().await;
+        let result = V1LoadResult::new(
+            V1LoadResultAnnotation::new(json!({}), json!({}), json!({}), json!({})),
+            vec![],
+        );
+        let response = V1LoadResponse::new(vec![result.clone(), result]);
+        Mock::given(method("POST"))
+            .and(path("/v1/load"))
+            .and(body_partial_json(json!({
+                "queryType": "multi",
+                "query": [{ "measures": ["Orders.count"] }, { "measures": ["Users.count"] }],
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(response))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut configuration = Configuration::default();
+        configuration.base_path = server.uri();
+
+        let queries = ["Orders.count", "Users.count"]
+            .iter()
+            .map(|measure| {
+                let mut query = V1LoadRequestQuery::new();
+                query.measures = Some(vec![measure.to_string()]);
+                query
+            })
+            .collect();
+        let resp = load_v1_batch(&configuration, queries, CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(resp.results.len(), 2);
+    }
 }
@@ -1,5 +1,6 @@
 use std::error;
 use std::fmt;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct ResponseContent<T> {
@@ -15,6 +16,10 @@ pub enum Error<T> {
     Serde(serde_json::Error),
     Io(std::io::Error),
     ResponseError(ResponseContent<T>),
+    /// The load didn't complete within the configured timeout.
+    Timeout(Duration),
+    /// The load was cancelled by the caller.
+    Cancelled,
 }
 
 impl<T> fmt::Display for Error<T> {
@@ -25,6 +30,8 @@ impl<T> fmt::Display for Error<T> {
             Error::Serde(e) => ("serde", e.to_string()),
             Error::Io(e) => ("IO", e.to_string()),
             Error::ResponseError(e) => ("response", format!("{} {}", e.status, e.content)),
+            Error::Timeout(t) => ("load", format!("no result in {:?}", t)),
+            Error::Cancelled => ("load", "request was cancelled".to_string()),
         };
         write!(f, "error in {}: {}", module, e)
     }
@@ -40,7 +47,7 @@ impl<T: fmt::Debug> error::Error for Error<T> {
             Error::Reqwest(e) => e,
             Error::Serde(e) => e,
             Error::Io(e) => e,
-            Error::ResponseError(_) => return None,
+            Error::ResponseError(_) | Error::Timeout(_) | Error::Cancelled => return None,
         })
     }
 }
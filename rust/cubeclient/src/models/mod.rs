@@ -6,6 +6,8 @@ pub mod v1_cube_meta_measure;
 pub use self::v1_cube_meta_measure::V1CubeMetaMeasure;
 pub mod v1_cube_meta_segment;
 pub use self::v1_cube_meta_segment::V1CubeMetaSegment;
+pub mod v1_load_batch_request;
+pub use self::v1_load_batch_request::V1LoadBatchRequest;
 pub mod v1_load_request;
 pub use self::v1_load_request::V1LoadRequest;
 pub mod v1_load_request_query;
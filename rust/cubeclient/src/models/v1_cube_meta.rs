@@ -4,6 +4,8 @@ pub struct V1CubeMeta {
     pub name: String,
     #[serde(rename = "title", skip_serializing_if = "Option::is_none")]
     pub title: Option<String>,
+    #[serde(rename = "connectedComponent", skip_serializing_if = "Option::is_none")]
+    pub connected_component: Option<u32>,
     #[serde(rename = "measures")]
     pub measures: Vec<crate::models::V1CubeMetaMeasure>,
     #[serde(rename = "dimensions")]
@@ -22,6 +24,7 @@ impl V1CubeMeta {
         V1CubeMeta {
             name,
             title: None,
+            connected_component: None,
             measures,
             dimensions,
             segments,
@@ -0,0 +1,17 @@
+/// Several queries loaded with one `multi` request, results come back in the same order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct V1LoadBatchRequest {
+    #[serde(rename = "queryType")]
+    pub query_type: String,
+    #[serde(rename = "query")]
+    pub query: Vec<crate::models::V1LoadRequestQuery>,
+}
+
+impl V1LoadBatchRequest {
+    pub fn new(query: Vec<crate::models::V1LoadRequestQuery>) -> V1LoadBatchRequest {
+        V1LoadBatchRequest {
+            query_type: "multi".to_string(),
+            query,
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! MySQL protocol helpers shared by Cube Store and cubesql.
+//!
+//! Server-side prepared statements are executed by replacing `?` placeholders with literals of
+//! the bound parameters, so the query runs the same way as a text query.
+use std::fmt::{Display, Formatter};
+
+use msql_srv::{ParamValue, ValueInner};
+
+#[derive(Debug)]
+pub struct BindError {
+    pub message: String,
+}
+
+impl Display for BindError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for BindError {}
+
+/// Byte offsets of `?` placeholders outside of quoted strings and identifiers.
+pub fn placeholder_positions(query: &str) -> Vec<usize> {
+    let mut positions = Vec::new();
+    let mut quote: Option<char> = None;
+    for (i, c) in query.char_indices() {
+        match (quote, c) {
+            (None, '\'') | (None, '"') | (None, '`') => quote = Some(c),
+            (None, '?') => positions.push(i),
+            // A doubled quote closes and reopens the string, which keeps the state right.
+            (Some(q), c) if q == c => quote = None,
+            _ => {}
+        }
+    }
+
+    positions
+}
+
+/// Replaces placeholders of `query` with `values`, which are SQL literals.
+pub fn bind_params(query: &str, values: &[String]) -> Result<String, BindError> {
+    let positions = placeholder_positions(query);
+    if positions.len() != values.len() {
+        return Err(BindError {
+            message: format!(
+                "Statement expects {} parameters, {} given",
+                positions.len(),
+                values.len()
+            ),
+        });
+    }
+
+    let mut result = String::with_capacity(query.len());
+    let mut last = 0;
+    for (position, value) in positions.iter().zip(values.iter()) {
+        result.push_str(&query[last..*position]);
+        result.push_str(value);
+        last = position + 1;
+    }
+    result.push_str(&query[last..]);
+
+    Ok(result)
+}
+
+fn quote_string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Dates and datetimes are sent as year, month, day, then optional hour, minute, second and
+/// microseconds.
+fn datetime_literal(bytes: &[u8]) -> String {
+    if bytes.len() < 4 {
+        return quote_string("0000-00-00 00:00:00");
+    }
+
+    let year = u16::from_le_bytes([bytes[0], bytes[1]]);
+    let mut literal = format!("{:04}-{:02}-{:02}", year, bytes[2], bytes[3]);
+    if bytes.len() >= 7 {
+        literal.push_str(&format!(" {:02}:{:02}:{:02}", bytes[4], bytes[5], bytes[6]));
+    }
+    if bytes.len() >= 11 {
+        let micros = u32::from_le_bytes([bytes[7], bytes[8], bytes[9], bytes[10]]);
+        literal.push_str(&format!(".{:06}", micros));
+    }
+
+    quote_string(&literal)
+}
+
+/// Times are sent as sign, days, hour, minute, second and optional microseconds.
+fn time_literal(bytes: &[u8]) -> String {
+    if bytes.len() < 8 {
+        return quote_string("00:00:00");
+    }
+
+    let days = u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]);
+    let hours = days * 24 + bytes[5] as u32;
+    let sign = if bytes[0] == 1 { "-" } else { "" };
+
+    quote_string(&format!(
+        "{}{:02}:{:02}:{:02}",
+        sign, hours, bytes[6], bytes[7]
+    ))
+}
+
+/// Renders a bound parameter as a SQL literal.
+pub fn param_to_sql(param: ParamValue) -> String {
+    match param.value.into_inner() {
+        ValueInner::NULL => "NULL".to_string(),
+        ValueInner::Bytes(bytes) => quote_string(&String::from_utf8_lossy(bytes)),
+        ValueInner::Int(v) => v.to_string(),
+        ValueInner::UInt(v) => v.to_string(),
+        ValueInner::Double(v) => v.to_string(),
+        ValueInner::Date(bytes) | ValueInner::Datetime(bytes) => datetime_literal(bytes),
+        ValueInner::Time(bytes) => time_literal(bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_params() {
+        let query = "SELECT '?', `a?` FROM s.orders WHERE status = ? AND name = 'it''s ?' LIMIT ?";
+        assert_eq!(placeholder_positions(query).len(), 2);
+        assert_eq!(
+            bind_params(query, &[quote_string("it's"), "10".to_string()]).unwrap(),
+            "SELECT '?', `a?` FROM s.orders WHERE status = 'it''s' AND name = 'it''s ?' LIMIT 10"
+        );
+        assert!(bind_params(query, &["1".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_datetime_literal() {
+        assert_eq!(datetime_literal(&[0xE5, 0x07, 1, 31]), "'2021-01-31'");
+        assert_eq!(
+            datetime_literal(&[0xE5, 0x07, 1, 31, 23, 5, 9]),
+            "'2021-01-31 23:05:09'"
+        );
+        assert_eq!(time_literal(&[1, 1, 0, 0, 0, 2, 3, 4]), "'-26:03:04'");
+    }
+}
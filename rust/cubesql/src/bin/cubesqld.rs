@@ -31,7 +31,13 @@ fn main() {
 
     let runtime = Builder::new_multi_thread().enable_all().build().unwrap();
     runtime.block_on(async move {
-        let services = config.configure().await;
+        let services = match config.configure().await {
+            Ok(services) => services,
+            Err(e) => {
+                log::error!("{}", e);
+                std::process::exit(1);
+            }
+        };
         track_event("Cube SQL Start".to_string(), HashMap::new()).await;
         stop_on_ctrl_c(&services).await;
         services.wait_processing_loops().await.unwrap();
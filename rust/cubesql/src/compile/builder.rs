@@ -2,9 +2,14 @@ use cubeclient::models::{
     V1LoadRequestQuery, V1LoadRequestQueryFilterItem, V1LoadRequestQueryTimeDimension,
 };
 use msql_srv::ColumnType;
+use sqlparser::ast;
 
+use super::postprocessing::{PostColumn, PostExpr, PostProcessing};
 use super::CompiledQuery;
 
+/// Maximum number of rows Cube.js returns for a query.
+pub const MAX_LOADED_ROWS: usize = 50000;
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct CompiledQueryFieldMeta {
     pub column_from: String,
@@ -25,6 +30,13 @@ pub struct QueryBuilder {
     offset: Option<i32>,
     // query meta for response hydration
     meta: Vec<CompiledQueryFieldMeta>,
+    // post-processing of the response, output columns are computed from `meta` columns
+    post_processing: bool,
+    columns: Vec<PostColumn>,
+    post_filters: Vec<PostExpr>,
+    post_order: Vec<(PostExpr, bool)>,
+    post_limit: Option<usize>,
+    post_offset: Option<usize>,
 }
 
 impl QueryBuilder {
@@ -39,16 +51,48 @@ impl QueryBuilder {
             filters: vec![],
             limit: None,
             offset: None,
+            post_processing: false,
+            columns: vec![],
+            post_filters: vec![],
+            post_order: vec![],
+            post_limit: None,
+            post_offset: None,
         }
     }
 
+    fn push_meta(&mut self, meta: CompiledQueryFieldMeta) -> usize {
+        self.meta.push(meta);
+
+        self.meta.len() - 1
+    }
+
+    fn push_column(&mut self, meta: CompiledQueryFieldMeta) {
+        self.columns.push(PostColumn {
+            name: meta.column_to.clone(),
+            expr: PostExpr::Column(self.meta.len()),
+            column_type: meta.column_type,
+        });
+        self.meta.push(meta);
+    }
+
     pub fn with_time_dimension(
         &mut self,
         td: V1LoadRequestQueryTimeDimension,
         meta: CompiledQueryFieldMeta,
     ) {
         self.time_dimensions.push(td);
-        self.meta.push(meta);
+        self.push_column(meta);
+    }
+
+    /// Requests a time dimension which is used only by post-processing, returns its column.
+    pub fn with_hidden_time_dimension(
+        &mut self,
+        td: V1LoadRequestQueryTimeDimension,
+        meta: CompiledQueryFieldMeta,
+    ) -> usize {
+        self.post_processing = true;
+        self.time_dimensions.push(td);
+        self.push_meta(meta)
     }
 
     pub fn push_date_range_for_time_dimenssion(
@@ -69,7 +113,14 @@ impl QueryBuilder {
 
     pub fn with_measure(&mut self, name: String, meta: CompiledQueryFieldMeta) {
         self.measures.push(name);
-        self.meta.push(meta);
+        self.push_column(meta);
+    }
+
+    /// Requests a measure which is used only by post-processing, returns its column.
+    pub fn with_hidden_measure(&mut self, name: String, meta: CompiledQueryFieldMeta) -> usize {
+        self.post_processing = true;
+        self.measures.push(name);
+        self.push_meta(meta)
     }
 
     pub fn with_segment(&mut self, name: String) {
@@ -78,7 +129,71 @@ impl QueryBuilder {
 
     pub fn with_dimension(&mut self, name: String, meta: CompiledQueryFieldMeta) {
         self.dimensions.push(name);
-        self.meta.push(meta);
+        self.push_column(meta);
+    }
+
+    /// Requests a dimension which is used only by post-processing, returns its column.
+    pub fn with_hidden_dimension(&mut self, name: String, meta: CompiledQueryFieldMeta) -> usize {
+        self.post_processing = true;
+        self.dimensions.push(name);
+        self.push_meta(meta)
+    }
+
+    /// Adds an output column computed from the response.
+    pub fn with_computed_column(&mut self, column: PostColumn) {
+        self.post_processing = true;
+        self.columns.push(column);
+    }
+
+    /// Replaces output columns with columns computed from the response.
+    pub fn with_columns(&mut self, columns: Vec<PostColumn>) {
+        self.post_processing = true;
+        self.columns = columns;
+    }
+
+    pub fn get_columns(&self) -> &Vec<PostColumn> {
+        &self.columns
+    }
+
+    /// Index of the response column with the member `column_from`.
+    pub fn find_column(&self, column_from: &String) -> Option<usize> {
+        self.meta
+            .iter()
+            .position(|meta| meta.column_from.eq(column_from))
+    }
+
+    /// Output column named `name`.
+    pub fn find_output_column(&self, name: &String) -> Option<&PostColumn> {
+        self.columns.iter().find(|column| column.name.eq(name))
+    }
+
+    pub fn with_post_filter(&mut self, filter: PostExpr) {
+        self.post_processing = true;
+        self.post_filters.push(filter);
+    }
+
+    pub fn with_post_order(&mut self, expr: PostExpr, asc: bool) {
+        self.post_processing = true;
+        self.post_order.push((expr, asc));
+    }
+
+    pub fn with_post_limit(&mut self, limit: usize) {
+        self.post_processing = true;
+        self.post_limit = Some(limit);
+    }
+
+    pub fn with_post_offset(&mut self, offset: usize) {
+        self.post_processing = true;
+        self.post_offset = Some(offset);
+    }
+
+    pub fn has_post_processing(&self) -> bool {
+        self.post_processing
+    }
+
+    /// Rows are filtered or sorted after loading, so LIMIT and OFFSET can't be pushed to Cube.js.
+    pub fn has_post_rows_processing(&self) -> bool {
+        !self.post_filters.is_empty() || !self.post_order.is_empty()
     }
 
     pub fn with_limit(&mut self, limit: i32) {
@@ -101,7 +216,35 @@ impl QueryBuilder {
         self.filters.push(filter);
     }
 
+    pub fn with_meta(&mut self, meta: Vec<CompiledQueryFieldMeta>) {
+        self.meta = vec![];
+        self.columns = vec![];
+        for field in meta {
+            self.push_column(field);
+        }
+    }
+
+    pub fn get_meta(&self) -> &Vec<CompiledQueryFieldMeta> {
+        &self.meta
+    }
+
+    pub fn reset_order(&mut self) {
+        self.order = vec![];
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.limit.is_some() || self.offset.is_some()
+    }
+
     pub fn build(&self) -> super::CompiledQuery {
+        // All rows have to be loaded to filter or sort them locally. Ask for as many rows as
+        // Cube.js can return and fail if the response may be truncated.
+        let load_limit = if self.limit.is_none() && self.has_post_rows_processing() {
+            Some(MAX_LOADED_ROWS)
+        } else {
+            None
+        };
+
         CompiledQuery {
             request: V1LoadRequestQuery {
                 measures: Some(self.measures.clone()),
@@ -117,7 +260,7 @@ impl QueryBuilder {
                 } else {
                     None
                 },
-                limit: self.limit,
+                limit: self.limit.or(load_limit.map(|limit| limit as i32)),
                 offset: self.offset,
                 filters: if !self.filters.is_empty() {
                     Some(self.filters.clone())
@@ -126,6 +269,27 @@ impl QueryBuilder {
                 },
             },
             meta: self.meta.clone(),
+            post_processing: if self.post_processing {
+                Some(PostProcessing {
+                    columns: self.columns.clone(),
+                    filter: self.post_filters.iter().cloned().fold(None, |acc, filter| {
+                        Some(match acc {
+                            Some(left) => PostExpr::BinaryOp {
+                                left: Box::new(left),
+                                op: ast::BinaryOperator::And,
+                                right: Box::new(filter),
+                            },
+                            None => filter,
+                        })
+                    }),
+                    order: self.post_order.clone(),
+                    limit: self.post_limit,
+                    offset: self.post_offset,
+                    load_limit,
+                })
+            } else {
+                None
+            },
         }
     }
 }
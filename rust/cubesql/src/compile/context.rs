@@ -11,6 +11,9 @@ use crate::{
 
 use super::CompilationResult;
 
+/// Pseudo-column for joining cubes by the relationship from the data model.
+pub const JOIN_FIELD: &str = "__cubeJoinField";
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Selection {
     TimeDimension(V1CubeMetaDimension, String),
@@ -21,73 +24,322 @@ pub enum Selection {
 
 #[derive(Debug)]
 pub struct QueryContext {
-    pub meta: V1CubeMeta,
+    /// Cubes in FROM, the first one is the cube the query selects from.
+    pub cubes: Vec<V1CubeMeta>,
+    /// Table names and aliases of cubes in FROM, mapped to the index in `cubes`.
+    relations: HashMap<String, usize>,
     aliases: HashMap<String, Selection>,
+    /// Alias of the derived table (or CTE) the query selects from instead of cubes.
+    /// Its columns are resolved through `aliases`.
+    derived_table: Option<String>,
+    /// Output columns of the query.
+    columns: Vec<(String, Selection)>,
 }
 
 impl QueryContext {
     pub fn new(meta: &V1CubeMeta) -> QueryContext {
-        QueryContext {
-            meta: meta.clone(),
+        let mut ctx = QueryContext {
+            cubes: vec![],
+            relations: HashMap::new(),
             aliases: HashMap::new(),
+            derived_table: None,
+            columns: vec![],
+        };
+        ctx.relations.insert(meta.name.clone(), 0);
+        ctx.cubes.push(meta.clone());
+
+        ctx
+    }
+
+    /// Context of a query selecting from a derived table with the output `columns` of the
+    /// subquery.
+    pub fn for_derived_table(alias: String, columns: &Vec<(String, Selection)>) -> QueryContext {
+        QueryContext {
+            cubes: vec![],
+            relations: HashMap::new(),
+            aliases: columns.iter().cloned().collect(),
+            derived_table: Some(alias),
+            columns: vec![],
         }
     }
 
-    pub fn find_selection_for_identifier(
-        &self,
-        column_name: &String,
-        check_alias: bool,
-    ) -> Option<Selection> {
-        for dimension in self.meta.dimensions.iter() {
-            if dimension.get_real_name().eq(column_name) {
-                return Some(Selection::Dimension(dimension.clone()));
+    /// Adds a joined cube, addressable by its name or `alias` in compound identifiers.
+    ///
+    /// Cube.js joins cubes by the relationships from the data model, so the ON condition is
+    /// not compiled. It must compare the `__cubeJoinField` pseudo-column, which stands for the
+    /// join keys of the model, of the joined cube and of a cube already in the query. Cube.js
+    /// left-joins cubes to the cube the query selects from, so a LEFT JOIN must join to that cube.
+    pub fn with_join(
+        &mut self,
+        meta: &V1CubeMeta,
+        alias: Option<String>,
+        operator: &ast::JoinOperator,
+    ) -> CompilationResult<()> {
+        let (on, is_left) = match operator {
+            ast::JoinOperator::Inner(ast::JoinConstraint::On(on)) => (on, false),
+            ast::JoinOperator::LeftOuter(ast::JoinConstraint::On(on)) => (on, true),
+            ast::JoinOperator::Inner(_) | ast::JoinOperator::LeftOuter(_) => {
+                return Err(CompilationError::Unsupported(
+                    "JOIN without ON condition (USING, NATURAL or no condition)".to_string(),
+                ));
             }
+            operator => {
+                return Err(CompilationError::Unsupported(format!(
+                    "JOIN type (only INNER and LEFT joins are supported): {:?}",
+                    operator
+                )));
+            }
+        };
+
+        let primary = &self.cubes[0];
+        let is_joinable = match (primary.connected_component, meta.connected_component) {
+            (Some(left), Some(right)) => left == right,
+            _ => false,
+        };
+        if !is_joinable {
+            return Err(CompilationError::User(format!(
+                "Unable to join {} with {}: there is no join path between these cubes in the data model",
+                primary.name, meta.name
+            )));
         }
 
-        for measure in self.meta.measures.iter() {
-            if measure.get_real_name().eq(column_name) {
-                return Some(Selection::Measure(measure.clone()));
+        let relation = alias.unwrap_or_else(|| meta.name.clone());
+        if self.relations.contains_key(&relation) {
+            return Err(CompilationError::User(format!(
+                "Table name {} is specified more than once, use an alias",
+                relation
+            )));
+        }
+
+        self.relations.insert(relation, self.cubes.len());
+        self.cubes.push(meta.clone());
+
+        let joined_to = self.check_join_condition(on)?;
+        if is_left && joined_to != 0 {
+            return Err(CompilationError::User(format!(
+                "LEFT JOIN of {} must join it to {}, the table the query selects from, to match the join of the data model: {}",
+                meta.name, self.cubes[0].name, on
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that `on` compares `__cubeJoinField` of the last joined cube with the one of a
+    /// cube joined before it and returns the index of the latter.
+    fn check_join_condition(&self, on: &ast::Expr) -> CompilationResult<usize> {
+        let joined = self.cubes.len() - 1;
+        let error = || {
+            CompilationError::User(format!(
+                "Join condition must be {}.{} = <table>.{}, joins use the keys from the data model: {}",
+                self.cubes[joined].name, JOIN_FIELD, JOIN_FIELD, on
+            ))
+        };
+        match on {
+            ast::Expr::Nested(e) => self.check_join_condition(e),
+            ast::Expr::BinaryOp {
+                left,
+                op: ast::BinaryOperator::Eq,
+                right,
+            } => {
+                let (left_cube, right_cube) =
+                    (self.find_join_column(left)?, self.find_join_column(right)?);
+                match (left_cube, right_cube) {
+                    (Some(l), Some(r)) if l.max(r) == joined && l.min(r) < joined => Ok(l.min(r)),
+                    _ => Err(error()),
+                }
             }
+            _ => Err(error()),
+        }
+    }
+
+    /// Index in `cubes` of the cube of `__cubeJoinField` in a join condition, None for other
+    /// expressions.
+    fn find_join_column(&self, expr: &ast::Expr) -> CompilationResult<Option<usize>> {
+        let (relation, column_name) = match expr {
+            ast::Expr::Identifier(i) => (None, &i.value),
+            ast::Expr::CompoundIdentifier(identifiers) => match identifiers.as_slice() {
+                [relation, column] | [_, relation, column] => {
+                    (Some(&relation.value), &column.value)
+                }
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        if column_name != JOIN_FIELD {
+            return Ok(None);
+        }
+
+        match relation {
+            Some(relation) => match self.relations.get(relation) {
+                Some(index) => Ok(Some(*index)),
+                None => Err(CompilationError::User(format!(
+                    "Unknown table: {}",
+                    relation
+                ))),
+            },
+            None => Err(CompilationError::User(format!(
+                "Column {} is ambiguous, qualify it with a table name",
+                column_name
+            ))),
         }
+    }
+
+    /// Renames the cube the query selects from to `alias`.
+    pub fn with_relation_alias(&mut self, alias: String) {
+        self.relations.retain(|_, index| *index != 0);
+        self.relations.insert(alias, 0);
+    }
+
+    /// Cubes which can contain a column qualified with `relation` (all cubes for None).
+    pub fn cubes_for_relation(
+        &self,
+        relation: Option<&String>,
+    ) -> CompilationResult<Vec<&V1CubeMeta>> {
+        match relation {
+            Some(relation) => match self.relations.get(relation) {
+                Some(index) => Ok(vec![&self.cubes[*index]]),
+                None => Err(CompilationError::User(format!(
+                    "Unknown table: {}",
+                    relation
+                ))),
+            },
+            None => Ok(self.cubes.iter().collect()),
+        }
+    }
+
+    /// Finds a member named `column_name` among `members` of the cubes of `relation`.
+    fn find_member<T>(
+        &self,
+        relation: Option<&String>,
+        column_name: &String,
+        members: impl Fn(&V1CubeMeta) -> Vec<(String, T)>,
+    ) -> CompilationResult<Option<T>> {
+        let mut result = None;
+
+        for cube in self.cubes_for_relation(relation)? {
+            for (name, member) in members(cube) {
+                if name.eq(column_name) {
+                    if result.is_some() {
+                        return Err(CompilationError::User(format!(
+                            "Column {} is ambiguous, qualify it with a table name",
+                            column_name
+                        )));
+                    }
 
-        for segment in self.meta.segments.iter() {
-            if segment.get_real_name().eq(column_name) {
-                return Some(Selection::Segment(segment.clone()));
+                    result = Some(member);
+                }
             }
         }
 
+        Ok(result)
+    }
+
+    fn find_selection_in_relation(
+        &self,
+        relation: Option<&String>,
+        column_name: &String,
+    ) -> CompilationResult<Option<Selection>> {
+        self.find_member(relation, column_name, |cube| {
+            let dimensions = cube
+                .dimensions
+                .iter()
+                .map(|d| (d.get_real_name(), Selection::Dimension(d.clone())));
+            let measures = cube
+                .measures
+                .iter()
+                .map(|m| (m.get_real_name(), Selection::Measure(m.clone())));
+            let segments = cube
+                .segments
+                .iter()
+                .map(|s| (s.get_real_name(), Selection::Segment(s.clone())));
+
+            dimensions.chain(measures).chain(segments).collect()
+        })
+    }
+
+    pub fn find_selection_for_identifier(
+        &self,
+        column_name: &String,
+        check_alias: bool,
+    ) -> CompilationResult<Option<Selection>> {
+        if let Some(selection) = self.find_selection_in_relation(None, column_name)? {
+            return Ok(Some(selection));
+        }
+
         if check_alias {
             if let Some(r) = self.aliases.get(column_name) {
                 // @todo Resolve without match!
                 match r {
-                    Selection::Dimension(d) => Some(Selection::Dimension(d.clone())),
-                    Selection::Measure(d) => Some(Selection::Measure(d.clone())),
+                    Selection::Dimension(d) => Ok(Some(Selection::Dimension(d.clone()))),
+                    Selection::Measure(d) => Ok(Some(Selection::Measure(d.clone()))),
                     Selection::TimeDimension(d, g) => {
-                        Some(Selection::TimeDimension(d.clone(), g.clone()))
+                        Ok(Some(Selection::TimeDimension(d.clone(), g.clone())))
                     }
                     s => panic!("Unable to map this selection type: {:?}", s),
                 }
             } else {
-                None
+                Ok(None)
             }
         } else {
-            None
+            Ok(None)
+        }
+    }
+
+    /// Resolves `relation.column` (or `db.relation.column`) identifiers.
+    pub fn find_selection_for_compound_identifier(
+        &self,
+        identifiers: &Vec<ast::Ident>,
+    ) -> CompilationResult<Option<Selection>> {
+        match identifiers.as_slice() {
+            [relation, column] if self.derived_table.as_ref() == Some(&relation.value) => {
+                Ok(self.aliases.get(&column.value).cloned())
+            }
+            [relation, column] | [_, relation, column] => {
+                self.find_selection_in_relation(Some(&relation.value), &column.value)
+            }
+            _ => Err(CompilationError::Unsupported(format!(
+                "Compound identifier: {:?}",
+                identifiers
+            ))),
         }
     }
 
     pub fn find_dimension_for_identifier(
         &self,
         column_name: &String,
-    ) -> Option<V1CubeMetaDimension> {
-        for dimension in self.meta.dimensions.iter() {
-            let (_, dimension_name) = dimension.name.split_once('.').unwrap();
+    ) -> CompilationResult<Option<V1CubeMetaDimension>> {
+        self.find_dimension_ignore_case(None, column_name)
+    }
 
-            if dimension_name.eq(column_name) {
-                return Some(dimension.clone());
-            }
-        }
+    /// Finds a dimension with the real name equal to `column_name` ignoring case.
+    fn find_dimension_ignore_case(
+        &self,
+        relation: Option<&String>,
+        column_name: &String,
+    ) -> CompilationResult<Option<V1CubeMetaDimension>> {
+        let column_name = column_name.to_lowercase();
+        self.find_member(relation, &column_name, |cube| {
+            cube.dimensions
+                .iter()
+                .map(|d| (d.get_real_name().to_lowercase(), d.clone()))
+                .collect()
+        })
+    }
 
-        None
+    /// Finds a measure with the real name equal to `column_name` ignoring case.
+    fn find_measure_ignore_case(
+        &self,
+        relation: Option<&String>,
+        column_name: &String,
+    ) -> CompilationResult<Option<V1CubeMetaMeasure>> {
+        let column_name = column_name.to_lowercase();
+        self.find_member(relation, &column_name, |cube| {
+            cube.measures
+                .iter()
+                .map(|m| (m.get_real_name().to_lowercase(), m.clone()))
+                .collect()
+        })
     }
 
     fn find_selection_for_binary_op(
@@ -101,7 +353,7 @@ impl QueryContext {
             if let Some(identifiers) = left_regexp.captures(expr_as_str) {
                 let identifier = identifiers.name("column").unwrap().as_str();
                 let result = self
-                    .find_dimension_for_identifier(&identifier.to_string())
+                    .find_dimension_for_identifier(&identifier.to_string())?
                     .map(|dimension| Selection::TimeDimension(dimension, "quarter".to_string()));
 
                 return Ok(result);
@@ -119,8 +371,9 @@ impl QueryContext {
             ast::Expr::BinaryOp { .. } => self.find_selection_for_binary_op(&expr.to_string()),
             ast::Expr::Function(f) => self.find_selection_for_function(f),
             ast::Expr::Identifier(i) => {
-                Ok(self.find_selection_for_identifier(&i.value.to_string(), false))
+                self.find_selection_for_identifier(&i.value.to_string(), false)
             }
+            ast::Expr::CompoundIdentifier(i) => self.find_selection_for_compound_identifier(i),
             _ => {
                 return Err(CompilationError::Unsupported(format!(
                     "Expression in selection: {:?}",
@@ -130,15 +383,23 @@ impl QueryContext {
         }
     }
 
-    fn unpack_identifier_from_arg(&self, arg: &ast::FunctionArg) -> CompilationResult<String> {
+    /// Returns the table qualifier (if any) and the lowercase column name of the argument.
+    fn unpack_identifier_from_arg(
+        &self,
+        arg: &ast::FunctionArg,
+    ) -> CompilationResult<(Option<String>, String)> {
         let argument = match arg {
             ast::FunctionArg::Named { arg, .. } => arg,
             ast::FunctionArg::Unnamed(expr) => expr,
         };
 
         let identifier = match argument {
-            ast::Expr::Wildcard => "*".to_string(),
-            ast::Expr::Identifier(i) => i.value.to_string().to_lowercase(),
+            ast::Expr::Wildcard => (None, "*".to_string()),
+            ast::Expr::Identifier(i) => (None, i.value.to_string().to_lowercase()),
+            ast::Expr::CompoundIdentifier(i) if i.len() == 2 => (
+                Some(i[0].value.to_string()),
+                i[1].value.to_string().to_lowercase(),
+            ),
             _ => {
                 return Err(CompilationError::Unsupported(format!(
                     "type of argument {:?}",
@@ -189,14 +450,10 @@ impl QueryContext {
                         )));
                     }
 
-                    let possible_dimension_name = self.unpack_identifier_from_arg(&f.args[0])?;
+                    let (relation, possible_dimension_name) =
+                        self.unpack_identifier_from_arg(&f.args[0])?;
 
-                    self.meta.dimensions.iter().find(|dimension| {
-                        dimension
-                            .get_real_name()
-                            .to_lowercase()
-                            .eq(&possible_dimension_name)
-                    })
+                    self.find_dimension_ignore_case(relation.as_ref(), &possible_dimension_name)?
                 }
                 _ => {
                     return Err(CompilationError::User(format!(
@@ -274,30 +531,26 @@ impl QueryContext {
                         )));
                     };
 
-                    let possible_dimension_name =
+                    let (relation, possible_dimension_name) =
                         self.unpack_identifier_from_arg(&date_sub.args[0])?;
 
-                    if let Some(r) = self.meta.dimensions.iter().find(|dimension| {
-                        dimension
-                            .get_real_name()
-                            .to_lowercase()
-                            .eq(&possible_dimension_name)
-                    }) {
-                        Ok(Some(Selection::TimeDimension(r.clone(), granularity)))
+                    if let Some(r) = self
+                        .find_dimension_ignore_case(relation.as_ref(), &possible_dimension_name)?
+                    {
+                        Ok(Some(Selection::TimeDimension(r, granularity)))
                     } else {
                         Ok(None)
                     }
                 }
-                [ast::FunctionArg::Unnamed(ast::Expr::Identifier(_i))] => {
-                    let possible_dimension_name = self.unpack_identifier_from_arg(&f.args[0])?;
-
-                    if let Some(r) = self.meta.dimensions.iter().find(|dimension| {
-                        dimension
-                            .get_real_name()
-                            .to_lowercase()
-                            .eq(&possible_dimension_name)
-                    }) {
-                        Ok(Some(Selection::TimeDimension(r.clone(), "day".to_string())))
+                [ast::FunctionArg::Unnamed(ast::Expr::Identifier(_))]
+                | [ast::FunctionArg::Unnamed(ast::Expr::CompoundIdentifier(_))] => {
+                    let (relation, possible_dimension_name) =
+                        self.unpack_identifier_from_arg(&f.args[0])?;
+
+                    if let Some(r) = self
+                        .find_dimension_ignore_case(relation.as_ref(), &possible_dimension_name)?
+                    {
+                        Ok(Some(Selection::TimeDimension(r, "day".to_string())))
                     } else {
                         return Err(CompilationError::User(format!(
                             "Unable to find dimension {} from expression: {}",
@@ -324,9 +577,12 @@ impl QueryContext {
                 ast::FunctionArg::Unnamed(expr) => expr,
             };
 
-            let measure_name = match argument {
-                ast::Expr::Wildcard => "*".to_string(),
-                ast::Expr::Identifier(i) => i.value.to_string(),
+            let (relation, measure_name) = match argument {
+                ast::Expr::Wildcard => (None, "*".to_string()),
+                ast::Expr::Identifier(i) => (None, i.value.to_string()),
+                ast::Expr::CompoundIdentifier(i) if i.len() == 2 => {
+                    (Some(i[0].value.to_string()), i[1].value.to_string())
+                }
                 _ => {
                     return Err(CompilationError::Unsupported(format!(
                         "type of argument {:?}",
@@ -349,13 +605,17 @@ impl QueryContext {
             };
 
             if call_agg_type.eq(&"count".to_string()) {
-                let measure_for_argument = self.meta.measures.iter().find(|measure| {
-                    if measure.agg_type.is_some() {
-                        let agg_type = measure.agg_type.clone().unwrap();
-                        agg_type.eq(&"count".to_string())
-                    } else {
-                        false
-                    }
+                // Without a table qualifier COUNT counts rows of the cube the query selects from.
+                let cubes = self.cubes_for_relation(relation.as_ref())?;
+                let measure_for_argument = cubes.first().and_then(|cube| {
+                    cube.measures.iter().find(|measure| {
+                        if measure.agg_type.is_some() {
+                            let agg_type = measure.agg_type.clone().unwrap();
+                            agg_type.eq(&"count".to_string())
+                        } else {
+                            false
+                        }
+                    })
                 });
 
                 if let Some(measure) = measure_for_argument {
@@ -364,7 +624,10 @@ impl QueryContext {
                     Ok(None)
                 }
             } else {
-                let selection_opt = self.find_selection_for_identifier(&measure_name, true);
+                let selection_opt = match &relation {
+                    Some(_) => self.find_selection_in_relation(relation.as_ref(), &measure_name)?,
+                    None => self.find_selection_for_identifier(&measure_name, true)?,
+                };
                 if let Some(selection) = selection_opt {
                     match selection {
                         Selection::Measure(measure) => {
@@ -403,15 +666,13 @@ impl QueryContext {
             }
         } else if fn_name.to_lowercase().eq("measure") {
             if f.args.len() == 1 {
-                let possible_measure_name = self.unpack_identifier_from_arg(&f.args[0])?;
-
-                if let Some(r) = self.meta.measures.iter().find(|measure| {
-                    measure
-                        .get_real_name()
-                        .to_lowercase()
-                        .eq(&possible_measure_name)
-                }) {
-                    Ok(Some(Selection::Measure(r.clone())))
+                let (relation, possible_measure_name) =
+                    self.unpack_identifier_from_arg(&f.args[0])?;
+
+                if let Some(r) =
+                    self.find_measure_ignore_case(relation.as_ref(), &possible_measure_name)?
+                {
+                    Ok(Some(Selection::Measure(r)))
                 } else {
                     Ok(None)
                 }
@@ -426,4 +687,18 @@ impl QueryContext {
     pub fn with_alias(&mut self, alias: String, selection: Selection) {
         self.aliases.insert(alias, selection);
     }
+
+    pub fn with_column(&mut self, name: String, selection: Selection) {
+        self.columns.push((name, selection));
+    }
+
+    pub fn get_columns(&self) -> &Vec<(String, Selection)> {
+        &self.columns
+    }
+
+    pub fn is_measure(&self, member: &String) -> bool {
+        self.cubes
+            .iter()
+            .any(|cube| cube.measures.iter().any(|m| m.name.eq(member)))
+    }
 }
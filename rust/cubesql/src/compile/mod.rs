@@ -1,28 +1,30 @@
-use std::{backtrace::Backtrace, convert::TryFrom, fmt};
+use std::{backtrace::Backtrace, collections::HashMap, convert::TryFrom, fmt};
 
 use chrono::{DateTime, TimeZone, Utc};
 use log::{debug, trace};
 use serde_json::json;
 use sqlparser::ast;
-use sqlparser::dialect::MySqlDialect;
+use sqlparser::dialect::{Dialect, MySqlDialect};
 use sqlparser::parser::Parser;
 
 use cubeclient::models::{
-    V1LoadRequestQuery, V1LoadRequestQueryFilterItem, V1LoadRequestQueryTimeDimension,
+    V1CubeMeta, V1LoadRequestQuery, V1LoadRequestQueryFilterItem, V1LoadRequestQueryTimeDimension,
 };
 
 pub use crate::schema::ctx::*;
 use crate::{
     compile::builder::QueryBuilder,
+    mysql::dataframe::TableValue,
     schema::{ctx, V1CubeMetaDimensionExt, V1CubeMetaMeasureExt, V1CubeMetaSegmentExt},
 };
-use msql_srv::ColumnType;
 
 use self::builder::*;
 use self::context::*;
+use self::postprocessing::{PostColumn, PostExpr, PostProcessing, POST_FUNCTIONS};
 
 pub mod builder;
 pub mod context;
+pub mod postprocessing;
 
 #[derive(Debug, PartialEq)]
 pub enum CompilationError {
@@ -71,8 +73,10 @@ fn compile_select_expr(
     builder: &mut QueryBuilder,
     mb_alias: Option<String>,
 ) -> CompilationResult<()> {
-    if let Some(selection) = ctx.find_selection_for_expr(expr)? {
-        match selection {
+    // Expressions which are not members are evaluated over the response, compilation of
+    // members reports the same errors there
+    if let Some(selection) = ctx.find_selection_for_expr(expr).unwrap_or(None) {
+        match selection.clone() {
             Selection::TimeDimension(dimension, granularity) => {
                 if let Some(alias) = mb_alias.clone() {
                     ctx.with_alias(
@@ -90,7 +94,7 @@ fn compile_select_expr(
                     CompiledQueryFieldMeta {
                         column_from: dimension.name.clone(),
                         column_to: mb_alias.unwrap_or(dimension.get_real_name()),
-                        column_type: ColumnType::MYSQL_TYPE_STRING,
+                        column_type: dimension.get_mysql_type(),
                     },
                 );
             }
@@ -118,10 +122,7 @@ fn compile_select_expr(
                     CompiledQueryFieldMeta {
                         column_from: dimension.name.clone(),
                         column_to: mb_alias.unwrap_or(dimension.get_real_name()),
-                        column_type: match dimension._type.as_str() {
-                            "number" => ColumnType::MYSQL_TYPE_DOUBLE,
-                            _ => ColumnType::MYSQL_TYPE_STRING,
-                        },
+                        column_type: dimension.get_mysql_type(),
                     },
                 );
             }
@@ -132,16 +133,263 @@ fn compile_select_expr(
                 )))
             }
         }
+
+        let column = builder.get_meta().last().unwrap().column_to.clone();
+        ctx.with_column(column, selection);
     } else {
-        return Err(CompilationError::Unknown(format!(
-            "Expression in selection: {}",
-            expr.to_string()
-        )));
+        let post_expr = compile_post_expression(expr, &mut |e: &ast::Expr| {
+            resolve_member(e, ctx, builder, MemberAccess::All)
+        })?;
+        let column_type = post_expr.column_type(builder.get_meta());
+
+        builder.with_computed_column(PostColumn {
+            name: mb_alias.unwrap_or(expr.to_string()),
+            expr: post_expr,
+            column_type,
+        });
     }
 
     Ok(())
 }
 
+/// Members which expressions evaluated after loading can request from Cube.js.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MemberAccess {
+    /// Any member, requested dimensions extend the grouping (SELECT).
+    All,
+    /// Measures and already selected dimensions (HAVING, ORDER BY).
+    Measures,
+    /// Already selected dimensions (WHERE).
+    Dimensions,
+}
+
+/// Returns the response column with `selection`, requesting it from Cube.js if needed.
+fn fetch_selection(
+    selection: &Selection,
+    builder: &mut QueryBuilder,
+    access: MemberAccess,
+) -> CompilationResult<usize> {
+    let member = match selection {
+        Selection::Measure(m) => m.name.clone(),
+        Selection::Dimension(d) | Selection::TimeDimension(d, _) => d.name.clone(),
+        Selection::Segment(s) => {
+            return Err(CompilationError::User(format!(
+                "Unable to use segment {} in expression",
+                s.get_real_name()
+            )));
+        }
+    };
+
+    if let Some(index) = builder.find_column(&member) {
+        return Ok(index);
+    }
+
+    match (selection, access) {
+        (Selection::Measure(m), MemberAccess::Dimensions) => Err(CompilationError::User(format!(
+            "Unable to use measure {} in WHERE, use HAVING instead",
+            m.name
+        ))),
+        (Selection::Measure(m), _) => Ok(builder.with_hidden_measure(
+            m.name.clone(),
+            CompiledQueryFieldMeta {
+                column_from: m.name.clone(),
+                column_to: m.name.clone(),
+                column_type: m.get_mysql_type(),
+            },
+        )),
+        (Selection::Dimension(d), MemberAccess::All) => Ok(builder.with_hidden_dimension(
+            d.name.clone(),
+            CompiledQueryFieldMeta {
+                column_from: d.name.clone(),
+                column_to: d.name.clone(),
+                column_type: d.get_mysql_type(),
+            },
+        )),
+        (Selection::TimeDimension(d, granularity), MemberAccess::All) => Ok(builder
+            .with_hidden_time_dimension(
+                V1LoadRequestQueryTimeDimension {
+                    dimension: d.name.clone(),
+                    granularity: granularity.clone(),
+                    date_range: None,
+                },
+                CompiledQueryFieldMeta {
+                    column_from: d.name.clone(),
+                    column_to: d.name.clone(),
+                    column_type: d.get_mysql_type(),
+                },
+            )),
+        _ => Err(CompilationError::User(format!(
+            "Unable to use {} in expression evaluated after loading, add it to the selection",
+            member
+        ))),
+    }
+}
+
+/// Resolves members (and aliases of computed columns) to response columns.
+fn resolve_member(
+    expr: &ast::Expr,
+    ctx: &QueryContext,
+    builder: &mut QueryBuilder,
+    access: MemberAccess,
+) -> CompilationResult<Option<PostExpr>> {
+    let selection_opt = match expr {
+        ast::Expr::Identifier(i) => ctx.find_selection_for_identifier(&i.value, true)?,
+        ast::Expr::CompoundIdentifier(i) => ctx.find_selection_for_compound_identifier(i)?,
+        ast::Expr::Function(f) => ctx.find_selection_for_function(f)?,
+        _ => None,
+    };
+
+    match (selection_opt, expr) {
+        (Some(selection), _) => Ok(Some(PostExpr::Column(fetch_selection(
+            &selection, builder, access,
+        )?))),
+        (None, ast::Expr::Identifier(i)) => Ok(builder
+            .find_output_column(&i.value)
+            .map(|column| column.expr.clone())),
+        _ => Ok(None),
+    }
+}
+
+/// Compiles an expression which Cube.js can't evaluate into an expression over the response,
+/// `resolve` maps members to response columns.
+fn compile_post_expression(
+    expr: &ast::Expr,
+    resolve: &mut dyn FnMut(&ast::Expr) -> CompilationResult<Option<PostExpr>>,
+) -> CompilationResult<PostExpr> {
+    if let Some(column) = resolve(expr)? {
+        return Ok(column);
+    }
+
+    let compiled = match expr {
+        ast::Expr::Identifier(_) | ast::Expr::CompoundIdentifier(_) => {
+            return Err(CompilationError::Unknown(format!(
+                "Unknown column: {}",
+                expr
+            )));
+        }
+        ast::Expr::Nested(expr) => compile_post_expression(expr, resolve)?,
+        ast::Expr::Value(value) => PostExpr::Literal(match value {
+            ast::Value::Number(v, _) => match v.parse::<i64>() {
+                Ok(n) => TableValue::Int64(n),
+                Err(_) => TableValue::Float64(v.parse::<f64>().map_err(|e| {
+                    CompilationError::User(format!("Unable to parse number {}: {}", v, e))
+                })?),
+            },
+            ast::Value::SingleQuotedString(v) => TableValue::String(v.clone()),
+            ast::Value::Boolean(v) => TableValue::Boolean(*v),
+            ast::Value::Null => TableValue::Null,
+            _ => {
+                return Err(CompilationError::Unsupported(format!(
+                    "Value in expression: {:?}",
+                    value
+                )));
+            }
+        }),
+        ast::Expr::BinaryOp { left, op, right } => match op {
+            ast::BinaryOperator::Plus
+            | ast::BinaryOperator::Minus
+            | ast::BinaryOperator::Multiply
+            | ast::BinaryOperator::Divide
+            | ast::BinaryOperator::Modulus
+            | ast::BinaryOperator::Gt
+            | ast::BinaryOperator::Lt
+            | ast::BinaryOperator::GtEq
+            | ast::BinaryOperator::LtEq
+            | ast::BinaryOperator::Eq
+            | ast::BinaryOperator::NotEq
+            | ast::BinaryOperator::And
+            | ast::BinaryOperator::Or
+            | ast::BinaryOperator::Like
+            | ast::BinaryOperator::NotLike => PostExpr::BinaryOp {
+                left: Box::new(compile_post_expression(left, resolve)?),
+                op: op.clone(),
+                right: Box::new(compile_post_expression(right, resolve)?),
+            },
+            _ => {
+                return Err(CompilationError::Unsupported(format!(
+                    "Operator in expression: {:?}",
+                    op
+                )));
+            }
+        },
+        ast::Expr::UnaryOp { op, expr } => match op {
+            ast::UnaryOperator::Not | ast::UnaryOperator::Minus | ast::UnaryOperator::Plus => {
+                PostExpr::UnaryOp {
+                    op: op.clone(),
+                    expr: Box::new(compile_post_expression(expr, resolve)?),
+                }
+            }
+            _ => {
+                return Err(CompilationError::Unsupported(format!(
+                    "Operator in expression: {:?}",
+                    op
+                )));
+            }
+        },
+        ast::Expr::IsNull(expr) => PostExpr::IsNull {
+            expr: Box::new(compile_post_expression(expr, resolve)?),
+            negated: false,
+        },
+        ast::Expr::IsNotNull(expr) => PostExpr::IsNull {
+            expr: Box::new(compile_post_expression(expr, resolve)?),
+            negated: true,
+        },
+        ast::Expr::Case {
+            operand,
+            conditions,
+            results,
+            else_result,
+        } => PostExpr::Case {
+            operand: match operand {
+                Some(operand) => Some(Box::new(compile_post_expression(operand, resolve)?)),
+                None => None,
+            },
+            conditions: conditions
+                .iter()
+                .map(|condition| compile_post_expression(condition, resolve))
+                .collect::<CompilationResult<Vec<_>>>()?,
+            results: results
+                .iter()
+                .map(|result| compile_post_expression(result, resolve))
+                .collect::<CompilationResult<Vec<_>>>()?,
+            else_result: match else_result {
+                Some(else_result) => Some(Box::new(compile_post_expression(else_result, resolve)?)),
+                None => None,
+            },
+        },
+        ast::Expr::Function(f) => {
+            let name = f.name.to_string().to_lowercase();
+            if f.distinct || !POST_FUNCTIONS.contains(&name.as_str()) {
+                return Err(CompilationError::Unsupported(format!(
+                    "Function in expression: {}",
+                    f
+                )));
+            }
+
+            let args =
+                f.args
+                    .iter()
+                    .map(|arg| match arg {
+                        ast::FunctionArg::Unnamed(arg) => compile_post_expression(arg, resolve),
+                        ast::FunctionArg::Named { .. } => Err(CompilationError::Unsupported(
+                            format!("Named argument in function: {}", f),
+                        )),
+                    })
+                    .collect::<CompilationResult<Vec<_>>>()?;
+
+            PostExpr::Function { name, args }
+        }
+        _ => {
+            return Err(CompilationError::Unsupported(format!(
+                "Expression: {}",
+                expr
+            )));
+        }
+    };
+
+    Ok(compiled)
+}
+
 #[derive(Debug, Clone, PartialEq)]
 enum CompiledExpression {
     Selection(Selection),
@@ -197,16 +445,27 @@ fn compile_expression(
     ctx: &QueryContext,
 ) -> CompilationResult<CompiledExpression> {
     match expr {
+        // Identifiers which are not members may be aliases of columns evaluated after loading
         ast::Expr::Identifier(ident) => {
-            if let Some(selection) = ctx.find_selection_for_identifier(&ident.value, true) {
+            if let Some(selection) = ctx.find_selection_for_identifier(&ident.value, true)? {
                 Ok(CompiledExpression::Selection(selection))
             } else {
-                Err(CompilationError::User(format!(
+                Err(CompilationError::Unsupported(format!(
                     "Unable to find selection for: {:?}",
                     ident
                 )))
             }
         }
+        ast::Expr::CompoundIdentifier(identifiers) => {
+            if let Some(selection) = ctx.find_selection_for_compound_identifier(identifiers)? {
+                Ok(CompiledExpression::Selection(selection))
+            } else {
+                Err(CompilationError::Unsupported(format!(
+                    "Unable to find selection for: {:?}",
+                    identifiers
+                )))
+            }
+        }
         ast::Expr::UnaryOp { expr, op } => match op {
             ast::UnaryOperator::Minus => match *expr.clone() {
                 ast::Expr::Value(value) => match value {
@@ -236,6 +495,11 @@ fn compile_expression(
             ))),
         },
         ast::Expr::Function(f) => {
+            // Aggregate functions over measures, e.g. in HAVING
+            if let Some(selection) = ctx.find_selection_for_function(f)? {
+                return Ok(CompiledExpression::Selection(selection));
+            }
+
             match f.name.to_string().to_lowercase().as_str() {
                 //
                 "str_to_date" => match f.args.as_slice() {
@@ -284,7 +548,7 @@ fn compile_expression(
                         f
                     ))),
                 },
-                _ => Err(CompilationError::User(format!(
+                _ => Err(CompilationError::Unsupported(format!(
                     "Unsupported function: {:?}",
                     f
                 ))),
@@ -325,8 +589,8 @@ fn compiled_binary_op_expr(
     };
 
     let filter = match selection_to_filter {
-        // Compile to CompiledFilter::Filter
-        Selection::Dimension(_) => CompiledFilter::Filter {
+        // Compile to CompiledFilter::Filter, filters on measures come from HAVING
+        Selection::Dimension(_) | Selection::Measure(_) => CompiledFilter::Filter {
             member,
             operator: match op {
                 ast::BinaryOperator::NotLike => "notContains".to_string(),
@@ -345,7 +609,7 @@ fn compiled_binary_op_expr(
                 ast::BinaryOperator::Lt => match filter {
                     // @todo -1 day
                     CompiledExpression::DateLiteral(_) => "beforeDate".to_string(),
-                    _ => "lte".to_string(),
+                    _ => "lt".to_string(),
                 },
                 ast::BinaryOperator::LtEq => match filter {
                     CompiledExpression::DateLiteral(_) => "beforeDate".to_string(),
@@ -477,13 +741,13 @@ fn compile_where_expression(
                     Selection::TimeDimension(t, _) => Ok(t),
                     Selection::Dimension(d) => Ok(d),
                     Selection::Segment(_) | Selection::Measure(_) => {
-                        Err(CompilationError::User(format!(
+                        Err(CompilationError::Unsupported(format!(
                             "Column for IsNull must be a Dimension or TimeDimension, actual: {:?}",
                             compiled_expr
                         )))
                     }
                 },
-                _ => Err(CompilationError::User(format!(
+                _ => Err(CompilationError::Unsupported(format!(
                     "Column for IsNull must be a Dimension or TimeDimension, actual: {:?}",
                     compiled_expr
                 ))),
@@ -507,13 +771,13 @@ fn compile_where_expression(
                     Selection::TimeDimension(t, _) => Ok(t),
                     Selection::Dimension(d) => Ok(d),
                     Selection::Segment(_) | Selection::Measure(_) => {
-                        Err(CompilationError::User(format!(
+                        Err(CompilationError::Unsupported(format!(
                             "Column for IsNull must be a Dimension or TimeDimension, actual: {:?}",
                             compiled_expr
                         )))
                     }
                 },
-                _ => Err(CompilationError::User(format!(
+                _ => Err(CompilationError::Unsupported(format!(
                     "Column for IsNull must be a Dimension or TimeDimension, actual: {:?}",
                     compiled_expr
                 ))),
@@ -542,13 +806,13 @@ fn compile_where_expression(
                     Selection::TimeDimension(t, _) => Ok(t),
                     Selection::Dimension(d) => Ok(d),
                     Selection::Segment(_) | Selection::Measure(_) => {
-                        Err(CompilationError::User(format!(
+                        Err(CompilationError::Unsupported(format!(
                             "Column for IsNull must be a Dimension or TimeDimension, actual: {:?}",
                             compiled_expr
                         )))
                     }
                 },
-                _ => Err(CompilationError::User(format!(
+                _ => Err(CompilationError::Unsupported(format!(
                     "Column for IsNull must be a Dimension or TimeDimension, actual: {:?}",
                     compiled_expr
                 ))),
@@ -571,13 +835,13 @@ fn compile_where_expression(
                     Selection::TimeDimension(t, _) => Ok(t),
                     Selection::Dimension(d) => Ok(d),
                     Selection::Segment(_) | Selection::Measure(_) => {
-                        Err(CompilationError::User(format!(
+                        Err(CompilationError::Unsupported(format!(
                             "Column for InExpr must be a Dimension or TimeDimension, actual: {:?}",
                             compiled_expr
                         )))
                     }
                 },
-                _ => Err(CompilationError::User(format!(
+                _ => Err(CompilationError::Unsupported(format!(
                     "Column for InExpr must be a Dimension or TimeDimension, actual: {:?}",
                     compiled_expr
                 ))),
@@ -719,19 +983,18 @@ fn compile_group(
     _builder: &mut QueryBuilder,
 ) -> CompilationResult<()> {
     for group in grouping.iter() {
-        match &group {
-            ast::Expr::Identifier(i) => {
-                if let Some(selection) = ctx.find_selection_for_identifier(&i.to_string(), true) {
-                    match selection {
-                        Selection::Segment(s) => {
-                            return Err(CompilationError::User(format!(
-                                "Unable to use segment {} in GROUP BY",
-                                s.get_real_name()
-                            )));
-                        }
-                        _ => {}
-                    }
-                };
+        let selection = match &group {
+            ast::Expr::Identifier(i) => ctx.find_selection_for_identifier(&i.to_string(), true)?,
+            ast::Expr::CompoundIdentifier(i) => ctx.find_selection_for_compound_identifier(i)?,
+            _ => None,
+        };
+
+        match selection {
+            Some(Selection::Segment(s)) => {
+                return Err(CompilationError::User(format!(
+                    "Unable to use segment {} in GROUP BY",
+                    s.get_real_name()
+                )));
             }
             _ => {}
         }
@@ -740,11 +1003,11 @@ fn compile_group(
     Ok(())
 }
 
-fn compile_where(
+fn compile_filter_tree(
     selection: &ast::Expr,
     ctx: &QueryContext,
-    builder: &mut QueryBuilder,
-) -> CompilationResult<()> {
+    clause: &str,
+) -> CompilationResult<CompiledFilterTree> {
     let filters = match &selection {
         binary @ ast::Expr::BinaryOp { left, right, op } => match op {
             ast::BinaryOperator::Like
@@ -769,8 +1032,8 @@ fn compile_where(
             }
             _ => {
                 return Err(CompilationError::Unsupported(format!(
-                    "Operator for binary expression in WHERE clause: {:?}",
-                    selection
+                    "Operator for binary expression in {} clause: {:?}",
+                    clause, selection
                 )));
             }
         },
@@ -780,12 +1043,117 @@ fn compile_where(
         between @ ast::Expr::Between { .. } => compile_where_expression(between, ctx)?,
         _ => {
             return Err(CompilationError::Unsupported(format!(
-                "Expression in WHERE clause: {:?}",
-                selection
+                "Expression in {} clause: {:?}",
+                clause, selection
             )));
         }
     };
 
+    Ok(filters)
+}
+
+fn find_measure_filter(node: &CompiledFilterTree, ctx: &QueryContext) -> Option<String> {
+    match node {
+        CompiledFilterTree::Filter(CompiledFilter::Filter { member, .. }) => {
+            if ctx.is_measure(member) {
+                Some(member.clone())
+            } else {
+                None
+            }
+        }
+        CompiledFilterTree::Filter(CompiledFilter::SegmentFilter { .. }) => None,
+        CompiledFilterTree::And(left, right) | CompiledFilterTree::Or(left, right) => {
+            find_measure_filter(left, ctx).or_else(|| find_measure_filter(right, ctx))
+        }
+    }
+}
+
+fn compile_where(
+    selection: &ast::Expr,
+    ctx: &QueryContext,
+    builder: &mut QueryBuilder,
+) -> CompilationResult<()> {
+    let filters = match compile_filter_tree(selection, ctx, "WHERE") {
+        Ok(filters) => filters,
+        Err(CompilationError::Unsupported(_)) => {
+            return compile_conjunctions(selection, ctx, builder, "WHERE", MemberAccess::Dimensions)
+        }
+        Err(e) => return Err(e),
+    };
+
+    if let Some(member) = find_measure_filter(&filters, ctx) {
+        return Err(CompilationError::User(format!(
+            "Unable to use measure {} in WHERE, use HAVING instead",
+            member
+        )));
+    }
+
+    push_filters(filters, builder)
+}
+
+fn compile_having(
+    having: &ast::Expr,
+    ctx: &QueryContext,
+    builder: &mut QueryBuilder,
+) -> CompilationResult<()> {
+    match compile_filter_tree(having, ctx, "HAVING") {
+        Ok(filters) => push_filters(filters, builder),
+        Err(CompilationError::Unsupported(_)) => {
+            compile_conjunctions(having, ctx, builder, "HAVING", MemberAccess::Measures)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Compiles top-level AND conditions one by one: conditions Cube.js can evaluate are pushed
+/// down as filters, unsupported ones filter the response.
+fn compile_conjunctions(
+    expr: &ast::Expr,
+    ctx: &QueryContext,
+    builder: &mut QueryBuilder,
+    clause: &str,
+    access: MemberAccess,
+) -> CompilationResult<()> {
+    match expr {
+        ast::Expr::BinaryOp {
+            left,
+            op: ast::BinaryOperator::And,
+            right,
+        } => {
+            compile_conjunctions(left, ctx, builder, clause, access)?;
+            compile_conjunctions(right, ctx, builder, clause, access)
+        }
+        ast::Expr::Nested(expr) => compile_conjunctions(expr, ctx, builder, clause, access),
+        _ => match compile_filter_tree(expr, ctx, clause) {
+            Ok(filters)
+                if access != MemberAccess::Dimensions
+                    || find_measure_filter(&filters, ctx).is_none() =>
+            {
+                push_filters(filters, builder)
+            }
+            Ok(_) | Err(CompilationError::Unsupported(_)) => {
+                compile_post_filter(expr, ctx, builder, access)
+            }
+            Err(e) => Err(e),
+        },
+    }
+}
+
+fn compile_post_filter(
+    expr: &ast::Expr,
+    ctx: &QueryContext,
+    builder: &mut QueryBuilder,
+    access: MemberAccess,
+) -> CompilationResult<()> {
+    let filter = compile_post_expression(expr, &mut |e: &ast::Expr| {
+        resolve_member(e, ctx, builder, access)
+    })?;
+    builder.with_post_filter(filter);
+
+    Ok(())
+}
+
+fn push_filters(filters: CompiledFilterTree, builder: &mut QueryBuilder) -> CompilationResult<()> {
     trace!("Filters (before optimization): {:?}", filters);
 
     let filters = optimize_where_filters(None, filters, builder);
@@ -802,46 +1170,45 @@ fn compile_order(
     order_by: &Vec<ast::OrderByExpr>,
     ctx: &QueryContext,
     builder: &mut QueryBuilder,
+) -> CompilationResult<()> {
+    match push_order(order_by, ctx, builder) {
+        // Sort the response when some of expressions are not members
+        Err(CompilationError::Unsupported(_)) | Err(CompilationError::Unknown(_)) => {
+            builder.reset_order();
+            compile_post_order(order_by, ctx, builder)
+        }
+        result => result,
+    }
+}
+
+fn compile_post_order(
+    order_by: &Vec<ast::OrderByExpr>,
+    ctx: &QueryContext,
+    builder: &mut QueryBuilder,
+) -> CompilationResult<()> {
+    for order_expr in order_by.iter() {
+        let expr = compile_post_expression(&order_expr.expr, &mut |e: &ast::Expr| {
+            resolve_member(e, ctx, builder, MemberAccess::Measures)
+        })?;
+        builder.with_post_order(expr, order_expr.asc.unwrap_or(true));
+    }
+
+    Ok(())
+}
+
+fn push_order(
+    order_by: &Vec<ast::OrderByExpr>,
+    ctx: &QueryContext,
+    builder: &mut QueryBuilder,
 ) -> CompilationResult<()> {
     if !order_by.is_empty() {
         for order_expr in order_by.iter() {
-            match &order_expr.expr {
+            let selection_opt = match &order_expr.expr {
                 ast::Expr::Identifier(i) => {
-                    if let Some(selection) = ctx.find_selection_for_identifier(&i.to_string(), true)
-                    {
-                        let direction_as_str = if let Some(direction) = order_expr.asc {
-                            if direction {
-                                "asc".to_string()
-                            } else {
-                                "desc".to_string()
-                            }
-                        } else {
-                            "asc".to_string()
-                        };
-
-                        match selection {
-                            Selection::Dimension(d) => {
-                                builder.with_order(vec![d.name.clone(), direction_as_str])
-                            }
-                            Selection::Measure(m) => {
-                                builder.with_order(vec![m.name.clone(), direction_as_str])
-                            }
-                            Selection::TimeDimension(t, _) => {
-                                builder.with_order(vec![t.name.clone(), direction_as_str])
-                            }
-                            Selection::Segment(s) => {
-                                return Err(CompilationError::User(format!(
-                                    "Unable to use segment {} in ORDER BY",
-                                    s.get_real_name()
-                                )));
-                            }
-                        };
-                    } else {
-                        return Err(CompilationError::Unknown(format!(
-                            "Unknown dimension: {}",
-                            i.to_string()
-                        )));
-                    }
+                    ctx.find_selection_for_identifier(&i.to_string(), true)?
+                }
+                ast::Expr::CompoundIdentifier(i) => {
+                    ctx.find_selection_for_compound_identifier(i)?
                 }
                 _ => {
                     return Err(CompilationError::Unsupported(format!(
@@ -849,6 +1216,41 @@ fn compile_order(
                         order_expr.expr
                     )));
                 }
+            };
+
+            if let Some(selection) = selection_opt {
+                let direction_as_str = if let Some(direction) = order_expr.asc {
+                    if direction {
+                        "asc".to_string()
+                    } else {
+                        "desc".to_string()
+                    }
+                } else {
+                    "asc".to_string()
+                };
+
+                match selection {
+                    Selection::Dimension(d) => {
+                        builder.with_order(vec![d.name.clone(), direction_as_str])
+                    }
+                    Selection::Measure(m) => {
+                        builder.with_order(vec![m.name.clone(), direction_as_str])
+                    }
+                    Selection::TimeDimension(t, _) => {
+                        builder.with_order(vec![t.name.clone(), direction_as_str])
+                    }
+                    Selection::Segment(s) => {
+                        return Err(CompilationError::User(format!(
+                            "Unable to use segment {} in ORDER BY",
+                            s.get_real_name()
+                        )));
+                    }
+                };
+            } else {
+                return Err(CompilationError::Unknown(format!(
+                    "Unknown dimension: {}",
+                    order_expr.expr.to_string()
+                )));
             }
         }
     }
@@ -864,20 +1266,10 @@ fn compile_select(expr: &ast::Select, ctx: &mut QueryContext) -> CompilationResu
             // println!("{:?}", projection);
 
             match projection {
-                ast::SelectItem::Wildcard => {
-                    for dimension in ctx.meta.dimensions.iter() {
-                        builder.with_dimension(
-                            dimension.name.clone(),
-                            CompiledQueryFieldMeta {
-                                column_from: dimension.name.clone(),
-                                column_to: dimension.get_real_name(),
-                                column_type: match dimension._type.as_str() {
-                                    "number" => ColumnType::MYSQL_TYPE_DOUBLE,
-                                    _ => ColumnType::MYSQL_TYPE_STRING,
-                                },
-                            },
-                        )
-                    }
+                ast::SelectItem::Wildcard => compile_wildcard(ctx, None, &mut builder)?,
+                ast::SelectItem::QualifiedWildcard(ast::ObjectName(identifiers)) => {
+                    let relation = identifiers.last().map(|i| i.value.clone());
+                    compile_wildcard(ctx, relation.as_ref(), &mut builder)?
                 }
                 ast::SelectItem::UnnamedExpr(expr) => {
                     compile_select_expr(expr, ctx, &mut builder, None)?
@@ -885,12 +1277,6 @@ fn compile_select(expr: &ast::Select, ctx: &mut QueryContext) -> CompilationResu
                 ast::SelectItem::ExprWithAlias { expr, alias } => {
                     compile_select_expr(expr, ctx, &mut builder, Some(alias.to_string()))?
                 }
-                _ => {
-                    return Err(CompilationError::Unsupported(format!(
-                        "Unsupported projection: {:?}",
-                        projection
-                    )));
-                }
             }
         }
     }
@@ -898,6 +1284,32 @@ fn compile_select(expr: &ast::Select, ctx: &mut QueryContext) -> CompilationResu
     Ok(builder)
 }
 
+fn compile_wildcard(
+    ctx: &mut QueryContext,
+    relation: Option<&String>,
+    builder: &mut QueryBuilder,
+) -> CompilationResult<()> {
+    let dimensions = ctx
+        .cubes_for_relation(relation)?
+        .iter()
+        .flat_map(|cube| cube.dimensions.clone())
+        .collect::<Vec<_>>();
+
+    for dimension in dimensions {
+        builder.with_dimension(
+            dimension.name.clone(),
+            CompiledQueryFieldMeta {
+                column_from: dimension.name.clone(),
+                column_to: dimension.get_real_name(),
+                column_type: dimension.get_mysql_type(),
+            },
+        );
+        ctx.with_column(dimension.get_real_name(), Selection::Dimension(dimension));
+    }
+
+    Ok(())
+}
+
 fn compile_table_factor(expr: &ast::TableFactor) -> CompilationResult<String> {
     match expr {
         ast::TableFactor::Table { name, .. } => match name {
@@ -922,97 +1334,313 @@ fn compile_table_factor(expr: &ast::TableFactor) -> CompilationResult<String> {
     }
 }
 
-fn compile_statement(
-    stmt: &ast::Statement,
+fn compile_table_alias(expr: &ast::TableFactor) -> Option<String> {
+    match expr {
+        ast::TableFactor::Table {
+            alias: Some(alias), ..
+        } => Some(alias.name.value.clone()),
+        _ => None,
+    }
+}
+
+fn find_cube(
     tenant: &ctx::TenantContext,
-) -> CompilationResult<CompiledQuery> {
-    match stmt {
-        ast::Statement::Query(q) => {
-            match &q.body {
-                sqlparser::ast::SetExpr::Select(select) => {
-                    if !select.cluster_by.is_empty() {
-                        return Err(CompilationError::Unsupported(
-                            "Query with CLUSTER BY instruction(s)".to_string(),
-                        ));
-                    }
+    relation: &ast::TableFactor,
+) -> CompilationResult<V1CubeMeta> {
+    let table_name = compile_table_factor(relation)?;
 
-                    if q.with.is_some() {
-                        return Err(CompilationError::Unsupported(
-                            "Query with CTE instruction(s)".to_string(),
-                        ));
-                    }
+    if let Some(cube) = tenant.find_cube_with_name(table_name.clone()) {
+        Ok(cube)
+    } else {
+        Err(CompilationError::Unknown(format!(
+            "Unknown cube: {}",
+            table_name
+        )))
+    }
+}
 
-                    if select.having.is_some() {
-                        return Err(CompilationError::Unsupported(
-                            "Query with HAVING instruction(s)".to_string(),
-                        ));
-                    }
+/// Cube.js joins cubes by the relationships from the data model, so join conditions are only
+/// checked to use `__cubeJoinField`. Joined cubes must be connected in the join graph of the
+/// primary cube.
+fn compile_from(
+    from: &ast::TableWithJoins,
+    tenant: &ctx::TenantContext,
+) -> CompilationResult<QueryContext> {
+    let mut ctx = QueryContext::new(&find_cube(tenant, &from.relation)?);
+    if let Some(alias) = compile_table_alias(&from.relation) {
+        ctx.with_relation_alias(alias);
+    }
 
-                    let from_table = if select.from.len() == 1 {
-                        if !select.from[0].joins.is_empty() {
-                            return Err(CompilationError::Unsupported(
-                                "Query with JOIN instruction(s)".to_string(),
-                            ));
-                        }
+    for join in from.joins.iter() {
+        ctx.with_join(
+            &find_cube(tenant, &join.relation)?,
+            compile_table_alias(&join.relation),
+            &join.join_operator,
+        )?;
+    }
 
-                        &select.from[0]
-                    } else {
-                        return Err(CompilationError::Unsupported(
-                            "Query with multiple tables in from".to_string(),
-                        ));
-                    };
+    Ok(ctx)
+}
 
-                    let table_name = compile_table_factor(&from_table.relation)?;
+/// LIMIT and OFFSET are pushed to Cube.js unless rows are filtered or sorted after loading
+/// (`post`).
+fn compile_limit(q: &ast::Query, builder: &mut QueryBuilder, post: bool) -> CompilationResult<()> {
+    if let Some(limit_expr) = &q.limit {
+        let limit = limit_expr.to_string().parse::<i32>().map_err(|e| {
+            CompilationError::Unsupported(format!("Unable to parse limit: {}", e.to_string()))
+        })?;
+
+        if post {
+            builder.with_post_limit(limit.max(0) as usize);
+        } else {
+            builder.with_limit(limit);
+        }
+    }
 
-                    if let Some(cube) = tenant.find_cube_with_name(table_name.clone()) {
-                        // println!("{:?}", select.projection);
-                        let mut ctx = QueryContext::new(&cube);
-                        let mut builder = compile_select(select, &mut ctx)?;
+    if let Some(offset_expr) = &q.offset {
+        let offset = offset_expr.value.to_string().parse::<i32>().map_err(|e| {
+            CompilationError::Unsupported(format!("Unable to parse offset: {}", e.to_string()))
+        })?;
 
-                        if let Some(limit_expr) = &q.limit {
-                            let limit = limit_expr.to_string().parse::<i32>().map_err(|e| {
-                                CompilationError::Unsupported(format!(
-                                    "Unable to parse limit: {}",
-                                    e.to_string()
-                                ))
-                            })?;
+        if post {
+            builder.with_post_offset(offset.max(0) as usize);
+        } else {
+            builder.with_offset(offset);
+        }
+    }
 
-                            builder.with_limit(limit);
-                        }
+    Ok(())
+}
 
-                        if let Some(offset_expr) = &q.offset {
-                            let offset =
-                                offset_expr.value.to_string().parse::<i32>().map_err(|e| {
-                                    CompilationError::Unsupported(format!(
-                                        "Unable to parse offset: {}",
-                                        e.to_string()
-                                    ))
-                                })?;
+/// Subquery the relation selects from: a derived table or a reference to a CTE.
+fn find_subquery<'a>(
+    relation: &'a ast::TableFactor,
+    ctes: &'a HashMap<String, ast::Query>,
+) -> Option<(&'a ast::Query, String)> {
+    match relation {
+        ast::TableFactor::Derived {
+            subquery, alias, ..
+        } => Some((
+            subquery.as_ref(),
+            alias
+                .as_ref()
+                .map(|a| a.name.value.clone())
+                .unwrap_or_default(),
+        )),
+        ast::TableFactor::Table {
+            name: ast::ObjectName(identifiers),
+            alias,
+            ..
+        } if identifiers.len() == 1 => ctes.get(&identifiers[0].value).map(|query| {
+            (
+                query,
+                alias
+                    .as_ref()
+                    .map(|a| a.name.value.clone())
+                    .unwrap_or_else(|| identifiers[0].value.clone()),
+            )
+        }),
+        _ => None,
+    }
+}
 
-                            builder.with_offset(offset);
-                        }
+/// Derived tables are flattened into the cube query of the subquery, so the outer query
+/// can only rename, filter, reorder and limit its columns.
+fn compile_derived_select(
+    q: &ast::Query,
+    select: &ast::Select,
+    alias: String,
+    mut builder: QueryBuilder,
+    inner_ctx: QueryContext,
+) -> CompilationResult<(QueryBuilder, QueryContext)> {
+    if !select.from[0].joins.is_empty() {
+        return Err(CompilationError::Unsupported(
+            "JOIN with derived table".to_string(),
+        ));
+    }
 
-                        compile_group(&select.group_by, &ctx, &mut builder)?;
-                        compile_order(&q.order_by, &ctx, &mut builder)?;
+    if !select.group_by.is_empty() || select.having.is_some() || select.distinct {
+        return Err(CompilationError::Unsupported(
+            "GROUP BY, HAVING or DISTINCT over derived table".to_string(),
+        ));
+    }
 
-                        if let Some(selection) = &select.selection {
-                            compile_where(selection, &ctx, &mut builder)?;
-                        }
+    if builder.has_post_processing() {
+        return Err(CompilationError::Unsupported(
+            "Derived table with expressions evaluated after loading".to_string(),
+        ));
+    }
 
-                        Ok(builder.build())
-                    } else {
-                        return Err(CompilationError::Unknown(format!(
-                            "Unknown cube: {}",
-                            table_name
-                        )));
-                    }
+    // Rows of a limited subquery can only be filtered, sorted and limited after loading
+    let post = builder.is_limited()
+        && (select.selection.is_some()
+            || !q.order_by.is_empty()
+            || q.limit.is_some()
+            || q.offset.is_some());
+
+    let mut ctx = QueryContext::for_derived_table(alias, inner_ctx.get_columns());
+    let inner_meta = builder.get_meta().clone();
+    let mut meta = vec![];
+    let mut columns = vec![];
+
+    for projection in select.projection.iter() {
+        let (column, mb_alias) = match projection {
+            ast::SelectItem::Wildcard | ast::SelectItem::QualifiedWildcard(_) => {
+                meta.extend(inner_meta.iter().cloned());
+                columns.extend(builder.get_columns().iter().cloned());
+                for (name, selection) in inner_ctx.get_columns() {
+                    ctx.with_column(name.clone(), selection.clone());
                 }
-                _ => Err(CompilationError::Unsupported(
-                    "Unsupported Query".to_string(),
-                )),
+
+                continue;
+            }
+            ast::SelectItem::UnnamedExpr(expr) => (expr, None),
+            ast::SelectItem::ExprWithAlias { expr, alias } => (expr, Some(alias.value.clone())),
+        };
+
+        let column_name = match column {
+            ast::Expr::Identifier(i) => i.value.clone(),
+            ast::Expr::CompoundIdentifier(i) if i.len() == 2 => i[1].value.clone(),
+            _ => {
+                return Err(CompilationError::Unsupported(format!(
+                    "Projection over derived table: {:?}",
+                    column
+                )));
+            }
+        };
+
+        let (index, selection) = match (
+            inner_meta.iter().position(|m| m.column_to == column_name),
+            inner_ctx
+                .get_columns()
+                .iter()
+                .find(|(name, _)| name == &column_name),
+        ) {
+            (Some(index), Some((_, selection))) => (index, selection.clone()),
+            _ => {
+                return Err(CompilationError::User(format!(
+                    "Unknown column: {}",
+                    column_name
+                )));
             }
+        };
+
+        let column_to = mb_alias.unwrap_or(column_name);
+        meta.push(CompiledQueryFieldMeta {
+            column_to: column_to.clone(),
+            ..inner_meta[index].clone()
+        });
+        columns.push(PostColumn {
+            name: column_to.clone(),
+            expr: PostExpr::Column(index),
+            column_type: inner_meta[index].column_type,
+        });
+        ctx.with_alias(column_to.clone(), selection.clone());
+        ctx.with_column(column_to, selection);
+    }
+
+    if post {
+        builder.with_columns(columns);
+
+        if let Some(selection) = &select.selection {
+            compile_post_filter(selection, &ctx, &mut builder, MemberAccess::Measures)?;
+        }
+
+        compile_post_order(&q.order_by, &ctx, &mut builder)?;
+    } else {
+        builder.with_meta(meta);
+
+        if let Some(selection) = &select.selection {
+            compile_where(selection, &ctx, &mut builder)?;
+        }
+
+        if !q.order_by.is_empty() {
+            builder.reset_order();
+            compile_order(&q.order_by, &ctx, &mut builder)?;
+        }
+    }
 
-            // println!("{:?}", q);
+    compile_limit(q, &mut builder, post)?;
+
+    Ok((builder, ctx))
+}
+
+fn compile_query(
+    q: &ast::Query,
+    tenant: &ctx::TenantContext,
+    ctes: &HashMap<String, ast::Query>,
+) -> CompilationResult<(QueryBuilder, QueryContext)> {
+    let mut ctes = ctes.clone();
+    if let Some(with) = &q.with {
+        if with.recursive {
+            return Err(CompilationError::Unsupported(
+                "Query with recursive CTE".to_string(),
+            ));
+        }
+
+        for cte in with.cte_tables.iter() {
+            ctes.insert(cte.alias.name.value.clone(), cte.query.clone());
+        }
+    }
+
+    let select = match &q.body {
+        ast::SetExpr::Select(select) => select,
+        _ => {
+            return Err(CompilationError::Unsupported(
+                "Unsupported Query".to_string(),
+            ));
+        }
+    };
+
+    if !select.cluster_by.is_empty() {
+        return Err(CompilationError::Unsupported(
+            "Query with CLUSTER BY instruction(s)".to_string(),
+        ));
+    }
+
+    let from_table = if select.from.len() == 1 {
+        &select.from[0]
+    } else {
+        return Err(CompilationError::Unsupported(
+            "Query with multiple tables in from".to_string(),
+        ));
+    };
+
+    if let Some((subquery, alias)) = find_subquery(&from_table.relation, &ctes) {
+        let (builder, inner_ctx) = compile_query(subquery, tenant, &ctes)?;
+
+        return compile_derived_select(q, select, alias, builder, inner_ctx);
+    }
+
+    let mut ctx = compile_from(from_table, tenant)?;
+    let mut builder = compile_select(select, &mut ctx)?;
+
+    compile_group(&select.group_by, &ctx, &mut builder)?;
+    compile_order(&q.order_by, &ctx, &mut builder)?;
+
+    if let Some(selection) = &select.selection {
+        compile_where(selection, &ctx, &mut builder)?;
+    }
+
+    if let Some(having) = &select.having {
+        compile_having(having, &ctx, &mut builder)?;
+    }
+
+    let post = builder.has_post_rows_processing();
+    compile_limit(q, &mut builder, post)?;
+
+    Ok((builder, ctx))
+}
+
+fn compile_statement(
+    stmt: &ast::Statement,
+    tenant: &ctx::TenantContext,
+) -> CompilationResult<CompiledQuery> {
+    match stmt {
+        ast::Statement::Query(q) => {
+            let (builder, _) = compile_query(q, tenant, &HashMap::new())?;
+
+            Ok(builder.build())
         }
         _ => Err(CompilationError::Unsupported("Unsupported AST".to_string())),
     }
@@ -1022,14 +1650,23 @@ fn compile_statement(
 pub struct CompiledQuery {
     pub request: V1LoadRequestQuery,
     pub meta: Vec<CompiledQueryFieldMeta>,
+    /// Evaluation of the parts of the query Cube.js can't handle, applied to the response.
+    pub post_processing: Option<PostProcessing>,
 }
 
 pub fn convert_sql_to_cube_query(
     query: &String,
     tenant: &ctx::TenantContext,
 ) -> CompilationResult<CompiledQuery> {
-    let dialect = MySqlDialect {};
-    let parse_result = Parser::parse_sql(&dialect, query);
+    convert_sql_to_cube_query_with_dialect(query, tenant, &MySqlDialect {})
+}
+
+pub fn convert_sql_to_cube_query_with_dialect(
+    query: &String,
+    tenant: &ctx::TenantContext,
+    dialect: &dyn Dialect,
+) -> CompilationResult<CompiledQuery> {
+    let parse_result = Parser::parse_sql(dialect, query);
 
     match parse_result {
         Err(error) => Err(CompilationError::User(format!(
@@ -1049,6 +1686,7 @@ mod tests {
     use cubeclient::models::{
         V1CubeMeta, V1CubeMetaDimension, V1CubeMetaMeasure, V1CubeMetaSegment,
     };
+    use msql_srv::ColumnType;
 
     use super::*;
 
@@ -1057,6 +1695,7 @@ mod tests {
             V1CubeMeta {
                 name: "KibanaSampleDataEcommerce".to_string(),
                 title: None,
+                connected_component: Some(1),
                 dimensions: vec![
                     V1CubeMetaDimension {
                         name: "KibanaSampleDataEcommerce.order_date".to_string(),
@@ -1110,9 +1749,32 @@ mod tests {
                     },
                 ],
             },
+            V1CubeMeta {
+                name: "Customers".to_string(),
+                title: None,
+                connected_component: Some(1),
+                dimensions: vec![
+                    V1CubeMetaDimension {
+                        name: "Customers.city".to_string(),
+                        _type: "string".to_string(),
+                    },
+                    V1CubeMetaDimension {
+                        name: "Customers.customer_gender".to_string(),
+                        _type: "string".to_string(),
+                    },
+                ],
+                measures: vec![V1CubeMetaMeasure {
+                    name: "Customers.count".to_string(),
+                    title: None,
+                    _type: "number".to_string(),
+                    agg_type: Some("count".to_string()),
+                }],
+                segments: vec![],
+            },
             V1CubeMeta {
                 name: "Logs".to_string(),
                 title: None,
+                connected_component: Some(2),
                 dimensions: vec![],
                 measures: vec![
                     V1CubeMetaMeasure {
@@ -1134,9 +1796,7 @@ mod tests {
     }
 
     fn get_test_tenant_ctx() -> ctx::TenantContext {
-        ctx::TenantContext {
-            cubes: get_test_meta(),
-        }
+        ctx::TenantContext::new(get_test_meta())
     }
 
     #[test]
@@ -1167,19 +1827,20 @@ mod tests {
                     CompiledQueryFieldMeta {
                         column_from: "KibanaSampleDataEcommerce.maxPrice".to_string(),
                         column_to: "maxPrice".to_string(),
-                        column_type: ColumnType::MYSQL_TYPE_DOUBLE,
+                        column_type: ColumnType::MYSQL_TYPE_NEWDECIMAL,
                     },
                     CompiledQueryFieldMeta {
                         column_from: "KibanaSampleDataEcommerce.minPrice".to_string(),
                         column_to: "minPrice".to_string(),
-                        column_type: ColumnType::MYSQL_TYPE_DOUBLE,
+                        column_type: ColumnType::MYSQL_TYPE_NEWDECIMAL,
                     },
                     CompiledQueryFieldMeta {
                         column_from: "KibanaSampleDataEcommerce.avgPrice".to_string(),
                         column_to: "avgPrice".to_string(),
-                        column_type: ColumnType::MYSQL_TYPE_DOUBLE,
+                        column_type: ColumnType::MYSQL_TYPE_NEWDECIMAL,
                     },
-                ]
+                ],
+                post_processing: None,
             }
         )
     }
@@ -1213,19 +1874,20 @@ mod tests {
                     CompiledQueryFieldMeta {
                         column_from: "KibanaSampleDataEcommerce.maxPrice".to_string(),
                         column_to: "maxPrice".to_string(),
-                        column_type: ColumnType::MYSQL_TYPE_DOUBLE,
+                        column_type: ColumnType::MYSQL_TYPE_NEWDECIMAL,
                     },
                     CompiledQueryFieldMeta {
                         column_from: "KibanaSampleDataEcommerce.minPrice".to_string(),
                         column_to: "minPrice".to_string(),
-                        column_type: ColumnType::MYSQL_TYPE_DOUBLE,
+                        column_type: ColumnType::MYSQL_TYPE_NEWDECIMAL,
                     },
                     CompiledQueryFieldMeta {
                         column_from: "KibanaSampleDataEcommerce.avgPrice".to_string(),
                         column_to: "avgPrice".to_string(),
-                        column_type: ColumnType::MYSQL_TYPE_DOUBLE,
+                        column_type: ColumnType::MYSQL_TYPE_NEWDECIMAL,
                     },
-                ]
+                ],
+                post_processing: None,
             }
         )
     }
@@ -1470,14 +2132,15 @@ mod tests {
                     CompiledQueryFieldMeta {
                         column_from: "KibanaSampleDataEcommerce.order_date".to_string(),
                         column_to: "order_date".to_string(),
-                        column_type: ColumnType::MYSQL_TYPE_STRING,
+                        column_type: ColumnType::MYSQL_TYPE_DATETIME,
                     },
                     CompiledQueryFieldMeta {
                         column_from: "KibanaSampleDataEcommerce.customer_gender".to_string(),
                         column_to: "customer_gender".to_string(),
                         column_type: ColumnType::MYSQL_TYPE_STRING,
                     }
-                ]
+                ],
+                post_processing: None,
             }
         )
     }
@@ -1503,6 +2166,7 @@ mod tests {
                         column_to: "count".to_string(),
                         column_type: ColumnType::MYSQL_TYPE_LONGLONG,
                     }],
+                    post_processing: None,
                 },
             ),
             (
@@ -1521,8 +2185,9 @@ mod tests {
                     meta: vec![CompiledQueryFieldMeta {
                         column_from: "Logs.agentCount".to_string(),
                         column_to: "agentCount".to_string(),
-                        column_type: ColumnType::MYSQL_TYPE_DOUBLE,
+                        column_type: ColumnType::MYSQL_TYPE_LONGLONG,
                     }],
+                    post_processing: None,
                 },
             ),
             (
@@ -1541,8 +2206,9 @@ mod tests {
                     meta: vec![CompiledQueryFieldMeta {
                         column_from: "Logs.agentCountApprox".to_string(),
                         column_to: "agentCountApprox".to_string(),
-                        column_type: ColumnType::MYSQL_TYPE_DOUBLE,
+                        column_type: ColumnType::MYSQL_TYPE_LONGLONG,
                     }],
+                    post_processing: None,
                 },
             ),
             (
@@ -1561,8 +2227,9 @@ mod tests {
                     meta: vec![CompiledQueryFieldMeta {
                         column_from: "KibanaSampleDataEcommerce.maxPrice".to_string(),
                         column_to: "maxPrice".to_string(),
-                        column_type: ColumnType::MYSQL_TYPE_DOUBLE,
+                        column_type: ColumnType::MYSQL_TYPE_NEWDECIMAL,
                     }],
+                    post_processing: None,
                 },
             ),
         ];
@@ -1614,6 +2281,505 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_select_join() {
+        let query = convert_sql_to_cube_query(
+            &"SELECT o.customer_gender, c.city, COUNT(*), MAX(o.maxPrice) AS max_price
+                FROM KibanaSampleDataEcommerce o
+                LEFT JOIN Customers c ON o.__cubeJoinField = c.__cubeJoinField
+                WHERE c.city = 'Berlin'
+                GROUP BY o.customer_gender, c.city
+                ORDER BY c.city DESC"
+                .to_string(),
+            &get_test_tenant_ctx(),
+        );
+
+        assert_eq!(
+            query.unwrap(),
+            CompiledQuery {
+                request: V1LoadRequestQuery {
+                    measures: Some(vec![
+                        "KibanaSampleDataEcommerce.count".to_string(),
+                        "KibanaSampleDataEcommerce.maxPrice".to_string(),
+                    ]),
+                    segments: Some(vec![]),
+                    dimensions: Some(vec![
+                        "KibanaSampleDataEcommerce.customer_gender".to_string(),
+                        "Customers.city".to_string(),
+                    ]),
+                    time_dimensions: None,
+                    order: Some(vec![vec!["Customers.city".to_string(), "desc".to_string()]]),
+                    limit: None,
+                    offset: None,
+                    filters: Some(vec![V1LoadRequestQueryFilterItem {
+                        member: Some("Customers.city".to_string()),
+                        operator: Some("equals".to_string()),
+                        values: Some(vec!["Berlin".to_string()]),
+                        or: None,
+                        and: None,
+                    }]),
+                },
+                meta: vec![
+                    CompiledQueryFieldMeta {
+                        column_from: "KibanaSampleDataEcommerce.customer_gender".to_string(),
+                        column_to: "customer_gender".to_string(),
+                        column_type: ColumnType::MYSQL_TYPE_STRING,
+                    },
+                    CompiledQueryFieldMeta {
+                        column_from: "Customers.city".to_string(),
+                        column_to: "city".to_string(),
+                        column_type: ColumnType::MYSQL_TYPE_STRING,
+                    },
+                    CompiledQueryFieldMeta {
+                        column_from: "KibanaSampleDataEcommerce.count".to_string(),
+                        column_to: "count".to_string(),
+                        column_type: ColumnType::MYSQL_TYPE_LONGLONG,
+                    },
+                    CompiledQueryFieldMeta {
+                        column_from: "KibanaSampleDataEcommerce.maxPrice".to_string(),
+                        column_to: "max_price".to_string(),
+                        column_type: ColumnType::MYSQL_TYPE_NEWDECIMAL,
+                    },
+                ],
+                post_processing: None,
+            }
+        )
+    }
+
+    #[test]
+    fn test_select_join_error() {
+        let variants = vec![
+            (
+                "SELECT customer_gender FROM KibanaSampleDataEcommerce k JOIN Customers c ON k.__cubeJoinField = c.__cubeJoinField".to_string(),
+                CompilationError::User("Column customer_gender is ambiguous, qualify it with a table name".to_string()),
+            ),
+            (
+                "SELECT COUNT(*) FROM KibanaSampleDataEcommerce JOIN Logs ON KibanaSampleDataEcommerce.__cubeJoinField = Logs.__cubeJoinField".to_string(),
+                CompilationError::User("Unable to join KibanaSampleDataEcommerce with Logs: there is no join path between these cubes in the data model".to_string()),
+            ),
+            (
+                "SELECT COUNT(*) FROM KibanaSampleDataEcommerce k JOIN Customers k ON k.id = k.id".to_string(),
+                CompilationError::User("Table name k is specified more than once, use an alias".to_string()),
+            ),
+            (
+                "SELECT u.city FROM KibanaSampleDataEcommerce k JOIN Customers c ON k.__cubeJoinField = c.__cubeJoinField".to_string(),
+                CompilationError::User("Unknown table: u".to_string()),
+            ),
+            (
+                "SELECT COUNT(*) FROM KibanaSampleDataEcommerce k JOIN Customers c ON __cubeJoinField = c.__cubeJoinField".to_string(),
+                CompilationError::User("Column __cubeJoinField is ambiguous, qualify it with a table name".to_string()),
+            ),
+            (
+                "SELECT COUNT(*) FROM KibanaSampleDataEcommerce k JOIN Customers c ON k.customer_gender = c.city".to_string(),
+                CompilationError::User("Join condition must be Customers.__cubeJoinField = <table>.__cubeJoinField, joins use the keys from the data model: k.customer_gender = c.city".to_string()),
+            ),
+            (
+                "SELECT COUNT(*) FROM KibanaSampleDataEcommerce k JOIN Customers c ON k.count = c.__cubeJoinField".to_string(),
+                CompilationError::User("Join condition must be Customers.__cubeJoinField = <table>.__cubeJoinField, joins use the keys from the data model: k.count = c.__cubeJoinField".to_string()),
+            ),
+            (
+                "SELECT COUNT(*) FROM KibanaSampleDataEcommerce k JOIN Customers c ON k.__cubeJoinField = k.__cubeJoinField".to_string(),
+                CompilationError::User("Join condition must be Customers.__cubeJoinField = <table>.__cubeJoinField, joins use the keys from the data model: k.__cubeJoinField = k.__cubeJoinField".to_string()),
+            ),
+            (
+                "SELECT COUNT(*) FROM KibanaSampleDataEcommerce k JOIN Customers c ON k.__cubeJoinField = c.__cubeJoinField AND k.customer_gender = c.city".to_string(),
+                CompilationError::User("Join condition must be Customers.__cubeJoinField = <table>.__cubeJoinField, joins use the keys from the data model: k.__cubeJoinField = c.__cubeJoinField AND k.customer_gender = c.city".to_string()),
+            ),
+            (
+                "SELECT COUNT(*) FROM KibanaSampleDataEcommerce k JOIN Customers c ON k.__cubeJoinField <> c.__cubeJoinField".to_string(),
+                CompilationError::User("Join condition must be Customers.__cubeJoinField = <table>.__cubeJoinField, joins use the keys from the data model: k.__cubeJoinField <> c.__cubeJoinField".to_string()),
+            ),
+            (
+                "SELECT COUNT(*) FROM KibanaSampleDataEcommerce k JOIN Customers c ON k.__cubeJoinField = c.__cubeJoinField LEFT JOIN KibanaSampleDataEcommerce k2 ON c.__cubeJoinField = k2.__cubeJoinField".to_string(),
+                CompilationError::User("LEFT JOIN of KibanaSampleDataEcommerce must join it to KibanaSampleDataEcommerce, the table the query selects from, to match the join of the data model: c.__cubeJoinField = k2.__cubeJoinField".to_string()),
+            ),
+            (
+                "SELECT COUNT(*) FROM KibanaSampleDataEcommerce k JOIN Customers c USING (city)".to_string(),
+                CompilationError::Unsupported("JOIN without ON condition (USING, NATURAL or no condition)".to_string()),
+            ),
+        ];
+
+        for (input_query, expected_error) in variants.iter() {
+            let query = convert_sql_to_cube_query(&input_query, &get_test_tenant_ctx());
+
+            match &query {
+                Ok(_) => panic!("Query ({}) should return error", input_query),
+                Err(e) => assert_eq!(e, expected_error),
+            }
+        }
+
+        let query = convert_sql_to_cube_query(
+            &"SELECT COUNT(*) FROM KibanaSampleDataEcommerce k RIGHT JOIN Customers c ON k.__cubeJoinField = c.__cubeJoinField".to_string(),
+            &get_test_tenant_ctx(),
+        );
+        assert!(
+            matches!(query, Err(CompilationError::Unsupported(_))),
+            "{:?}",
+            query
+        );
+    }
+
+    #[test]
+    fn test_select_having() {
+        let query = convert_sql_to_cube_query(
+            &"SELECT customer_gender, COUNT(*) FROM KibanaSampleDataEcommerce
+                WHERE customer_gender = 'female'
+                GROUP BY customer_gender
+                HAVING MAX(maxPrice) < 100"
+                .to_string(),
+            &get_test_tenant_ctx(),
+        );
+
+        assert_eq!(
+            query.unwrap().request,
+            V1LoadRequestQuery {
+                measures: Some(vec!["KibanaSampleDataEcommerce.count".to_string()]),
+                segments: Some(vec![]),
+                dimensions: Some(vec!["KibanaSampleDataEcommerce.customer_gender".to_string()]),
+                time_dimensions: None,
+                order: None,
+                limit: None,
+                offset: None,
+                filters: Some(vec![
+                    V1LoadRequestQueryFilterItem {
+                        member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                        operator: Some("equals".to_string()),
+                        values: Some(vec!["female".to_string()]),
+                        or: None,
+                        and: None,
+                    },
+                    V1LoadRequestQueryFilterItem {
+                        member: Some("KibanaSampleDataEcommerce.maxPrice".to_string()),
+                        operator: Some("lt".to_string()),
+                        values: Some(vec!["100".to_string()]),
+                        or: None,
+                        and: None,
+                    },
+                ]),
+            }
+        )
+    }
+
+    #[test]
+    fn test_select_derived_table() {
+        let query = convert_sql_to_cube_query(
+            &"SELECT t.gender, t.cnt AS orders FROM (
+                    SELECT customer_gender AS gender, COUNT(*) AS cnt
+                    FROM KibanaSampleDataEcommerce
+                    GROUP BY customer_gender
+                ) t
+                WHERE t.cnt > 5
+                ORDER BY orders DESC
+                LIMIT 10"
+                .to_string(),
+            &get_test_tenant_ctx(),
+        );
+
+        assert_eq!(
+            query.unwrap(),
+            CompiledQuery {
+                request: V1LoadRequestQuery {
+                    measures: Some(vec!["KibanaSampleDataEcommerce.count".to_string()]),
+                    segments: Some(vec![]),
+                    dimensions: Some(vec!["KibanaSampleDataEcommerce.customer_gender".to_string()]),
+                    time_dimensions: None,
+                    order: Some(vec![vec![
+                        "KibanaSampleDataEcommerce.count".to_string(),
+                        "desc".to_string()
+                    ]]),
+                    limit: Some(10),
+                    offset: None,
+                    filters: Some(vec![V1LoadRequestQueryFilterItem {
+                        member: Some("KibanaSampleDataEcommerce.count".to_string()),
+                        operator: Some("gt".to_string()),
+                        values: Some(vec!["5".to_string()]),
+                        or: None,
+                        and: None,
+                    }]),
+                },
+                meta: vec![
+                    CompiledQueryFieldMeta {
+                        column_from: "KibanaSampleDataEcommerce.customer_gender".to_string(),
+                        column_to: "gender".to_string(),
+                        column_type: ColumnType::MYSQL_TYPE_STRING,
+                    },
+                    CompiledQueryFieldMeta {
+                        column_from: "KibanaSampleDataEcommerce.count".to_string(),
+                        column_to: "orders".to_string(),
+                        column_type: ColumnType::MYSQL_TYPE_LONGLONG,
+                    },
+                ],
+                post_processing: None,
+            }
+        )
+    }
+
+    #[test]
+    fn test_select_cte() {
+        let query = convert_sql_to_cube_query(
+            &"WITH genders AS (
+                    SELECT customer_gender, COUNT(*) FROM KibanaSampleDataEcommerce GROUP BY customer_gender
+                )
+                SELECT * FROM genders WHERE customer_gender = 'male'"
+                .to_string(),
+            &get_test_tenant_ctx(),
+        );
+
+        assert_eq!(
+            query.unwrap(),
+            CompiledQuery {
+                request: V1LoadRequestQuery {
+                    measures: Some(vec!["KibanaSampleDataEcommerce.count".to_string()]),
+                    segments: Some(vec![]),
+                    dimensions: Some(vec!["KibanaSampleDataEcommerce.customer_gender".to_string()]),
+                    time_dimensions: None,
+                    order: None,
+                    limit: None,
+                    offset: None,
+                    filters: Some(vec![V1LoadRequestQueryFilterItem {
+                        member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                        operator: Some("equals".to_string()),
+                        values: Some(vec!["male".to_string()]),
+                        or: None,
+                        and: None,
+                    }]),
+                },
+                meta: vec![
+                    CompiledQueryFieldMeta {
+                        column_from: "KibanaSampleDataEcommerce.customer_gender".to_string(),
+                        column_to: "customer_gender".to_string(),
+                        column_type: ColumnType::MYSQL_TYPE_STRING,
+                    },
+                    CompiledQueryFieldMeta {
+                        column_from: "KibanaSampleDataEcommerce.count".to_string(),
+                        column_to: "count".to_string(),
+                        column_type: ColumnType::MYSQL_TYPE_LONGLONG,
+                    },
+                ],
+                post_processing: None,
+            }
+        )
+    }
+
+    #[test]
+    fn test_select_derived_table_error() {
+        let variants = vec![
+            (
+                "SELECT customer_gender FROM KibanaSampleDataEcommerce WHERE maxPrice > 10".to_string(),
+                CompilationError::User("Unable to use measure KibanaSampleDataEcommerce.maxPrice in WHERE, use HAVING instead".to_string()),
+            ),
+            (
+                "SELECT t.city FROM (SELECT customer_gender FROM KibanaSampleDataEcommerce) t".to_string(),
+                CompilationError::User("Unknown column: city".to_string()),
+            ),
+            (
+                "SELECT customer_gender FROM (SELECT customer_gender FROM KibanaSampleDataEcommerce) t GROUP BY customer_gender".to_string(),
+                CompilationError::Unsupported("GROUP BY, HAVING or DISTINCT over derived table".to_string()),
+            ),
+        ];
+
+        for (input_query, expected_error) in variants.iter() {
+            let query = convert_sql_to_cube_query(&input_query, &get_test_tenant_ctx());
+
+            match &query {
+                Ok(_) => panic!("Query ({}) should return error", input_query),
+                Err(e) => assert_eq!(e, expected_error),
+            }
+        }
+    }
+
+    #[test]
+    fn test_select_post_processing() {
+        let query = convert_sql_to_cube_query(
+            &"SELECT customer_gender, MAX(maxPrice) / COUNT(*) AS avg_price, UPPER(customer_gender)
+                FROM KibanaSampleDataEcommerce
+                GROUP BY customer_gender
+                ORDER BY avg_price DESC
+                LIMIT 5"
+                .to_string(),
+            &get_test_tenant_ctx(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            query.request,
+            V1LoadRequestQuery {
+                measures: Some(vec![
+                    "KibanaSampleDataEcommerce.maxPrice".to_string(),
+                    "KibanaSampleDataEcommerce.count".to_string(),
+                ]),
+                segments: Some(vec![]),
+                dimensions: Some(vec!["KibanaSampleDataEcommerce.customer_gender".to_string()]),
+                time_dimensions: None,
+                order: None,
+                limit: Some(50000),
+                offset: None,
+                filters: None,
+            }
+        );
+
+        let avg_price = PostExpr::BinaryOp {
+            left: Box::new(PostExpr::Column(1)),
+            op: ast::BinaryOperator::Divide,
+            right: Box::new(PostExpr::Column(2)),
+        };
+        assert_eq!(
+            query.post_processing,
+            Some(PostProcessing {
+                columns: vec![
+                    PostColumn {
+                        name: "customer_gender".to_string(),
+                        expr: PostExpr::Column(0),
+                        column_type: ColumnType::MYSQL_TYPE_STRING,
+                    },
+                    PostColumn {
+                        name: "avg_price".to_string(),
+                        expr: avg_price.clone(),
+                        column_type: ColumnType::MYSQL_TYPE_DOUBLE,
+                    },
+                    PostColumn {
+                        name: "UPPER(customer_gender)".to_string(),
+                        expr: PostExpr::Function {
+                            name: "upper".to_string(),
+                            args: vec![PostExpr::Column(0)],
+                        },
+                        column_type: ColumnType::MYSQL_TYPE_STRING,
+                    },
+                ],
+                filter: None,
+                order: vec![(avg_price, false)],
+                limit: Some(5),
+                offset: None,
+                load_limit: Some(50000),
+            })
+        );
+    }
+
+    #[test]
+    fn test_where_post_processing() {
+        let query = convert_sql_to_cube_query(
+            &"SELECT customer_gender, COUNT(*) FROM KibanaSampleDataEcommerce
+                WHERE customer_gender = 'female' AND LOWER(customer_gender) LIKE 'fe%'
+                GROUP BY customer_gender
+                LIMIT 10"
+                .to_string(),
+            &get_test_tenant_ctx(),
+        )
+        .unwrap();
+
+        assert_eq!(query.request.limit, Some(50000));
+        assert_eq!(
+            query.request.filters,
+            Some(vec![V1LoadRequestQueryFilterItem {
+                member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                operator: Some("equals".to_string()),
+                values: Some(vec!["female".to_string()]),
+                or: None,
+                and: None,
+            }])
+        );
+
+        let post_processing = query.post_processing.unwrap();
+        assert_eq!(
+            post_processing.filter,
+            Some(PostExpr::BinaryOp {
+                left: Box::new(PostExpr::Function {
+                    name: "lower".to_string(),
+                    args: vec![PostExpr::Column(0)],
+                }),
+                op: ast::BinaryOperator::Like,
+                right: Box::new(PostExpr::Literal(TableValue::String("fe%".to_string()))),
+            })
+        );
+        assert_eq!(post_processing.limit, Some(10));
+        assert_eq!(post_processing.load_limit, Some(50000));
+    }
+
+    #[test]
+    fn test_select_derived_table_post_processing() {
+        let query = convert_sql_to_cube_query(
+            &"SELECT * FROM (
+                    SELECT customer_gender, COUNT(*) AS cnt
+                    FROM KibanaSampleDataEcommerce
+                    GROUP BY customer_gender
+                    ORDER BY cnt DESC
+                    LIMIT 3
+                ) t
+                ORDER BY customer_gender"
+                .to_string(),
+            &get_test_tenant_ctx(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            query.request.order,
+            Some(vec![vec![
+                "KibanaSampleDataEcommerce.count".to_string(),
+                "desc".to_string()
+            ]])
+        );
+        assert_eq!(query.request.limit, Some(3));
+
+        let post_processing = query.post_processing.unwrap();
+        assert_eq!(
+            post_processing
+                .columns
+                .iter()
+                .map(|column| column.name.clone())
+                .collect::<Vec<_>>(),
+            vec!["customer_gender".to_string(), "cnt".to_string()]
+        );
+        assert_eq!(post_processing.order, vec![(PostExpr::Column(0), true)]);
+        assert_eq!(post_processing.limit, None);
+        assert_eq!(post_processing.load_limit, None);
+    }
+
+    #[test]
+    fn test_post_processing_error() {
+        let variants = vec![
+            (
+                "SELECT customer_gender FROM KibanaSampleDataEcommerce WHERE maxPrice * 2 > 10".to_string(),
+                CompilationError::User("Unable to use measure KibanaSampleDataEcommerce.maxPrice in WHERE, use HAVING instead".to_string()),
+            ),
+            (
+                "SELECT COUNT(*) FROM KibanaSampleDataEcommerce WHERE LOWER(customer_gender) = 'male'".to_string(),
+                CompilationError::User("Unable to use KibanaSampleDataEcommerce.customer_gender in expression evaluated after loading, add it to the selection".to_string()),
+            ),
+            (
+                "SELECT COUNT(*) + 1 FROM KibanaSampleDataEcommerce ORDER BY is_male".to_string(),
+                CompilationError::User("Unable to use segment is_male in ORDER BY".to_string()),
+            ),
+        ];
+
+        for (input_query, expected_error) in variants.iter() {
+            let query = convert_sql_to_cube_query(&input_query, &get_test_tenant_ctx());
+
+            match &query {
+                Ok(_) => panic!("Query ({}) should return error", input_query),
+                Err(e) => assert_eq!(e, expected_error),
+            }
+        }
+
+        // Only unsupported filters are evaluated after loading, user errors are returned as is.
+        for input_query in &[
+            "SELECT customer_gender FROM KibanaSampleDataEcommerce \
+             WHERE customer_gender = 'female' AND order_date > STR_TO_DATE('2021-01-01', '%Y')",
+            "SELECT customer_gender, COUNT(*) FROM KibanaSampleDataEcommerce \
+             GROUP BY customer_gender \
+             HAVING COUNT(*) > 1 AND order_date > STR_TO_DATE('2021-01-01', '%Y')",
+        ] {
+            match convert_sql_to_cube_query(&input_query.to_string(), &get_test_tenant_ctx()) {
+                Err(CompilationError::User(e)) => assert!(
+                    e.starts_with("Wrong type of argument"),
+                    "Query ({}) returned unexpected error: {}",
+                    input_query,
+                    e
+                ),
+                r => panic!("Query ({}) should return user error: {:?}", input_query, r),
+            }
+        }
+    }
+
     #[test]
     fn test_group_by_date_granularity() {
         let supported_granularities = vec![
@@ -1668,9 +2834,10 @@ mod tests {
                         CompiledQueryFieldMeta {
                             column_from: "KibanaSampleDataEcommerce.order_date".to_string(),
                             column_to: "__timestamp".to_string(),
-                            column_type: ColumnType::MYSQL_TYPE_STRING,
+                            column_type: ColumnType::MYSQL_TYPE_DATETIME,
                         }
-                    ]
+                    ],
+                    post_processing: None,
                 }
             )
         }
@@ -1777,6 +2944,26 @@ mod tests {
                     and: None,
                 }]),
             ),
+            (
+                "taxful_total_price < 5".to_string(),
+                Some(vec![V1LoadRequestQueryFilterItem {
+                    member: Some("KibanaSampleDataEcommerce.taxful_total_price".to_string()),
+                    operator: Some("lt".to_string()),
+                    values: Some(vec!["5".to_string()]),
+                    or: None,
+                    and: None,
+                }]),
+            ),
+            (
+                "taxful_total_price <= 5".to_string(),
+                Some(vec![V1LoadRequestQueryFilterItem {
+                    member: Some("KibanaSampleDataEcommerce.taxful_total_price".to_string()),
+                    operator: Some("lte".to_string()),
+                    values: Some(vec!["5".to_string()]),
+                    or: None,
+                    and: None,
+                }]),
+            ),
             // IN
             (
                 "customer_gender IN ('FEMALE', 'MALE')".to_string(),
@@ -0,0 +1,978 @@
+use std::cmp::Ordering;
+
+use msql_srv::ColumnType;
+use sqlparser::ast;
+
+use crate::compile::builder::CompiledQueryFieldMeta;
+use crate::mysql::dataframe::{
+    format_timestamp, parse_timestamp, Column, DataFrame, Row, TableValue,
+};
+use crate::CubeError;
+
+/// Expression over the columns of a Cube.js response, evaluated after loading.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PostExpr {
+    /// Column of the response by its index in `CompiledQuery::meta`.
+    Column(usize),
+    Literal(TableValue),
+    BinaryOp {
+        left: Box<PostExpr>,
+        op: ast::BinaryOperator,
+        right: Box<PostExpr>,
+    },
+    UnaryOp {
+        op: ast::UnaryOperator,
+        expr: Box<PostExpr>,
+    },
+    IsNull {
+        expr: Box<PostExpr>,
+        negated: bool,
+    },
+    Case {
+        operand: Option<Box<PostExpr>>,
+        conditions: Vec<PostExpr>,
+        results: Vec<PostExpr>,
+        else_result: Option<Box<PostExpr>>,
+    },
+    Function {
+        name: String,
+        args: Vec<PostExpr>,
+    },
+}
+
+pub const POST_FUNCTIONS: [&str; 12] = [
+    "lower",
+    "upper",
+    "length",
+    "char_length",
+    "concat",
+    "substring",
+    "substr",
+    "trim",
+    "coalesce",
+    "ifnull",
+    "abs",
+    "round",
+];
+
+impl PostExpr {
+    /// Type of the values produced by the expression over columns with `input` meta.
+    pub fn column_type(&self, input: &Vec<CompiledQueryFieldMeta>) -> ColumnType {
+        match self {
+            PostExpr::Column(index) => input[*index].column_type,
+            PostExpr::Literal(value) => match value {
+                TableValue::Int64(_) => ColumnType::MYSQL_TYPE_LONGLONG,
+                TableValue::Float64(_) => ColumnType::MYSQL_TYPE_DOUBLE,
+                TableValue::Decimal(_) => ColumnType::MYSQL_TYPE_NEWDECIMAL,
+                TableValue::Boolean(_) => ColumnType::MYSQL_TYPE_TINY,
+                TableValue::Timestamp(_) => ColumnType::MYSQL_TYPE_DATETIME,
+                TableValue::String(_) | TableValue::Null => ColumnType::MYSQL_TYPE_STRING,
+            },
+            PostExpr::BinaryOp { left, op, right } => match op {
+                ast::BinaryOperator::Plus
+                | ast::BinaryOperator::Minus
+                | ast::BinaryOperator::Multiply
+                | ast::BinaryOperator::Modulus
+                | ast::BinaryOperator::Divide => {
+                    let (left, right) = (left.column_type(input), right.column_type(input));
+                    if *op != ast::BinaryOperator::Divide && is_integer(left) && is_integer(right) {
+                        ColumnType::MYSQL_TYPE_LONGLONG
+                    } else if (is_decimal(left) || is_decimal(right))
+                        && (is_decimal(left) || is_integer(left))
+                        && (is_decimal(right) || is_integer(right))
+                    {
+                        ColumnType::MYSQL_TYPE_NEWDECIMAL
+                    } else {
+                        ColumnType::MYSQL_TYPE_DOUBLE
+                    }
+                }
+                _ => ColumnType::MYSQL_TYPE_TINY,
+            },
+            PostExpr::UnaryOp { op, expr } => match op {
+                ast::UnaryOperator::Not => ColumnType::MYSQL_TYPE_TINY,
+                _ => expr.column_type(input),
+            },
+            PostExpr::IsNull { .. } => ColumnType::MYSQL_TYPE_TINY,
+            PostExpr::Case { results, .. } => results
+                .first()
+                .map(|result| result.column_type(input))
+                .unwrap_or(ColumnType::MYSQL_TYPE_STRING),
+            PostExpr::Function { name, args } => match name.as_str() {
+                "length" | "char_length" => ColumnType::MYSQL_TYPE_LONGLONG,
+                "coalesce" | "ifnull" | "abs" | "round" => args
+                    .first()
+                    .map(|arg| arg.column_type(input))
+                    .unwrap_or(ColumnType::MYSQL_TYPE_STRING),
+                _ => ColumnType::MYSQL_TYPE_STRING,
+            },
+        }
+    }
+
+    pub fn evaluate(&self, row: &Row) -> Result<TableValue, CubeError> {
+        match self {
+            PostExpr::Column(index) => row.values().get(*index).cloned().ok_or_else(|| {
+                CubeError::internal(format!("Column {} is out of row bounds", index))
+            }),
+            PostExpr::Literal(value) => Ok(value.clone()),
+            PostExpr::BinaryOp { left, op, right } => {
+                let left = left.evaluate(row)?;
+                // Short circuit for three-valued AND/OR
+                match (op, &left) {
+                    (ast::BinaryOperator::And, TableValue::Boolean(false)) => {
+                        return Ok(TableValue::Boolean(false))
+                    }
+                    (ast::BinaryOperator::Or, TableValue::Boolean(true)) => {
+                        return Ok(TableValue::Boolean(true))
+                    }
+                    _ => {}
+                };
+
+                binary_op(&left, op, &right.evaluate(row)?)
+            }
+            PostExpr::UnaryOp { op, expr } => match (op, expr.evaluate(row)?) {
+                (_, TableValue::Null) => Ok(TableValue::Null),
+                (ast::UnaryOperator::Not, TableValue::Boolean(v)) => Ok(TableValue::Boolean(!v)),
+                (ast::UnaryOperator::Minus, TableValue::Int64(v)) => Ok(TableValue::Int64(-v)),
+                (ast::UnaryOperator::Minus, TableValue::Float64(v)) => Ok(TableValue::Float64(-v)),
+                (ast::UnaryOperator::Minus, TableValue::Decimal(v)) => {
+                    Ok(TableValue::Decimal(match v.strip_prefix('-') {
+                        Some(v) => v.to_string(),
+                        None => format!("-{}", v),
+                    }))
+                }
+                (ast::UnaryOperator::Plus, v) => Ok(v),
+                (op, v) => Err(CubeError::user(format!(
+                    "Unable to apply {:?} to {:?}",
+                    op, v
+                ))),
+            },
+            PostExpr::IsNull { expr, negated } => {
+                let is_null = expr.evaluate(row)? == TableValue::Null;
+                Ok(TableValue::Boolean(is_null != *negated))
+            }
+            PostExpr::Case {
+                operand,
+                conditions,
+                results,
+                else_result,
+            } => {
+                let operand = match operand {
+                    Some(operand) => Some(operand.evaluate(row)?),
+                    None => None,
+                };
+
+                for (condition, result) in conditions.iter().zip(results.iter()) {
+                    let condition = condition.evaluate(row)?;
+                    let matched = match &operand {
+                        Some(operand) => compare(operand, &condition) == Some(Ordering::Equal),
+                        None => condition == TableValue::Boolean(true),
+                    };
+                    if matched {
+                        return result.evaluate(row);
+                    }
+                }
+
+                match else_result {
+                    Some(else_result) => else_result.evaluate(row),
+                    None => Ok(TableValue::Null),
+                }
+            }
+            PostExpr::Function { name, args } => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.evaluate(row))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                function(name, args)
+            }
+        }
+    }
+}
+
+fn is_integer(column_type: ColumnType) -> bool {
+    match column_type {
+        ColumnType::MYSQL_TYPE_SHORT
+        | ColumnType::MYSQL_TYPE_LONG
+        | ColumnType::MYSQL_TYPE_LONGLONG => true,
+        _ => false,
+    }
+}
+
+fn is_decimal(column_type: ColumnType) -> bool {
+    column_type == ColumnType::MYSQL_TYPE_NEWDECIMAL
+        || column_type == ColumnType::MYSQL_TYPE_DECIMAL
+}
+
+fn as_f64(value: &TableValue) -> Option<f64> {
+    match value {
+        TableValue::Int64(v) => Some(*v as f64),
+        TableValue::Float64(v) => Some(*v),
+        TableValue::String(v) | TableValue::Decimal(v) => v.parse::<f64>().ok(),
+        _ => None,
+    }
+}
+
+fn as_string(value: &TableValue) -> Option<String> {
+    match value {
+        TableValue::Null => None,
+        TableValue::String(v) | TableValue::Decimal(v) => Some(v.clone()),
+        TableValue::Int64(v) => Some(v.to_string()),
+        TableValue::Float64(v) => Some(v.to_string()),
+        TableValue::Boolean(v) => Some(if *v { "1" } else { "0" }.to_string()),
+        TableValue::Timestamp(v) => Some(format_timestamp(v)),
+    }
+}
+
+fn binary_op(
+    left: &TableValue,
+    op: &ast::BinaryOperator,
+    right: &TableValue,
+) -> Result<TableValue, CubeError> {
+    let value = match op {
+        ast::BinaryOperator::And | ast::BinaryOperator::Or => match (left, right) {
+            (TableValue::Boolean(l), TableValue::Boolean(r)) => {
+                TableValue::Boolean(if *op == ast::BinaryOperator::And {
+                    *l && *r
+                } else {
+                    *l || *r
+                })
+            }
+            (_, TableValue::Boolean(false)) if *op == ast::BinaryOperator::And => {
+                TableValue::Boolean(false)
+            }
+            (_, TableValue::Boolean(true)) if *op == ast::BinaryOperator::Or => {
+                TableValue::Boolean(true)
+            }
+            _ => TableValue::Null,
+        },
+        _ if *left == TableValue::Null || *right == TableValue::Null => TableValue::Null,
+        ast::BinaryOperator::Plus
+        | ast::BinaryOperator::Minus
+        | ast::BinaryOperator::Multiply
+        | ast::BinaryOperator::Divide
+        | ast::BinaryOperator::Modulus => arithmetic(left, op, right),
+        ast::BinaryOperator::Like | ast::BinaryOperator::NotLike => {
+            match (as_string(left), as_string(right)) {
+                (Some(value), Some(pattern)) => TableValue::Boolean(
+                    like(&value, &pattern) == (*op == ast::BinaryOperator::Like),
+                ),
+                _ => TableValue::Null,
+            }
+        }
+        _ => match compare(left, right) {
+            Some(ordering) => TableValue::Boolean(match op {
+                ast::BinaryOperator::Eq => ordering == Ordering::Equal,
+                ast::BinaryOperator::NotEq => ordering != Ordering::Equal,
+                ast::BinaryOperator::Gt => ordering == Ordering::Greater,
+                ast::BinaryOperator::GtEq => ordering != Ordering::Less,
+                ast::BinaryOperator::Lt => ordering == Ordering::Less,
+                ast::BinaryOperator::LtEq => ordering != Ordering::Greater,
+                op => {
+                    return Err(CubeError::user(format!(
+                        "Unsupported binary operator: {:?}",
+                        op
+                    )))
+                }
+            }),
+            None => TableValue::Null,
+        },
+    };
+
+    Ok(value)
+}
+
+/// Integer arithmetic stays integer (NULL on overflow), decimals with decimals or integers stay
+/// exact decimals, integer division and other operands use f64.
+fn arithmetic(left: &TableValue, op: &ast::BinaryOperator, right: &TableValue) -> TableValue {
+    if let (TableValue::Int64(l), TableValue::Int64(r)) = (left, right) {
+        let result = match op {
+            ast::BinaryOperator::Plus => l.checked_add(*r),
+            ast::BinaryOperator::Minus => l.checked_sub(*r),
+            ast::BinaryOperator::Multiply => l.checked_mul(*r),
+            ast::BinaryOperator::Modulus => l.checked_rem(*r),
+            _ => None,
+        };
+        if *op != ast::BinaryOperator::Divide {
+            return result.map(TableValue::Int64).unwrap_or(TableValue::Null);
+        }
+    }
+
+    let decimals = match (left, right) {
+        (TableValue::Decimal(l), TableValue::Decimal(r)) => Some((l.clone(), r.clone())),
+        (TableValue::Decimal(l), TableValue::Int64(r)) => Some((l.clone(), r.to_string())),
+        (TableValue::Int64(l), TableValue::Decimal(r)) => Some((l.to_string(), r.clone())),
+        _ => None,
+    };
+    if let Some((Some(l), Some(r))) = decimals.map(|(l, r)| (parse_decimal(&l), parse_decimal(&r)))
+    {
+        return decimal_arithmetic(l, op, r)
+            .map(|(mantissa, scale)| TableValue::Decimal(format_decimal(mantissa, scale)))
+            .unwrap_or(TableValue::Null);
+    }
+
+    match (as_f64(left), as_f64(right)) {
+        (Some(l), Some(r)) => match op {
+            ast::BinaryOperator::Plus => TableValue::Float64(l + r),
+            ast::BinaryOperator::Minus => TableValue::Float64(l - r),
+            ast::BinaryOperator::Multiply => TableValue::Float64(l * r),
+            ast::BinaryOperator::Divide if r != 0.0 => TableValue::Float64(l / r),
+            ast::BinaryOperator::Modulus if r != 0.0 => TableValue::Float64(l % r),
+            _ => TableValue::Null,
+        },
+        _ => TableValue::Null,
+    }
+}
+
+fn function(name: &str, args: Vec<TableValue>) -> Result<TableValue, CubeError> {
+    let value = match (name, args.as_slice()) {
+        ("coalesce", _) | ("ifnull", [_, _]) => args
+            .into_iter()
+            .find(|arg| *arg != TableValue::Null)
+            .unwrap_or(TableValue::Null),
+        ("concat", _) => match args.iter().map(as_string).collect::<Option<Vec<_>>>() {
+            Some(parts) => TableValue::String(parts.concat()),
+            None => TableValue::Null,
+        },
+        (_, [TableValue::Null, ..]) => TableValue::Null,
+        ("lower", [value]) => TableValue::String(as_string(value).unwrap().to_lowercase()),
+        ("upper", [value]) => TableValue::String(as_string(value).unwrap().to_uppercase()),
+        ("trim", [value]) => TableValue::String(as_string(value).unwrap().trim().to_string()),
+        ("length", [value]) | ("char_length", [value]) => {
+            TableValue::Int64(as_string(value).unwrap().chars().count() as i64)
+        }
+        ("substring", [value, rest @ ..]) | ("substr", [value, rest @ ..])
+            if rest.len() == 1 || rest.len() == 2 =>
+        {
+            let chars = as_string(value).unwrap().chars().collect::<Vec<_>>();
+            let position = match &rest[0] {
+                TableValue::Int64(position) => *position,
+                _ => return Ok(TableValue::Null),
+            };
+            // Positions are 1-based, negative positions count from the end
+            let start = if position > 0 {
+                position - 1
+            } else {
+                chars.len() as i64 + position
+            };
+            let length = match rest.get(1) {
+                Some(TableValue::Int64(length)) => *length,
+                Some(_) => return Ok(TableValue::Null),
+                None => chars.len() as i64,
+            };
+            if position == 0 || start < 0 || length < 0 {
+                TableValue::String("".to_string())
+            } else {
+                TableValue::String(
+                    chars
+                        .iter()
+                        .skip(start as usize)
+                        .take(length as usize)
+                        .collect(),
+                )
+            }
+        }
+        ("abs", [TableValue::Int64(v)]) => TableValue::Int64(v.abs()),
+        ("abs", [TableValue::Float64(v)]) => TableValue::Float64(v.abs()),
+        ("abs", [TableValue::Decimal(v)]) => {
+            TableValue::Decimal(v.trim_start_matches('-').to_string())
+        }
+        ("round", [TableValue::Int64(v)]) | ("round", [TableValue::Int64(v), _]) => {
+            TableValue::Int64(*v)
+        }
+        ("round", [TableValue::Float64(v)]) => TableValue::Float64(v.round()),
+        ("round", [TableValue::Float64(v), TableValue::Int64(digits)]) => {
+            let factor = 10_f64.powi(*digits as i32);
+            TableValue::Float64((v * factor).round() / factor)
+        }
+        ("round", [TableValue::Decimal(v)]) => TableValue::Decimal(round_decimal(v, 0)),
+        ("round", [TableValue::Decimal(v), TableValue::Int64(digits)]) if *digits >= 0 => {
+            TableValue::Decimal(round_decimal(v, *digits as usize))
+        }
+        _ => {
+            return Err(CubeError::user(format!(
+                "Unable to evaluate {}({:?})",
+                name, args
+            )))
+        }
+    };
+
+    Ok(value)
+}
+
+/// Converts a value to the type of the column it is sent in.
+fn coerce(value: TableValue, column_type: ColumnType) -> TableValue {
+    match (value, column_type) {
+        (TableValue::Float64(v), ColumnType::MYSQL_TYPE_LONGLONG) => TableValue::Int64(v as i64),
+        (TableValue::Int64(v), ColumnType::MYSQL_TYPE_DOUBLE) => TableValue::Float64(v as f64),
+        (TableValue::Int64(v), ColumnType::MYSQL_TYPE_NEWDECIMAL) => {
+            TableValue::Decimal(v.to_string())
+        }
+        (TableValue::Float64(v), ColumnType::MYSQL_TYPE_NEWDECIMAL) => {
+            TableValue::Decimal(v.to_string())
+        }
+        (TableValue::Decimal(v), ColumnType::MYSQL_TYPE_DOUBLE) => match v.parse::<f64>() {
+            Ok(v) => TableValue::Float64(v),
+            Err(_) => TableValue::Null,
+        },
+        (TableValue::Null, _) => TableValue::Null,
+        (TableValue::String(v), ColumnType::MYSQL_TYPE_DATETIME) => match parse_timestamp(&v) {
+            Some(timestamp) => TableValue::Timestamp(timestamp),
+            None => TableValue::String(v),
+        },
+        (TableValue::String(v), _) => TableValue::String(v),
+        (v, ColumnType::MYSQL_TYPE_STRING) => TableValue::String(as_string(&v).unwrap()),
+        (v, _) => v,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostColumn {
+    pub name: String,
+    pub expr: PostExpr,
+    pub column_type: ColumnType,
+}
+
+/// Part of a query which Cube.js cannot evaluate, applied to the rows of its response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostProcessing {
+    /// Output columns of the query.
+    pub columns: Vec<PostColumn>,
+    pub filter: Option<PostExpr>,
+    /// Sort expressions with ascending flag.
+    pub order: Vec<(PostExpr, bool)>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    /// Limit sent to Cube.js when rows are filtered or sorted after loading. A response with
+    /// this many rows may be truncated, so it's rejected instead of being processed.
+    pub load_limit: Option<usize>,
+}
+
+impl PostProcessing {
+    pub fn execute(&self, input: DataFrame) -> Result<DataFrame, CubeError> {
+        if let Some(load_limit) = self.load_limit {
+            if input.get_rows().len() >= load_limit {
+                return Err(CubeError::user(format!(
+                    "Query needs to load more than {} rows from Cube.js to filter or sort them. \
+                     Use filters and ordering Cube.js can evaluate or limit the query",
+                    load_limit - 1
+                )));
+            }
+        }
+
+        let mut rows = vec![];
+        for row in input.into_rows() {
+            let matched = match &self.filter {
+                Some(filter) => filter.evaluate(&row)? == TableValue::Boolean(true),
+                None => true,
+            };
+            if matched {
+                rows.push(row);
+            }
+        }
+
+        if !self.order.is_empty() {
+            let mut keyed_rows = rows
+                .into_iter()
+                .map(|row| -> Result<_, CubeError> {
+                    let keys = self
+                        .order
+                        .iter()
+                        .map(|(expr, _)| expr.evaluate(&row))
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    Ok((keys, row))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            keyed_rows.sort_by(|(a, _), (b, _)| {
+                for (i, (_, asc)) in self.order.iter().enumerate() {
+                    // NULLs go first in ascending order as in MySQL
+                    let ordering = match (&a[i], &b[i]) {
+                        (TableValue::Null, TableValue::Null) => Ordering::Equal,
+                        (TableValue::Null, _) => Ordering::Less,
+                        (_, TableValue::Null) => Ordering::Greater,
+                        (a, b) => compare(a, b).unwrap_or(Ordering::Equal),
+                    };
+                    let ordering = if *asc { ordering } else { ordering.reverse() };
+                    if ordering != Ordering::Equal {
+                        return ordering;
+                    }
+                }
+
+                Ordering::Equal
+            });
+
+            rows = keyed_rows.into_iter().map(|(_, row)| row).collect();
+        }
+
+        let rows = rows
+            .into_iter()
+            .skip(self.offset.unwrap_or(0))
+            .take(self.limit.unwrap_or(usize::MAX))
+            .map(|row| -> Result<_, CubeError> {
+                let values = self
+                    .columns
+                    .iter()
+                    .map(|column| {
+                        let value = column.expr.evaluate(&row)?;
+                        Ok(coerce(value, column.column_type))
+                    })
+                    .collect::<Result<Vec<_>, CubeError>>()?;
+
+                Ok(Row::new(values))
+            })
+            .collect::<Result<Vec<_>, CubeError>>()?;
+
+        let columns = self
+            .columns
+            .iter()
+            .map(|column| Column::new(column.name.clone(), column.column_type))
+            .collect();
+
+        Ok(DataFrame::new(columns, rows))
+    }
+}
+
+pub fn compare(left: &TableValue, right: &TableValue) -> Option<Ordering> {
+    match (left, right) {
+        (TableValue::String(l), TableValue::String(r)) => Some(l.cmp(r)),
+        (TableValue::Int64(l), TableValue::Int64(r)) => Some(l.cmp(r)),
+        (TableValue::Boolean(l), TableValue::Boolean(r)) => Some(l.cmp(r)),
+        (TableValue::Float64(l), TableValue::Float64(r)) => l.partial_cmp(r),
+        (TableValue::Int64(l), TableValue::Float64(r)) => (*l as f64).partial_cmp(r),
+        (TableValue::Float64(l), TableValue::Int64(r)) => l.partial_cmp(&(*r as f64)),
+        (TableValue::Decimal(l), TableValue::Decimal(r)) => Some(compare_decimals(l, r)),
+        (TableValue::Decimal(l), TableValue::Int64(r)) => Some(compare_decimals(l, &r.to_string())),
+        (TableValue::Int64(l), TableValue::Decimal(r)) => Some(compare_decimals(&l.to_string(), r)),
+        (TableValue::Decimal(l), TableValue::Float64(r)) => l.parse::<f64>().ok()?.partial_cmp(r),
+        (TableValue::Float64(l), TableValue::Decimal(r)) => l.partial_cmp(&r.parse::<f64>().ok()?),
+        (TableValue::Int64(l), TableValue::String(r)) => r.parse::<i64>().ok().map(|r| l.cmp(&r)),
+        (TableValue::String(l), TableValue::Int64(r)) => l.parse::<i64>().ok().map(|l| l.cmp(r)),
+        (TableValue::Timestamp(l), TableValue::Timestamp(r)) => Some(l.cmp(r)),
+        (TableValue::Timestamp(l), TableValue::String(r)) => parse_timestamp(r).map(|r| l.cmp(&r)),
+        (TableValue::String(l), TableValue::Timestamp(r)) => parse_timestamp(l).map(|l| l.cmp(r)),
+        _ => None,
+    }
+}
+
+/// Compares decimals in their text form digit by digit, so no precision is lost.
+fn compare_decimals(left: &str, right: &str) -> Ordering {
+    fn parts(value: &str) -> (bool, &str, &str) {
+        let (negative, digits) = match value.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, value),
+        };
+        let (int, frac) = digits.split_once('.').unwrap_or((digits, ""));
+        let int = int.trim_start_matches('0');
+        let frac = frac.trim_end_matches('0');
+        // Negative zero is zero
+        (negative && !(int.is_empty() && frac.is_empty()), int, frac)
+    }
+
+    let (l_negative, l_int, l_frac) = parts(left);
+    let (r_negative, r_int, r_frac) = parts(right);
+    let magnitude = l_int
+        .len()
+        .cmp(&r_int.len())
+        .then_with(|| l_int.cmp(r_int))
+        .then_with(|| l_frac.cmp(r_frac));
+    match (l_negative, r_negative) {
+        (false, true) => Ordering::Greater,
+        (true, false) => Ordering::Less,
+        (false, false) => magnitude,
+        (true, true) => magnitude.reverse(),
+    }
+}
+
+/// Parses a decimal into its digits as an integer and the number of fractional digits.
+fn parse_decimal(value: &str) -> Option<(i128, u32)> {
+    let (negative, unsigned) = match value.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, value),
+    };
+    let (int, frac) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    if (int.is_empty() && frac.is_empty())
+        || !int.bytes().chain(frac.bytes()).all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    let digits = format!("{}{}", int, frac).parse::<i128>().ok()?;
+    Some((if negative { -digits } else { digits }, frac.len() as u32))
+}
+
+fn format_decimal(mantissa: i128, scale: u32) -> String {
+    let digits = format!(
+        "{:0>width$}",
+        mantissa.unsigned_abs(),
+        width = scale as usize + 1
+    );
+    let (int, frac) = digits.split_at(digits.len() - scale as usize);
+    let sign = if mantissa < 0 { "-" } else { "" };
+    if scale == 0 {
+        format!("{}{}", sign, int)
+    } else {
+        format!("{}{}.{}", sign, int, frac)
+    }
+}
+
+/// Exact arithmetic over parsed decimals, None on overflow or division by zero. Like in MySQL,
+/// quotients have 4 more fractional digits than the dividend.
+fn decimal_arithmetic(
+    (l, l_scale): (i128, u32),
+    op: &ast::BinaryOperator,
+    (r, r_scale): (i128, u32),
+) -> Option<(i128, u32)> {
+    let rescale = |v: i128, digits: u32| v.checked_mul(10i128.checked_pow(digits)?);
+    let scale = l_scale.max(r_scale);
+    let (l_aligned, r_aligned) = (rescale(l, scale - l_scale)?, rescale(r, scale - r_scale)?);
+    match op {
+        ast::BinaryOperator::Plus => Some((l_aligned.checked_add(r_aligned)?, scale)),
+        ast::BinaryOperator::Minus => Some((l_aligned.checked_sub(r_aligned)?, scale)),
+        ast::BinaryOperator::Multiply => Some((l.checked_mul(r)?, l_scale + r_scale)),
+        ast::BinaryOperator::Modulus if r != 0 => Some((l_aligned.checked_rem(r_aligned)?, scale)),
+        ast::BinaryOperator::Divide if r != 0 => {
+            let dividend = rescale(l, r_scale + 4)?;
+            let (quotient, remainder) = (dividend / r, dividend % r);
+            // Round half away from zero
+            let quotient = if remainder.unsigned_abs() * 2 >= r.unsigned_abs() {
+                if (dividend < 0) != (r < 0) {
+                    quotient - 1
+                } else {
+                    quotient + 1
+                }
+            } else {
+                quotient
+            };
+            Some((quotient, l_scale + 4))
+        }
+        _ => None,
+    }
+}
+
+/// Rounds a decimal half away from zero to `digits` fractional digits.
+fn round_decimal(value: &str, digits: usize) -> String {
+    let (negative, unsigned) = match value.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, value),
+    };
+    let (int, frac) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    if frac.len() <= digits {
+        return value.to_string();
+    }
+
+    let mut kept = format!("{}{}", int, &frac[..digits]).into_bytes();
+    if frac.as_bytes()[digits] >= b'5' {
+        let mut i = kept.len();
+        loop {
+            if i == 0 {
+                kept.insert(0, b'1');
+                break;
+            }
+            i -= 1;
+            if kept[i] == b'9' {
+                kept[i] = b'0';
+            } else {
+                kept[i] += 1;
+                break;
+            }
+        }
+    }
+    let kept = String::from_utf8(kept).unwrap();
+    let (int, frac) = kept.split_at(kept.len() - digits);
+    let int = if int.is_empty() { "0" } else { int };
+    let sign = if negative && kept.bytes().any(|c| c != b'0') {
+        "-"
+    } else {
+        ""
+    };
+    if digits == 0 {
+        format!("{}{}", sign, int)
+    } else {
+        format!("{}{}.{}", sign, int, frac)
+    }
+}
+
+/// SQL `LIKE` matching with `%` and `_` wildcards.
+pub fn like(value: &str, pattern: &str) -> bool {
+    let value = value.chars().collect::<Vec<_>>();
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    // Positions in the pattern reachable after consuming a prefix of the value.
+    let mut states = vec![false; pattern.len() + 1];
+    states[0] = true;
+    for i in 0..pattern.len() {
+        if pattern[i] == '%' && states[i] {
+            states[i + 1] = true;
+        }
+    }
+    for c in value {
+        let mut next = vec![false; pattern.len() + 1];
+        for i in 0..pattern.len() {
+            if !states[i] {
+                continue;
+            }
+            match pattern[i] {
+                '%' => {
+                    next[i] = true;
+                    next[i + 1] = true;
+                }
+                '_' => next[i + 1] = true,
+                p if p == c => next[i + 1] = true,
+                _ => {}
+            }
+        }
+        for i in 0..pattern.len() {
+            if pattern[i] == '%' && next[i] {
+                next[i + 1] = true;
+            }
+        }
+        states = next;
+    }
+    states[pattern.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(index: usize) -> Box<PostExpr> {
+        Box::new(PostExpr::Column(index))
+    }
+
+    fn test_frame() -> DataFrame {
+        DataFrame::new(
+            vec![
+                Column::new("gender".to_string(), ColumnType::MYSQL_TYPE_STRING),
+                Column::new("count".to_string(), ColumnType::MYSQL_TYPE_LONGLONG),
+                Column::new("maxPrice".to_string(), ColumnType::MYSQL_TYPE_DOUBLE),
+            ],
+            vec![
+                Row::new(vec![
+                    TableValue::String("female".to_string()),
+                    TableValue::Int64(4),
+                    TableValue::Float64(10.0),
+                ]),
+                Row::new(vec![
+                    TableValue::String("male".to_string()),
+                    TableValue::Int64(0),
+                    TableValue::Float64(20.5),
+                ]),
+                Row::new(vec![
+                    TableValue::Null,
+                    TableValue::Int64(2),
+                    TableValue::Null,
+                ]),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_decimals() {
+        let decimal = |v: &str| TableValue::Decimal(v.to_string());
+        assert_eq!(
+            compare(
+                &decimal("12345678901234567.89"),
+                &decimal("12345678901234567.9")
+            ),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            compare(&decimal("-1.50"), &decimal("-1.5")),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(
+            compare(&decimal("-0.0"), &TableValue::Int64(0)),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(compare(&decimal("-2"), &decimal("1")), Some(Ordering::Less));
+        assert_eq!(
+            round_decimal("12345678901234567.895", 2),
+            "12345678901234567.90"
+        );
+        assert_eq!(round_decimal("-9.5", 0), "-10");
+        assert_eq!(round_decimal("-0.04", 1), "0.0");
+        assert_eq!(
+            coerce(TableValue::Int64(5), ColumnType::MYSQL_TYPE_NEWDECIMAL),
+            decimal("5")
+        );
+
+        // Arithmetic over decimals is exact.
+        let plus = ast::BinaryOperator::Plus;
+        assert_eq!(
+            arithmetic(&decimal("12345678901234567.89"), &plus, &decimal("0.01")),
+            decimal("12345678901234567.90")
+        );
+        assert_eq!(
+            arithmetic(&decimal("0.1"), &plus, &decimal("0.2")),
+            decimal("0.3")
+        );
+        assert_eq!(
+            arithmetic(
+                &decimal("-1.5"),
+                &ast::BinaryOperator::Minus,
+                &TableValue::Int64(2)
+            ),
+            decimal("-3.5")
+        );
+        assert_eq!(
+            arithmetic(
+                &decimal("1.25"),
+                &ast::BinaryOperator::Multiply,
+                &decimal("-0.2")
+            ),
+            decimal("-0.250")
+        );
+        assert_eq!(
+            arithmetic(&decimal("2"), &ast::BinaryOperator::Divide, &decimal("3")),
+            decimal("0.6667")
+        );
+        assert_eq!(
+            arithmetic(
+                &decimal("-1.00"),
+                &ast::BinaryOperator::Divide,
+                &TableValue::Int64(8)
+            ),
+            decimal("-0.125000")
+        );
+        assert_eq!(
+            arithmetic(
+                &decimal("5.5"),
+                &ast::BinaryOperator::Modulus,
+                &decimal("2")
+            ),
+            decimal("1.5")
+        );
+        assert_eq!(
+            arithmetic(&decimal("1"), &ast::BinaryOperator::Divide, &decimal("0.0")),
+            TableValue::Null
+        );
+        assert_eq!(
+            arithmetic(&decimal("1.5"), &plus, &TableValue::Float64(1.0)),
+            TableValue::Float64(2.5)
+        );
+    }
+
+    #[test]
+    fn test_like() {
+        assert!(like("Orders", "Ord%"));
+        assert!(like("Orders", "%ders"));
+        assert!(like("Orders", "O_d%s"));
+        assert!(like("Orders", "%"));
+        assert!(!like("Orders", "Users%"));
+        assert!(!like("Orders", "Order"));
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let row = &test_frame().into_rows()[1];
+
+        let ratio = PostExpr::BinaryOp {
+            left: column(2),
+            op: ast::BinaryOperator::Divide,
+            right: column(1),
+        };
+        assert_eq!(ratio.evaluate(row).unwrap(), TableValue::Null);
+
+        let sum = PostExpr::BinaryOp {
+            left: column(1),
+            op: ast::BinaryOperator::Plus,
+            right: Box::new(PostExpr::Literal(TableValue::Int64(1))),
+        };
+        assert_eq!(sum.evaluate(row).unwrap(), TableValue::Int64(1));
+
+        let case = PostExpr::Case {
+            operand: None,
+            conditions: vec![PostExpr::BinaryOp {
+                left: column(2),
+                op: ast::BinaryOperator::Gt,
+                right: Box::new(PostExpr::Literal(TableValue::Int64(20))),
+            }],
+            results: vec![PostExpr::Literal(TableValue::String("high".to_string()))],
+            else_result: Some(Box::new(PostExpr::Literal(TableValue::String(
+                "low".to_string(),
+            )))),
+        };
+        assert_eq!(
+            case.evaluate(row).unwrap(),
+            TableValue::String("high".to_string())
+        );
+
+        let concat = PostExpr::Function {
+            name: "concat".to_string(),
+            args: vec![
+                PostExpr::Function {
+                    name: "upper".to_string(),
+                    args: vec![PostExpr::Function {
+                        name: "substring".to_string(),
+                        args: vec![
+                            PostExpr::Column(0),
+                            PostExpr::Literal(TableValue::Int64(1)),
+                            PostExpr::Literal(TableValue::Int64(1)),
+                        ],
+                    }],
+                },
+                PostExpr::Literal(TableValue::String(":".to_string())),
+                PostExpr::Column(1),
+            ],
+        };
+        assert_eq!(
+            concat.evaluate(row).unwrap(),
+            TableValue::String("M:0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_execute() {
+        let post_processing = PostProcessing {
+            columns: vec![
+                PostColumn {
+                    name: "gender".to_string(),
+                    expr: PostExpr::Column(0),
+                    column_type: ColumnType::MYSQL_TYPE_STRING,
+                },
+                PostColumn {
+                    name: "double_count".to_string(),
+                    expr: PostExpr::BinaryOp {
+                        left: column(1),
+                        op: ast::BinaryOperator::Multiply,
+                        right: Box::new(PostExpr::Literal(TableValue::Int64(2))),
+                    },
+                    column_type: ColumnType::MYSQL_TYPE_LONGLONG,
+                },
+            ],
+            filter: Some(PostExpr::BinaryOp {
+                left: column(1),
+                op: ast::BinaryOperator::Gt,
+                right: Box::new(PostExpr::Literal(TableValue::Int64(0))),
+            }),
+            order: vec![(PostExpr::Column(2), false)],
+            limit: Some(1),
+            offset: None,
+            load_limit: None,
+        };
+
+        let frame = post_processing.execute(test_frame()).unwrap();
+        assert_eq!(
+            frame.get_columns()[1].get_name(),
+            "double_count".to_string()
+        );
+        assert_eq!(
+            frame.get_rows(),
+            &vec![Row::new(vec![
+                TableValue::String("female".to_string()),
+                TableValue::Int64(8)
+            ])]
+        );
+
+        // Response with as many rows as requested from Cube.js might be truncated
+        let truncated = PostProcessing {
+            load_limit: Some(3),
+            ..post_processing.clone()
+        };
+        assert!(truncated.execute(test_frame()).is_err());
+        let complete = PostProcessing {
+            load_limit: Some(4),
+            ..post_processing
+        };
+        assert_eq!(complete.execute(test_frame()).unwrap().get_rows().len(), 1);
+    }
+}
@@ -3,24 +3,34 @@ pub mod processing_loop;
 
 use crate::config::injection::{DIService, Injector, InjectorRef};
 use crate::config::processing_loop::ProcessingLoop;
+use crate::mysql::jwt_auth::JwtSqlAuthService;
 use crate::mysql::{MySqlServer, SqlAuthDefaultImpl, SqlAuthService};
+use crate::postgres::PostgresServer;
+use crate::schema::cache::MetaCacheMetrics;
 use crate::schema::{SchemaService, SchemaServiceDefaultImpl};
 use crate::telemetry::{start_track_event_loop, stop_track_event_loop};
 use crate::CubeError;
 use futures::future::join_all;
-use log::error;
+use log::{error, info};
 
 use mockall::automock;
 
 use std::env;
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// How often counters of the meta cache are logged if they changed.
+const META_CACHE_METRICS_LOG_INTERVAL: Duration = Duration::from_secs(300);
 
 #[derive(Clone)]
 pub struct CubeServices {
     pub injector: Arc<Injector>,
+    /// Cancelled by [CubeServices::stop_processing_loops] to stop loops of this struct.
+    stop: CancellationToken,
 }
 
 impl CubeServices {
@@ -59,13 +69,55 @@ impl CubeServices {
                 Ok(())
             }));
         }
+        if self.injector.has_service_typed::<PostgresServer>().await {
+            let postgres_server = self.injector.get_service_typed::<PostgresServer>().await;
+            futures.push(tokio::spawn(async move {
+                match postgres_server.processing_loop().await {
+                    Err(e) => println!("{}", e.to_string()),
+                    Ok(_) => {}
+                }
+
+                Ok(())
+            }));
+        }
         futures.push(tokio::spawn(async move {
             start_track_event_loop().await;
             Ok(())
         }));
+        let schema_service = self.injector.get_service_typed::<dyn SchemaService>().await;
+        let stop = self.stop.clone();
+        futures.push(tokio::spawn(async move {
+            Self::log_meta_cache_metrics(schema_service, stop).await;
+            Ok(())
+        }));
         Ok(futures)
     }
 
+    async fn log_meta_cache_metrics(
+        schema_service: Arc<dyn SchemaService>,
+        stop: CancellationToken,
+    ) {
+        let mut logged = MetaCacheMetrics::default();
+        loop {
+            tokio::select! {
+                _ = stop.cancelled() => return,
+                _ = tokio::time::sleep(META_CACHE_METRICS_LOG_INTERVAL) => {}
+            }
+            let metrics = schema_service.meta_cache_metrics();
+            if metrics != logged {
+                info!(
+                    "Meta cache: {} hits, {} stale hits, {} misses, {} invalidations, {} errors",
+                    metrics.hits,
+                    metrics.stale_hits,
+                    metrics.misses,
+                    metrics.invalidations,
+                    metrics.errors
+                );
+                logged = metrics;
+            }
+        }
+    }
+
     pub async fn stop_processing_loops(&self) -> Result<(), CubeError> {
         if self.injector.has_service_typed::<MySqlServer>().await {
             self.injector
@@ -74,7 +126,15 @@ impl CubeServices {
                 .stop_processing()
                 .await?;
         }
+        if self.injector.has_service_typed::<PostgresServer>().await {
+            self.injector
+                .get_service_typed::<PostgresServer>()
+                .await
+                .stop_processing()
+                .await?;
+        }
         stop_track_event_loop().await;
+        self.stop.cancel();
         Ok(())
     }
 }
@@ -89,13 +149,22 @@ pub struct Config {
 pub trait ConfigObj: DIService {
     fn bind_address(&self) -> &Option<String>;
 
+    fn postgres_bind_address(&self) -> &Option<String>;
+
     fn query_timeout(&self) -> u64;
+
+    fn meta_cache_ttl(&self) -> u64;
+
+    fn auth_config_path(&self) -> &Option<String>;
 }
 
 #[derive(Debug, Clone)]
 pub struct ConfigObjImpl {
     pub bind_address: Option<String>,
+    pub postgres_bind_address: Option<String>,
     pub query_timeout: u64,
+    pub meta_cache_ttl: u64,
+    pub auth_config_path: Option<String>,
 }
 
 crate::di_service!(ConfigObjImpl, [ConfigObj]);
@@ -106,9 +175,21 @@ impl ConfigObj for ConfigObjImpl {
         &self.bind_address
     }
 
+    fn postgres_bind_address(&self) -> &Option<String> {
+        &self.postgres_bind_address
+    }
+
     fn query_timeout(&self) -> u64 {
         self.query_timeout
     }
+
+    fn meta_cache_ttl(&self) -> u64 {
+        self.meta_cache_ttl
+    }
+
+    fn auth_config_path(&self) -> &Option<String> {
+        &self.auth_config_path
+    }
 }
 
 lazy_static! {
@@ -122,6 +203,10 @@ impl Config {
             .ok()
             .map(|v| v.parse::<u64>().unwrap())
             .unwrap_or(120);
+        let meta_cache_ttl = env::var("CUBESQL_META_CACHE_TTL")
+            .ok()
+            .map(|v| v.parse::<u64>().unwrap())
+            .unwrap_or(60);
         Config {
             injector: Injector::new(),
             config_obj: Arc::new(ConfigObjImpl {
@@ -131,7 +216,12 @@ impl Config {
                             .map(|v| v.parse::<u16>().unwrap())
                             .unwrap_or(3306u16)),
                 )),
+                postgres_bind_address: env::var("CUBESQL_PG_PORT")
+                    .ok()
+                    .map(|port| format!("0.0.0.0:{}", port.parse::<u16>().unwrap())),
                 query_timeout,
+                meta_cache_ttl,
+                auth_config_path: env::var("CUBESQL_AUTH_CONFIG").ok(),
             }),
         }
     }
@@ -142,7 +232,10 @@ impl Config {
             injector: Injector::new(),
             config_obj: Arc::new(ConfigObjImpl {
                 bind_address: None,
+                postgres_bind_address: None,
                 query_timeout,
+                meta_cache_ttl: 0,
+                auth_config_path: None,
             }),
         }
     }
@@ -166,24 +259,43 @@ impl Config {
         self.injector.clone()
     }
 
-    pub async fn configure_injector(&self) {
+    pub async fn configure_injector(&self) -> Result<(), CubeError> {
         let config_obj_to_register = self.config_obj.clone();
         self.injector
             .register_typed::<dyn ConfigObj, _, _, _>(async move |_| config_obj_to_register)
             .await;
 
         self.injector
-            .register_typed::<dyn SchemaService, _, _, _>(async move |_| {
-                Arc::new(SchemaServiceDefaultImpl)
+            .register_typed::<dyn SchemaService, _, _, _>(async move |i| {
+                let config = i.get_service_typed::<dyn ConfigObj>().await;
+                SchemaServiceDefaultImpl::new(config.meta_cache_ttl(), config.query_timeout())
             })
             .await;
 
+        if self.config_obj.bind_address().is_some()
+            || self.config_obj.postgres_bind_address().is_some()
+        {
+            if let Some(path) = self.config_obj.auth_config_path() {
+                // Loaded here rather than lazily to fail on startup with an invalid config.
+                let auth = Arc::new(JwtSqlAuthService::from_file(path).map_err(|e| {
+                    CubeError::user(format!(
+                        "Unable to load CUBESQL_AUTH_CONFIG {}: {}",
+                        path, e
+                    ))
+                })?);
+                self.injector
+                    .register_typed::<dyn SqlAuthService, _, _, _>(async move |_| auth)
+                    .await;
+            } else {
+                self.injector
+                    .register_typed::<dyn SqlAuthService, _, _, _>(async move |_| {
+                        Arc::new(SqlAuthDefaultImpl)
+                    })
+                    .await;
+            }
+        }
+
         if self.config_obj.bind_address().is_some() {
-            self.injector
-                .register_typed::<dyn SqlAuthService, _, _, _>(async move |_| {
-                    Arc::new(SqlAuthDefaultImpl)
-                })
-                .await;
             self.injector
                 .register_typed::<MySqlServer, _, _, _>(async move |i| {
                     MySqlServer::new(
@@ -199,17 +311,37 @@ impl Config {
                 })
                 .await;
         }
+
+        if self.config_obj.postgres_bind_address().is_some() {
+            self.injector
+                .register_typed::<PostgresServer, _, _, _>(async move |i| {
+                    PostgresServer::new(
+                        i.get_service_typed::<dyn ConfigObj>()
+                            .await
+                            .postgres_bind_address()
+                            .as_ref()
+                            .unwrap()
+                            .to_string(),
+                        i.get_service_typed().await,
+                        i.get_service_typed().await,
+                    )
+                })
+                .await;
+        }
+
+        Ok(())
     }
 
     pub async fn cube_services(&self) -> CubeServices {
         CubeServices {
             injector: self.injector.clone(),
+            stop: CancellationToken::new(),
         }
     }
 
-    pub async fn configure(&self) -> CubeServices {
-        self.configure_injector().await;
-        self.cube_services().await
+    pub async fn configure(&self) -> Result<CubeServices, CubeError> {
+        self.configure_injector().await?;
+        Ok(self.cube_services().await)
     }
 }
 
@@ -29,6 +29,7 @@ use tokio::time::error::Elapsed;
 pub mod compile;
 pub mod config;
 pub mod mysql;
+pub mod postgres;
 pub mod schema;
 pub mod telemetry;
 
@@ -88,6 +89,12 @@ impl<T> From<cubeclient::apis::Error<T>> for CubeError {
     }
 }
 
+impl From<cubemysql::BindError> for CubeError {
+    fn from(v: cubemysql::BindError) -> Self {
+        CubeError::user(v.message)
+    }
+}
+
 impl From<crate::compile::CompilationError> for CubeError {
     fn from(v: crate::compile::CompilationError) -> Self {
         CubeError::internal(format!("{:?}\n{}", v, Backtrace::capture()))
@@ -215,3 +222,9 @@ impl From<tokio::sync::AcquireError> for CubeError {
         CubeError::from_error(v)
     }
 }
+
+impl From<jsonwebtoken::errors::Error> for CubeError {
+    fn from(v: jsonwebtoken::errors::Error) -> Self {
+        CubeError::from_error(v)
+    }
+}
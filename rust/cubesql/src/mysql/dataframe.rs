@@ -1,3 +1,4 @@
+use chrono::{NaiveDate, NaiveDateTime};
 use log::{error, warn};
 use msql_srv::ColumnType;
 
@@ -78,6 +79,32 @@ impl Row {
 
                     values.push(column_value);
                 }
+                ColumnType::MYSQL_TYPE_NEWDECIMAL => {
+                    let column_value = match value {
+                        serde_json::Value::Null => TableValue::Null,
+                        // Integers stay exact, f64 Display never uses an exponent
+                        serde_json::Value::Number(number) => match number.as_i64() {
+                            Some(v) => TableValue::Decimal(v.to_string()),
+                            None => match number.as_f64() {
+                                Some(v) => TableValue::Decimal(v.to_string()),
+                                None => TableValue::Null,
+                            },
+                        },
+                        serde_json::Value::String(s) if is_decimal(s) => {
+                            TableValue::Decimal(s.clone())
+                        }
+                        v => {
+                            error!(
+                                "Unable to map value {:?} to MYSQL_TYPE_NEWDECIMAL (returning null)",
+                                v
+                            );
+
+                            TableValue::Null
+                        }
+                    };
+
+                    values.push(column_value);
+                }
                 ColumnType::MYSQL_TYPE_DOUBLE => {
                     let column_value = match value {
                         serde_json::Value::Null => TableValue::Null,
@@ -95,8 +122,8 @@ impl Row {
                         },
                         v => {
                             error!(
-                                "Unable to map value {:?} to MYSQL_TYPE_DOUBLE (returning null)",
-                                v
+                                "Unable to map value {:?} to {:?} (returning null)",
+                                v, column_meta.column_type
                             );
 
                             TableValue::Null
@@ -112,10 +139,39 @@ impl Row {
                         values.push(TableValue::Null);
                     }
                 }
+                ColumnType::MYSQL_TYPE_DATETIME => {
+                    let column_value = match value {
+                        serde_json::Value::Null => TableValue::Null,
+                        serde_json::Value::String(s) => match parse_timestamp(s) {
+                            Some(v) => TableValue::Timestamp(v),
+                            None => {
+                                warn!("Unable to parse value as timestamp: {}", s);
+
+                                TableValue::Null
+                            }
+                        },
+                        v => {
+                            error!(
+                                "Unable to map value {:?} to MYSQL_TYPE_DATETIME (returning null)",
+                                v
+                            );
+
+                            TableValue::Null
+                        }
+                    };
+
+                    values.push(column_value);
+                }
                 ColumnType::MYSQL_TYPE_TINY => {
                     let column_value = match value {
                         serde_json::Value::Null => TableValue::Null,
                         serde_json::Value::Bool(v) => TableValue::Boolean(*v),
+                        serde_json::Value::String(s) if s == "true" || s == "1" => {
+                            TableValue::Boolean(true)
+                        }
+                        serde_json::Value::String(s) if s == "false" || s == "0" => {
+                            TableValue::Boolean(false)
+                        }
                         v => {
                             error!(
                                 "Unable to map value {:?} to MYSQL_TYPE_TINY (boolean) (returning null)",
@@ -146,6 +202,36 @@ pub enum TableValue {
     Int64(i64),
     Boolean(bool),
     Float64(f64),
+    /// Exact decimal in its text form, as Cube.js returns it.
+    Decimal(String),
+    Timestamp(NaiveDateTime),
+}
+
+/// Whether `value` is a plain decimal number like `-12.50`, without exponent.
+pub fn is_decimal(value: &str) -> bool {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    let (int, frac) = digits.split_once('.').unwrap_or((digits, ""));
+    !(int.is_empty() && frac.is_empty())
+        && int.chars().all(|c| c.is_ascii_digit())
+        && frac.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Parses timestamps as Cube.js returns them, e.g. `2021-01-31T00:00:00.000`.
+pub fn parse_timestamp(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim_end_matches('Z');
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f"))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .map(|date| date.and_hms(0, 0, 0))
+        })
+}
+
+/// Formats timestamps as MySQL does, with fractional seconds only when they aren't zero.
+pub fn format_timestamp(value: &NaiveDateTime) -> String {
+    value.format("%Y-%m-%d %H:%M:%S%.f").to_string()
 }
 
 pub struct DataFrame {
@@ -196,42 +282,79 @@ mod tests {
             CompiledQueryFieldMeta {
                 column_from: "KibanaSampleDataEcommerce.maxPrice".to_string(),
                 column_to: "maxPrice".to_string(),
-                column_type: ColumnType::MYSQL_TYPE_DOUBLE,
+                column_type: ColumnType::MYSQL_TYPE_NEWDECIMAL,
             },
             CompiledQueryFieldMeta {
                 column_from: "KibanaSampleDataEcommerce.isBool".to_string(),
                 column_to: "isBool".to_string(),
                 column_type: ColumnType::MYSQL_TYPE_TINY,
             },
+            CompiledQueryFieldMeta {
+                column_from: "KibanaSampleDataEcommerce.orderDate".to_string(),
+                column_to: "orderDate".to_string(),
+                column_type: ColumnType::MYSQL_TYPE_DATETIME,
+            },
         ];
 
         let response = r#"
             [
-                {"KibanaSampleDataEcommerce.count": null, "KibanaSampleDataEcommerce.maxPrice": null, "KibanaSampleDataEcommerce.isBool": null},
-                {"KibanaSampleDataEcommerce.count": 5, "KibanaSampleDataEcommerce.maxPrice": 5.05, "KibanaSampleDataEcommerce.isBool": true},
-                {"KibanaSampleDataEcommerce.count": "5", "KibanaSampleDataEcommerce.maxPrice": "5.05", "KibanaSampleDataEcommerce.isBool": false}
+                {"KibanaSampleDataEcommerce.count": null, "KibanaSampleDataEcommerce.maxPrice": null, "KibanaSampleDataEcommerce.isBool": null, "KibanaSampleDataEcommerce.orderDate": null},
+                {"KibanaSampleDataEcommerce.count": 5, "KibanaSampleDataEcommerce.maxPrice": 5.05, "KibanaSampleDataEcommerce.isBool": true, "KibanaSampleDataEcommerce.orderDate": "2021-01-31T10:20:30.500"},
+                {"KibanaSampleDataEcommerce.count": "5", "KibanaSampleDataEcommerce.maxPrice": "12345678901234567.89", "KibanaSampleDataEcommerce.isBool": "false", "KibanaSampleDataEcommerce.orderDate": "2021-01-31"}
             ]
         "#;
         let data = serde_json::from_str::<Vec<serde_json::Value>>(&response).unwrap();
         assert_eq!(
             Row::hydrate_from_response(&meta, data[0].as_object().unwrap()),
-            Row::new(vec![TableValue::Null, TableValue::Null, TableValue::Null])
+            Row::new(vec![
+                TableValue::Null,
+                TableValue::Null,
+                TableValue::Null,
+                TableValue::Null
+            ])
         );
         assert_eq!(
             Row::hydrate_from_response(&meta, data[1].as_object().unwrap()),
             Row::new(vec![
                 TableValue::Int64(5),
-                TableValue::Float64(5.05),
-                TableValue::Boolean(true)
+                TableValue::Decimal("5.05".to_string()),
+                TableValue::Boolean(true),
+                TableValue::Timestamp(
+                    NaiveDate::from_ymd(2021, 1, 31).and_hms_milli(10, 20, 30, 500)
+                )
             ])
         );
         assert_eq!(
             Row::hydrate_from_response(&meta, data[2].as_object().unwrap()),
             Row::new(vec![
                 TableValue::Int64(5),
-                TableValue::Float64(5.05),
-                TableValue::Boolean(false)
+                TableValue::Decimal("12345678901234567.89".to_string()),
+                TableValue::Boolean(false),
+                TableValue::Timestamp(NaiveDate::from_ymd(2021, 1, 31).and_hms(0, 0, 0))
             ])
         );
     }
+
+    #[test]
+    fn test_is_decimal() {
+        assert!(is_decimal("12"));
+        assert!(is_decimal("-0.50"));
+        assert!(is_decimal(".5"));
+        assert!(!is_decimal("-"));
+        assert!(!is_decimal("1e5"));
+        assert!(!is_decimal("NaN"));
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        let date = NaiveDate::from_ymd(2021, 1, 31);
+        assert_eq!(
+            format_timestamp(&date.and_hms(10, 20, 30)),
+            "2021-01-31 10:20:30"
+        );
+        assert_eq!(
+            format_timestamp(&date.and_hms_milli(10, 20, 30, 500)),
+            "2021-01-31 10:20:30.500"
+        );
+    }
 }
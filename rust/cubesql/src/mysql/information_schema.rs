@@ -0,0 +1,678 @@
+//! MySQL `information_schema` and `SHOW` emulation. Both are generated from cube metadata and
+//! queried as [`VirtualTable`]s, so `WHERE` and `LIKE` filters sent by BI tools work.
+use msql_srv::ColumnType;
+use regex::Regex;
+use sqlparser::ast;
+use sqlparser::dialect::MySqlDialect;
+
+use crate::compile::TenantContext;
+use crate::mysql::dataframe::{Column, DataFrame, TableValue};
+use crate::schema::virtual_table::{parse_single_table_select, VirtualTable};
+use crate::schema::V1CubeMetaExt;
+use crate::CubeError;
+
+const DATABASE: &str = "db";
+const INFORMATION_SCHEMA: &str = "information_schema";
+
+/// Schemas reported by `SHOW DATABASES` and `information_schema.SCHEMATA`.
+const SCHEMAS: [&str; 5] = [
+    DATABASE,
+    INFORMATION_SCHEMA,
+    "mysql",
+    "performance_schema",
+    "sys",
+];
+
+fn string(value: &str) -> TableValue {
+    TableValue::String(value.to_string())
+}
+
+fn int(value: i64) -> TableValue {
+    TableValue::Int64(value)
+}
+
+fn virtual_table(columns: Vec<Column>, rows: Vec<Vec<TableValue>>) -> VirtualTable {
+    VirtualTable::new(columns, rows)
+        .with_function("database", string(DATABASE))
+        .with_function("schema", string(DATABASE))
+}
+
+fn unquote(ident: &str) -> String {
+    ident.trim_matches('`').to_string()
+}
+
+/// Splits `db`.`table` into the schema (if present) and the table name.
+fn split_table_name(name: &str) -> (Option<String>, String) {
+    match name.split_once('.') {
+        Some((schema, table)) => (Some(unquote(schema)), unquote(table)),
+        None => (None, unquote(name)),
+    }
+}
+
+struct ColumnMeta {
+    name: String,
+    column_type: String,
+    nullable: bool,
+}
+
+impl ColumnMeta {
+    /// `varchar(255)` is reported as `varchar` with the maximum length of 255.
+    fn data_type(&self) -> (String, Option<i64>) {
+        match self.column_type.split_once('(') {
+            Some((data_type, length)) => (
+                data_type.to_string(),
+                length.trim_end_matches(')').parse::<i64>().ok(),
+            ),
+            None => (self.column_type.clone(), None),
+        }
+    }
+
+    fn collation(&self) -> TableValue {
+        if self.column_type.starts_with("varchar") {
+            string("utf8mb4_0900_ai_ci")
+        } else {
+            TableValue::Null
+        }
+    }
+}
+
+struct TableMeta {
+    schema: &'static str,
+    name: String,
+    table_type: &'static str,
+    columns: Vec<ColumnMeta>,
+}
+
+/// Cubes as base tables of `db` and the `information_schema` views themselves.
+fn all_tables(tenant: &TenantContext) -> Vec<TableMeta> {
+    let cubes = tenant.cubes.iter().map(|cube| TableMeta {
+        schema: DATABASE,
+        name: cube.name.clone(),
+        table_type: "BASE TABLE",
+        columns: cube
+            .get_columns()
+            .into_iter()
+            .map(|column| ColumnMeta {
+                name: column.get_name().clone(),
+                column_type: column.mysql_type_as_str().clone(),
+                nullable: column.mysql_can_be_null(),
+            })
+            .collect(),
+    });
+    let views = InformationSchemaTable::ALL.iter().map(|table| TableMeta {
+        schema: INFORMATION_SCHEMA,
+        name: table.name().to_string(),
+        table_type: "SYSTEM VIEW",
+        columns: table
+            .columns()
+            .into_iter()
+            .map(|column| ColumnMeta {
+                name: column.get_name(),
+                column_type: match column.get_type() {
+                    ColumnType::MYSQL_TYPE_LONGLONG => "bigint".to_string(),
+                    ColumnType::MYSQL_TYPE_DATETIME => "datetime".to_string(),
+                    _ => "varchar(64)".to_string(),
+                },
+                nullable: true,
+            })
+            .collect(),
+    });
+
+    cubes.chain(views).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InformationSchemaTable {
+    Schemata,
+    Tables,
+    Columns,
+    KeyColumnUsage,
+}
+
+impl InformationSchemaTable {
+    const ALL: [InformationSchemaTable; 4] = [
+        InformationSchemaTable::Schemata,
+        InformationSchemaTable::Tables,
+        InformationSchemaTable::Columns,
+        InformationSchemaTable::KeyColumnUsage,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            InformationSchemaTable::Schemata => "SCHEMATA",
+            InformationSchemaTable::Tables => "TABLES",
+            InformationSchemaTable::Columns => "COLUMNS",
+            InformationSchemaTable::KeyColumnUsage => "KEY_COLUMN_USAGE",
+        }
+    }
+
+    fn from_name(name: &ast::ObjectName) -> Option<Self> {
+        match name.0.as_slice() {
+            [schema, table] if schema.value.eq_ignore_ascii_case(INFORMATION_SCHEMA) => Self::ALL
+                .iter()
+                .find(|t| t.name().eq_ignore_ascii_case(&table.value))
+                .cloned(),
+            _ => None,
+        }
+    }
+
+    fn columns(&self) -> Vec<Column> {
+        let column = |name: &str, column_type| Column::new(name.to_string(), column_type);
+        let string = ColumnType::MYSQL_TYPE_STRING;
+        let int = ColumnType::MYSQL_TYPE_LONGLONG;
+        match self {
+            InformationSchemaTable::Schemata => vec![
+                column("CATALOG_NAME", string),
+                column("SCHEMA_NAME", string),
+                column("DEFAULT_CHARACTER_SET_NAME", string),
+                column("DEFAULT_COLLATION_NAME", string),
+                column("SQL_PATH", string),
+            ],
+            InformationSchemaTable::Tables => vec![
+                column("TABLE_CATALOG", string),
+                column("TABLE_SCHEMA", string),
+                column("TABLE_NAME", string),
+                column("TABLE_TYPE", string),
+                column("ENGINE", string),
+                column("TABLE_ROWS", int),
+                column("CREATE_TIME", ColumnType::MYSQL_TYPE_DATETIME),
+                column("TABLE_COLLATION", string),
+                column("TABLE_COMMENT", string),
+            ],
+            InformationSchemaTable::Columns => vec![
+                column("TABLE_CATALOG", string),
+                column("TABLE_SCHEMA", string),
+                column("TABLE_NAME", string),
+                column("COLUMN_NAME", string),
+                column("ORDINAL_POSITION", int),
+                column("COLUMN_DEFAULT", string),
+                column("IS_NULLABLE", string),
+                column("DATA_TYPE", string),
+                column("CHARACTER_MAXIMUM_LENGTH", int),
+                column("NUMERIC_PRECISION", int),
+                column("NUMERIC_SCALE", int),
+                column("COLLATION_NAME", string),
+                column("COLUMN_TYPE", string),
+                column("COLUMN_KEY", string),
+                column("EXTRA", string),
+                column("COLUMN_COMMENT", string),
+            ],
+            InformationSchemaTable::KeyColumnUsage => vec![
+                column("CONSTRAINT_CATALOG", string),
+                column("CONSTRAINT_SCHEMA", string),
+                column("CONSTRAINT_NAME", string),
+                column("TABLE_CATALOG", string),
+                column("TABLE_SCHEMA", string),
+                column("TABLE_NAME", string),
+                column("COLUMN_NAME", string),
+                column("ORDINAL_POSITION", int),
+                column("POSITION_IN_UNIQUE_CONSTRAINT", int),
+                column("REFERENCED_TABLE_SCHEMA", string),
+                column("REFERENCED_TABLE_NAME", string),
+                column("REFERENCED_COLUMN_NAME", string),
+            ],
+        }
+    }
+
+    fn rows(&self, tenant: &TenantContext) -> Vec<Vec<TableValue>> {
+        match self {
+            InformationSchemaTable::Schemata => SCHEMAS
+                .iter()
+                .map(|schema| {
+                    vec![
+                        string("def"),
+                        string(schema),
+                        string("utf8mb4"),
+                        string("utf8mb4_0900_ai_ci"),
+                        TableValue::Null,
+                    ]
+                })
+                .collect(),
+            InformationSchemaTable::Tables => all_tables(tenant)
+                .iter()
+                .map(|table| {
+                    let base_table = table.schema == DATABASE;
+                    vec![
+                        string("def"),
+                        string(table.schema),
+                        string(&table.name),
+                        string(table.table_type),
+                        if base_table {
+                            string("InnoDB")
+                        } else {
+                            TableValue::Null
+                        },
+                        TableValue::Null,
+                        TableValue::Null,
+                        if base_table {
+                            string("utf8mb4_0900_ai_ci")
+                        } else {
+                            TableValue::Null
+                        },
+                        string(""),
+                    ]
+                })
+                .collect(),
+            InformationSchemaTable::Columns => all_tables(tenant)
+                .iter()
+                .flat_map(|table| {
+                    table.columns.iter().enumerate().map(move |(i, column)| {
+                        let (data_type, length) = column.data_type();
+                        let (precision, scale) = if data_type == "int" {
+                            (int(10), int(0))
+                        } else {
+                            (TableValue::Null, TableValue::Null)
+                        };
+                        vec![
+                            string("def"),
+                            string(table.schema),
+                            string(&table.name),
+                            string(&column.name),
+                            int(i as i64 + 1),
+                            TableValue::Null,
+                            string(if column.nullable { "YES" } else { "NO" }),
+                            string(&data_type),
+                            length.map(int).unwrap_or(TableValue::Null),
+                            precision,
+                            scale,
+                            column.collation(),
+                            string(&column.column_type),
+                            string(""),
+                            string(""),
+                            string(""),
+                        ]
+                    })
+                })
+                .collect(),
+            // Cubes don't declare keys.
+            InformationSchemaTable::KeyColumnUsage => vec![],
+        }
+    }
+}
+
+pub struct InformationSchemaQuery {
+    table: InformationSchemaTable,
+    query: ast::Query,
+}
+
+impl InformationSchemaQuery {
+    /// Recognizes a select from a single `information_schema` table.
+    pub fn parse(query: &str) -> Option<InformationSchemaQuery> {
+        let (name, query) = parse_single_table_select(query, &MySqlDialect {})?;
+        let table = InformationSchemaTable::from_name(&name)?;
+        Some(InformationSchemaQuery { table, query })
+    }
+
+    pub fn execute(&self, tenant: &TenantContext) -> Result<DataFrame, CubeError> {
+        virtual_table(self.table.columns(), self.table.rows(tenant)).select(&self.query)
+    }
+}
+
+lazy_static! {
+    static ref SHOW_DATABASES: Regex =
+        Regex::new(r"(?is)^show\s+(?:databases|schemas)(?:\s+(?P<filter>like|where)\s+(?P<expr>.+))?$")
+            .unwrap();
+    static ref SHOW_TABLES: Regex = Regex::new(
+        r"(?is)^show\s+(?P<full>full\s+)?tables(?:\s+(?:from|in)\s+(?P<db>\S+))?(?:\s+(?P<filter>like|where)\s+(?P<expr>.+))?$"
+    )
+    .unwrap();
+    static ref SHOW_COLUMNS: Regex = Regex::new(
+        r"(?is)^show\s+(?P<full>full\s+)?(?:columns|fields)\s+(?:from|in)\s+(?P<table>\S+)(?:\s+(?:from|in)\s+(?P<db>\S+))?(?:\s+(?P<filter>like|where)\s+(?P<expr>.+))?$"
+    )
+    .unwrap();
+    static ref DESCRIBE: Regex =
+        Regex::new(r"(?is)^(?:describe|desc|explain)\s+(?P<table>\S+)(?:\s+(?P<column>\S+))?$")
+            .unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ShowCommand {
+    Databases,
+    Tables {
+        full: bool,
+        schema: String,
+    },
+    Columns {
+        full: bool,
+        schema: String,
+        table: String,
+    },
+}
+
+/// `SHOW DATABASES`, `SHOW [FULL] TABLES`, `SHOW [FULL] COLUMNS` and `DESCRIBE`, with optional
+/// `LIKE` or `WHERE` filters.
+pub struct ShowQuery {
+    command: ShowCommand,
+    query: ast::Query,
+}
+
+impl ShowQuery {
+    pub fn parse(query: &str) -> Option<ShowQuery> {
+        let query = query.trim().trim_end_matches(';').trim();
+
+        let (command, filter) = if let Some(captures) = SHOW_DATABASES.captures(query) {
+            (ShowCommand::Databases, Self::filter(&captures, "Database"))
+        } else if let Some(captures) = SHOW_TABLES.captures(query) {
+            let schema = captures
+                .name("db")
+                .map(|db| unquote(db.as_str()))
+                .unwrap_or_else(|| DATABASE.to_string());
+            let filter = Self::filter(&captures, &format!("Tables_in_{}", schema));
+            (
+                ShowCommand::Tables {
+                    full: captures.name("full").is_some(),
+                    schema,
+                },
+                filter,
+            )
+        } else if let Some(captures) = SHOW_COLUMNS.captures(query) {
+            let (schema, table) = split_table_name(captures.name("table")?.as_str());
+            let schema = captures
+                .name("db")
+                .map(|db| unquote(db.as_str()))
+                .or(schema)
+                .unwrap_or_else(|| DATABASE.to_string());
+            (
+                ShowCommand::Columns {
+                    full: captures.name("full").is_some(),
+                    schema,
+                    table,
+                },
+                Self::filter(&captures, "Field"),
+            )
+        } else if let Some(captures) = DESCRIBE.captures(query) {
+            let (schema, table) = split_table_name(captures.name("table")?.as_str());
+            // EXPLAIN SELECT ... explains a query, not a table.
+            if table.eq_ignore_ascii_case("select") {
+                return None;
+            }
+            let filter = captures.name("column").map(|column| {
+                let column = column.as_str();
+                if column.starts_with('\'') {
+                    format!("Field LIKE {}", column)
+                } else {
+                    format!("Field LIKE '{}'", unquote(column))
+                }
+            });
+            (
+                ShowCommand::Columns {
+                    full: false,
+                    schema: schema.unwrap_or_else(|| DATABASE.to_string()),
+                    table,
+                },
+                filter,
+            )
+        } else {
+            return None;
+        };
+
+        let select = match filter {
+            Some(filter) => format!("SELECT * FROM show_result WHERE {}", filter),
+            None => "SELECT * FROM show_result".to_string(),
+        };
+        let (_, query) = parse_single_table_select(&select, &MySqlDialect {})?;
+
+        Some(ShowQuery { command, query })
+    }
+
+    /// `LIKE` filters the first column of the result, `WHERE` can use any of them.
+    fn filter(captures: &regex::Captures, like_column: &str) -> Option<String> {
+        let expr = captures.name("expr")?.as_str();
+        if captures
+            .name("filter")?
+            .as_str()
+            .eq_ignore_ascii_case("like")
+        {
+            Some(format!("{} LIKE {}", like_column, expr))
+        } else {
+            Some(expr.to_string())
+        }
+    }
+
+    pub fn execute(&self, tenant: &TenantContext) -> Result<DataFrame, CubeError> {
+        let column = |name: &str| Column::new(name.to_string(), ColumnType::MYSQL_TYPE_STRING);
+        let (columns, rows) = match &self.command {
+            ShowCommand::Databases => (
+                vec![column("Database")],
+                SCHEMAS.iter().map(|schema| vec![string(schema)]).collect(),
+            ),
+            ShowCommand::Tables { full, schema } => {
+                if !SCHEMAS.contains(&schema.as_str()) {
+                    return Err(CubeError::user(format!("Unknown database '{}'", schema)));
+                }
+
+                let mut columns = vec![column(&format!("Tables_in_{}", schema))];
+                if *full {
+                    columns.push(column("Table_type"));
+                }
+                let rows = all_tables(tenant)
+                    .into_iter()
+                    .filter(|table| table.schema == schema.as_str())
+                    .map(|table| {
+                        let mut row = vec![string(&table.name)];
+                        if *full {
+                            row.push(string(table.table_type));
+                        }
+                        row
+                    })
+                    .collect();
+                (columns, rows)
+            }
+            ShowCommand::Columns {
+                full,
+                schema,
+                table,
+            } => {
+                let table_meta = all_tables(tenant)
+                    .into_iter()
+                    .find(|t| t.schema == schema.as_str() && t.name.eq_ignore_ascii_case(table))
+                    .ok_or_else(|| {
+                        CubeError::user(format!("Table '{}.{}' doesn't exist", schema, table))
+                    })?;
+
+                let mut columns = vec![column("Field"), column("Type")];
+                if *full {
+                    columns.push(column("Collation"));
+                }
+                columns.extend(
+                    ["Null", "Key", "Default", "Extra"]
+                        .iter()
+                        .map(|name| column(name)),
+                );
+                if *full {
+                    columns.push(column("Privileges"));
+                    columns.push(column("Comment"));
+                }
+
+                let rows = table_meta
+                    .columns
+                    .iter()
+                    .map(|c| {
+                        let mut row = vec![string(&c.name), string(&c.column_type)];
+                        if *full {
+                            row.push(c.collation());
+                        }
+                        row.extend(vec![
+                            string(if c.nullable { "YES" } else { "NO" }),
+                            string(""),
+                            TableValue::Null,
+                            string(""),
+                        ]);
+                        if *full {
+                            row.push(string("select"));
+                            row.push(string(""));
+                        }
+                        row
+                    })
+                    .collect();
+                (columns, rows)
+            }
+        };
+
+        virtual_table(columns, rows).select(&self.query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cubeclient::models::{V1CubeMeta, V1CubeMetaDimension, V1CubeMetaMeasure};
+
+    use crate::mysql::dataframe::Row;
+
+    use super::*;
+
+    fn get_test_tenant_ctx() -> TenantContext {
+        TenantContext::new(vec![
+            V1CubeMeta {
+                name: "Orders".to_string(),
+                title: None,
+                connected_component: Some(1),
+                dimensions: vec![V1CubeMetaDimension {
+                    name: "Orders.status".to_string(),
+                    _type: "string".to_string(),
+                }],
+                measures: vec![V1CubeMetaMeasure {
+                    name: "Orders.count".to_string(),
+                    title: None,
+                    _type: "number".to_string(),
+                    agg_type: Some("count".to_string()),
+                }],
+                segments: vec![],
+            },
+            V1CubeMeta {
+                name: "Users".to_string(),
+                title: None,
+                connected_component: Some(1),
+                dimensions: vec![],
+                measures: vec![],
+                segments: vec![],
+            },
+        ])
+    }
+
+    fn strings(values: Vec<&str>) -> Row {
+        Row::new(values.into_iter().map(string).collect())
+    }
+
+    fn show(query: &str) -> DataFrame {
+        ShowQuery::parse(query)
+            .unwrap()
+            .execute(&get_test_tenant_ctx())
+            .unwrap()
+    }
+
+    fn select(query: &str) -> DataFrame {
+        InformationSchemaQuery::parse(query)
+            .unwrap()
+            .execute(&get_test_tenant_ctx())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_information_schema() {
+        assert!(InformationSchemaQuery::parse("SELECT * FROM Orders").is_none());
+
+        let tables = select(
+            "SELECT TABLE_NAME, TABLE_TYPE FROM information_schema.TABLES \
+             WHERE TABLE_SCHEMA = database() ORDER BY table_name DESC",
+        );
+        assert_eq!(tables.get_columns()[0].get_name(), "TABLE_NAME");
+        assert_eq!(
+            tables.get_rows(),
+            &vec![
+                strings(vec!["Users", "BASE TABLE"]),
+                strings(vec!["Orders", "BASE TABLE"]),
+            ]
+        );
+
+        let columns = select(
+            "SELECT column_name, data_type, character_maximum_length, is_nullable \
+             FROM information_schema.columns WHERE table_schema = 'db' AND table_name = 'Orders'",
+        );
+        assert_eq!(
+            columns.get_rows(),
+            &vec![
+                Row::new(vec![
+                    string("count"),
+                    string("int"),
+                    TableValue::Null,
+                    string("NO")
+                ]),
+                Row::new(vec![
+                    string("status"),
+                    string("varchar"),
+                    int(255),
+                    string("YES")
+                ]),
+            ]
+        );
+
+        let schemata = select(
+            "SELECT schema_name FROM information_schema.schemata WHERE schema_name LIKE 'inf%'",
+        );
+        assert_eq!(
+            schemata.get_rows(),
+            &vec![strings(vec!["information_schema"])]
+        );
+
+        let keys = select("SELECT * FROM information_schema.key_column_usage");
+        assert_eq!(keys.get_columns().len(), 12);
+        assert_eq!(keys.len(), 0);
+    }
+
+    #[test]
+    fn test_show_tables() {
+        let tables = show("SHOW TABLES");
+        assert_eq!(tables.get_columns()[0].get_name(), "Tables_in_db");
+        assert_eq!(
+            tables.get_rows(),
+            &vec![strings(vec!["Orders"]), strings(vec!["Users"])]
+        );
+
+        let tables = show("show full tables from `db` like 'U%';");
+        assert_eq!(
+            tables.get_rows(),
+            &vec![strings(vec!["Users", "BASE TABLE"])]
+        );
+
+        let tables =
+            show("SHOW FULL TABLES IN information_schema WHERE Table_type = 'SYSTEM VIEW'");
+        assert_eq!(tables.len(), InformationSchemaTable::ALL.len());
+
+        let databases = show("SHOW DATABASES LIKE 'db'");
+        assert_eq!(databases.get_rows(), &vec![strings(vec!["db"])]);
+
+        assert!(ShowQuery::parse("SHOW TABLES FROM unknown")
+            .unwrap()
+            .execute(&get_test_tenant_ctx())
+            .is_err());
+    }
+
+    #[test]
+    fn test_show_columns() {
+        let columns = show("SHOW COLUMNS FROM Orders");
+        assert_eq!(
+            columns
+                .get_columns()
+                .iter()
+                .map(|c| c.get_name())
+                .collect::<Vec<_>>(),
+            vec!["Field", "Type", "Null", "Key", "Default", "Extra"]
+        );
+        assert_eq!(columns.len(), 2);
+
+        let columns = show("SHOW FULL COLUMNS FROM `Orders` FROM db WHERE Field = 'status'");
+        assert_eq!(columns.get_columns().len(), 9);
+        assert_eq!(columns.get_rows()[0].values()[0], string("status"));
+
+        let columns = show("DESCRIBE db.`Orders` 'st%'");
+        assert_eq!(columns.get_rows()[0].values()[0], string("status"));
+        assert_eq!(columns.len(), 1);
+
+        assert!(ShowQuery::parse("EXPLAIN SELECT 1").is_none());
+        assert!(ShowQuery::parse("DESCRIBE Unknown")
+            .unwrap()
+            .execute(&get_test_tenant_ctx())
+            .is_err());
+    }
+}
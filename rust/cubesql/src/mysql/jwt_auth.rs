@@ -0,0 +1,295 @@
+//! Authenticator which signs a JWT for the Cube.js API per SQL user. Claims of the token come
+//! from the user's configured security context and the session variables it's allowed to set,
+//! so Cube.js can apply row-level security through `queryRewrite`.
+use std::collections::HashMap;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde_derive::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::mysql::{AuthContext, SqlAuthService};
+use crate::CubeError;
+
+fn default_token_ttl() -> u64 {
+    24 * 60 * 60
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JwtAuthConfig {
+    /// Cube.js API path, e.g. `http://localhost:4000/cubejs-api`.
+    #[serde(rename = "cubeUrl")]
+    pub cube_url: String,
+    /// `CUBEJS_API_SECRET` of the Cube.js deployment, tokens are signed with HS256.
+    pub secret: String,
+    /// Lifetime of signed tokens in seconds.
+    #[serde(rename = "tokenTtl", default = "default_token_ttl")]
+    pub token_ttl: u64,
+    pub users: HashMap<String, JwtAuthUser>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JwtAuthUser {
+    pub password: Option<String>,
+    /// Claims of every token signed for the user.
+    #[serde(rename = "securityContext", default)]
+    pub security_context: Map<String, Value>,
+    /// Claims the user may set with `SET cube.<name> = <value>`.
+    #[serde(rename = "sessionVariables", default)]
+    pub session_variables: Vec<String>,
+}
+
+pub struct JwtSqlAuthService {
+    config: JwtAuthConfig,
+}
+
+crate::di_service!(JwtSqlAuthService, [SqlAuthService]);
+
+impl JwtSqlAuthService {
+    pub fn new(config: JwtAuthConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, CubeError> {
+        let config = serde_json::from_str(&fs::read_to_string(path)?)?;
+        Ok(Self::new(config))
+    }
+
+    fn user(&self, user: &Option<String>) -> Result<&JwtAuthUser, CubeError> {
+        user.as_ref()
+            .and_then(|name| self.config.users.get(name))
+            .ok_or_else(|| {
+                CubeError::user(format!(
+                    "Access denied for user '{}'",
+                    user.as_deref().unwrap_or_default()
+                ))
+            })
+    }
+
+    fn security_context(
+        user: &JwtAuthUser,
+        session_variables: &HashMap<String, Value>,
+    ) -> Map<String, Value> {
+        let mut claims = user.security_context.clone();
+        for (name, value) in session_variables.iter() {
+            claims.insert(name.clone(), value.clone());
+        }
+        claims
+    }
+
+    fn sign(
+        &self,
+        user: &JwtAuthUser,
+        session_variables: &HashMap<String, Value>,
+    ) -> Result<String, CubeError> {
+        let mut claims = Self::security_context(user, session_variables);
+
+        let now = Self::now()?;
+        claims.insert("iat".to_string(), Value::from(now));
+        claims.insert("exp".to_string(), Value::from(now + self.config.token_ttl));
+
+        Ok(encode(
+            &Header::default(),
+            &Value::Object(claims),
+            &EncodingKey::from_secret(self.config.secret.as_bytes()),
+        )?)
+    }
+
+    /// Expiration time of a token signed by this service.
+    fn expires_at(&self, token: &str) -> Result<u64, CubeError> {
+        let claims = decode::<Map<String, Value>>(
+            token,
+            &DecodingKey::from_secret(self.config.secret.as_bytes()),
+            &Validation {
+                validate_exp: false,
+                ..Validation::default()
+            },
+        )?
+        .claims;
+        claims
+            .get("exp")
+            .and_then(|exp| exp.as_u64())
+            .ok_or_else(|| CubeError::internal("Access token without exp claim".to_string()))
+    }
+
+    fn now() -> Result<u64, CubeError> {
+        Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+    }
+}
+
+#[async_trait]
+impl SqlAuthService for JwtSqlAuthService {
+    async fn authenticate(&self, user: Option<String>) -> Result<AuthContext, CubeError> {
+        let user_config = self.user(&user)?;
+        let session_variables = HashMap::new();
+
+        Ok(AuthContext {
+            password: user_config.password.clone(),
+            access_token: self.sign(user_config, &session_variables)?,
+            base_path: self.config.cube_url.clone(),
+            user,
+            security_context: Some(Value::Object(Self::security_context(
+                user_config,
+                &session_variables,
+            ))),
+            session_variables,
+        })
+    }
+
+    async fn set_session_variable(
+        &self,
+        ctx: &AuthContext,
+        name: &str,
+        value: Value,
+    ) -> Result<AuthContext, CubeError> {
+        let user_config = self.user(&ctx.user)?;
+        if !user_config.session_variables.iter().any(|v| v == name) {
+            return Err(CubeError::user(format!(
+                "User '{}' isn't allowed to set cube.{}",
+                ctx.user.as_deref().unwrap_or_default(),
+                name
+            )));
+        }
+
+        let mut session_variables = ctx.session_variables.clone();
+        session_variables.insert(name.to_string(), value);
+
+        Ok(AuthContext {
+            access_token: self.sign(user_config, &session_variables)?,
+            security_context: Some(Value::Object(Self::security_context(
+                user_config,
+                &session_variables,
+            ))),
+            session_variables,
+            ..ctx.clone()
+        })
+    }
+
+    async fn refresh(&self, ctx: &AuthContext) -> Result<Option<AuthContext>, CubeError> {
+        // Re-sign once less than a tenth of the token lifetime is left.
+        let expires_at = self.expires_at(&ctx.access_token)?;
+        if Self::now()? + self.config.token_ttl / 10 < expires_at {
+            return Ok(None);
+        }
+
+        let user_config = self.user(&ctx.user)?;
+        Ok(Some(AuthContext {
+            access_token: self.sign(user_config, &ctx.session_variables)?,
+            ..ctx.clone()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn claims(ctx: &AuthContext) -> Map<String, Value> {
+        decode::<Map<String, Value>>(
+            &ctx.access_token,
+            &DecodingKey::from_secret(b"secret"),
+            &Validation::default(),
+        )
+        .unwrap()
+        .claims
+    }
+
+    #[tokio::test]
+    async fn test_jwt_auth() {
+        let path = std::env::temp_dir().join(format!("cubesql-auth-{}.json", uuid::Uuid::new_v4()));
+        fs::write(
+            &path,
+            json!({
+                "cubeUrl": "http://localhost:4000/cubejs-api",
+                "secret": "secret",
+                "users": {
+                    "analyst": {
+                        "password": "pass",
+                        "securityContext": { "role": "analyst", "tenant_id": 1 },
+                        "sessionVariables": ["region"]
+                    }
+                }
+            })
+            .to_string(),
+        )
+        .unwrap();
+        let auth = JwtSqlAuthService::from_file(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(auth.authenticate(Some("admin".to_string())).await.is_err());
+
+        let ctx = auth
+            .authenticate(Some("analyst".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(ctx.password, Some("pass".to_string()));
+        assert_eq!(ctx.base_path, "http://localhost:4000/cubejs-api");
+        let token_claims = claims(&ctx);
+        assert_eq!(token_claims["role"], json!("analyst"));
+        assert_eq!(token_claims["tenant_id"], json!(1));
+        assert!(token_claims.get("region").is_none());
+
+        let ctx = auth
+            .set_session_variable(&ctx, "region", json!("EU"))
+            .await
+            .unwrap();
+        assert_eq!(claims(&ctx)["region"], json!("EU"));
+        assert_eq!(claims(&ctx)["tenant_id"], json!(1));
+
+        // Only the listed claims can be set from the session.
+        assert!(auth
+            .set_session_variable(&ctx, "tenant_id", json!(2))
+            .await
+            .is_err());
+
+        assert!(auth.refresh(&ctx).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_jwt_auth_refresh() {
+        let auth = JwtSqlAuthService::new(JwtAuthConfig {
+            cube_url: "http://localhost:4000/cubejs-api".to_string(),
+            secret: "secret".to_string(),
+            token_ttl: 0,
+            users: vec![(
+                "analyst".to_string(),
+                JwtAuthUser {
+                    password: None,
+                    security_context: json!({ "tenant_id": 1 }).as_object().unwrap().clone(),
+                    session_variables: vec!["region".to_string()],
+                },
+            )]
+            .into_iter()
+            .collect(),
+        });
+
+        let ctx = auth
+            .authenticate(Some("analyst".to_string()))
+            .await
+            .unwrap();
+        let ctx = auth
+            .set_session_variable(&ctx, "region", json!("EU"))
+            .await
+            .unwrap();
+
+        // The token expires immediately, so it's re-signed with the same claims.
+        let refreshed = auth.refresh(&ctx).await.unwrap().unwrap();
+        assert_eq!(refreshed.security_context, ctx.security_context);
+        let token_claims = decode::<Map<String, Value>>(
+            &refreshed.access_token,
+            &DecodingKey::from_secret(b"secret"),
+            &Validation {
+                validate_exp: false,
+                ..Validation::default()
+            },
+        )
+        .unwrap()
+        .claims;
+        assert_eq!(token_claims["tenant_id"], json!(1));
+        assert_eq!(token_claims["region"], json!("EU"));
+    }
+}
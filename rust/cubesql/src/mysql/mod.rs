@@ -1,9 +1,12 @@
+use std::collections::HashMap;
 use std::env;
 use std::io;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
 
 use async_trait::async_trait;
+use cubemysql::{bind_params, param_to_sql, placeholder_positions};
 
 use log::debug;
 use log::error;
@@ -11,35 +14,94 @@ use log::trace;
 
 use msql_srv::*;
 
+use regex::Regex;
 use serde_json::json;
-use sqlparser::dialect::MySqlDialect;
+use sqlparser::dialect::{Dialect, MySqlDialect};
 use sqlparser::parser::Parser;
 use tokio::net::TcpListener;
 use tokio::sync::{watch, RwLock};
+use tokio_util::sync::CancellationToken;
 
-use crate::compile::convert_sql_to_cube_query;
+use crate::compile::{
+    convert_sql_to_cube_query, convert_sql_to_cube_query_with_dialect, CompiledQuery,
+};
 use crate::config::processing_loop::ProcessingLoop;
 use crate::mysql::dataframe::DataFrame;
 use crate::mysql::dataframe::Row;
 use crate::mysql::dataframe::TableValue;
+use crate::mysql::information_schema::{InformationSchemaQuery, ShowQuery};
+use crate::mysql::prepared_statement::{result_columns, PreparedStatement};
+use crate::schema::SchemaService;
 use crate::schema::V1CubeMetaExt;
-use crate::schema::{SchemaService};
 use crate::CubeError;
 use sqlparser::ast::{ShowCreateObject, Statement};
 
 pub mod dataframe;
+pub mod information_schema;
+pub mod jwt_auth;
+pub mod prepared_statement;
 
 struct Backend {
     auth: Arc<dyn SqlAuthService>,
     schema: Arc<dyn SchemaService>,
     context: Option<AuthContext>,
+    statements: HashMap<u32, PreparedStatement>,
+    next_statement_id: u32,
+    connection_id: u32,
+    running_queries: Arc<RunningQueries>,
+}
+
+/// Cancellation tokens of the queries running on each connection, so `KILL QUERY <id>` from
+/// another connection can stop waiting for Cube.js.
+#[derive(Default)]
+struct RunningQueries {
+    tokens: Mutex<HashMap<u32, CancellationToken>>,
+}
+
+impl RunningQueries {
+    fn start(&self, connection_id: u32) -> CancellationToken {
+        let token = CancellationToken::new();
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(connection_id, token.clone());
+        token
+    }
+
+    fn finish(&self, connection_id: u32) {
+        self.tokens.lock().unwrap().remove(&connection_id);
+    }
+
+    /// Returns false if `connection_id` has no running query.
+    fn cancel(&self, connection_id: u32) -> bool {
+        match self.tokens.lock().unwrap().get(&connection_id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
 }
 
 impl Backend {
+    /// Renews the access token of the connection before it expires.
+    async fn refresh_context(&mut self) -> Result<(), CubeError> {
+        if let Some(ctx) = &self.context {
+            if let Some(ctx) = self.auth.refresh(ctx).await? {
+                self.context = Some(ctx);
+            }
+        }
+
+        Ok(())
+    }
+
     async fn execute_query<'a>(
         &'a mut self,
         query: &'a str,
     ) -> Result<Arc<dataframe::DataFrame>, CubeError> {
+        self.refresh_context().await?;
+
         let _start = SystemTime::now();
         trace!("RAW QUERY: {}", query);
         let query = str::replace(query, "\n", " ");
@@ -50,6 +112,30 @@ impl Backend {
         let query_lower = query_lower.replace("db.`", "");
         let query_lower = query_lower.replace("`", "");
 
+        if let Some((name, value)) = parse_session_variable(&query) {
+            let auth_ctx = if self.context.is_some() {
+                self.context.as_ref().unwrap()
+            } else {
+                return Err(CubeError::user("must be auth".to_string()));
+            };
+
+            self.context = Some(
+                self.auth
+                    .set_session_variable(auth_ctx, &name, value)
+                    .await?,
+            );
+
+            return Ok(Arc::new(DataFrame::new(vec![], vec![])));
+        }
+
+        if let Some(connection_id) = parse_kill_query(&query) {
+            if !self.running_queries.cancel(connection_id) {
+                debug!("No running query on connection {} to kill", connection_id);
+            }
+
+            return Ok(Arc::new(DataFrame::new(vec![], vec![])));
+        }
+
         let ignore = match query_lower.as_str() {
             "set names latin1" => true,
             "rollback" => true,
@@ -201,34 +287,6 @@ impl Backend {
                     )
                 ),
             )
-        } else if query_lower.eq("show schemas") {
-            return Ok(
-                Arc::new(
-                    DataFrame::new(
-                        vec![dataframe::Column::new(
-                            "Database".to_string(),
-                            ColumnType::MYSQL_TYPE_STRING,
-                        )],
-                        vec![
-                            dataframe::Row::new(vec![
-                                dataframe::TableValue::String("db".to_string())
-                            ]),
-                            dataframe::Row::new(vec![
-                                dataframe::TableValue::String("information_schema".to_string())
-                            ]),
-                            dataframe::Row::new(vec![
-                                dataframe::TableValue::String("mysql".to_string())
-                            ]),
-                            dataframe::Row::new(vec![
-                                dataframe::TableValue::String("performance_schema".to_string())
-                            ]),
-                            dataframe::Row::new(vec![
-                                dataframe::TableValue::String("sys".to_string())
-                            ])
-                        ]
-                    )
-                ),
-            )
         } else if query_lower.eq("select connection_id()") {
             return Ok(
                 Arc::new(
@@ -238,7 +296,7 @@ impl Backend {
                             ColumnType::MYSQL_TYPE_LONGLONG,
                         )],
                         vec![dataframe::Row::new(vec![
-                            dataframe::TableValue::Int64(2)
+                            dataframe::TableValue::Int64(self.connection_id as i64)
                         ])]
                     )
                 ),
@@ -257,80 +315,30 @@ impl Backend {
                     )
                 ),
             )
-        } else if query_lower.starts_with("describe") || query_lower.starts_with("explain") {
-            let dialect = MySqlDialect {};
-            let parse_result = Parser::parse_sql(&dialect, &query)?;
+        } else if let Some(show_query) = ShowQuery::parse(&query) {
+            let auth_ctx = if self.context.is_some() {
+                self.context.as_ref().unwrap()
+            } else {
+                return Err(CubeError::user("must be auth".to_string()))
+            };
 
-            let query = &parse_result[0];
+            let ctx = self.schema
+                .get_ctx_for_tenant(auth_ctx)
+                .await?;
 
-            match query {
-                Statement::ExplainTable { table_name, .. } => {
-                    let table_name_filter = if table_name.0.len() == 2 {
-                        &table_name.0[1].value
-                    } else {
-                        &table_name.0[0].value
-                    };
+            return Ok(Arc::new(show_query.execute(&ctx)?));
+        } else if let Some(information_schema_query) = InformationSchemaQuery::parse(&query) {
+            let auth_ctx = if self.context.is_some() {
+                self.context.as_ref().unwrap()
+            } else {
+                return Err(CubeError::user("must be auth".to_string()))
+            };
 
-                    let ctx = if self.context.is_some() {
-                        self.context.as_ref().unwrap()
-                    } else {
-                        return Err(CubeError::user("must be auth".to_string()))
-                    };
-
-                    let ctx = self.schema
-                        .get_ctx_for_tenant(ctx)
-                        .await?;
-
-                    if let Some(cube) = ctx.cubes.iter().find(|c| c.name.eq(table_name_filter)) {
-                        let rows = cube.get_columns().iter().map(|column| Row::new(
-                            vec![
-                                TableValue::String(column.get_name().clone()),
-                                TableValue::String(column.mysql_type_as_str().clone()),
-                                TableValue::String(if column.mysql_can_be_null() { "Yes".to_string() } else { "No".to_string() }),
-                                TableValue::String("".to_string()),
-                                TableValue::Null,
-                                TableValue::String("".to_string()),
-                            ]
-                        )).collect();
-
-
-                        return Ok(Arc::new(DataFrame::new(
-                            vec![
-                                dataframe::Column::new(
-                                    "Field".to_string(),
-                                    ColumnType::MYSQL_TYPE_STRING
-                                ),
-                                dataframe::Column::new(
-                                    "Type".to_string(),
-                                    ColumnType::MYSQL_TYPE_STRING
-                                ),
-                                dataframe::Column::new(
-                                    "Null".to_string(),
-                                    ColumnType::MYSQL_TYPE_STRING
-                                ),
-                                dataframe::Column::new(
-                                    "Key".to_string(),
-                                    ColumnType::MYSQL_TYPE_STRING
-                                ),
-                                dataframe::Column::new(
-                                    "Default".to_string(),
-                                    ColumnType::MYSQL_TYPE_STRING
-                                ),
-                                dataframe::Column::new(
-                                    "Extra".to_string(),
-                                    ColumnType::MYSQL_TYPE_STRING
-                                )
-                            ],
-                            rows
-                        )))
-                    } else {
-                        return Err(CubeError::internal("Unknown table".to_string()))
-                    }
-                }
-                _ => {
-                    return Err(CubeError::internal("Unexpected type in ExplainTable".to_string()))
-                }
-            }
+            let ctx = self.schema
+                .get_ctx_for_tenant(auth_ctx)
+                .await?;
+
+            return Ok(Arc::new(information_schema_query.execute(&ctx)?));
         } else if query_lower.starts_with("show create table") {
             let dialect = MySqlDialect {};
             let parse_result = Parser::parse_sql(&dialect, &query)
@@ -358,7 +366,7 @@ impl Backend {
                                 .get_ctx_for_tenant(ctx)
                                 .await?;
 
-                            if let Some(cube) = ctx.cubes.iter().find(|c| c.name.eq(table_name_filter)) {
+                            if let Some(cube) = ctx.find_cube_with_name(table_name_filter.to_string()) {
                                 let mut fields: Vec<String> = vec![];
 
                                 for column in &cube.get_columns() {
@@ -401,36 +409,6 @@ impl Backend {
                     return Err(CubeError::internal("Unexpected AST in ShowCreate method".to_string()))
                 }
             }
-        } else if query_lower.starts_with("show full tables from") {
-            let auth_ctx = if self.context.is_some() {
-                self.context.as_ref().unwrap()
-            } else {
-                return Err(CubeError::user("must be auth".to_string()))
-            };
-
-            let ctx = self.schema
-                .get_ctx_for_tenant(auth_ctx)
-                .await?;
-
-            let values = ctx.cubes.iter()
-                .map(|cube| Row::new(vec![
-                    TableValue::String(cube.name.clone()),
-                    TableValue::String("BASE TABLE".to_string()),
-                ])).collect();
-
-            return Ok(Arc::new(DataFrame::new(
-                vec![
-                    dataframe::Column::new(
-                        "Tables_in_db".to_string(),
-                        ColumnType::MYSQL_TYPE_STRING
-                    ),
-                    dataframe::Column::new(
-                        "Table_type".to_string(),
-                        ColumnType::MYSQL_TYPE_STRING
-                    )
-                ],
-                values
-            )))
         } else if !ignore {
             trace!("query was not detected");
 
@@ -440,54 +418,18 @@ impl Backend {
                 return Err(CubeError::user("must be auth".to_string()))
             };
 
-            let ctx = self.schema
-                .get_ctx_for_tenant(auth_ctx)
-                .await?;
-
-            let compiled_query = convert_sql_to_cube_query(&query, &ctx)?;
-
-            debug!("Request {}", json!(compiled_query.request).to_string());
-            debug!("Meta {:?}", compiled_query.meta);
-
-            let response = self.schema
-                .request(compiled_query.request, auth_ctx)
-                .await?;
-
-            let mut columns: Vec<dataframe::Column> = vec![];
-
-            for column_meta in &compiled_query.meta {
-                columns.push(dataframe::Column::new(
-                    column_meta.column_to.clone(),
-                    column_meta.column_type
-                ));
-            }
-
-            let mut rows: Vec<dataframe::Row> = vec![];
-
-            if let Some(result) = response.results.first() {
-                debug!("Columns {:?}", columns);
-                debug!("Hydration mapping {:?}", compiled_query.meta);
-                trace!("Response from Cube.js {:?}", result.data);
-
-                for row in result.data.iter() {
-                    if let Some(record) = row.as_object() {
-                        rows.push(
-                            Row::hydrate_from_response(&compiled_query.meta, record)
-                        );
-                    } else {
-                        error!(
-                            "Unable to map row to DataFrame::Row: {:?}, skipping row",
-                            row
-                        );
-                    }
-                }
-
-                return Ok(Arc::new(DataFrame::new(
-                    columns,
-                    rows
-                )))
-            }
+            let cancellation = self.running_queries.start(self.connection_id);
+            let result = load_cube_query(
+                self.schema.as_ref(),
+                auth_ctx,
+                &query,
+                &MySqlDialect {},
+                cancellation,
+            )
+            .await;
+            self.running_queries.finish(self.connection_id);
 
+            return result;
         }
 
         // if start.elapsed().unwrap().as_millis() > 200 && query_lower.starts_with("select") {
@@ -504,6 +446,101 @@ impl Backend {
             Err(CubeError::internal("Unsupported query".to_string()))
         }
     }
+
+    /// Compiles `query` with empty strings in place of its parameters to describe the result.
+    /// Queries which compile only with actual values are described on execute.
+    async fn prepare_statement(&mut self, query: &str) -> Result<PreparedStatement, CubeError> {
+        self.refresh_context().await?;
+
+        let query = str::replace(query, "\n", " ");
+        let params = placeholder_positions(&query).len();
+
+        let columns = match &self.context {
+            Some(auth_ctx) => {
+                let ctx = self.schema.get_ctx_for_tenant(auth_ctx).await?;
+                let placeholder_query = bind_params(&query, &vec!["''".to_string(); params])?;
+
+                match convert_sql_to_cube_query(&placeholder_query, &ctx) {
+                    Ok(compiled_query) => result_columns(&compiled_query),
+                    Err(e) => {
+                        trace!("Unable to describe prepared statement: {}", e.to_string());
+
+                        vec![]
+                    }
+                }
+            }
+            None => vec![],
+        };
+
+        Ok(PreparedStatement {
+            query,
+            params,
+            columns,
+        })
+    }
+}
+
+fn write_query_result<W: io::Write>(
+    query: &str,
+    result: Result<Arc<DataFrame>, CubeError>,
+    results: QueryResultWriter<W>,
+) -> Result<(), io::Error> {
+    match result {
+        Err(e) => {
+            error!("Error during processing {}: {}", query, e.to_string());
+            results.error(ErrorKind::ER_INTERNAL_ERROR, e.message.as_bytes())?;
+
+            Ok(())
+        }
+        Ok(data_frame) => {
+            let columns = data_frame
+                .get_columns()
+                .iter()
+                .map(|c| Column {
+                    table: "result".to_string(), // TODO
+                    column: c.get_name(),
+                    coltype: c.get_type(),
+                    colflags: ColumnFlags::empty(),
+                })
+                .collect::<Vec<_>>();
+
+            let mut rw = results.start(&columns)?;
+
+            for row in data_frame.get_rows().iter() {
+                for (value, column) in row.values().iter().zip(columns.iter()) {
+                    let is_decimal = column.coltype == ColumnType::MYSQL_TYPE_NEWDECIMAL;
+                    match value {
+                        dataframe::TableValue::String(s) => rw.write_col(s)?,
+                        dataframe::TableValue::Boolean(s) => {
+                            if column.coltype == ColumnType::MYSQL_TYPE_TINY {
+                                rw.write_col(*s as i8)?
+                            } else {
+                                rw.write_col(s.to_string())?
+                            }
+                        }
+                        // Decimals are sent as text in both the text and binary protocols
+                        dataframe::TableValue::Float64(s) if is_decimal => {
+                            rw.write_col(s.to_string())?
+                        }
+                        dataframe::TableValue::Float64(s) => rw.write_col(s)?,
+                        dataframe::TableValue::Int64(s) if is_decimal => {
+                            rw.write_col(s.to_string())?
+                        }
+                        dataframe::TableValue::Int64(s) => rw.write_col(s)?,
+                        dataframe::TableValue::Decimal(s) => rw.write_col(s)?,
+                        dataframe::TableValue::Timestamp(s) => rw.write_col(s)?,
+                        dataframe::TableValue::Null => rw.write_col(Option::<String>::None)?,
+                    }
+                }
+
+                rw.end_row()?;
+            }
+
+            rw.finish()?;
+
+            Ok(())
+        }
+    }
 }
 
 #[async_trait]
@@ -512,25 +549,73 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for Backend {
 
     async fn on_prepare<'a>(
         &'a mut self,
-        _query: &'a str,
+        query: &'a str,
         info: StatementMetaWriter<'a, W>,
     ) -> Result<(), Self::Error> {
-        info.reply(42, &[], &[])
+        let statement = match self.prepare_statement(query).await {
+            Ok(statement) => statement,
+            Err(e) => {
+                error!("Error during preparing {}: {}", query, e.to_string());
+                return info.error(ErrorKind::ER_INTERNAL_ERROR, e.message.as_bytes());
+            }
+        };
+
+        let params = (0..statement.params)
+            .map(|_| Column {
+                table: "".to_string(),
+                column: "?".to_string(),
+                coltype: ColumnType::MYSQL_TYPE_VAR_STRING,
+                colflags: ColumnFlags::empty(),
+            })
+            .collect::<Vec<_>>();
+        let columns = statement
+            .columns
+            .iter()
+            .map(|c| Column {
+                table: "result".to_string(), // TODO
+                column: c.get_name(),
+                coltype: c.get_type(),
+                colflags: ColumnFlags::empty(),
+            })
+            .collect::<Vec<_>>();
+
+        let id = self.next_statement_id;
+        self.next_statement_id += 1;
+        self.statements.insert(id, statement);
+
+        info.reply(id, &params, &columns)
     }
 
     async fn on_execute<'a>(
         &'a mut self,
-        _id: u32,
-        _params: ParamParser<'a>,
+        id: u32,
+        params: ParamParser<'a>,
         results: QueryResultWriter<'a, W>,
     ) -> Result<(), Self::Error> {
-        results.completed(0, 0)
+        let query = match self.statements.get(&id) {
+            Some(statement) => statement.query.clone(),
+            None => {
+                return results.error(
+                    ErrorKind::ER_UNKNOWN_STMT_HANDLER,
+                    format!("Unknown prepared statement: {}", id).as_bytes(),
+                )
+            }
+        };
+
+        let values = params.into_iter().map(param_to_sql).collect::<Vec<_>>();
+        let result = match bind_params(&query, &values) {
+            Ok(bound_query) => self.execute_query(&bound_query).await,
+            Err(e) => Err(e.into()),
+        };
+
+        write_query_result(&query, result, results)
     }
 
-    async fn on_close<'a>(&'a mut self, _stmt: u32)
+    async fn on_close<'a>(&'a mut self, stmt: u32)
     where
         W: 'async_trait,
     {
+        self.statements.remove(&stmt);
     }
 
     async fn on_query<'a>(
@@ -538,46 +623,9 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for Backend {
         query: &'a str,
         results: QueryResultWriter<'a, W>,
     ) -> Result<(), Self::Error> {
-        match self.execute_query(query).await {
-            Err(e) => {
-                error!("Error during processing {}: {}", query, e.to_string());
-                results.error(ErrorKind::ER_INTERNAL_ERROR, e.message.as_bytes())?;
-
-                Ok(())
-            }
-            Ok(data_frame) => {
-                let columns = data_frame
-                    .get_columns()
-                    .iter()
-                    .map(|c| Column {
-                        table: "result".to_string(), // TODO
-                        column: c.get_name(),
-                        coltype: c.get_type(),
-                        colflags: ColumnFlags::empty(),
-                    })
-                    .collect::<Vec<_>>();
-
-                let mut rw = results.start(&columns)?;
-
-                for row in data_frame.get_rows().iter() {
-                    for (_i, value) in row.values().iter().enumerate() {
-                        match value {
-                            dataframe::TableValue::String(s) => rw.write_col(s)?,
-                            dataframe::TableValue::Boolean(s) => rw.write_col(s.to_string())?,
-                            dataframe::TableValue::Float64(s) => rw.write_col(s)?,
-                            dataframe::TableValue::Int64(s) => rw.write_col(s)?,
-                            dataframe::TableValue::Null => rw.write_col(Option::<String>::None)?,
-                        }
-                    }
-
-                    rw.end_row()?;
-                }
+        let result = self.execute_query(query).await;
 
-                rw.finish()?;
-
-                Ok(())
-            }
-        }
+        write_query_result(query, result, results)
     }
 
     async fn on_auth<'a>(&'a mut self, user: Vec<u8>) -> Result<Option<Vec<u8>>, Self::Error>
@@ -603,10 +651,120 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for Backend {
     }
 }
 
+/// Compiles `query` into a Cube.js load request, runs it and hydrates the response.
+pub async fn load_cube_query(
+    schema: &dyn SchemaService,
+    auth_ctx: &AuthContext,
+    query: &String,
+    dialect: &dyn Dialect,
+    cancellation: CancellationToken,
+) -> Result<Arc<DataFrame>, CubeError> {
+    let ctx = schema.get_ctx_for_tenant(auth_ctx).await?;
+
+    let compiled_query = convert_sql_to_cube_query_with_dialect(query, &ctx, dialect)?;
+
+    debug!("Request {}", json!(compiled_query.request).to_string());
+    debug!("Meta {:?}", compiled_query.meta);
+
+    let response = schema
+        .request(compiled_query.request.clone(), auth_ctx, cancellation)
+        .await?;
+
+    match response.results.first() {
+        Some(result) => hydrate_cube_result(&compiled_query, &result.data),
+        None => Err(CubeError::internal("Unsupported query".to_string())),
+    }
+}
+
+/// Compiles `queries` and runs them with one Cube.js batch request, frames follow the order of
+/// `queries`.
+pub async fn load_cube_queries(
+    schema: &dyn SchemaService,
+    auth_ctx: &AuthContext,
+    queries: &[String],
+    dialect: &dyn Dialect,
+    cancellation: CancellationToken,
+) -> Result<Vec<Arc<DataFrame>>, CubeError> {
+    let ctx = schema.get_ctx_for_tenant(auth_ctx).await?;
+
+    let compiled_queries = queries
+        .iter()
+        .map(|query| convert_sql_to_cube_query_with_dialect(query, &ctx, dialect))
+        .collect::<Result<Vec<_>, _>>()?;
+    let requests = compiled_queries
+        .iter()
+        .map(|compiled_query| compiled_query.request.clone())
+        .collect::<Vec<_>>();
+
+    debug!("Batch request {}", json!(requests).to_string());
+
+    let response = schema
+        .request_batch(requests, auth_ctx, cancellation)
+        .await?;
+
+    if response.results.len() != compiled_queries.len() {
+        return Err(CubeError::internal(format!(
+            "Expected {} results in the batch response, got {}",
+            compiled_queries.len(),
+            response.results.len()
+        )));
+    }
+
+    compiled_queries
+        .iter()
+        .zip(response.results.iter())
+        .map(|(compiled_query, result)| hydrate_cube_result(compiled_query, &result.data))
+        .collect()
+}
+
+fn hydrate_cube_result(
+    compiled_query: &CompiledQuery,
+    data: &Vec<serde_json::Value>,
+) -> Result<Arc<DataFrame>, CubeError> {
+    let mut columns: Vec<dataframe::Column> = vec![];
+
+    for column_meta in &compiled_query.meta {
+        columns.push(dataframe::Column::new(
+            column_meta.column_to.clone(),
+            column_meta.column_type,
+        ));
+    }
+
+    debug!("Columns {:?}", columns);
+    debug!("Hydration mapping {:?}", compiled_query.meta);
+    trace!("Response from Cube.js {:?}", data);
+
+    let mut rows: Vec<dataframe::Row> = vec![];
+
+    for row in data.iter() {
+        if let Some(record) = row.as_object() {
+            rows.push(Row::hydrate_from_response(&compiled_query.meta, record));
+        } else {
+            error!(
+                "Unable to map row to DataFrame::Row: {:?}, skipping row",
+                row
+            );
+        }
+    }
+
+    let frame = DataFrame::new(columns, rows);
+
+    match &compiled_query.post_processing {
+        Some(post_processing) => {
+            debug!("Post-processing {:?}", post_processing);
+
+            Ok(Arc::new(post_processing.execute(frame)?))
+        }
+        None => Ok(Arc::new(frame)),
+    }
+}
+
 pub struct MySqlServer {
     address: String,
     auth: Arc<dyn SqlAuthService>,
     schema: Arc<dyn SchemaService>,
+    last_connection_id: AtomicU32,
+    running_queries: Arc<RunningQueries>,
     close_socket_rx: RwLock<watch::Receiver<bool>>,
     close_socket_tx: watch::Sender<bool>,
 }
@@ -643,12 +801,18 @@ impl ProcessingLoop for MySqlServer {
 
             let auth = self.auth.clone();
             let schema = self.schema.clone();
+            let connection_id = self.last_connection_id.fetch_add(1, Ordering::SeqCst) + 1;
+            let running_queries = self.running_queries.clone();
             tokio::spawn(async move {
                 if let Err(e) = AsyncMysqlIntermediary::run_on(
                     Backend {
                         auth,
                         schema,
                         context: None,
+                        statements: HashMap::new(),
+                        next_statement_id: 1,
+                        connection_id,
+                        running_queries,
                     },
                     socket,
                 )
@@ -677,22 +841,94 @@ impl MySqlServer {
             address,
             auth,
             schema,
+            last_connection_id: AtomicU32::new(0),
+            running_queries: Arc::new(RunningQueries::default()),
             close_socket_rx: RwLock::new(close_socket_rx),
             close_socket_tx,
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct AuthContext {
     pub password: Option<String>,
     pub access_token: String,
     pub base_path: String,
+    pub user: Option<String>,
+    /// Values set with `SET cube.<name> = <value>` in the session.
+    pub session_variables: HashMap<String, serde_json::Value>,
+    /// Claims the access token is signed with. Connections with the same security context
+    /// share the cached meta, without it the access token identifies the tenant.
+    pub security_context: Option<serde_json::Value>,
 }
 
 #[async_trait]
 pub trait SqlAuthService: Send + Sync {
     async fn authenticate(&self, user: Option<String>) -> Result<AuthContext, CubeError>;
+
+    /// Returns the context for queries after `SET cube.<name> = <value>`, usually with an access
+    /// token for the changed security context.
+    async fn set_session_variable(
+        &self,
+        _ctx: &AuthContext,
+        name: &str,
+        _value: serde_json::Value,
+    ) -> Result<AuthContext, CubeError> {
+        Err(CubeError::user(format!(
+            "Session variable cube.{} isn't supported by the authentication",
+            name
+        )))
+    }
+
+    /// Returns a context with a renewed access token if the token of `ctx` is about to expire.
+    async fn refresh(&self, _ctx: &AuthContext) -> Result<Option<AuthContext>, CubeError> {
+        Ok(None)
+    }
+}
+
+lazy_static! {
+    static ref SET_SESSION_VARIABLE: Regex = Regex::new(
+        r"(?is)^set\s+(?:session\s+)?@?cube\.(?P<name>[a-z_][a-z0-9_]*)\s*(?:=|to)\s*(?P<value>.+?)\s*;?$"
+    )
+    .unwrap();
+}
+
+lazy_static! {
+    static ref KILL_QUERY: Regex =
+        Regex::new(r"(?i)^kill\s+(?:query\s+|connection\s+)?(?P<id>\d+)\s*;?$").unwrap();
+}
+
+/// Recognizes `KILL [QUERY | CONNECTION] <id>` and returns the id of the connection whose running
+/// query should be cancelled. The connection itself is kept open.
+pub fn parse_kill_query(query: &str) -> Option<u32> {
+    let captures = KILL_QUERY.captures(query.trim())?;
+
+    captures["id"].parse().ok()
+}
+
+/// Recognizes `SET cube.<name> = <value>`. Quoted values are strings, numbers and booleans keep
+/// their type in the security context.
+pub fn parse_session_variable(query: &str) -> Option<(String, serde_json::Value)> {
+    let captures = SET_SESSION_VARIABLE.captures(query.trim())?;
+    let name = captures.name("name")?.as_str().to_string();
+    let raw = captures.name("value")?.as_str();
+
+    let quoted = raw.len() >= 2
+        && ((raw.starts_with('\'') && raw.ends_with('\''))
+            || (raw.starts_with('"') && raw.ends_with('"')));
+    let value = if quoted {
+        let quote = &raw[..1];
+        serde_json::Value::String(raw[1..raw.len() - 1].replace(&quote.repeat(2), quote))
+    } else {
+        match serde_json::from_str::<serde_json::Value>(raw) {
+            Ok(value @ serde_json::Value::Number(_)) | Ok(value @ serde_json::Value::Bool(_)) => {
+                value
+            }
+            _ => serde_json::Value::String(raw.to_string()),
+        }
+    };
+
+    Some((name, value))
 }
 
 pub struct SqlAuthDefaultImpl;
@@ -701,7 +937,7 @@ crate::di_service!(SqlAuthDefaultImpl, [SqlAuthService]);
 
 #[async_trait]
 impl SqlAuthService for SqlAuthDefaultImpl {
-    async fn authenticate(&self, _user: Option<String>) -> Result<AuthContext, CubeError> {
+    async fn authenticate(&self, user: Option<String>) -> Result<AuthContext, CubeError> {
         Ok(AuthContext {
             password: None,
             access_token: env::var("CUBESQL_CUBE_TOKEN")
@@ -710,6 +946,31 @@ impl SqlAuthService for SqlAuthDefaultImpl {
             base_path: env::var("CUBESQL_CUBE_URL")
                 .ok()
                 .unwrap_or_else(|| panic!("CUBESQL_CUBE_URL is a required ENV variable")),
+            user,
+            session_variables: HashMap::new(),
+            security_context: None,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_session_variable() {
+        assert_eq!(
+            parse_session_variable("SET cube.tenant_id = 'it''s'"),
+            Some(("tenant_id".to_string(), json!("it's")))
+        );
+        assert_eq!(
+            parse_session_variable("set @cube.tenant_id=42;"),
+            Some(("tenant_id".to_string(), json!(42)))
+        );
+        assert_eq!(
+            parse_session_variable("SET SESSION cube.is_admin TO false"),
+            Some(("is_admin".to_string(), json!(false)))
+        );
+        assert_eq!(parse_session_variable("SET names utf8"), None);
+    }
+}
@@ -0,0 +1,28 @@
+//! Server-side prepared statements, see [cubemysql] for binding their parameters. The bound
+//! query is compiled into the same filters as a text query.
+use crate::compile::CompiledQuery;
+use crate::mysql::dataframe::Column;
+
+pub struct PreparedStatement {
+    pub query: String,
+    pub params: usize,
+    /// Result columns known at prepare time, empty if the query couldn't be compiled without
+    /// its parameters.
+    pub columns: Vec<Column>,
+}
+
+/// Columns of the data frame which executing `compiled_query` returns.
+pub fn result_columns(compiled_query: &CompiledQuery) -> Vec<Column> {
+    match &compiled_query.post_processing {
+        Some(post_processing) => post_processing
+            .columns
+            .iter()
+            .map(|column| Column::new(column.name.clone(), column.column_type))
+            .collect(),
+        None => compiled_query
+            .meta
+            .iter()
+            .map(|meta| Column::new(meta.column_to.clone(), meta.column_type))
+            .collect(),
+    }
+}
@@ -0,0 +1,79 @@
+//! Query cancellation. Clients cancel a query by opening a new connection and sending a
+//! `CancelRequest` with the process id and secret key they got in `BackendKeyData`.
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use tokio_util::sync::CancellationToken;
+
+struct RegisteredConnection {
+    secret_key: i32,
+    /// Token of the query running on the connection.
+    query: Option<CancellationToken>,
+}
+
+/// Connections by process id.
+#[derive(Default)]
+pub struct CancelRegistry {
+    connections: Mutex<HashMap<i32, RegisteredConnection>>,
+}
+
+impl CancelRegistry {
+    pub fn register(&self, process_id: i32, secret_key: i32) {
+        self.connections.lock().unwrap().insert(
+            process_id,
+            RegisteredConnection {
+                secret_key,
+                query: None,
+            },
+        );
+    }
+
+    pub fn unregister(&self, process_id: i32) {
+        self.connections.lock().unwrap().remove(&process_id);
+    }
+
+    /// Returns the token a query starting on the connection is cancelled with.
+    pub fn start_query(&self, process_id: i32) -> CancellationToken {
+        let token = CancellationToken::new();
+        if let Some(connection) = self.connections.lock().unwrap().get_mut(&process_id) {
+            connection.query = Some(token.clone());
+        }
+        token
+    }
+
+    pub fn cancel(&self, process_id: i32, secret_key: i32) {
+        if let Some(connection) = self.connections.lock().unwrap().get(&process_id) {
+            if connection.secret_key == secret_key {
+                if let Some(query) = &connection.query {
+                    query.cancel();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_registry() {
+        let registry = CancelRegistry::default();
+        registry.register(1, 42);
+
+        let query = registry.start_query(1);
+        registry.cancel(1, 41);
+        registry.cancel(2, 42);
+        assert!(!query.is_cancelled(), "wrong key must not cancel");
+
+        registry.cancel(1, 42);
+        assert!(query.is_cancelled());
+
+        // Cancellation doesn't carry over to the next query.
+        let next_query = registry.start_query(1);
+        assert!(!next_query.is_cancelled());
+
+        registry.unregister(1);
+        assert!(registry.connections.lock().unwrap().is_empty());
+    }
+}
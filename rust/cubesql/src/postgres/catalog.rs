@@ -0,0 +1,449 @@
+//! Answers session and catalog queries that Postgres clients issue on connect and while
+//! browsing the schema. Catalog tables are generated from cube metadata and queried as
+//! [`VirtualTable`]s.
+use std::sync::Arc;
+
+use msql_srv::ColumnType;
+use sqlparser::ast;
+use sqlparser::dialect::PostgreSqlDialect;
+
+use crate::compile::TenantContext;
+use crate::mysql::dataframe::{Column, DataFrame, Row, TableValue};
+use crate::postgres::pg_type::{pg_type_for_cube_column, pg_type_name_for_cube_column, ALL_TYPES};
+use crate::postgres::QueryResult;
+use crate::schema::virtual_table::{parse_single_table_select, VirtualTable};
+use crate::schema::V1CubeMetaExt;
+use crate::CubeError;
+
+pub const SERVER_VERSION: &str = "13.2";
+
+/// Reported to clients on startup and available through `SHOW`.
+pub const SERVER_PARAMETERS: [(&str, &str); 8] = [
+    ("server_version", SERVER_VERSION),
+    ("server_encoding", "UTF8"),
+    ("client_encoding", "UTF8"),
+    ("DateStyle", "ISO, MDY"),
+    ("TimeZone", "UTC"),
+    ("integer_datetimes", "on"),
+    ("standard_conforming_strings", "on"),
+    ("IntervalStyle", "postgres"),
+];
+
+const DATABASE: &str = "db";
+const SCHEMA: &str = "public";
+const SCHEMA_OID: i64 = 2200;
+/// Oid of the first cube in `pg_class`, the lowest oid Postgres assigns to user objects.
+const FIRST_TABLE_OID: i64 = 16384;
+
+fn single_value(name: &str, column_type: ColumnType, value: TableValue) -> QueryResult {
+    QueryResult::Rows(Arc::new(DataFrame::new(
+        vec![Column::new(name.to_string(), column_type)],
+        vec![Row::new(vec![value])],
+    )))
+}
+
+fn string_value(name: &str, value: &str) -> QueryResult {
+    single_value(
+        name,
+        ColumnType::MYSQL_TYPE_STRING,
+        TableValue::String(value.to_string()),
+    )
+}
+
+/// Queries that don't depend on cube metadata: transaction control, `SET`, `SHOW` and
+/// session functions.
+pub fn session_query(query: &str, user: &str) -> Option<QueryResult> {
+    let query_lower = query
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let command = |tag: &str| Some(QueryResult::Command(tag.to_string()));
+    match query_lower.as_str() {
+        "begin" | "start transaction" => return command("BEGIN"),
+        "commit" | "end" => return command("COMMIT"),
+        "rollback" => return command("ROLLBACK"),
+        "discard all" => return command("DISCARD ALL"),
+        "select version()" | "select pg_catalog.version()" => {
+            return Some(string_value(
+                "version",
+                &format!(
+                    "PostgreSQL {} on x86_64-pc-linux-gnu (Cube SQL)",
+                    SERVER_VERSION
+                ),
+            ))
+        }
+        "select current_schema()" | "select current_schema" => {
+            return Some(string_value("current_schema", SCHEMA))
+        }
+        "select current_database()" => return Some(string_value("current_database", DATABASE)),
+        "select current_user" | "select session_user" | "select user" => {
+            return Some(string_value("current_user", user))
+        }
+        "select 1" => {
+            return Some(single_value(
+                "?column?",
+                ColumnType::MYSQL_TYPE_LONG,
+                TableValue::Int64(1),
+            ))
+        }
+        "show transaction isolation level" | "show default_transaction_isolation" => {
+            return Some(string_value("transaction_isolation", "read committed"))
+        }
+        "show search_path" => return Some(string_value("search_path", SCHEMA)),
+        // SQLAlchemy checks how strings are returned on connect.
+        "select cast('test plain returns' as varchar(60)) as anon_1" => {
+            return Some(string_value("anon_1", "test plain returns"))
+        }
+        "select cast('test unicode returns' as varchar(60)) as anon_1" => {
+            return Some(string_value("anon_1", "test unicode returns"))
+        }
+        _ => {}
+    }
+
+    if query_lower.starts_with("set ") {
+        return command("SET");
+    }
+    if query_lower.starts_with("deallocate ") {
+        return command("DEALLOCATE");
+    }
+    if let Some(name) = query_lower.strip_prefix("show ") {
+        return SERVER_PARAMETERS
+            .iter()
+            .find(|(n, _)| n.to_lowercase() == name)
+            .map(|(n, v)| string_value(n, v));
+    }
+
+    None
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CatalogTable {
+    Schemata,
+    Tables,
+    Columns,
+    PgNamespace,
+    PgTables,
+    PgType,
+    PgClass,
+    PgAttribute,
+}
+
+impl CatalogTable {
+    fn from_name(name: &ast::ObjectName) -> Option<Self> {
+        let parts = name
+            .0
+            .iter()
+            .map(|i| i.value.to_lowercase())
+            .collect::<Vec<_>>();
+        let (schema, table) = match parts.as_slice() {
+            [table] => ("pg_catalog", table.as_str()),
+            [schema, table] => (schema.as_str(), table.as_str()),
+            _ => return None,
+        };
+        match (schema, table) {
+            ("information_schema", "schemata") => Some(CatalogTable::Schemata),
+            ("information_schema", "tables") => Some(CatalogTable::Tables),
+            ("information_schema", "columns") => Some(CatalogTable::Columns),
+            ("pg_catalog", "pg_namespace") => Some(CatalogTable::PgNamespace),
+            ("pg_catalog", "pg_tables") => Some(CatalogTable::PgTables),
+            ("pg_catalog", "pg_type") => Some(CatalogTable::PgType),
+            ("pg_catalog", "pg_class") => Some(CatalogTable::PgClass),
+            ("pg_catalog", "pg_attribute") => Some(CatalogTable::PgAttribute),
+            _ => None,
+        }
+    }
+
+    fn columns(&self) -> Vec<Column> {
+        let column = |name: &str, column_type| Column::new(name.to_string(), column_type);
+        let string = ColumnType::MYSQL_TYPE_STRING;
+        let int = ColumnType::MYSQL_TYPE_LONGLONG;
+        let boolean = ColumnType::MYSQL_TYPE_TINY;
+        match self {
+            CatalogTable::Schemata => vec![
+                column("catalog_name", string),
+                column("schema_name", string),
+                column("schema_owner", string),
+            ],
+            CatalogTable::Tables => vec![
+                column("table_catalog", string),
+                column("table_schema", string),
+                column("table_name", string),
+                column("table_type", string),
+            ],
+            CatalogTable::Columns => vec![
+                column("table_catalog", string),
+                column("table_schema", string),
+                column("table_name", string),
+                column("column_name", string),
+                column("ordinal_position", int),
+                column("is_nullable", string),
+                column("data_type", string),
+            ],
+            CatalogTable::PgNamespace => vec![column("oid", int), column("nspname", string)],
+            CatalogTable::PgTables => vec![
+                column("schemaname", string),
+                column("tablename", string),
+                column("tableowner", string),
+            ],
+            CatalogTable::PgType => vec![
+                column("oid", int),
+                column("typname", string),
+                column("typlen", int),
+            ],
+            CatalogTable::PgClass => vec![
+                column("oid", int),
+                column("relname", string),
+                column("relnamespace", int),
+                column("relkind", string),
+                column("relowner", string),
+            ],
+            CatalogTable::PgAttribute => vec![
+                column("attrelid", int),
+                column("attname", string),
+                column("atttypid", int),
+                column("attlen", int),
+                column("attnum", int),
+                column("attnotnull", boolean),
+                column("attisdropped", boolean),
+            ],
+        }
+    }
+
+    fn rows(&self, tenant: &TenantContext, user: &str) -> Vec<Vec<TableValue>> {
+        let string = |v: &str| TableValue::String(v.to_string());
+        match self {
+            CatalogTable::Schemata => ["information_schema", "pg_catalog", SCHEMA]
+                .iter()
+                .map(|s| vec![string(DATABASE), string(s), string(user)])
+                .collect(),
+            CatalogTable::Tables => tenant
+                .cubes
+                .iter()
+                .map(|cube| {
+                    vec![
+                        string(DATABASE),
+                        string(SCHEMA),
+                        string(&cube.name),
+                        string("BASE TABLE"),
+                    ]
+                })
+                .collect(),
+            CatalogTable::Columns => tenant
+                .cubes
+                .iter()
+                .flat_map(|cube| {
+                    cube.get_columns()
+                        .into_iter()
+                        .enumerate()
+                        .map(move |(i, column)| {
+                            vec![
+                                string(DATABASE),
+                                string(SCHEMA),
+                                string(&cube.name),
+                                string(column.get_name()),
+                                TableValue::Int64(i as i64 + 1),
+                                string(if column.mysql_can_be_null() {
+                                    "YES"
+                                } else {
+                                    "NO"
+                                }),
+                                string(pg_type_name_for_cube_column(column.mysql_type_as_str())),
+                            ]
+                        })
+                })
+                .collect(),
+            CatalogTable::PgNamespace => [(11, "pg_catalog"), (SCHEMA_OID, SCHEMA)]
+                .iter()
+                .map(|(oid, name)| vec![TableValue::Int64(*oid), string(name)])
+                .collect(),
+            CatalogTable::PgTables => tenant
+                .cubes
+                .iter()
+                .map(|cube| vec![string(SCHEMA), string(&cube.name), string(user)])
+                .collect(),
+            CatalogTable::PgType => ALL_TYPES
+                .iter()
+                .map(|t| {
+                    vec![
+                        TableValue::Int64(t.oid as i64),
+                        string(t.name),
+                        TableValue::Int64(t.len as i64),
+                    ]
+                })
+                .collect(),
+            CatalogTable::PgClass => tenant
+                .cubes
+                .iter()
+                .enumerate()
+                .map(|(i, cube)| {
+                    vec![
+                        TableValue::Int64(FIRST_TABLE_OID + i as i64),
+                        string(&cube.name),
+                        TableValue::Int64(SCHEMA_OID),
+                        string("r"),
+                        string(user),
+                    ]
+                })
+                .collect(),
+            CatalogTable::PgAttribute => tenant
+                .cubes
+                .iter()
+                .enumerate()
+                .flat_map(|(i, cube)| {
+                    cube.get_columns()
+                        .into_iter()
+                        .enumerate()
+                        .map(move |(j, column)| {
+                            let pg_type = pg_type_for_cube_column(column.mysql_type_as_str());
+                            vec![
+                                TableValue::Int64(FIRST_TABLE_OID + i as i64),
+                                string(column.get_name()),
+                                TableValue::Int64(pg_type.oid as i64),
+                                TableValue::Int64(pg_type.len as i64),
+                                TableValue::Int64(j as i64 + 1),
+                                TableValue::Boolean(!column.mysql_can_be_null()),
+                                TableValue::Boolean(false),
+                            ]
+                        })
+                })
+                .collect(),
+        }
+    }
+}
+
+pub struct CatalogQuery {
+    table: CatalogTable,
+    query: ast::Query,
+}
+
+impl CatalogQuery {
+    /// Recognizes a select from a single catalog table.
+    pub fn parse(query: &str) -> Option<CatalogQuery> {
+        let (name, query) = parse_single_table_select(query, &PostgreSqlDialect {})?;
+        let table = CatalogTable::from_name(&name)?;
+        Some(CatalogQuery { table, query })
+    }
+
+    pub fn execute(&self, tenant: &TenantContext, user: &str) -> Result<DataFrame, CubeError> {
+        VirtualTable::new(self.table.columns(), self.table.rows(tenant, user))
+            .with_function("current_schema", TableValue::String(SCHEMA.to_string()))
+            .with_function("current_database", TableValue::String(DATABASE.to_string()))
+            .select(&self.query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cubeclient::models::{V1CubeMeta, V1CubeMetaDimension, V1CubeMetaMeasure};
+
+    use super::*;
+
+    fn get_test_tenant_ctx() -> TenantContext {
+        TenantContext::new(vec![
+            V1CubeMeta {
+                name: "Orders".to_string(),
+                title: None,
+                connected_component: Some(1),
+                dimensions: vec![V1CubeMetaDimension {
+                    name: "Orders.status".to_string(),
+                    _type: "string".to_string(),
+                }],
+                measures: vec![V1CubeMetaMeasure {
+                    name: "Orders.count".to_string(),
+                    title: None,
+                    _type: "number".to_string(),
+                    agg_type: Some("count".to_string()),
+                }],
+                segments: vec![],
+            },
+            V1CubeMeta {
+                name: "Users".to_string(),
+                title: None,
+                connected_component: Some(1),
+                dimensions: vec![],
+                measures: vec![],
+                segments: vec![],
+            },
+        ])
+    }
+
+    fn execute(query: &str) -> DataFrame {
+        CatalogQuery::parse(query)
+            .unwrap()
+            .execute(&get_test_tenant_ctx(), "cube")
+            .unwrap()
+    }
+
+    #[test]
+    fn test_session_query() {
+        assert!(matches!(
+            session_query("SET extra_float_digits = 3", "cube"),
+            Some(QueryResult::Command(tag)) if tag == "SET"
+        ));
+        assert!(matches!(
+            session_query("SHOW  server_version", "cube"),
+            Some(QueryResult::Rows(_))
+        ));
+        assert!(session_query("SELECT * FROM Orders", "cube").is_none());
+    }
+
+    #[test]
+    fn test_catalog_tables() {
+        assert!(CatalogQuery::parse("SELECT * FROM Orders").is_none());
+
+        let tables = execute(
+            "SELECT table_name FROM information_schema.tables \
+             WHERE table_schema = 'public' ORDER BY table_name DESC",
+        );
+        assert_eq!(tables.get_columns()[0].get_name(), "table_name");
+        assert_eq!(
+            tables.get_rows(),
+            &vec![
+                Row::new(vec![TableValue::String("Users".to_string())]),
+                Row::new(vec![TableValue::String("Orders".to_string())]),
+            ]
+        );
+
+        let columns = execute(
+            "SELECT column_name AS name, data_type FROM information_schema.columns \
+             WHERE table_name = 'Orders' AND column_name LIKE 's%'",
+        );
+        assert_eq!(columns.get_columns()[0].get_name(), "name");
+        assert_eq!(
+            columns.get_rows(),
+            &vec![Row::new(vec![
+                TableValue::String("status".to_string()),
+                TableValue::String("text".to_string()),
+            ])]
+        );
+
+        let tables = execute("SELECT tablename FROM pg_tables LIMIT 1");
+        assert_eq!(tables.len(), 1);
+
+        let attributes = execute(
+            "SELECT attname, atttypid FROM pg_catalog.pg_attribute \
+             WHERE attrelid = 16384 AND attnum > 0 ORDER BY attnum",
+        );
+        assert_eq!(
+            attributes.get_rows(),
+            &vec![
+                Row::new(vec![
+                    TableValue::String("count".to_string()),
+                    TableValue::Int64(20),
+                ]),
+                Row::new(vec![
+                    TableValue::String("status".to_string()),
+                    TableValue::Int64(25),
+                ]),
+            ]
+        );
+
+        let classes = execute("SELECT oid FROM pg_class WHERE relname = 'Users'");
+        assert_eq!(
+            classes.get_rows(),
+            &vec![Row::new(vec![TableValue::Int64(16385)])]
+        );
+    }
+}
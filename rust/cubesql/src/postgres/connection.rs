@@ -0,0 +1,755 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use log::{debug, trace};
+use sqlparser::dialect::PostgreSqlDialect;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::sync::CancellationToken;
+
+use crate::compile::convert_sql_to_cube_query_with_dialect;
+use crate::mysql::dataframe::{format_timestamp, Column, DataFrame, TableValue};
+use crate::mysql::prepared_statement::result_columns;
+use crate::mysql::{
+    load_cube_queries, load_cube_query, parse_session_variable, AuthContext, SqlAuthService,
+};
+use crate::postgres::cancel::CancelRegistry;
+use crate::postgres::catalog::{session_query, CatalogQuery, SERVER_PARAMETERS};
+use crate::postgres::pg_type::{self, pg_type_for_column};
+use crate::postgres::protocol::{
+    read_message, read_startup, write_encryption_refused, write_messages, BackendMessage,
+    DescribeTarget, FieldDescription, FrontendMessage, StartupMessage,
+};
+use crate::postgres::QueryResult;
+use crate::schema::SchemaService;
+use crate::{CubeError, CubeErrorCauseType};
+
+struct PreparedStatement {
+    query: String,
+    param_types: Vec<u32>,
+}
+
+struct Portal {
+    query: String,
+    /// Filled on the first Describe or Execute of the portal.
+    result: Option<QueryResult>,
+    /// Rows already sent by Execute calls limited with max_rows.
+    sent_rows: usize,
+}
+
+pub struct Connection<S> {
+    socket: S,
+    auth: Arc<dyn SqlAuthService>,
+    schema: Arc<dyn SchemaService>,
+    cancel_registry: Arc<CancelRegistry>,
+    process_id: i32,
+    user: String,
+    auth_ctx: Option<AuthContext>,
+    statements: HashMap<String, PreparedStatement>,
+    portals: HashMap<String, Portal>,
+    /// Responses are buffered until Sync, Flush or the end of a simple query.
+    output: Vec<BackendMessage>,
+    /// Messages of the extended protocol are skipped up to Sync after an error.
+    ignore_till_sync: bool,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send + Sync> Connection<S> {
+    pub fn new(
+        socket: S,
+        auth: Arc<dyn SqlAuthService>,
+        schema: Arc<dyn SchemaService>,
+        cancel_registry: Arc<CancelRegistry>,
+    ) -> Self {
+        Self {
+            socket,
+            auth,
+            schema,
+            cancel_registry,
+            process_id: 0,
+            user: String::new(),
+            auth_ctx: None,
+            statements: HashMap::new(),
+            portals: HashMap::new(),
+            output: Vec::new(),
+            ignore_till_sync: false,
+        }
+    }
+
+    pub async fn run(mut self, process_id: i32, secret_key: i32) -> Result<(), CubeError> {
+        self.process_id = process_id;
+        if !self.startup(process_id, secret_key).await? {
+            return Ok(());
+        }
+
+        loop {
+            let message = match read_message(&mut self.socket).await? {
+                Some(FrontendMessage::Terminate) | None => return Ok(()),
+                Some(message) => message,
+            };
+            trace!("Postgres message: {:?}", message);
+
+            match message {
+                FrontendMessage::Query(query) => {
+                    self.ignore_till_sync = false;
+                    if let Err(e) = self.simple_query(query).await {
+                        self.output.push(error_response(e));
+                    }
+                    self.output.push(BackendMessage::ReadyForQuery);
+                    self.flush().await?;
+                }
+                FrontendMessage::Sync => {
+                    self.ignore_till_sync = false;
+                    self.output.push(BackendMessage::ReadyForQuery);
+                    self.flush().await?;
+                }
+                FrontendMessage::Flush => self.flush().await?,
+                _ if self.ignore_till_sync => {}
+                message => {
+                    if let Err(e) = self.extended_query(message).await {
+                        self.output.push(error_response(e));
+                        self.ignore_till_sync = true;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns false if the connection should be closed without processing queries.
+    async fn startup(&mut self, process_id: i32, secret_key: i32) -> Result<bool, CubeError> {
+        let parameters = loop {
+            match read_startup(&mut self.socket).await? {
+                StartupMessage::SslRequest | StartupMessage::GssEncRequest => {
+                    write_encryption_refused(&mut self.socket).await?
+                }
+                StartupMessage::CancelRequest {
+                    process_id: target_process_id,
+                    secret_key: target_secret_key,
+                } => {
+                    self.cancel_registry
+                        .cancel(target_process_id, target_secret_key);
+                    return Ok(false);
+                }
+                StartupMessage::Startup { parameters } => break parameters,
+            }
+        };
+
+        let user = parameters.get("user").cloned();
+        let auth_ctx = match self.auth.authenticate(user.clone()).await {
+            Ok(auth_ctx) => auth_ctx,
+            Err(e) => {
+                write_messages(&mut self.socket, &[error_response(e)]).await?;
+                return Ok(false);
+            }
+        };
+        self.user = user.unwrap_or_default();
+
+        if let Some(password) = &auth_ctx.password {
+            write_messages(
+                &mut self.socket,
+                &[BackendMessage::AuthenticationCleartextPassword],
+            )
+            .await?;
+            let authenticated = match read_message(&mut self.socket).await? {
+                Some(FrontendMessage::Password(received)) => &received == password,
+                _ => false,
+            };
+            if !authenticated {
+                let error = BackendMessage::ErrorResponse {
+                    code: "28P01".to_string(),
+                    message: format!("password authentication failed for user \"{}\"", self.user),
+                };
+                write_messages(&mut self.socket, &[error]).await?;
+                return Ok(false);
+            }
+        }
+        self.auth_ctx = Some(auth_ctx);
+
+        self.output.push(BackendMessage::AuthenticationOk);
+        for (name, value) in SERVER_PARAMETERS.iter() {
+            self.output.push(BackendMessage::ParameterStatus(
+                name.to_string(),
+                value.to_string(),
+            ));
+        }
+        self.output.push(BackendMessage::BackendKeyData {
+            process_id,
+            secret_key,
+        });
+        self.output.push(BackendMessage::ReadyForQuery);
+        self.flush().await?;
+        Ok(true)
+    }
+
+    async fn flush(&mut self) -> Result<(), CubeError> {
+        let output = std::mem::take(&mut self.output);
+        write_messages(&mut self.socket, &output).await
+    }
+
+    /// Renews the access token of the connection before it expires.
+    async fn refresh_auth_ctx(&mut self) -> Result<(), CubeError> {
+        if let Some(ctx) = &self.auth_ctx {
+            if let Some(ctx) = self.auth.refresh(ctx).await? {
+                self.auth_ctx = Some(ctx);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs each statement of `query`. Consecutive Cube.js queries are loaded with one batch
+    /// request, results are still sent per statement.
+    async fn simple_query(&mut self, query: String) -> Result<(), CubeError> {
+        self.refresh_auth_ctx().await?;
+
+        let statements = split_statements(&query);
+        let mut i = 0;
+        while i < statements.len() {
+            let batch = statements[i..]
+                .iter()
+                .take_while(|statement| self.is_cube_query(statement))
+                .count();
+            let results = if batch > 1 {
+                let frames = self.load_batch(&statements[i..i + batch]).await?;
+                i += batch;
+                frames.into_iter().map(QueryResult::Rows).collect()
+            } else {
+                let result = self.execute_query(&statements[i]).await?;
+                i += 1;
+                vec![result]
+            };
+            for result in results {
+                if let QueryResult::Rows(frame) = &result {
+                    self.output
+                        .push(BackendMessage::RowDescription(row_description(
+                            frame.get_columns(),
+                        )));
+                }
+                send_result(&mut self.output, &result, 0, None);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `query` is loaded from Cube.js rather than answered by the connection itself.
+    fn is_cube_query(&self, query: &str) -> bool {
+        !query.is_empty()
+            && parse_session_variable(query).is_none()
+            && session_query(query, &self.user).is_none()
+            && CatalogQuery::parse(query).is_none()
+    }
+
+    async fn load_batch(&self, queries: &[String]) -> Result<Vec<Arc<DataFrame>>, CubeError> {
+        debug!("BATCH: {:?}", queries);
+        let cancellation = self.cancel_registry.start_query(self.process_id);
+        load_cube_queries(
+            self.schema.as_ref(),
+            self.auth_ctx.as_ref().unwrap(),
+            queries,
+            &PostgreSqlDialect {},
+            cancellation.clone(),
+        )
+        .await
+        .map_err(|e| cancelled_error(&cancellation).unwrap_or(e))
+    }
+
+    async fn extended_query(&mut self, message: FrontendMessage) -> Result<(), CubeError> {
+        self.refresh_auth_ctx().await?;
+
+        match message {
+            FrontendMessage::Parse {
+                statement,
+                query,
+                param_types,
+            } => {
+                self.statements
+                    .insert(statement, PreparedStatement { query, param_types });
+                self.output.push(BackendMessage::ParseComplete);
+            }
+            FrontendMessage::Bind {
+                portal,
+                statement,
+                param_formats,
+                params,
+                result_formats,
+            } => {
+                if param_formats
+                    .iter()
+                    .chain(result_formats.iter())
+                    .any(|f| *f != 0)
+                {
+                    return Err(CubeError::user(
+                        "Binary format is not supported".to_string(),
+                    ));
+                }
+                let statement = self.statement(&statement)?;
+                let query = bind_params(&statement.query, &statement.param_types, &params)?;
+                self.portals.insert(
+                    portal,
+                    Portal {
+                        query,
+                        result: None,
+                        sent_rows: 0,
+                    },
+                );
+                self.output.push(BackendMessage::BindComplete);
+            }
+            FrontendMessage::Describe {
+                target: DescribeTarget::Statement,
+                name,
+            } => {
+                let statement = self.statement(&name)?;
+                let param_types = statement
+                    .param_types
+                    .iter()
+                    .map(|t| if *t == 0 { pg_type::TEXT.oid } else { *t })
+                    .collect::<Vec<_>>();
+                // Parameters don't change the result columns, any values of their types do.
+                let params = (0..count_params(&statement.query))
+                    .map(|i| {
+                        let param_type = statement.param_types.get(i).cloned().unwrap_or(0);
+                        if NUMERIC_TYPES.contains(&param_type) {
+                            Some(b"0".to_vec())
+                        } else {
+                            Some(Vec::new())
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let query = bind_params(&statement.query, &statement.param_types, &params)?;
+                let description = self.describe_query(&query).await?;
+                self.output
+                    .push(BackendMessage::ParameterDescription(param_types));
+                self.output.push(description);
+            }
+            FrontendMessage::Describe {
+                target: DescribeTarget::Portal,
+                name,
+            } => {
+                self.ensure_portal_result(&name).await?;
+                let portal = self.portal(&name)?;
+                let description = describe_result(portal.result.as_ref().unwrap());
+                self.output.push(description);
+            }
+            FrontendMessage::Execute { portal, max_rows } => {
+                self.ensure_portal_result(&portal).await?;
+                let portal = self
+                    .portals
+                    .get_mut(&portal)
+                    .ok_or_else(|| unknown_portal(&portal))?;
+                let limit = if max_rows > 0 {
+                    Some(max_rows as usize)
+                } else {
+                    None
+                };
+                let sent = send_result(
+                    &mut self.output,
+                    portal.result.as_ref().unwrap(),
+                    portal.sent_rows,
+                    limit,
+                );
+                portal.sent_rows += sent;
+            }
+            FrontendMessage::Close { target, name } => {
+                match target {
+                    DescribeTarget::Statement => {
+                        self.statements.remove(&name);
+                    }
+                    DescribeTarget::Portal => {
+                        self.portals.remove(&name);
+                    }
+                }
+                self.output.push(BackendMessage::CloseComplete);
+            }
+            message => {
+                return Err(CubeError::user(format!(
+                    "Unexpected message: {:?}",
+                    message
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn statement(&self, name: &str) -> Result<&PreparedStatement, CubeError> {
+        self.statements.get(name).ok_or_else(|| {
+            CubeError::user(format!("Prepared statement \"{}\" does not exist", name))
+        })
+    }
+
+    fn portal(&self, name: &str) -> Result<&Portal, CubeError> {
+        self.portals.get(name).ok_or_else(|| unknown_portal(name))
+    }
+
+    async fn ensure_portal_result(&mut self, name: &str) -> Result<(), CubeError> {
+        let portal = self.portal(name)?;
+        if portal.result.is_none() {
+            let query = portal.query.clone();
+            let result = self.execute_query(&query).await?;
+            self.portals.get_mut(name).unwrap().result = Some(result);
+        }
+        Ok(())
+    }
+
+    /// Result description of `query` without loading it from Cube.js.
+    async fn describe_query(&self, query: &str) -> Result<BackendMessage, CubeError> {
+        let query = query.trim().trim_end_matches(';').trim();
+        if query.is_empty() || parse_session_variable(query).is_some() {
+            return Ok(BackendMessage::NoData);
+        }
+
+        if let Some(result) = session_query(query, &self.user) {
+            return Ok(describe_result(&result));
+        }
+
+        let auth_ctx = self.auth_ctx.as_ref().unwrap();
+        let ctx = self.schema.get_ctx_for_tenant(auth_ctx).await?;
+        if let Some(catalog_query) = CatalogQuery::parse(query) {
+            let frame = catalog_query.execute(&ctx, &self.user)?;
+            return Ok(BackendMessage::RowDescription(row_description(
+                frame.get_columns(),
+            )));
+        }
+
+        let compiled_query = convert_sql_to_cube_query_with_dialect(
+            &query.to_string(),
+            &ctx,
+            &PostgreSqlDialect {},
+        )?;
+        Ok(BackendMessage::RowDescription(row_description(
+            &result_columns(&compiled_query),
+        )))
+    }
+
+    async fn execute_query(&mut self, query: &str) -> Result<QueryResult, CubeError> {
+        debug!("QUERY: {}", query);
+        let query = query.trim().trim_end_matches(';').trim();
+        if query.is_empty() {
+            return Ok(QueryResult::Empty);
+        }
+
+        if let Some((name, value)) = parse_session_variable(query) {
+            let auth_ctx = self.auth_ctx.as_ref().unwrap();
+            self.auth_ctx = Some(
+                self.auth
+                    .set_session_variable(auth_ctx, &name, value)
+                    .await?,
+            );
+            return Ok(QueryResult::Command("SET".to_string()));
+        }
+
+        if let Some(result) = session_query(query, &self.user) {
+            return Ok(result);
+        }
+
+        let auth_ctx = self.auth_ctx.as_ref().unwrap();
+        if let Some(catalog_query) = CatalogQuery::parse(query) {
+            let ctx = self.schema.get_ctx_for_tenant(auth_ctx).await?;
+            return Ok(QueryResult::Rows(Arc::new(
+                catalog_query.execute(&ctx, &self.user)?,
+            )));
+        }
+
+        let cancellation = self.cancel_registry.start_query(self.process_id);
+        let frame = load_cube_query(
+            self.schema.as_ref(),
+            auth_ctx,
+            &query.to_string(),
+            &PostgreSqlDialect {},
+            cancellation.clone(),
+        )
+        .await
+        .map_err(|e| cancelled_error(&cancellation).unwrap_or(e))?;
+        Ok(QueryResult::Rows(frame))
+    }
+}
+
+/// The error Postgres reports for a query stopped by a CancelRequest.
+fn cancelled_error(cancellation: &CancellationToken) -> Option<CubeError> {
+    if cancellation.is_cancelled() {
+        Some(CubeError::user(
+            "canceling statement due to user request".to_string(),
+        ))
+    } else {
+        None
+    }
+}
+
+/// Splits a simple query into its statements on `;` outside of quotes.
+fn split_statements(query: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in query.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == ';' => {
+                statements.push(query[start..i].trim().to_string());
+                start = i + 1;
+            }
+            None => {}
+        }
+    }
+    statements.push(query[start..].trim().to_string());
+
+    let statements = statements
+        .into_iter()
+        .filter(|statement| !statement.is_empty())
+        .collect::<Vec<_>>();
+    if statements.is_empty() {
+        // An empty query still gets an EmptyQueryResponse.
+        vec![String::new()]
+    } else {
+        statements
+    }
+}
+
+fn unknown_portal(name: &str) -> CubeError {
+    CubeError::user(format!("Portal \"{}\" does not exist", name))
+}
+
+fn error_response(error: CubeError) -> BackendMessage {
+    let code = match error.cause {
+        CubeErrorCauseType::User => "42000",
+        CubeErrorCauseType::Internal => "XX000",
+    };
+    BackendMessage::ErrorResponse {
+        code: code.to_string(),
+        message: error.message,
+    }
+}
+
+fn row_description(columns: &[Column]) -> Vec<FieldDescription> {
+    columns
+        .iter()
+        .map(|c| {
+            let pg_type = pg_type_for_column(c.get_type());
+            FieldDescription {
+                name: c.get_name(),
+                type_oid: pg_type.oid,
+                type_len: pg_type.len,
+            }
+        })
+        .collect()
+}
+
+fn describe_result(result: &QueryResult) -> BackendMessage {
+    match result {
+        QueryResult::Rows(frame) => {
+            BackendMessage::RowDescription(row_description(frame.get_columns()))
+        }
+        QueryResult::Command(_) | QueryResult::Empty => BackendMessage::NoData,
+    }
+}
+
+/// Sends rows starting at `offset`, at most `limit` of them. Returns the number of sent rows.
+fn send_result(
+    output: &mut Vec<BackendMessage>,
+    result: &QueryResult,
+    offset: usize,
+    limit: Option<usize>,
+) -> usize {
+    match result {
+        QueryResult::Rows(frame) => {
+            let rows = frame.get_rows().iter().skip(offset);
+            let rows = rows.take(limit.unwrap_or(usize::MAX)).collect::<Vec<_>>();
+            for row in rows.iter() {
+                output.push(BackendMessage::DataRow(
+                    row.values().iter().map(encode_value).collect(),
+                ));
+            }
+            if offset + rows.len() < frame.len() {
+                output.push(BackendMessage::PortalSuspended);
+            } else {
+                output.push(BackendMessage::CommandComplete(format!(
+                    "SELECT {}",
+                    rows.len()
+                )));
+            }
+            rows.len()
+        }
+        QueryResult::Command(tag) => {
+            output.push(BackendMessage::CommandComplete(tag.clone()));
+            0
+        }
+        QueryResult::Empty => {
+            output.push(BackendMessage::EmptyQueryResponse);
+            0
+        }
+    }
+}
+
+fn encode_value(value: &TableValue) -> Option<String> {
+    match value {
+        TableValue::Null => None,
+        TableValue::String(v) | TableValue::Decimal(v) => Some(v.clone()),
+        TableValue::Int64(v) => Some(v.to_string()),
+        TableValue::Float64(v) => Some(v.to_string()),
+        TableValue::Boolean(v) => Some(if *v { "t" } else { "f" }.to_string()),
+        TableValue::Timestamp(v) => Some(format_timestamp(v)),
+    }
+}
+
+/// Calls `f` with the number of each `$n` placeholder outside of quotes and its byte range.
+fn for_each_placeholder(query: &str, mut f: impl FnMut(usize, std::ops::Range<usize>)) {
+    let bytes = query.as_bytes();
+    let mut quote = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == b'\'' || c == b'"' => quote = Some(c),
+            None if c == b'$' => {
+                let start = i;
+                while i + 1 < bytes.len() && bytes[i + 1].is_ascii_digit() {
+                    i += 1;
+                }
+                if start < i {
+                    f(query[start + 1..i + 1].parse().unwrap_or(0), start..i + 1);
+                }
+            }
+            None => {}
+        }
+        i += 1;
+    }
+}
+
+fn count_params(query: &str) -> usize {
+    let mut count = 0;
+    for_each_placeholder(query, |n, _| count = count.max(n));
+    count
+}
+
+const NUMERIC_TYPES: [u32; 6] = [
+    pg_type::INT8.oid,
+    pg_type::INT4.oid,
+    21,  // int2
+    700, // float4
+    pg_type::FLOAT8.oid,
+    pg_type::NUMERIC.oid,
+];
+
+/// Substitutes parameters in the text format into the query as literals.
+fn bind_params(
+    query: &str,
+    param_types: &Vec<u32>,
+    params: &Vec<Option<Vec<u8>>>,
+) -> Result<String, CubeError> {
+    let mut literals = Vec::new();
+    for (i, param) in params.iter().enumerate() {
+        let literal = match param {
+            None => "NULL".to_string(),
+            Some(bytes) => {
+                let value = String::from_utf8(bytes.clone())?;
+                let param_type = param_types.get(i).cloned().unwrap_or(0);
+                let is_number = value.parse::<f64>().is_ok();
+                if is_number && (param_type == 0 || NUMERIC_TYPES.contains(&param_type)) {
+                    value
+                } else if NUMERIC_TYPES.contains(&param_type) {
+                    return Err(CubeError::user(format!(
+                        "Invalid number in parameter ${}: {}",
+                        i + 1,
+                        value
+                    )));
+                } else {
+                    format!("'{}'", value.replace('\'', "''"))
+                }
+            }
+        };
+        literals.push(literal);
+    }
+
+    let mut result = String::new();
+    let mut last = 0;
+    let mut error = None;
+    for_each_placeholder(query, |n, range| {
+        result.push_str(&query[last..range.start]);
+        match literals.get(n.wrapping_sub(1)) {
+            Some(literal) => result.push_str(literal),
+            None => error = Some(n),
+        }
+        last = range.end;
+    });
+    if let Some(n) = error {
+        return Err(CubeError::user(format!(
+            "No value bound for parameter ${}",
+            n
+        )));
+    }
+    result.push_str(&query[last..]);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_params() {
+        let params = vec![
+            Some(b"it's".to_vec()),
+            Some(b"10".to_vec()),
+            None,
+            Some(b"5".to_vec()),
+        ];
+        let param_types = vec![pg_type::TEXT.oid, 0, 0, pg_type::VARCHAR.oid];
+        assert_eq!(
+            bind_params(
+                "SELECT a FROM t WHERE b = $1 AND c = '$2' AND d > $2 AND e = $3 AND f = $4",
+                &param_types,
+                &params
+            )
+            .unwrap(),
+            "SELECT a FROM t WHERE b = 'it''s' AND c = '$2' AND d > 10 AND e = NULL AND f = '5'"
+        );
+        assert!(bind_params("SELECT $5", &param_types, &params).is_err());
+        assert_eq!(count_params("SELECT $1, $12, '$20'"), 12);
+    }
+
+    #[test]
+    fn test_split_statements() {
+        assert_eq!(
+            split_statements("SELECT 1; SELECT ';' AS \"a;b\";;"),
+            vec!["SELECT 1".to_string(), "SELECT ';' AS \"a;b\"".to_string()]
+        );
+        assert_eq!(split_statements(" ; "), vec![String::new()]);
+    }
+
+    #[test]
+    fn test_send_result_with_max_rows() {
+        use crate::mysql::dataframe::{Column, Row};
+        use msql_srv::ColumnType;
+
+        let frame = DataFrame::new(
+            vec![Column::new("a".to_string(), ColumnType::MYSQL_TYPE_TINY)],
+            vec![
+                Row::new(vec![TableValue::Boolean(true)]),
+                Row::new(vec![TableValue::Null]),
+                Row::new(vec![TableValue::Boolean(false)]),
+            ],
+        );
+        let result = QueryResult::Rows(Arc::new(frame));
+
+        let mut output = Vec::new();
+        assert_eq!(send_result(&mut output, &result, 0, Some(2)), 2);
+        assert_eq!(
+            output,
+            vec![
+                BackendMessage::DataRow(vec![Some("t".to_string())]),
+                BackendMessage::DataRow(vec![None]),
+                BackendMessage::PortalSuspended,
+            ]
+        );
+
+        let mut output = Vec::new();
+        assert_eq!(send_result(&mut output, &result, 2, Some(2)), 1);
+        assert_eq!(
+            output,
+            vec![
+                BackendMessage::DataRow(vec![Some("f".to_string())]),
+                BackendMessage::CommandComplete("SELECT 1".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use log::error;
+use tokio::net::TcpListener;
+use tokio::sync::{watch, RwLock};
+
+use crate::config::processing_loop::ProcessingLoop;
+use crate::mysql::dataframe::DataFrame;
+use crate::mysql::SqlAuthService;
+use crate::postgres::cancel::CancelRegistry;
+use crate::postgres::connection::Connection;
+use crate::schema::SchemaService;
+use crate::CubeError;
+
+pub mod cancel;
+pub mod catalog;
+pub mod connection;
+pub mod pg_type;
+pub mod protocol;
+
+pub enum QueryResult {
+    Rows(Arc<DataFrame>),
+    /// Statements without a result set, completed with the command tag.
+    Command(String),
+    Empty,
+}
+
+pub struct PostgresServer {
+    address: String,
+    auth: Arc<dyn SqlAuthService>,
+    schema: Arc<dyn SchemaService>,
+    last_process_id: AtomicI32,
+    cancel_registry: Arc<CancelRegistry>,
+    close_socket_rx: RwLock<watch::Receiver<bool>>,
+    close_socket_tx: watch::Sender<bool>,
+}
+
+crate::di_service!(PostgresServer, []);
+
+#[async_trait]
+impl ProcessingLoop for PostgresServer {
+    async fn processing_loop(&self) -> Result<(), CubeError> {
+        let listener = TcpListener::bind(self.address.clone()).await?;
+
+        println!("Postgres port open on {}", self.address);
+
+        loop {
+            let mut stop_receiver = self.close_socket_rx.write().await;
+            let (socket, _) = tokio::select! {
+                res = stop_receiver.changed() => {
+                    if res.is_err() || *stop_receiver.borrow() {
+                        return Ok(());
+                    } else {
+                        continue;
+                    }
+                }
+                accept_res = listener.accept() => {
+                    match accept_res {
+                        Ok(res) => res,
+                        Err(err) => {
+                            error!("Network error: {}", err);
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            let process_id = self.last_process_id.fetch_add(1, Ordering::SeqCst) + 1;
+            let secret_key = uuid::Uuid::new_v4().as_u128() as i32;
+            self.cancel_registry.register(process_id, secret_key);
+            let connection = Connection::new(
+                socket,
+                self.auth.clone(),
+                self.schema.clone(),
+                self.cancel_registry.clone(),
+            );
+            let cancel_registry = self.cancel_registry.clone();
+            tokio::spawn(async move {
+                if let Err(e) = connection.run(process_id, secret_key).await {
+                    error!("Error during processing Postgres connection: {}", e);
+                }
+                cancel_registry.unregister(process_id);
+            });
+        }
+    }
+
+    async fn stop_processing(&self) -> Result<(), CubeError> {
+        self.close_socket_tx.send(true)?;
+        Ok(())
+    }
+}
+
+impl PostgresServer {
+    pub fn new(
+        address: String,
+        auth: Arc<dyn SqlAuthService>,
+        schema: Arc<dyn SchemaService>,
+    ) -> Arc<Self> {
+        let (close_socket_tx, close_socket_rx) = watch::channel(false);
+        Arc::new(Self {
+            address,
+            auth,
+            schema,
+            last_process_id: AtomicI32::new(0),
+            cancel_registry: Arc::new(CancelRegistry::default()),
+            close_socket_rx: RwLock::new(close_socket_rx),
+            close_socket_tx,
+        })
+    }
+}
@@ -0,0 +1,109 @@
+use msql_srv::ColumnType;
+
+#[derive(Debug, PartialEq)]
+pub struct PgType {
+    pub oid: u32,
+    pub name: &'static str,
+    /// Size of the type in bytes, -1 for variable length types.
+    pub len: i16,
+}
+
+pub const BOOL: PgType = PgType {
+    oid: 16,
+    name: "bool",
+    len: 1,
+};
+
+pub const INT8: PgType = PgType {
+    oid: 20,
+    name: "int8",
+    len: 8,
+};
+
+pub const INT4: PgType = PgType {
+    oid: 23,
+    name: "int4",
+    len: 4,
+};
+
+pub const TEXT: PgType = PgType {
+    oid: 25,
+    name: "text",
+    len: -1,
+};
+
+pub const FLOAT8: PgType = PgType {
+    oid: 701,
+    name: "float8",
+    len: 8,
+};
+
+pub const VARCHAR: PgType = PgType {
+    oid: 1043,
+    name: "varchar",
+    len: -1,
+};
+
+pub const TIMESTAMP: PgType = PgType {
+    oid: 1114,
+    name: "timestamp",
+    len: 8,
+};
+
+pub const NUMERIC: PgType = PgType {
+    oid: 1700,
+    name: "numeric",
+    len: -1,
+};
+
+pub const ALL_TYPES: [&PgType; 8] = [
+    &BOOL, &INT8, &INT4, &TEXT, &FLOAT8, &VARCHAR, &TIMESTAMP, &NUMERIC,
+];
+
+/// Postgres type used to send a data frame column.
+pub fn pg_type_for_column(column_type: ColumnType) -> &'static PgType {
+    match column_type {
+        ColumnType::MYSQL_TYPE_TINY => &BOOL,
+        ColumnType::MYSQL_TYPE_SHORT | ColumnType::MYSQL_TYPE_LONG => &INT4,
+        ColumnType::MYSQL_TYPE_LONGLONG => &INT8,
+        ColumnType::MYSQL_TYPE_FLOAT | ColumnType::MYSQL_TYPE_DOUBLE => &FLOAT8,
+        ColumnType::MYSQL_TYPE_DECIMAL | ColumnType::MYSQL_TYPE_NEWDECIMAL => &NUMERIC,
+        ColumnType::MYSQL_TYPE_DATETIME | ColumnType::MYSQL_TYPE_TIMESTAMP => &TIMESTAMP,
+        _ => &TEXT,
+    }
+}
+
+/// Postgres type name of a cube column, as reported by `V1CubeMetaExt::get_columns`.
+pub fn pg_type_name_for_cube_column(mysql_type: &str) -> &'static str {
+    match mysql_type {
+        "int" => "bigint",
+        "decimal" => "numeric",
+        "boolean" => "boolean",
+        "datetime" => "timestamp without time zone",
+        _ => "text",
+    }
+}
+
+/// Postgres type of a cube column, as reported by `V1CubeMetaExt::get_columns`.
+pub fn pg_type_for_cube_column(mysql_type: &str) -> &'static PgType {
+    match mysql_type {
+        "int" => &INT8,
+        "decimal" => &NUMERIC,
+        "boolean" => &BOOL,
+        "datetime" => &TIMESTAMP,
+        _ => &TEXT,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pg_type_for_column() {
+        assert_eq!(pg_type_for_column(ColumnType::MYSQL_TYPE_LONGLONG).oid, 20);
+        assert_eq!(pg_type_for_column(ColumnType::MYSQL_TYPE_DOUBLE).oid, 701);
+        assert_eq!(pg_type_for_column(ColumnType::MYSQL_TYPE_TINY).oid, 16);
+        assert_eq!(pg_type_for_column(ColumnType::MYSQL_TYPE_STRING).oid, 25);
+    }
+}
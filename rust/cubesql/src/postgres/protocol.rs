@@ -0,0 +1,495 @@
+//! Messages of the PostgreSQL frontend/backend protocol, version 3.0.
+//!
+//! See https://www.postgresql.org/docs/current/protocol-message-formats.html
+use std::collections::HashMap;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::CubeError;
+
+const PROTOCOL_VERSION: i32 = 196608;
+const SSL_REQUEST_CODE: i32 = 80877103;
+const CANCEL_REQUEST_CODE: i32 = 80877102;
+const GSSENC_REQUEST_CODE: i32 = 80877104;
+
+// Anything larger is considered to be an invalid message.
+const MAX_MESSAGE_LEN: i32 = 64 * 1024 * 1024;
+
+#[derive(Debug, PartialEq)]
+pub enum StartupMessage {
+    Startup {
+        parameters: HashMap<String, String>,
+    },
+    SslRequest,
+    GssEncRequest,
+    /// Sent on a new connection to cancel the query running on another one.
+    CancelRequest {
+        process_id: i32,
+        secret_key: i32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DescribeTarget {
+    Statement,
+    Portal,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FrontendMessage {
+    /// Simple query protocol.
+    Query(String),
+    Parse {
+        statement: String,
+        query: String,
+        param_types: Vec<u32>,
+    },
+    Bind {
+        portal: String,
+        statement: String,
+        param_formats: Vec<i16>,
+        params: Vec<Option<Vec<u8>>>,
+        result_formats: Vec<i16>,
+    },
+    Describe {
+        target: DescribeTarget,
+        name: String,
+    },
+    Execute {
+        portal: String,
+        max_rows: i32,
+    },
+    Close {
+        target: DescribeTarget,
+        name: String,
+    },
+    Sync,
+    Flush,
+    Terminate,
+    Password(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDescription {
+    pub name: String,
+    pub type_oid: u32,
+    pub type_len: i16,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BackendMessage {
+    AuthenticationOk,
+    AuthenticationCleartextPassword,
+    ParameterStatus(String, String),
+    BackendKeyData {
+        process_id: i32,
+        secret_key: i32,
+    },
+    ReadyForQuery,
+    RowDescription(Vec<FieldDescription>),
+    /// Values in the text format, [None] is NULL.
+    DataRow(Vec<Option<String>>),
+    CommandComplete(String),
+    EmptyQueryResponse,
+    ErrorResponse {
+        code: String,
+        message: String,
+    },
+    ParseComplete,
+    BindComplete,
+    CloseComplete,
+    NoData,
+    PortalSuspended,
+    ParameterDescription(Vec<u32>),
+}
+
+pub async fn read_startup(
+    socket: &mut (impl AsyncRead + Unpin),
+) -> Result<StartupMessage, CubeError> {
+    let len = socket.read_i32().await?;
+    let body = read_body(socket, len - 4).await?;
+    let mut buffer = Buffer::new(&body);
+    let code = buffer.read_i32()?;
+    match code {
+        SSL_REQUEST_CODE => Ok(StartupMessage::SslRequest),
+        GSSENC_REQUEST_CODE => Ok(StartupMessage::GssEncRequest),
+        CANCEL_REQUEST_CODE => Ok(StartupMessage::CancelRequest {
+            process_id: buffer.read_i32()?,
+            secret_key: buffer.read_i32()?,
+        }),
+        PROTOCOL_VERSION => {
+            let mut parameters = HashMap::new();
+            loop {
+                let name = buffer.read_string()?;
+                if name.is_empty() {
+                    break;
+                }
+                let value = buffer.read_string()?;
+                parameters.insert(name, value);
+            }
+            Ok(StartupMessage::Startup { parameters })
+        }
+        v => Err(CubeError::user(format!(
+            "Unsupported protocol version: {}.{}",
+            v >> 16,
+            v & 0xffff
+        ))),
+    }
+}
+
+/// Reads the next message, [None] if the client closed the connection.
+pub async fn read_message(
+    socket: &mut (impl AsyncRead + Unpin),
+) -> Result<Option<FrontendMessage>, CubeError> {
+    let tag = match socket.read_u8().await {
+        Ok(tag) => tag,
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let len = socket.read_i32().await?;
+    let body = read_body(socket, len - 4).await?;
+    let mut buffer = Buffer::new(&body);
+    let message = match tag {
+        b'Q' => FrontendMessage::Query(buffer.read_string()?),
+        b'P' => {
+            let statement = buffer.read_string()?;
+            let query = buffer.read_string()?;
+            let count = buffer.read_i16()?;
+            let mut param_types = Vec::new();
+            for _ in 0..count {
+                param_types.push(buffer.read_i32()? as u32);
+            }
+            FrontendMessage::Parse {
+                statement,
+                query,
+                param_types,
+            }
+        }
+        b'B' => {
+            let portal = buffer.read_string()?;
+            let statement = buffer.read_string()?;
+            let param_formats = buffer.read_i16_list()?;
+            let count = buffer.read_i16()?;
+            let mut params = Vec::new();
+            for _ in 0..count {
+                let len = buffer.read_i32()?;
+                if len < 0 {
+                    params.push(None);
+                } else {
+                    params.push(Some(buffer.read_bytes(len as usize)?.to_vec()));
+                }
+            }
+            let result_formats = buffer.read_i16_list()?;
+            FrontendMessage::Bind {
+                portal,
+                statement,
+                param_formats,
+                params,
+                result_formats,
+            }
+        }
+        b'D' => FrontendMessage::Describe {
+            target: buffer.read_target()?,
+            name: buffer.read_string()?,
+        },
+        b'E' => FrontendMessage::Execute {
+            portal: buffer.read_string()?,
+            max_rows: buffer.read_i32()?,
+        },
+        b'C' => FrontendMessage::Close {
+            target: buffer.read_target()?,
+            name: buffer.read_string()?,
+        },
+        b'S' => FrontendMessage::Sync,
+        b'H' => FrontendMessage::Flush,
+        b'X' => FrontendMessage::Terminate,
+        b'p' => FrontendMessage::Password(buffer.read_string()?),
+        tag => {
+            return Err(CubeError::user(format!(
+                "Unsupported message type: '{}'",
+                tag as char
+            )))
+        }
+    };
+    Ok(Some(message))
+}
+
+async fn read_body(socket: &mut (impl AsyncRead + Unpin), len: i32) -> Result<Vec<u8>, CubeError> {
+    if len < 0 || MAX_MESSAGE_LEN < len {
+        return Err(CubeError::user(format!("Invalid message length: {}", len)));
+    }
+    let mut body = vec![0; len as usize];
+    socket.read_exact(&mut body).await?;
+    Ok(body)
+}
+
+/// Responses to the SSL and GSSAPI encryption requests are a single byte without a header.
+pub async fn write_encryption_refused(
+    socket: &mut (impl AsyncWrite + Unpin),
+) -> Result<(), CubeError> {
+    socket.write_u8(b'N').await?;
+    socket.flush().await?;
+    Ok(())
+}
+
+pub async fn write_messages(
+    socket: &mut (impl AsyncWrite + Unpin),
+    messages: &[BackendMessage],
+) -> Result<(), CubeError> {
+    let mut buffer = Vec::new();
+    for message in messages {
+        message.encode(&mut buffer);
+    }
+    socket.write_all(&buffer).await?;
+    socket.flush().await?;
+    Ok(())
+}
+
+impl BackendMessage {
+    pub fn encode(&self, buffer: &mut Vec<u8>) {
+        let (tag, body) = match self {
+            BackendMessage::AuthenticationOk => (b'R', 0i32.to_be_bytes().to_vec()),
+            BackendMessage::AuthenticationCleartextPassword => (b'R', 3i32.to_be_bytes().to_vec()),
+            BackendMessage::ParameterStatus(name, value) => {
+                let mut body = Vec::new();
+                put_string(&mut body, name);
+                put_string(&mut body, value);
+                (b'S', body)
+            }
+            BackendMessage::BackendKeyData {
+                process_id,
+                secret_key,
+            } => {
+                let mut body = process_id.to_be_bytes().to_vec();
+                body.extend_from_slice(&secret_key.to_be_bytes());
+                (b'K', body)
+            }
+            // Transactions are not supported, so the session is always idle.
+            BackendMessage::ReadyForQuery => (b'Z', vec![b'I']),
+            BackendMessage::RowDescription(fields) => {
+                let mut body = (fields.len() as i16).to_be_bytes().to_vec();
+                for field in fields {
+                    put_string(&mut body, &field.name);
+                    // Table OID and attribute number.
+                    body.extend_from_slice(&0i32.to_be_bytes());
+                    body.extend_from_slice(&0i16.to_be_bytes());
+                    body.extend_from_slice(&field.type_oid.to_be_bytes());
+                    body.extend_from_slice(&field.type_len.to_be_bytes());
+                    // Type modifier and text format.
+                    body.extend_from_slice(&(-1i32).to_be_bytes());
+                    body.extend_from_slice(&0i16.to_be_bytes());
+                }
+                (b'T', body)
+            }
+            BackendMessage::DataRow(values) => {
+                let mut body = (values.len() as i16).to_be_bytes().to_vec();
+                for value in values {
+                    match value {
+                        Some(v) => {
+                            body.extend_from_slice(&(v.len() as i32).to_be_bytes());
+                            body.extend_from_slice(v.as_bytes());
+                        }
+                        None => body.extend_from_slice(&(-1i32).to_be_bytes()),
+                    }
+                }
+                (b'D', body)
+            }
+            BackendMessage::CommandComplete(tag) => {
+                let mut body = Vec::new();
+                put_string(&mut body, tag);
+                (b'C', body)
+            }
+            BackendMessage::EmptyQueryResponse => (b'I', vec![]),
+            BackendMessage::ErrorResponse { code, message } => {
+                let mut body = Vec::new();
+                for (field, value) in [
+                    (b'S', "ERROR"),
+                    (b'V', "ERROR"),
+                    (b'C', code.as_str()),
+                    (b'M', message.as_str()),
+                ]
+                .iter()
+                {
+                    body.push(*field);
+                    put_string(&mut body, value);
+                }
+                body.push(0);
+                (b'E', body)
+            }
+            BackendMessage::ParseComplete => (b'1', vec![]),
+            BackendMessage::BindComplete => (b'2', vec![]),
+            BackendMessage::CloseComplete => (b'3', vec![]),
+            BackendMessage::NoData => (b'n', vec![]),
+            BackendMessage::PortalSuspended => (b's', vec![]),
+            BackendMessage::ParameterDescription(types) => {
+                let mut body = (types.len() as i16).to_be_bytes().to_vec();
+                for oid in types {
+                    body.extend_from_slice(&oid.to_be_bytes());
+                }
+                (b't', body)
+            }
+        };
+        buffer.push(tag);
+        buffer.extend_from_slice(&(body.len() as i32 + 4).to_be_bytes());
+        buffer.extend_from_slice(&body);
+    }
+}
+
+fn put_string(buffer: &mut Vec<u8>, value: &str) {
+    buffer.extend_from_slice(value.as_bytes());
+    buffer.push(0);
+}
+
+struct Buffer<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Buffer<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], CubeError> {
+        if self.data.len() < self.pos + len {
+            return Err(CubeError::user("Unexpected end of message".to_string()));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn read_i16(&mut self) -> Result<i16, CubeError> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.read_bytes(2)?);
+        Ok(i16::from_be_bytes(bytes))
+    }
+
+    fn read_i32(&mut self) -> Result<i32, CubeError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.read_bytes(4)?);
+        Ok(i32::from_be_bytes(bytes))
+    }
+
+    fn read_i16_list(&mut self) -> Result<Vec<i16>, CubeError> {
+        let count = self.read_i16()?;
+        let mut values = Vec::new();
+        for _ in 0..count {
+            values.push(self.read_i16()?);
+        }
+        Ok(values)
+    }
+
+    fn read_string(&mut self) -> Result<String, CubeError> {
+        let rest = &self.data[self.pos..];
+        let end = rest
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| CubeError::user("Unterminated string in message".to_string()))?;
+        self.pos += end + 1;
+        Ok(String::from_utf8(rest[..end].to_vec())?)
+    }
+
+    fn read_target(&mut self) -> Result<DescribeTarget, CubeError> {
+        match self.read_bytes(1)?[0] {
+            b'S' => Ok(DescribeTarget::Statement),
+            b'P' => Ok(DescribeTarget::Portal),
+            t => Err(CubeError::user(format!(
+                "Unexpected describe target: '{}'",
+                t as char
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frontend_message(tag: u8, body: &[u8]) -> Vec<u8> {
+        let mut message = vec![tag];
+        message.extend_from_slice(&(body.len() as i32 + 4).to_be_bytes());
+        message.extend_from_slice(body);
+        message
+    }
+
+    #[tokio::test]
+    async fn test_read_startup() {
+        let mut body = PROTOCOL_VERSION.to_be_bytes().to_vec();
+        body.extend_from_slice(b"user\0cube\0database\0db\0\0");
+        let mut message = (body.len() as i32 + 4).to_be_bytes().to_vec();
+        message.extend_from_slice(&body);
+
+        let mut parameters = HashMap::new();
+        parameters.insert("user".to_string(), "cube".to_string());
+        parameters.insert("database".to_string(), "db".to_string());
+        assert_eq!(
+            read_startup(&mut message.as_slice()).await.unwrap(),
+            StartupMessage::Startup { parameters }
+        );
+
+        let mut message = 8i32.to_be_bytes().to_vec();
+        message.extend_from_slice(&SSL_REQUEST_CODE.to_be_bytes());
+        assert_eq!(
+            read_startup(&mut message.as_slice()).await.unwrap(),
+            StartupMessage::SslRequest
+        );
+
+        let mut message = 16i32.to_be_bytes().to_vec();
+        message.extend_from_slice(&CANCEL_REQUEST_CODE.to_be_bytes());
+        message.extend_from_slice(&7i32.to_be_bytes());
+        message.extend_from_slice(&(-42i32).to_be_bytes());
+        assert_eq!(
+            read_startup(&mut message.as_slice()).await.unwrap(),
+            StartupMessage::CancelRequest {
+                process_id: 7,
+                secret_key: -42
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_extended_query() {
+        let mut input = frontend_message(b'P', b"s1\0SELECT $1\0\x00\x01\x00\x00\x00\x19");
+        input.extend(frontend_message(
+            b'B',
+            b"\0s1\0\x00\x00\x00\x02\x00\x00\x00\x02ab\xff\xff\xff\xff\x00\x00",
+        ));
+        input.extend(frontend_message(b'S', b""));
+        let mut input = input.as_slice();
+
+        assert_eq!(
+            read_message(&mut input).await.unwrap(),
+            Some(FrontendMessage::Parse {
+                statement: "s1".to_string(),
+                query: "SELECT $1".to_string(),
+                param_types: vec![25],
+            })
+        );
+        assert_eq!(
+            read_message(&mut input).await.unwrap(),
+            Some(FrontendMessage::Bind {
+                portal: "".to_string(),
+                statement: "s1".to_string(),
+                param_formats: vec![],
+                params: vec![Some(b"ab".to_vec()), None],
+                result_formats: vec![],
+            })
+        );
+        assert_eq!(
+            read_message(&mut input).await.unwrap(),
+            Some(FrontendMessage::Sync)
+        );
+        assert_eq!(read_message(&mut input).await.unwrap(), None);
+    }
+
+    #[test]
+    fn test_encode_data_row() {
+        let mut buffer = Vec::new();
+        BackendMessage::DataRow(vec![Some("1".to_string()), None]).encode(&mut buffer);
+        assert_eq!(
+            buffer,
+            b"D\x00\x00\x00\x0f\x00\x02\x00\x00\x00\x011\xff\xff\xff\xff".to_vec()
+        );
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use cubeclient::models::V1MetaResponse;
+use log::{error, trace};
+use tokio::sync::{Mutex as AsyncMutex, RwLock};
+
+use crate::compile::TenantContext;
+use crate::CubeError;
+
+#[async_trait]
+pub trait MetaLoader: Send + Sync {
+    async fn load(&self) -> Result<V1MetaResponse, CubeError>;
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetaCacheMetrics {
+    /// Lookups served from a fresh entry.
+    pub hits: u64,
+    /// Lookups served from an expired entry while it is refreshed in background.
+    pub stale_hits: u64,
+    /// Lookups which waited for the meta to load.
+    pub misses: u64,
+    /// Reloads which found a changed meta.
+    pub invalidations: u64,
+    pub errors: u64,
+}
+
+struct MetaCacheEntry {
+    ctx: Arc<TenantContext>,
+    hash: u64,
+    loaded_at: Instant,
+    refreshing: AtomicBool,
+}
+
+/// Per-tenant cache of `TenantContext`. Entries are fresh for `ttl`, during the next `ttl` they
+/// are still served while the meta is reloaded in background, after that they are reloaded
+/// before use. A reload keeps the cached context if the hash of the meta didn't change.
+/// Lookups of a cached tenant share a read lock, only one lookup loads a missing tenant while
+/// the others wait for it.
+pub struct MetaCache {
+    ttl: Duration,
+    entries: RwLock<HashMap<String, MetaCacheEntry>>,
+    loading: Mutex<HashMap<String, Arc<AsyncMutex<()>>>>,
+    hits: AtomicU64,
+    stale_hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl MetaCache {
+    pub fn new(ttl: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            ttl,
+            entries: RwLock::new(HashMap::new()),
+            loading: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            stale_hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+        })
+    }
+
+    pub async fn get_or_load(
+        self: &Arc<Self>,
+        tenant: String,
+        loader: Arc<dyn MetaLoader>,
+    ) -> Result<Arc<TenantContext>, CubeError> {
+        {
+            let entries = self.entries.read().await;
+            if let Some(entry) = entries.get(&tenant) {
+                let age = entry.loaded_at.elapsed();
+                if age < self.ttl {
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+
+                    return Ok(entry.ctx.clone());
+                }
+
+                if age < self.ttl * 2 {
+                    self.stale_hits.fetch_add(1, Ordering::Relaxed);
+                    if !entry.refreshing.swap(true, Ordering::SeqCst) {
+                        let cache = self.clone();
+                        let tenant = tenant.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = cache.refresh(tenant, loader).await {
+                                error!("Unable to refresh meta: {}", e);
+                            }
+                        });
+                    }
+
+                    return Ok(entry.ctx.clone());
+                }
+            }
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let lock = self
+            .loading
+            .lock()
+            .unwrap()
+            .entry(tenant.clone())
+            .or_default()
+            .clone();
+        let result = {
+            let _loading = lock.lock().await;
+            // The lookup holding the lock before may have loaded the tenant already
+            let loaded = match self.entries.read().await.get(&tenant) {
+                Some(entry) if entry.loaded_at.elapsed() < self.ttl => Some(entry.ctx.clone()),
+                _ => None,
+            };
+            match loaded {
+                Some(ctx) => Ok(ctx),
+                None => self.refresh(tenant.clone(), loader).await,
+            }
+        };
+
+        let mut loading = self.loading.lock().unwrap();
+        if Arc::strong_count(&lock) == 2 {
+            loading.remove(&tenant);
+        }
+
+        result
+    }
+
+    /// Loads the meta of `tenant` and updates its entry.
+    pub async fn refresh(
+        &self,
+        tenant: String,
+        loader: Arc<dyn MetaLoader>,
+    ) -> Result<Arc<TenantContext>, CubeError> {
+        let response = match loader.load().await {
+            Ok(response) => response,
+            Err(e) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+                if let Some(entry) = self.entries.read().await.get(&tenant) {
+                    entry.refreshing.store(false, Ordering::SeqCst);
+                }
+
+                return Err(e);
+            }
+        };
+
+        let mut hasher = DefaultHasher::new();
+        serde_json::to_string(&response)?.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let mut entries = self.entries.write().await;
+        let now = Instant::now();
+        let expiration = self.ttl * 2;
+        entries.retain(|_, entry| now.duration_since(entry.loaded_at) < expiration);
+
+        let ctx = match entries.get(&tenant) {
+            Some(entry) if entry.hash == hash => entry.ctx.clone(),
+            cached => {
+                if cached.is_some() {
+                    self.invalidations.fetch_add(1, Ordering::Relaxed);
+                }
+
+                trace!("Meta of tenant changed, hash: {}", hash);
+                Arc::new(TenantContext::new(response.cubes.unwrap_or_default()))
+            }
+        };
+
+        entries.insert(
+            tenant,
+            MetaCacheEntry {
+                ctx: ctx.clone(),
+                hash,
+                loaded_at: now,
+                refreshing: AtomicBool::new(false),
+            },
+        );
+
+        Ok(ctx)
+    }
+
+    pub fn metrics(&self) -> MetaCacheMetrics {
+        MetaCacheMetrics {
+            hits: self.hits.load(Ordering::Relaxed),
+            stale_hits: self.stale_hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use cubeclient::models::V1CubeMeta;
+
+    use super::*;
+
+    struct TestLoader {
+        cubes: Mutex<Vec<String>>,
+        loads: AtomicU64,
+    }
+
+    impl TestLoader {
+        fn new(cubes: Vec<&str>) -> Arc<Self> {
+            Arc::new(Self {
+                cubes: Mutex::new(cubes.into_iter().map(|c| c.to_string()).collect()),
+                loads: AtomicU64::new(0),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl MetaLoader for TestLoader {
+        async fn load(&self) -> Result<V1MetaResponse, CubeError> {
+            self.loads.fetch_add(1, Ordering::SeqCst);
+            tokio::task::yield_now().await;
+            let cubes = self
+                .cubes
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|name| V1CubeMeta {
+                    name: name.clone(),
+                    title: None,
+                    dimensions: vec![],
+                    measures: vec![],
+                    segments: vec![],
+                    connected_component: None,
+                })
+                .collect();
+
+            Ok(V1MetaResponse { cubes: Some(cubes) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_meta_cache_hits() {
+        let cache = MetaCache::new(Duration::from_secs(3600));
+        let loader = TestLoader::new(vec!["Orders"]);
+
+        let first = cache
+            .get_or_load("a".to_string(), loader.clone())
+            .await
+            .unwrap();
+        let second = cache
+            .get_or_load("a".to_string(), loader.clone())
+            .await
+            .unwrap();
+        cache
+            .get_or_load("b".to_string(), loader.clone())
+            .await
+            .unwrap();
+
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(second.find_cube_with_name("Orders".to_string()).is_some());
+        assert_eq!(loader.loads.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            cache.metrics(),
+            MetaCacheMetrics {
+                hits: 1,
+                misses: 2,
+                ..MetaCacheMetrics::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_meta_cache_single_loader() {
+        let cache = MetaCache::new(Duration::from_secs(3600));
+        let loader = TestLoader::new(vec!["Orders"]);
+
+        let contexts = futures::future::join_all(
+            (0..3).map(|_| cache.get_or_load("a".to_string(), loader.clone())),
+        )
+        .await;
+
+        assert_eq!(loader.loads.load(Ordering::SeqCst), 1);
+        for ctx in contexts {
+            assert!(ctx
+                .unwrap()
+                .find_cube_with_name("Orders".to_string())
+                .is_some());
+        }
+        assert!(cache.loading.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_meta_cache_invalidation() {
+        let cache = MetaCache::new(Duration::from_secs(3600));
+        let loader = TestLoader::new(vec!["Orders"]);
+
+        let first = cache
+            .refresh("a".to_string(), loader.clone())
+            .await
+            .unwrap();
+        let same = cache
+            .refresh("a".to_string(), loader.clone())
+            .await
+            .unwrap();
+        assert!(Arc::ptr_eq(&first, &same));
+
+        loader.cubes.lock().unwrap().push("Users".to_string());
+        let changed = cache
+            .refresh("a".to_string(), loader.clone())
+            .await
+            .unwrap();
+        assert!(!Arc::ptr_eq(&first, &changed));
+        assert!(changed.find_cube_with_name("Users".to_string()).is_some());
+        assert_eq!(cache.metrics().invalidations, 1);
+
+        let cached = cache
+            .get_or_load("a".to_string(), loader.clone())
+            .await
+            .unwrap();
+        assert!(Arc::ptr_eq(&changed, &cached));
+    }
+}
@@ -1,17 +1,30 @@
+use std::collections::HashMap;
+
 use cubeclient::models::V1CubeMeta;
 
 pub struct TenantContext {
     pub cubes: Vec<V1CubeMeta>,
+    /// Index of cubes by name.
+    cube_by_name: HashMap<String, usize>,
 }
 
 impl TenantContext {
-    pub fn find_cube_with_name(&self, name: String) -> Option<V1CubeMeta> {
-        for cube in self.cubes.iter() {
-            if cube.name.eq(&name) {
-                return Some(cube.clone());
-            }
+    pub fn new(cubes: Vec<V1CubeMeta>) -> TenantContext {
+        let cube_by_name = cubes
+            .iter()
+            .enumerate()
+            .map(|(i, cube)| (cube.name.clone(), i))
+            .collect();
+
+        TenantContext {
+            cubes,
+            cube_by_name,
         }
+    }
 
-        None
+    pub fn find_cube_with_name(&self, name: String) -> Option<V1CubeMeta> {
+        self.cube_by_name
+            .get(&name)
+            .map(|index| self.cubes[*index].clone())
     }
 }
@@ -1,38 +1,71 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
 
 use cubeclient::apis::{configuration::Configuration, default_api as cube_api};
 use cubeclient::models::{
     V1CubeMeta, V1CubeMetaDimension, V1CubeMetaMeasure, V1CubeMetaSegment, V1LoadRequest,
-    V1LoadRequestQuery, V1LoadResponse,
+    V1LoadRequestQuery, V1LoadResponse, V1MetaResponse,
 };
 use msql_srv::ColumnType;
+use tokio_util::sync::CancellationToken;
 
 use crate::compile::TenantContext;
 use crate::mysql::AuthContext;
+use crate::schema::cache::{MetaCache, MetaCacheMetrics, MetaLoader};
 use crate::CubeError;
 
+pub mod cache;
 pub mod ctx;
+pub mod virtual_table;
 
 #[async_trait]
 pub trait SchemaService: Send + Sync {
-    async fn get_ctx_for_tenant(&self, ctx: &AuthContext) -> Result<TenantContext, CubeError>;
+    async fn get_ctx_for_tenant(&self, ctx: &AuthContext) -> Result<Arc<TenantContext>, CubeError>;
 
+    /// Loads `query`, stops waiting for Cube.js once `cancellation` is cancelled.
     async fn request(
         &self,
         query: V1LoadRequestQuery,
         ctx: &AuthContext,
+        cancellation: CancellationToken,
     ) -> Result<V1LoadResponse, CubeError>;
+
+    /// Loads `queries` with one request, results come back in the same order.
+    async fn request_batch(
+        &self,
+        queries: Vec<V1LoadRequestQuery>,
+        ctx: &AuthContext,
+        cancellation: CancellationToken,
+    ) -> Result<V1LoadResponse, CubeError>;
+
+    /// Counters of meta lookups since the start, all zero for services without a meta cache.
+    fn meta_cache_metrics(&self) -> MetaCacheMetrics {
+        MetaCacheMetrics::default()
+    }
 }
 
-pub struct SchemaServiceDefaultImpl;
+pub struct SchemaServiceDefaultImpl {
+    meta_cache: Arc<MetaCache>,
+    query_timeout: Duration,
+}
 
 impl SchemaServiceDefaultImpl {
+    /// `meta_cache_ttl` is the time in seconds the meta of a tenant is reused for,
+    /// `query_timeout` is the time in seconds a load may wait for Cube.js.
+    pub fn new(meta_cache_ttl: u64, query_timeout: u64) -> Arc<Self> {
+        Arc::new(Self {
+            meta_cache: MetaCache::new(Duration::from_secs(meta_cache_ttl)),
+            query_timeout: Duration::from_secs(query_timeout),
+        })
+    }
+
     fn get_client_config_for_ctx(&self, ctx: &AuthContext) -> Configuration {
         let mut cube_config = Configuration::default();
         cube_config.bearer_access_token = Some(ctx.access_token.clone());
         cube_config.base_path = ctx.base_path.clone();
+        cube_config.load_polling.timeout = Some(self.query_timeout);
 
         cube_config
     }
@@ -40,34 +73,68 @@ impl SchemaServiceDefaultImpl {
 
 crate::di_service!(SchemaServiceDefaultImpl, [SchemaService]);
 
+struct CubeApiMetaLoader {
+    config: Configuration,
+}
+
 #[async_trait]
-impl SchemaService for SchemaServiceDefaultImpl {
-    async fn get_ctx_for_tenant(&self, ctx: &AuthContext) -> Result<TenantContext, CubeError> {
-        let response = cube_api::meta_v1(&self.get_client_config_for_ctx(ctx)).await?;
+impl MetaLoader for CubeApiMetaLoader {
+    async fn load(&self) -> Result<V1MetaResponse, CubeError> {
+        Ok(cube_api::meta_v1(&self.config).await?)
+    }
+}
 
-        let ctx = if let Some(cubes) = response.cubes {
-            TenantContext { cubes }
-        } else {
-            TenantContext { cubes: vec![] }
+#[async_trait]
+impl SchemaService for SchemaServiceDefaultImpl {
+    async fn get_ctx_for_tenant(&self, ctx: &AuthContext) -> Result<Arc<TenantContext>, CubeError> {
+        // Tokens are re-signed with new expiration, the security context stays the same
+        let tenant = match &ctx.security_context {
+            Some(security_context) => format!("{}|{}", ctx.base_path, security_context),
+            None => format!("{}|{}", ctx.base_path, ctx.access_token),
         };
+        let loader = Arc::new(CubeApiMetaLoader {
+            config: self.get_client_config_for_ctx(ctx),
+        });
 
-        Ok(ctx)
+        self.meta_cache.get_or_load(tenant, loader).await
     }
 
     async fn request(
         &self,
         query: V1LoadRequestQuery,
         ctx: &AuthContext,
+        cancellation: CancellationToken,
     ) -> Result<V1LoadResponse, CubeError> {
         let request = V1LoadRequest {
             query: Some(query),
             query_type: Some("multi".to_string()),
         };
+        let response = cube_api::load_v1_with_cancellation(
+            &self.get_client_config_for_ctx(ctx),
+            Some(request),
+            cancellation,
+        )
+        .await?;
+
+        Ok(response)
+    }
+
+    async fn request_batch(
+        &self,
+        queries: Vec<V1LoadRequestQuery>,
+        ctx: &AuthContext,
+        cancellation: CancellationToken,
+    ) -> Result<V1LoadResponse, CubeError> {
         let response =
-            cube_api::load_v1(&self.get_client_config_for_ctx(ctx), Some(request)).await?;
+            cube_api::load_v1_batch(&self.get_client_config_for_ctx(ctx), queries, cancellation)
+                .await?;
 
         Ok(response)
     }
+
+    fn meta_cache_metrics(&self) -> MetaCacheMetrics {
+        self.meta_cache.metrics()
+    }
 }
 
 pub trait V1CubeMetaMeasureExt {
@@ -103,28 +170,46 @@ impl V1CubeMetaMeasureExt for V1CubeMetaMeasure {
     }
 
     fn get_mysql_type(&self) -> ColumnType {
-        let from_type = match &self._type.to_lowercase().as_str() {
-            &"number" => ColumnType::MYSQL_TYPE_DOUBLE,
-            &"boolean" => ColumnType::MYSQL_TYPE_TINY,
-            _ => ColumnType::MYSQL_TYPE_STRING,
-        };
-
         match &self.agg_type {
-            Some(agg_type) => match agg_type.as_str() {
-                "count" => ColumnType::MYSQL_TYPE_LONGLONG,
-                _ => from_type,
-            },
-            _ => from_type,
+            Some(agg_type)
+                if agg_type == "count"
+                    || agg_type == "countDistinct"
+                    || agg_type == "countDistinctApprox" =>
+            {
+                ColumnType::MYSQL_TYPE_LONGLONG
+            }
+            _ => mysql_type_for_member_type(&self._type),
         }
     }
 
     fn mysql_type_as_str(&self) -> String {
-        match self._type.to_lowercase().as_str() {
-            _ => "int".to_string(),
-        }
+        mysql_type_name(self.get_mysql_type())
     }
 }
 
+/// Column type of the values of a member with Cube.js type `member_type`. Numbers are sent as
+/// decimals as Cube.js returns them without loss of precision.
+fn mysql_type_for_member_type(member_type: &str) -> ColumnType {
+    match member_type.to_lowercase().as_str() {
+        "number" => ColumnType::MYSQL_TYPE_NEWDECIMAL,
+        "time" => ColumnType::MYSQL_TYPE_DATETIME,
+        "boolean" => ColumnType::MYSQL_TYPE_TINY,
+        _ => ColumnType::MYSQL_TYPE_STRING,
+    }
+}
+
+/// Type of a column in `SHOW CREATE TABLE` and `information_schema`.
+fn mysql_type_name(column_type: ColumnType) -> String {
+    match column_type {
+        ColumnType::MYSQL_TYPE_LONGLONG => "int",
+        ColumnType::MYSQL_TYPE_NEWDECIMAL => "decimal",
+        ColumnType::MYSQL_TYPE_DATETIME => "datetime",
+        ColumnType::MYSQL_TYPE_TINY => "boolean",
+        _ => "varchar(255)",
+    }
+    .to_string()
+}
+
 pub trait V1CubeMetaSegmentExt {
     fn get_real_name(&self) -> String;
 }
@@ -140,6 +225,8 @@ impl V1CubeMetaSegmentExt for V1CubeMetaSegment {
 pub trait V1CubeMetaDimensionExt {
     fn get_real_name(&self) -> String;
 
+    fn get_mysql_type(&self) -> ColumnType;
+
     fn mysql_can_be_null(&self) -> bool;
 
     fn mysql_type_as_str(&self) -> String;
@@ -152,16 +239,17 @@ impl V1CubeMetaDimensionExt for V1CubeMetaDimension {
         dimension_name.to_string()
     }
 
+    fn get_mysql_type(&self) -> ColumnType {
+        mysql_type_for_member_type(&self._type)
+    }
+
     fn mysql_can_be_null(&self) -> bool {
         // @todo Possible not null?
         true
     }
 
     fn mysql_type_as_str(&self) -> String {
-        match self._type.to_lowercase().as_str() {
-            "time" => "datetime".to_string(),
-            _ => "varchar(255)".to_string(),
-        }
+        mysql_type_name(self.get_mysql_type())
     }
 }
 
@@ -0,0 +1,290 @@
+//! In-memory tables generated from cube metadata, like `information_schema` and `pg_catalog`.
+//! They support simple `SELECT ... FROM <table> WHERE ... ORDER BY ... LIMIT ...` queries.
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use msql_srv::ColumnType;
+use sqlparser::ast;
+use sqlparser::dialect::Dialect;
+use sqlparser::parser::Parser;
+
+use crate::compile::postprocessing::{compare, like};
+use crate::mysql::dataframe::{Column, DataFrame, Row, TableValue};
+use crate::CubeError;
+
+/// Recognizes a select from a single table without joins and returns the table name.
+pub fn parse_single_table_select(
+    query: &str,
+    dialect: &dyn Dialect,
+) -> Option<(ast::ObjectName, ast::Query)> {
+    let mut statements = Parser::parse_sql(dialect, query).ok()?;
+    if statements.len() != 1 {
+        return None;
+    }
+    let query = match statements.remove(0) {
+        ast::Statement::Query(q) => *q,
+        _ => return None,
+    };
+    let name = match &query.body {
+        ast::SetExpr::Select(select) if select.from.len() == 1 => {
+            let from = &select.from[0];
+            if !from.joins.is_empty() {
+                return None;
+            }
+            match &from.relation {
+                ast::TableFactor::Table { name, .. } => name.clone(),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    Some((name, query))
+}
+
+pub struct VirtualTable {
+    columns: Vec<Column>,
+    rows: Vec<Vec<TableValue>>,
+    /// Values of argument-less functions, like `database()`, available in expressions.
+    functions: HashMap<String, TableValue>,
+}
+
+impl VirtualTable {
+    pub fn new(columns: Vec<Column>, rows: Vec<Vec<TableValue>>) -> VirtualTable {
+        VirtualTable {
+            columns,
+            rows,
+            functions: HashMap::new(),
+        }
+    }
+
+    pub fn with_function(mut self, name: &str, value: TableValue) -> VirtualTable {
+        self.functions.insert(name.to_lowercase(), value);
+        self
+    }
+
+    /// Runs `query` over the table. The `FROM` clause isn't checked, callers pick the table.
+    pub fn select(&self, query: &ast::Query) -> Result<DataFrame, CubeError> {
+        let select = match &query.body {
+            ast::SetExpr::Select(select) => select,
+            _ => {
+                return Err(CubeError::user(format!(
+                    "Unsupported query over virtual table: {}",
+                    query
+                )))
+            }
+        };
+
+        let mut rows = Vec::new();
+        for row in self.rows.iter() {
+            let matches = match &select.selection {
+                Some(selection) => self.eval(selection, row)? == TableValue::Boolean(true),
+                None => true,
+            };
+            if matches {
+                rows.push(row);
+            }
+        }
+
+        for order in query.order_by.iter().rev() {
+            let mut keyed = rows
+                .into_iter()
+                .map(|row| Ok((self.eval(&order.expr, row)?, row)))
+                .collect::<Result<Vec<_>, CubeError>>()?;
+            keyed.sort_by(|(a, _), (b, _)| {
+                let ordering = compare(a, b).unwrap_or(Ordering::Equal);
+                if order.asc == Some(false) {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            });
+            rows = keyed.into_iter().map(|(_, row)| row).collect();
+        }
+
+        if let Some(limit) = &query.limit {
+            let limit = limit.to_string().parse::<usize>().map_err(|e| {
+                CubeError::user(format!("Unable to parse limit: {}", e.to_string()))
+            })?;
+            rows.truncate(limit);
+        }
+
+        let mut result_columns = Vec::new();
+        let mut projection: Vec<&ast::Expr> = Vec::new();
+        let wildcard_exprs = self
+            .columns
+            .iter()
+            .map(|c| ast::Expr::Identifier(ast::Ident::new(c.get_name())))
+            .collect::<Vec<_>>();
+        for item in select.projection.iter() {
+            match item {
+                ast::SelectItem::Wildcard | ast::SelectItem::QualifiedWildcard(_) => {
+                    result_columns.extend(self.columns.iter().cloned());
+                    projection.extend(wildcard_exprs.iter());
+                }
+                ast::SelectItem::UnnamedExpr(expr) => {
+                    result_columns.push(self.describe(expr, None)?);
+                    projection.push(expr);
+                }
+                ast::SelectItem::ExprWithAlias { expr, alias } => {
+                    result_columns.push(self.describe(expr, Some(&alias.value))?);
+                    projection.push(expr);
+                }
+            }
+        }
+
+        let rows = rows
+            .iter()
+            .map(|row| {
+                Ok(Row::new(
+                    projection
+                        .iter()
+                        .map(|e| self.eval(e, row))
+                        .collect::<Result<Vec<_>, CubeError>>()?,
+                ))
+            })
+            .collect::<Result<Vec<_>, CubeError>>()?;
+
+        Ok(DataFrame::new(result_columns, rows))
+    }
+
+    fn column_index(&self, ident: &ast::Ident) -> Result<usize, CubeError> {
+        self.columns
+            .iter()
+            .position(|c| c.get_name().eq_ignore_ascii_case(&ident.value))
+            .ok_or_else(|| CubeError::user(format!("Unknown column: {}", ident.value)))
+    }
+
+    fn function_value(&self, f: &ast::Function) -> Result<TableValue, CubeError> {
+        let name = f.name.to_string().to_lowercase();
+        match self.functions.get(&name) {
+            Some(value) if f.args.is_empty() => Ok(value.clone()),
+            _ => Err(CubeError::user(format!(
+                "Unsupported function in catalog query: {}",
+                f
+            ))),
+        }
+    }
+
+    fn describe(&self, expr: &ast::Expr, alias: Option<&String>) -> Result<Column, CubeError> {
+        let column = match expr {
+            ast::Expr::Identifier(i) => self.columns[self.column_index(i)?].clone(),
+            ast::Expr::CompoundIdentifier(i) => {
+                self.columns[self.column_index(i.last().unwrap())?].clone()
+            }
+            ast::Expr::Value(ast::Value::Number(..)) => {
+                Column::new("?column?".to_string(), ColumnType::MYSQL_TYPE_LONGLONG)
+            }
+            ast::Expr::Value(_) => {
+                Column::new("?column?".to_string(), ColumnType::MYSQL_TYPE_STRING)
+            }
+            ast::Expr::Function(f) => {
+                self.function_value(f)?;
+                Column::new(f.to_string(), ColumnType::MYSQL_TYPE_STRING)
+            }
+            e => {
+                return Err(CubeError::user(format!(
+                    "Unsupported expression in catalog query: {}",
+                    e
+                )))
+            }
+        };
+        Ok(match alias {
+            Some(alias) => Column::new(alias.clone(), column.get_type()),
+            None => column,
+        })
+    }
+
+    fn eval(&self, expr: &ast::Expr, row: &Vec<TableValue>) -> Result<TableValue, CubeError> {
+        Ok(match expr {
+            ast::Expr::Identifier(i) => row[self.column_index(i)?].clone(),
+            ast::Expr::CompoundIdentifier(i) => row[self.column_index(i.last().unwrap())?].clone(),
+            ast::Expr::Value(value) => match value {
+                ast::Value::Number(n, _) => match n.parse::<i64>() {
+                    Ok(v) => TableValue::Int64(v),
+                    Err(_) => TableValue::Float64(n.parse::<f64>()?),
+                },
+                ast::Value::SingleQuotedString(s) => TableValue::String(s.clone()),
+                ast::Value::Boolean(b) => TableValue::Boolean(*b),
+                ast::Value::Null => TableValue::Null,
+                v => {
+                    return Err(CubeError::user(format!(
+                        "Unsupported value in catalog query: {}",
+                        v
+                    )))
+                }
+            },
+            ast::Expr::Function(f) => self.function_value(f)?,
+            ast::Expr::Nested(e) => self.eval(e, row)?,
+            // Casts like 'public'::regnamespace are compared by their text value.
+            ast::Expr::Cast { expr, .. } => self.eval(expr, row)?,
+            ast::Expr::IsNull(e) => TableValue::Boolean(self.eval(e, row)? == TableValue::Null),
+            ast::Expr::IsNotNull(e) => TableValue::Boolean(self.eval(e, row)? != TableValue::Null),
+            ast::Expr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                let value = self.eval(expr, row)?;
+                let mut found = false;
+                for item in list {
+                    if compare(&value, &self.eval(item, row)?) == Some(Ordering::Equal) {
+                        found = true;
+                    }
+                }
+                TableValue::Boolean(found != *negated)
+            }
+            ast::Expr::UnaryOp {
+                op: ast::UnaryOperator::Not,
+                expr,
+            } => match self.eval(expr, row)? {
+                TableValue::Boolean(b) => TableValue::Boolean(!b),
+                _ => TableValue::Null,
+            },
+            ast::Expr::BinaryOp { left, op, right } => {
+                let left = self.eval(left, row)?;
+                let right = self.eval(right, row)?;
+                match op {
+                    ast::BinaryOperator::And => TableValue::Boolean(
+                        left == TableValue::Boolean(true) && right == TableValue::Boolean(true),
+                    ),
+                    ast::BinaryOperator::Or => TableValue::Boolean(
+                        left == TableValue::Boolean(true) || right == TableValue::Boolean(true),
+                    ),
+                    ast::BinaryOperator::Like | ast::BinaryOperator::NotLike => {
+                        match (&left, &right) {
+                            (TableValue::String(value), TableValue::String(pattern)) => {
+                                TableValue::Boolean(
+                                    like(value, pattern) == (*op == ast::BinaryOperator::Like),
+                                )
+                            }
+                            _ => TableValue::Null,
+                        }
+                    }
+                    op => match compare(&left, &right) {
+                        None => TableValue::Null,
+                        Some(ordering) => TableValue::Boolean(match op {
+                            ast::BinaryOperator::Eq => ordering == Ordering::Equal,
+                            ast::BinaryOperator::NotEq => ordering != Ordering::Equal,
+                            ast::BinaryOperator::Lt => ordering == Ordering::Less,
+                            ast::BinaryOperator::LtEq => ordering != Ordering::Greater,
+                            ast::BinaryOperator::Gt => ordering == Ordering::Greater,
+                            ast::BinaryOperator::GtEq => ordering != Ordering::Less,
+                            op => {
+                                return Err(CubeError::user(format!(
+                                    "Unsupported operator in catalog query: {}",
+                                    op
+                                )))
+                            }
+                        }),
+                    },
+                }
+            }
+            e => {
+                return Err(CubeError::user(format!(
+                    "Unsupported expression in catalog query: {}",
+                    e
+                )))
+            }
+        })
+    }
+}
@@ -5,7 +5,7 @@ use cubestore::queryplanner::pretty_printers::{pp_phys_plan, pp_phys_plan_ext, P
 use cubestore::queryplanner::MIN_TOPK_STREAM_ROWS;
 use cubestore::sql::timestamp_from_string;
 use cubestore::store::DataFrame;
-use cubestore::table::{Row, TableValue, TimestampValue};
+use cubestore::table::{DateValue, Row, TableValue, TimestampValue};
 use cubestore::util::decimal::Decimal;
 use itertools::Itertools;
 use pretty_assertions::assert_eq;
@@ -93,6 +93,7 @@ pub fn sql_tests() -> Vec<(&'static str, TestFn)> {
         t("offset", offset),
         t("having", having),
         t("rolling_window_join", rolling_window_join),
+        t("rolling_window_range_join", rolling_window_range_join),
         t("rolling_window_query", rolling_window_query),
         t("rolling_window_exprs", rolling_window_exprs),
         t(
@@ -115,10 +116,23 @@ pub fn sql_tests() -> Vec<(&'static str, TestFn)> {
         t("decimal_index", decimal_index),
         t("float_index", float_index),
         t("date_add", date_add),
+        t("time_zones", time_zones),
+        t("string_functions", string_functions),
+        t("window_functions", window_functions),
+        t("planning_window_sort", planning_window_sort),
         t("now", now),
         t("dump", dump),
         t("unsorted_merge_assertion", unsorted_merge_assertion),
         t("unsorted_data_timestamps", unsorted_data_timestamps),
+        t(
+            "create_index_on_existing_data",
+            create_index_on_existing_data,
+        ),
+        t("index_recommendations", index_recommendations),
+        t("date_int32_decimal128", date_int32_decimal128),
+        t("insert_batch_id", insert_batch_id),
+        t("create_table_with_batch_id", create_table_with_batch_id),
+        t("swap_tables", swap_tables),
     ];
 
     fn t<F>(name: &'static str, f: fn(Box<dyn SqlClient>) -> F) -> (&'static str, TestFn)
@@ -2775,14 +2789,15 @@ async fn rolling_window_join(service: Box<dyn SqlClient>) {
         pp_phys_plan(plan.as_ref()),
         "Sort\
       \n  Projection, [date_to, name, SUM(Table.n)@2:n]\
-      \n    CrossJoinAgg, on: day@1 <= date_to@0\
-      \n      Projection, [datetrunc(Utf8(\"day\"),converttz(s.Data.day,Utf8(\"+00:00\")))@0:day, name, SUM(s.Data.n)@2:n]\
-      \n        FinalHashAggregate\
-      \n          Worker\
-      \n            PartialHashAggregate\
-      \n              Merge\
-      \n                Scan, index: default:1:[1], fields: *\
-      \n                  Empty"
+      \n    RangeJoinAgg, on: day@0 <= date_to@1\
+      \n      Sort\
+      \n        Projection, [datetrunc(Utf8(\"day\"),converttz(s.Data.day,Utf8(\"+00:00\")))@0:day, name, SUM(s.Data.n)@2:n]\
+      \n          FinalHashAggregate\
+      \n            Worker\
+      \n              PartialHashAggregate\
+      \n                Merge\
+      \n                  Scan, index: default:1:[1], fields: *\
+      \n                    Empty"
     );
 
     let plan = service
@@ -2795,14 +2810,15 @@ async fn rolling_window_join(service: Box<dyn SqlClient>) {
         "Sort\
         \n  Projection, [date_to, name, n]\
         \n    Projection, [date_to, name, SUM(Table.n)@2:n]\
-        \n      CrossJoinAgg, on: day@1 <= date_to@0\
-        \n        Projection, [datetrunc(Utf8(\"day\"),converttz(s.Data.day,Utf8(\"+00:00\")))@0:day, name, SUM(s.Data.n)@2:n]\
-        \n          FinalHashAggregate\
-        \n            Worker\
-        \n              PartialHashAggregate\
-        \n                Merge\
-        \n                  Scan, index: default:1:[1], fields: *\
-        \n                    Empty"
+        \n      RangeJoinAgg, on: day@0 <= date_to@1\
+        \n        Sort\
+        \n          Projection, [datetrunc(Utf8(\"day\"),converttz(s.Data.day,Utf8(\"+00:00\")))@0:day, name, SUM(s.Data.n)@2:n]\
+        \n            FinalHashAggregate\
+        \n              Worker\
+        \n                PartialHashAggregate\
+        \n                  Merge\
+        \n                    Scan, index: default:1:[1], fields: *\
+        \n                      Empty"
     );
 
     service
@@ -2840,6 +2856,78 @@ async fn rolling_window_join(service: Box<dyn SqlClient>) {
     }
 }
 
+async fn rolling_window_range_join(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query("CREATE TABLE s.Data(day int, name text, n int)")
+        .await
+        .unwrap();
+    service
+        .exec_query(
+            "INSERT INTO s.Data(day, name, n) VALUES (1, 'john', 10), \
+                                                     (1, 'sara', 7), \
+                                                     (3, 'sara', 3), \
+                                                     (3, 'john', 9), \
+                                                     (3, 'john', 11), \
+                                                     (5, 'timmy', 5), \
+                                                     (NULL, 'john', 100)",
+        )
+        .await
+        .unwrap();
+
+    let query = "SELECT Series.date_to, Table.name, sum(Table.n) as n FROM (\
+                   SELECT 0 date_from, 1 date_to \
+                   UNION ALL SELECT 1 date_from, 2 date_to \
+                   UNION ALL SELECT 2 date_from, 3 date_to \
+                   UNION ALL SELECT 4 date_from, 5 date_to \
+                   UNION ALL SELECT 6 date_from, 7 date_to\
+                 ) AS `Series` \
+                 LEFT JOIN (\
+                   SELECT day, name, sum(n) `n` FROM s.Data GROUP BY 1, 2\
+                 ) AS `Table` ON `Series`.date_from < `Table`.day AND `Table`.day <= `Series`.date_to \
+                 GROUP BY 1, 2 \
+                 ORDER BY 1, 2";
+    let plan = pp_phys_plan(service.plan_query(query).await.unwrap().worker.as_ref());
+    assert!(
+        plan.contains("RangeJoinAgg, on: date_from@0 < day@0 <= date_to@1"),
+        "{}",
+        plan
+    );
+
+    let r = service.exec_query(query).await.unwrap();
+    assert_eq!(
+        to_rows(&r),
+        rows(&[
+            (1, Some("john"), Some(10)),
+            (1, Some("sara"), Some(7)),
+            (2, None, None),
+            (3, Some("john"), Some(20)),
+            (3, Some("sara"), Some(3)),
+            (5, Some("timmy"), Some(5)),
+            (7, None, None),
+        ])
+    );
+
+    // Overlapping and nested windows.
+    let query = "SELECT Series.date_to, sum(Table.n) as n FROM (\
+                   SELECT 0 date_from, 3 date_to \
+                   UNION ALL SELECT 1 date_from, 4 date_to \
+                   UNION ALL SELECT 2 date_from, 5 date_to \
+                   UNION ALL SELECT 4 date_from, 7 date_to \
+                   UNION ALL SELECT 0 date_from, 10 date_to\
+                 ) AS `Series` \
+                 LEFT JOIN (\
+                   SELECT day, name, sum(n) `n` FROM s.Data GROUP BY 1, 2\
+                 ) AS `Table` ON `Series`.date_from < `Table`.day AND `Table`.day <= `Series`.date_to \
+                 GROUP BY 1 \
+                 ORDER BY 1";
+    let r = service.exec_query(query).await.unwrap();
+    assert_eq!(
+        to_rows(&r),
+        rows(&[(3, 40), (4, 23), (5, 28), (7, 5), (10, 45)])
+    );
+}
+
 async fn rolling_window_query(service: Box<dyn SqlClient>) {
     service.exec_query("CREATE SCHEMA s").await.unwrap();
     service
@@ -3784,6 +3872,414 @@ async fn unsorted_data_timestamps(service: Box<dyn SqlClient>) {
     assert_eq!(to_rows(&r), rows(&[(t, "a"), (t, "b"), (t, "c")]));
 }
 
+async fn time_zones(service: Box<dyn SqlClient>) {
+    let check = |expr: &str, expected| {
+        let expected = timestamp_from_string(expected).unwrap();
+        let service = &service;
+        let query = format!("SELECT {}", expr);
+        async move {
+            let actual = service.exec_query(&query).await.unwrap();
+            assert_eq!(to_rows(&actual), rows(&[expected]), "{}", query);
+        }
+    };
+
+    // Truncation in local time.
+    check(
+        "date_trunc('quarter', convert_tz(CAST('2021-04-01T03:00:00Z' as TIMESTAMP), 'America/New_York'))",
+        "2021-01-01T00:00:00Z",
+    )
+    .await;
+    check(
+        "date_trunc('week', convert_tz(CAST('2021-03-15T03:00:00Z' as TIMESTAMP), 'America/New_York'))",
+        "2021-03-08T00:00:00Z",
+    )
+    .await;
+    check(
+        "date_trunc('month', convert_tz(CAST('2021-01-01T02:00:00Z' as TIMESTAMP), 'America/Los_Angeles'))",
+        "2020-12-01T00:00:00Z",
+    )
+    .await;
+    check(
+        "date_trunc('year', convert_tz(CAST('2020-12-31T16:00:00Z' as TIMESTAMP), 'Asia/Tokyo'))",
+        "2021-01-01T00:00:00Z",
+    )
+    .await;
+    check(
+        "date_trunc('quarter', CAST('2021-08-20T10:00:00Z' as TIMESTAMP))",
+        "2021-07-01T00:00:00Z",
+    )
+    .await;
+    // Fixed offsets still work.
+    check(
+        "convert_tz(CAST('2021-01-01T00:00:00Z' as TIMESTAMP), '+00:00')",
+        "2021-01-01T00:00:00Z",
+    )
+    .await;
+
+    // Arithmetic keeps the local time across DST transitions.
+    check(
+        "date_add(CAST('2021-03-13T17:00:00Z' as TIMESTAMP), INTERVAL '1 day', 'America/New_York')",
+        "2021-03-14T16:00:00Z",
+    )
+    .await;
+    check(
+        "date_add(CAST('2021-10-15T04:00:00Z' as TIMESTAMP), INTERVAL '1 month', 'America/New_York')",
+        "2021-11-15T05:00:00Z",
+    )
+    .await;
+    check(
+        "date_sub(CAST('2021-11-15T05:00:00Z' as TIMESTAMP), INTERVAL '1 month', 'America/New_York')",
+        "2021-10-15T04:00:00Z",
+    )
+    .await;
+    // 02:30 does not exist on 2021-03-14 in New York and becomes 03:30.
+    check(
+        "date_add(CAST('2021-03-13T07:30:00Z' as TIMESTAMP), INTERVAL '1 day', 'America/New_York')",
+        "2021-03-14T07:30:00Z",
+    )
+    .await;
+    // 01:30 happens twice on 2021-11-07 in New York, the earlier one is used.
+    check(
+        "date_add(CAST('2021-11-06T05:30:00Z' as TIMESTAMP), INTERVAL '1 day', 'America/New_York')",
+        "2021-11-07T05:30:00Z",
+    )
+    .await;
+
+    service
+        .exec_query("SELECT convert_tz(CAST('2021-01-01T00:00:00Z' as TIMESTAMP), 'Mars/Olympus')")
+        .await
+        .unwrap_err();
+    service
+        .exec_query("SELECT date_add(CAST('2021-01-01T00:00:00Z' as TIMESTAMP), INTERVAL '1 day', 'Mars/Olympus')")
+        .await
+        .unwrap_err();
+
+    // Must work on columnar data, evaluated on workers.
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query("CREATE TABLE s.data(t timestamp, n int)")
+        .await
+        .unwrap();
+    service
+        .exec_query(
+            "INSERT INTO s.data(t, n) VALUES ('2021-03-31T23:00:00Z', 1), ('2021-04-01T05:00:00Z', 2), \
+                                              ('2021-07-01T03:00:00Z', 3), (NULL, 4)",
+        )
+        .await
+        .unwrap();
+    let r = service
+        .exec_query(
+            "SELECT date_trunc('quarter', convert_tz(t, 'America/New_York')) q, sum(n) \
+             FROM s.data GROUP BY 1 ORDER BY 1",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&r),
+        rows(&[
+            (None, 4),
+            (
+                Some(timestamp_from_string("2021-01-01T00:00:00Z").unwrap()),
+                1
+            ),
+            (
+                Some(timestamp_from_string("2021-04-01T00:00:00Z").unwrap()),
+                5
+            ),
+        ]),
+    );
+}
+
+async fn string_functions(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query("CREATE TABLE s.data(id int, s text)")
+        .await
+        .unwrap();
+    service
+        .exec_query(
+            "INSERT INTO s.data(id, s) VALUES (1, 'www.mysql.com'), (2, '10% off'), \
+                                              (3, '100 off'), (4, 'a_b'), (5, NULL)",
+        )
+        .await
+        .unwrap();
+
+    let r = service
+        .exec_query(
+            "SELECT id, substring(s, 5), substring(s, -3, 2), substring_index(s, '.', -2) \
+             FROM s.data ORDER BY id",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&r),
+        rows(&[
+            (1, Some("mysql.com"), Some("co"), Some("mysql.com")),
+            (2, Some("off"), Some("of"), Some("10% off")),
+            (3, Some("off"), Some("of"), Some("100 off")),
+            (4, Some(""), Some("a_"), Some("a_b")),
+            (5, None, None, None),
+        ])
+    );
+
+    let r = service
+        .exec_query(
+            "SELECT id, locate('o', s), locate('o', s, 7), instr(s, 'off') \
+             FROM s.data ORDER BY id",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&r),
+        rows(&[
+            (1, Some(12), Some(12), Some(0)),
+            (2, Some(5), Some(0), Some(5)),
+            (3, Some(5), Some(0), Some(5)),
+            (4, Some(0), Some(0), Some(0)),
+            (5, None, None, None),
+        ])
+    );
+
+    let r = service
+        .exec_query(
+            "SELECT id, regexp_like(s, '^[0-9]+ '), regexp_substr(s, '[0-9]+') \
+             FROM s.data ORDER BY id",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&r),
+        rows(&[
+            (1, Some(false), None),
+            (2, Some(false), Some("10")),
+            (3, Some(true), Some("100")),
+            (4, Some(false), None),
+            (5, None, None),
+        ])
+    );
+
+    let r = service
+        .exec_query(
+            "SELECT id, regexp_replace(s, '([0-9]+)', '<$1>'), split_part(s, '.', 2), \
+                    split_part(s, ' ', -1) \
+             FROM s.data ORDER BY id",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&r),
+        rows(&[
+            (
+                1,
+                Some("www.mysql.com"),
+                Some("mysql"),
+                Some("www.mysql.com")
+            ),
+            (2, Some("<10>% off"), Some(""), Some("off")),
+            (3, Some("<100> off"), Some(""), Some("off")),
+            (4, Some("a_b"), Some(""), Some("a_b")),
+            (5, None, None, None),
+        ])
+    );
+
+    // Built-in string functions are computed on workers, not on the router.
+    let r = service
+        .exec_query("SELECT id, lower(s), concat(s, '!') FROM s.data ORDER BY id")
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&r),
+        rows(&[
+            (1, Some("www.mysql.com"), Some("www.mysql.com!")),
+            (2, Some("10% off"), Some("10% off!")),
+            (3, Some("100 off"), Some("100 off!")),
+            (4, Some("a_b"), Some("a_b!")),
+            (5, None, None),
+        ])
+    );
+    let p = service
+        .plan_query("SELECT lower(s), concat(s, '!'), split_part(s, '.', 2) FROM s.data")
+        .await
+        .unwrap();
+    assert_eq!(
+        pp_phys_plan(p.router.as_ref()),
+        "ClusterSend, partitions: [[1]]"
+    );
+    let worker = pp_phys_plan(p.worker.as_ref());
+    assert!(
+        worker.starts_with("Worker\n  Projection, [lower(s"),
+        "unexpected worker plan: {}",
+        worker
+    );
+    assert!(
+        worker.contains("concat(s"),
+        "unexpected worker plan: {}",
+        worker
+    );
+    assert!(
+        worker.contains("SPLIT_PART(s"),
+        "unexpected worker plan: {}",
+        worker
+    );
+
+    // Escapes in LIKE patterns.
+    let r = service
+        .exec_query("SELECT id FROM s.data WHERE s LIKE '10\\%%' ORDER BY id")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[2]));
+    let r = service
+        .exec_query("SELECT id FROM s.data WHERE s NOT LIKE '%\\_%' ORDER BY id")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[1, 2, 3]));
+    let r = service
+        .exec_query("SELECT id FROM s.data WHERE s LIKE '10%' ORDER BY id")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[2, 3]));
+
+    // Scalar arguments.
+    let r = service
+        .exec_query("SELECT substring('Sakila', -5, 3), locate('bar', 'foobarbar', 5)")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[("aki", 7)]));
+
+    service
+        .exec_query("SELECT regexp_like(s, '(') FROM s.data")
+        .await
+        .unwrap_err();
+}
+
+async fn window_functions(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query("CREATE TABLE s.Data(url text, day int, hits int)")
+        .await
+        .unwrap();
+    service
+        .exec_query(
+            "INSERT INTO s.Data(url, day, hits) VALUES ('a', 1, 10), ('a', 2, 20), ('a', 3, 30), \
+                                                       ('b', 1, 5), ('b', 2, 15)",
+        )
+        .await
+        .unwrap();
+
+    // Running totals.
+    let r = service
+        .exec_query(
+            "SELECT url, day, SUM(hits) OVER (PARTITION BY url ORDER BY day), \
+                    ROW_NUMBER() OVER (PARTITION BY url ORDER BY day) \
+             FROM s.Data ORDER BY 1, 2",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&r),
+        rows(&[
+            ("a", 1, 10, 1),
+            ("a", 2, 30, 2),
+            ("a", 3, 60, 3),
+            ("b", 1, 5, 1),
+            ("b", 2, 20, 2),
+        ])
+    );
+
+    // Moving averages.
+    let r = service
+        .exec_query(
+            "SELECT url, day, AVG(hits) OVER (PARTITION BY url ORDER BY day \
+                                              ROWS BETWEEN 1 PRECEDING AND CURRENT ROW) \
+             FROM s.Data ORDER BY 1, 2",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&r),
+        rows(&[
+            ("a", 1, 10.),
+            ("a", 2, 15.),
+            ("a", 3, 25.),
+            ("b", 1, 5.),
+            ("b", 2, 10.),
+        ])
+    );
+
+    let r = service
+        .exec_query(
+            "SELECT url, day, LAG(hits) OVER (PARTITION BY url ORDER BY day), \
+                              LEAD(hits) OVER (PARTITION BY url ORDER BY day) \
+             FROM s.Data ORDER BY 1, 2",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&r),
+        rows(&[
+            ("a", 1, None, Some(20)),
+            ("a", 2, Some(10), Some(30)),
+            ("a", 3, Some(20), None),
+            ("b", 1, None, Some(15)),
+            ("b", 2, Some(5), None),
+        ])
+    );
+
+    let r = service
+        .exec_query(
+            "SELECT url, day, RANK() OVER (ORDER BY hits), COUNT(*) OVER (PARTITION BY url) \
+             FROM s.Data ORDER BY 1, 2",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&r),
+        rows(&[
+            ("a", 1, 2, 3),
+            ("a", 2, 4, 3),
+            ("a", 3, 5, 3),
+            ("b", 1, 1, 2),
+            ("b", 2, 3, 2),
+        ])
+    );
+
+    let r = service
+        .exec_query(
+            "SELECT url, MIN(hits) OVER (PARTITION BY url), MAX(hits) OVER (PARTITION BY url) \
+             FROM s.Data WHERE day = 1 ORDER BY 1",
+        )
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[("a", 10, 10), ("b", 5, 5)]));
+}
+
+async fn planning_window_sort(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query("CREATE TABLE s.Data(url text, day int, hits int)")
+        .await
+        .unwrap();
+
+    let sorts = |p: &str| p.lines().filter(|l| l.trim() == "Sort").count();
+
+    // The default index is sorted by (url, day), no need to sort for the window.
+    let p = service
+        .plan_query("SELECT url, day, SUM(hits) OVER (PARTITION BY url ORDER BY day) FROM s.Data")
+        .await
+        .unwrap();
+    let router = pp_phys_plan(p.router.as_ref());
+    assert!(router.contains("Window"), "{}", router);
+    assert_eq!(sorts(&router), 0, "{}", router);
+
+    // Ordering does not match the index.
+    let p = service
+        .plan_query("SELECT url, day, SUM(hits) OVER (PARTITION BY url ORDER BY hits) FROM s.Data")
+        .await
+        .unwrap();
+    let router = pp_phys_plan(p.router.as_ref());
+    assert_eq!(sorts(&router), 1, "{}", router);
+}
+
 async fn now(service: Box<dyn SqlClient>) {
     let r = service.exec_query("SELECT now()").await.unwrap();
     assert_eq!(r.get_rows().len(), 1);
@@ -3853,6 +4349,374 @@ async fn dump(service: Box<dyn SqlClient>) {
     );
 }
 
+async fn create_index_on_existing_data(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query("CREATE TABLE s.Data(a int, b int)")
+        .await
+        .unwrap();
+    service
+        .exec_query("INSERT INTO s.Data(a, b) VALUES (1, 10), (2, 20), (3, 10)")
+        .await
+        .unwrap();
+    service
+        .exec_query("CREATE INDEX by_b ON s.Data(b)")
+        .await
+        .unwrap();
+    // Rows inserted while the index is being built must not be lost or duplicated.
+    service
+        .exec_query("INSERT INTO s.Data(a, b) VALUES (4, 20), (5, 30)")
+        .await
+        .unwrap();
+
+    // The index is used once the background job copies existing data into it.
+    let mut attempts = 0;
+    loop {
+        let p = service
+            .plan_query("SELECT b, sum(a) FROM s.Data GROUP BY 1")
+            .await
+            .unwrap();
+        if pp_phys_plan(p.worker.as_ref()).contains("index: by_b") {
+            break;
+        }
+        attempts += 1;
+        assert!(attempts < 100, "index was not built in time");
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+
+    let r = service
+        .exec_query("SELECT b, sum(a) FROM s.Data GROUP BY 1 ORDER BY 1")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[(10, 4), (20, 6), (30, 5)]));
+}
+
+async fn index_recommendations(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query("CREATE TABLE s.Orders(id int, customer int, amount int)")
+        .await
+        .unwrap();
+    service
+        .exec_query("CREATE TABLE s.Customers(id int, name text)")
+        .await
+        .unwrap();
+    service
+        .exec_query("INSERT INTO s.Orders(id, customer, amount) VALUES (1, 1, 10), (2, 2, 20)")
+        .await
+        .unwrap();
+    service
+        .exec_query("INSERT INTO s.Customers(id, name) VALUES (1, 'a'), (2, 'b')")
+        .await
+        .unwrap();
+
+    for _ in 0..2 {
+        service
+            .exec_query(
+                "SELECT name, sum(amount) FROM s.Orders o \
+                 JOIN s.Customers c ON o.customer = c.id GROUP BY 1",
+            )
+            .await
+            .unwrap();
+    }
+    service
+        .exec_query("SELECT id FROM s.Customers WHERE name = 'a'")
+        .await
+        .unwrap();
+
+    // Patterns are recorded in the background.
+    let mut attempts = 0;
+    let r = loop {
+        let r = service
+            .exec_query("SHOW INDEX RECOMMENDATIONS")
+            .await
+            .unwrap();
+        if r.get_rows().len() == 2 && r.get_rows()[0].values()[4] == TableValue::Int(2) {
+            break r;
+        }
+        attempts += 1;
+        assert!(attempts < 100, "query patterns were not recorded in time");
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    };
+    let recommendations = r
+        .get_rows()
+        .iter()
+        .map(|r| match (&r.values()[1], &r.values()[2], &r.values()[6]) {
+            (TableValue::String(t), TableValue::String(k), TableValue::String(c)) => {
+                (t.clone(), k.clone(), c.clone())
+            }
+            _ => panic!("unexpected row: {:?}", r),
+        })
+        .collect_vec();
+    assert_eq!(
+        recommendations,
+        vec![
+            (
+                "Orders".to_string(),
+                "Join".to_string(),
+                "CREATE INDEX Orders_customer ON s.Orders (customer)".to_string()
+            ),
+            (
+                "Customers".to_string(),
+                "Filter".to_string(),
+                "CREATE INDEX Customers_name ON s.Customers (name)".to_string()
+            ),
+        ]
+    );
+
+    // Recommendations covered by an index are no longer shown.
+    service
+        .exec_query("CREATE INDEX Orders_customer ON s.Orders (customer)")
+        .await
+        .unwrap();
+    let r = service
+        .exec_query("SHOW INDEX RECOMMENDATIONS")
+        .await
+        .unwrap();
+    assert_eq!(r.get_rows().len(), 1);
+    assert_eq!(
+        r.get_rows()[0].values()[2],
+        TableValue::String("Filter".to_string())
+    );
+}
+
+async fn date_int32_decimal128(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query("CREATE TABLE s.Data(d date, i int32, b int, n decimal(38, 10))")
+        .await
+        .unwrap();
+    service
+        .exec_query(
+            "INSERT INTO s.Data(d, i, b, n) VALUES \
+             ('2021-01-02', -2, 1, '-1.5'), \
+             ('2021-01-01', 1, 5000000000, '12345678901234567890.0123456789'), \
+             (NULL, NULL, NULL, NULL)",
+        )
+        .await
+        .unwrap();
+
+    let r = service
+        .exec_query("SELECT d, i, b, n FROM s.Data ORDER BY d")
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&r),
+        vec![
+            vec![
+                TableValue::Null,
+                TableValue::Null,
+                TableValue::Null,
+                TableValue::Null
+            ],
+            vec![
+                TableValue::Date(DateValue::new(18628)),
+                TableValue::Int(1),
+                TableValue::Int(5000000000),
+                TableValue::Decimal(Decimal::new_i128(123456789012345678900123456789))
+            ],
+            vec![
+                TableValue::Date(DateValue::new(18629)),
+                TableValue::Int(-2),
+                TableValue::Int(1),
+                TableValue::Decimal(Decimal::new_i128(-15000000000))
+            ],
+        ]
+    );
+
+    let r = service
+        .exec_query("SELECT i FROM s.Data WHERE d >= CAST('2021-01-02' AS DATE)")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[-2]));
+
+    let r = service
+        .exec_query("SELECT SUM(n) FROM s.Data")
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&r),
+        vec![vec![TableValue::Decimal(Decimal::new_i128(
+            123456789012345678885123456789
+        ))]]
+    );
+
+    // Values of int32 columns are 32-bit, int columns stay 64-bit.
+    assert!(service
+        .exec_query("INSERT INTO s.Data(d, i, b, n) VALUES (NULL, 3000000000, NULL, NULL)")
+        .await
+        .is_err());
+    service
+        .exec_query("INSERT INTO s.Data(d, i, b, n) VALUES (NULL, NULL, 3000000000, NULL)")
+        .await
+        .unwrap();
+
+    // Declared scales are kept even if 64-bit decimals don't support them.
+    service
+        .exec_query("CREATE TABLE s.Scales(n decimal(18, 7))")
+        .await
+        .unwrap();
+    service
+        .exec_query("INSERT INTO s.Scales(n) VALUES ('1.2345678')")
+        .await
+        .unwrap();
+    let r = service.exec_query("SELECT n FROM s.Scales").await.unwrap();
+    assert_eq!(
+        to_rows(&r),
+        vec![vec![TableValue::Decimal(Decimal::new_i128(12345678))]]
+    );
+
+    let e = service
+        .exec_query("CREATE TABLE s.Wide(n decimal(40, 2))")
+        .await
+        .unwrap_err();
+    assert!(e.to_string().contains("at most 38"), "{}", e);
+}
+
+async fn insert_batch_id(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query("CREATE TABLE s.Data(n int)")
+        .await
+        .unwrap();
+
+    for _ in 0..2 {
+        service
+            .exec_query("INSERT INTO s.Data(n) VALUES (1), (2) BATCH ID 'first'")
+            .await
+            .unwrap();
+    }
+    service
+        .exec_query("INSERT INTO s.Data(n) VALUES (3) BATCH ID 'second'")
+        .await
+        .unwrap();
+    // No batch id, never deduplicated.
+    for _ in 0..2 {
+        service
+            .exec_query("INSERT INTO s.Data(n) VALUES (4)")
+            .await
+            .unwrap();
+    }
+
+    let r = service
+        .exec_query("SELECT n FROM s.Data ORDER BY n")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[1, 2, 3, 4, 4]));
+
+    service
+        .exec_query("INSERT INTO s.Data(n) VALUES (5) BATCH 'third'")
+        .await
+        .unwrap_err();
+}
+
+async fn create_table_with_batch_id(service: Box<dyn SqlClient>) {
+    let paths = {
+        let dir = env::temp_dir();
+
+        let path_1 = dir.clone().join("batch-id-1.csv");
+        let mut file = File::create(path_1.clone()).unwrap();
+        file.write_all("n\n1\n2\n".as_bytes()).unwrap();
+
+        let path_2 = dir.clone().join("batch-id-2.csv");
+        let mut file = File::create(path_2.clone()).unwrap();
+        file.write_all("n\n3\n".as_bytes()).unwrap();
+
+        vec![path_1, path_2]
+    };
+
+    let create_table = |batch_id: &str| {
+        format!(
+            "CREATE TABLE s.Data(n int) WITH (batch_id = '{}') LOCATION {}",
+            batch_id,
+            paths
+                .iter()
+                .map(|p| format!("'{}'", p.to_string_lossy()))
+                .join(",")
+        )
+    };
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service.exec_query(&create_table("import-1")).await.unwrap();
+
+    let r = service
+        .exec_query("SELECT n FROM s.Data ORDER BY n")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[1, 2, 3]));
+
+    // Re-submitting the same batch keeps the table and its data as is.
+    service.exec_query(&create_table("import-1")).await.unwrap();
+    let r = service
+        .exec_query("SELECT n FROM s.Data ORDER BY n")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[1, 2, 3]));
+
+    // Another batch can't create the same table again.
+    service
+        .exec_query(&create_table("import-2"))
+        .await
+        .unwrap_err();
+
+    // Only imports of locations are recorded under the batch id.
+    service
+        .exec_query("CREATE TABLE s.Inserted(n int) WITH (batch_id = 'import-1')")
+        .await
+        .unwrap_err();
+}
+
+async fn swap_tables(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query("CREATE TABLE s.Data(n int)")
+        .await
+        .unwrap();
+    service
+        .exec_query("INSERT INTO s.Data(n) VALUES (1)")
+        .await
+        .unwrap();
+    service
+        .exec_query("CREATE TABLE s.Data_v2(n int)")
+        .await
+        .unwrap();
+    service
+        .exec_query("INSERT INTO s.Data_v2(n) VALUES (2), (3)")
+        .await
+        .unwrap();
+
+    service
+        .exec_query("ALTER TABLE s.Data SWAP WITH s.Data_v2")
+        .await
+        .unwrap();
+    let r = service
+        .exec_query("SELECT n FROM s.Data ORDER BY n")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[2, 3]));
+    let r = service
+        .exec_query("SELECT n FROM s.Data_v2 ORDER BY n")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[1]));
+
+    // The old version is dropped by its new name.
+    service.exec_query("DROP TABLE s.Data_v2").await.unwrap();
+    let r = service
+        .exec_query("SELECT n FROM s.Data ORDER BY n")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[2, 3]));
+
+    service
+        .exec_query("ALTER TABLE s.Data SWAP WITH s.Data")
+        .await
+        .unwrap_err();
+    service
+        .exec_query("ALTER TABLE s.Data SWAP WITH s.Data_v2")
+        .await
+        .unwrap_err();
+}
+
 fn to_rows(d: &DataFrame) -> Vec<Vec<TableValue>> {
     return d
         .get_rows()
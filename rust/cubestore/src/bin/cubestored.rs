@@ -42,7 +42,10 @@ fn main() {
     runtime.block_on(async move {
         validate_config(config.config_obj().as_ref()).report_and_abort_on_errors();
 
-        config.configure_injector().await;
+        if let Err(e) = config.configure_injector().await {
+            log::error!("Cannot proceed with invalid configuration, exiting: {}", e);
+            std::process::exit(1)
+        }
 
         serve_status_probes(&config);
 
@@ -0,0 +1,195 @@
+//! Liveness tracking of cluster nodes.
+//!
+//! Workers send [NetworkMessage::NodeHeartBeat] to the router on the meta store port every
+//! `heart_beat_interval` seconds. The router marks nodes that didn't send a heart beat within
+//! `heart_beat_timeout` as unhealthy and replies with the status of all nodes, so workers route
+//! around unhealthy nodes the same way the router does. Nodes missing in `select_workers` are
+//! registered on their first heart beat, but they don't get partitions assigned.
+use crate::cluster::message::NetworkMessage;
+use crate::cluster::ClusterImpl;
+use crate::config::is_router;
+use crate::CubeError;
+use chrono::{DateTime, Utc};
+use futures_timer::Delay;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct NodeStatus {
+    pub node_name: String,
+    /// Whether the node is listed in `select_workers`.
+    pub configured: bool,
+    pub healthy: bool,
+    pub last_heart_beat: Option<DateTime<Utc>>,
+}
+
+pub struct Membership {
+    heart_beat_timeout: chrono::Duration,
+    /// Configured nodes are considered healthy until `heart_beat_timeout` passes since start.
+    started: DateTime<Utc>,
+    nodes: HashMap<String, NodeStatus>,
+}
+
+impl Membership {
+    pub fn new(configured: &[String], heart_beat_timeout: Duration, now: DateTime<Utc>) -> Self {
+        let nodes = configured
+            .iter()
+            .map(|n| {
+                (
+                    n.to_string(),
+                    NodeStatus {
+                        node_name: n.to_string(),
+                        configured: true,
+                        healthy: true,
+                        last_heart_beat: None,
+                    },
+                )
+            })
+            .collect();
+        Self {
+            heart_beat_timeout: chrono::Duration::from_std(heart_beat_timeout).unwrap(),
+            started: now,
+            nodes,
+        }
+    }
+
+    pub fn heart_beat(&mut self, node_name: &str, now: DateTime<Utc>) {
+        let status = self.nodes.entry(node_name.to_string()).or_insert_with(|| {
+            info!("Node {} registered in the cluster", node_name);
+            NodeStatus {
+                node_name: node_name.to_string(),
+                configured: false,
+                healthy: true,
+                last_heart_beat: None,
+            }
+        });
+        if !status.healthy {
+            info!("Node {} is healthy again", node_name);
+        }
+        status.healthy = true;
+        status.last_heart_beat = Some(now);
+    }
+
+    /// Marks nodes without recent heart beats as unhealthy.
+    pub fn refresh(&mut self, now: DateTime<Utc>) {
+        for status in self.nodes.values_mut() {
+            let last_seen = status.last_heart_beat.unwrap_or(self.started);
+            let healthy = now <= last_seen + self.heart_beat_timeout;
+            if status.healthy && !healthy {
+                warn!(
+                    "Node {} hasn't sent heart beats since {}, routing around it",
+                    status.node_name, last_seen
+                );
+            }
+            status.healthy = healthy;
+        }
+    }
+
+    /// Replaces node statuses with the ones reported by the router.
+    pub fn update(&mut self, statuses: Vec<NodeStatus>) {
+        self.nodes = statuses
+            .into_iter()
+            .map(|s| (s.node_name.clone(), s))
+            .collect();
+    }
+
+    /// Nodes we know nothing about are considered healthy.
+    pub fn is_healthy(&self, node_name: &str) -> bool {
+        self.nodes.get(node_name).map(|s| s.healthy).unwrap_or(true)
+    }
+
+    pub fn statuses(&self) -> Vec<NodeStatus> {
+        let mut statuses = self.nodes.values().cloned().collect::<Vec<_>>();
+        statuses.sort_by(|a, b| a.node_name.cmp(&b.node_name));
+        statuses
+    }
+}
+
+impl ClusterImpl {
+    pub(crate) fn node_statuses(&self) -> Vec<NodeStatus> {
+        self.membership.lock().unwrap().statuses()
+    }
+
+    pub(crate) fn is_node_healthy(&self, node_name: &str) -> bool {
+        self.membership.lock().unwrap().is_healthy(node_name)
+    }
+
+    pub(crate) fn process_heart_beat(&self, node_name: &str) -> Vec<NodeStatus> {
+        let mut membership = self.membership.lock().unwrap();
+        membership.heart_beat(node_name, Utc::now());
+        membership.statuses()
+    }
+
+    /// Router checks heart beats of workers, workers send heart beats to the router.
+    pub async fn wait_membership_loop(&self) {
+        let interval = Duration::from_secs(self.config_obj.heart_beat_interval());
+        loop {
+            if is_router(self.config_obj.as_ref()) {
+                self.membership.lock().unwrap().refresh(Utc::now());
+            } else if let Err(e) = self.send_heart_beat().await {
+                warn!("Can't send heart beat to the router: {}", e);
+            }
+            tokio::select! {
+                _ = self.stop_token.cancelled() => return,
+                _ = Delay::new(interval) => {}
+            }
+        }
+    }
+
+    async fn send_heart_beat(&self) -> Result<(), CubeError> {
+        let transport = match &self.meta_store_transport {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+        let response = transport
+            .meta_store_call(NetworkMessage::NodeHeartBeat(self.server_name.clone()))
+            .await?;
+        match response {
+            NetworkMessage::NodeHeartBeatResult(statuses) => {
+                self.membership.lock().unwrap().update(statuses);
+                Ok(())
+            }
+            x => Err(CubeError::internal(format!(
+                "Unexpected response to heart beat: {:?}",
+                x
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heart_beats() {
+        let start = Utc::now();
+        let workers = vec!["w1".to_string(), "w2".to_string()];
+        let mut m = Membership::new(&workers, Duration::from_secs(30), start);
+        assert!(m.is_healthy("w1"));
+        assert!(m.is_healthy("w2"));
+
+        m.heart_beat("w1", start + chrono::Duration::seconds(20));
+        m.heart_beat("w3", start + chrono::Duration::seconds(20));
+        m.refresh(start + chrono::Duration::seconds(40));
+        assert!(m.is_healthy("w1"));
+        assert!(!m.is_healthy("w2"));
+        assert!(m.is_healthy("w3"));
+
+        let statuses = m.statuses();
+        assert_eq!(
+            statuses
+                .iter()
+                .map(|s| (s.node_name.as_str(), s.configured, s.healthy))
+                .collect::<Vec<_>>(),
+            vec![("w1", true, true), ("w2", true, false), ("w3", false, true)]
+        );
+
+        m.heart_beat("w2", start + chrono::Duration::seconds(45));
+        m.refresh(start + chrono::Duration::seconds(60));
+        assert!(!m.is_healthy("w1"));
+        assert!(m.is_healthy("w2"));
+    }
+}
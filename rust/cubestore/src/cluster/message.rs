@@ -1,12 +1,13 @@
+use crate::cluster::membership::NodeStatus;
 use crate::metastore::{MetaStoreRpcMethodCall, MetaStoreRpcMethodResult};
 use crate::queryplanner::query_executor::SerializedRecordBatchStream;
 use crate::queryplanner::serialized_plan::SerializedPlan;
+use crate::util::tls::NetworkStream;
 use crate::CubeError;
 use arrow::datatypes::SchemaRef;
 use serde::{Deserialize, Serialize};
 use std::io::ErrorKind;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum NetworkMessage {
@@ -33,6 +34,10 @@ pub enum NetworkMessage {
 
     NotifyJobListeners,
     NotifyJobListenersSuccess,
+
+    /// Sent by workers to the router, see [crate::cluster::membership].
+    NodeHeartBeat(/*node_name*/ String),
+    NodeHeartBeatResult(Vec<NodeStatus>),
 }
 
 impl NetworkMessage {
@@ -44,7 +49,7 @@ impl NetworkMessage {
     }
 
     /// Returns true iff the client accepted the message.
-    pub async fn maybe_send(&self, socket: &mut TcpStream) -> Result<bool, CubeError> {
+    pub async fn maybe_send(&self, socket: &mut dyn NetworkStream) -> Result<bool, CubeError> {
         match self.send_impl(socket).await {
             Ok(()) => Ok(true),
             Err(e) if e.kind() == std::io::ErrorKind::ConnectionReset => Ok(false),
@@ -52,11 +57,11 @@ impl NetworkMessage {
         }
     }
 
-    pub async fn send(&self, socket: &mut TcpStream) -> Result<(), CubeError> {
+    pub async fn send(&self, socket: &mut dyn NetworkStream) -> Result<(), CubeError> {
         Ok(self.send_impl(socket).await?)
     }
 
-    async fn send_impl(&self, socket: &mut TcpStream) -> Result<(), std::io::Error> {
+    async fn send_impl(&self, socket: &mut dyn NetworkStream) -> Result<(), std::io::Error> {
         let mut ser = flexbuffers::FlexbufferSerializer::new();
         self.serialize(&mut ser).unwrap();
         let message_buffer = ser.take_buffer();
@@ -72,7 +77,7 @@ impl NetworkMessage {
         Ok(())
     }
 
-    pub async fn receive(socket: &mut TcpStream) -> Result<Self, CubeError> {
+    pub async fn receive(socket: &mut dyn NetworkStream) -> Result<Self, CubeError> {
         match Self::maybe_receive(socket).await? {
             Some(m) => Ok(m),
             None => Err(CubeError::internal("connection closed".to_string())),
@@ -80,7 +85,7 @@ impl NetworkMessage {
     }
 
     /// Either receives a message or waits for the connection to close.
-    pub async fn maybe_receive(socket: &mut TcpStream) -> Result<Option<Self>, CubeError> {
+    pub async fn maybe_receive(socket: &mut dyn NetworkStream) -> Result<Option<Self>, CubeError> {
         let len = socket.read_u64().await;
         if let Err(e) = &len {
             // TODO: corner case with `0 < n < 8` read bytes.
@@ -1,5 +1,7 @@
+pub mod membership;
 pub mod message;
 
+pub mod rebalance;
 pub mod transport;
 #[cfg(not(target_os = "windows"))]
 pub mod worker_pool;
@@ -8,7 +10,9 @@ pub mod worker_pool;
 use crate::cluster::worker_pool::{worker_main, MessageProcessor, WorkerPool};
 
 use crate::ack_error;
+use crate::cluster::membership::{Membership, NodeStatus};
 use crate::cluster::message::NetworkMessage;
+use crate::cluster::rebalance::{pick_worker, WorkerRouting};
 use crate::cluster::transport::{ClusterTransport, MetaStoreTransport, WorkerConnection};
 use crate::config::injection::DIService;
 use crate::config::is_router;
@@ -28,6 +32,8 @@ use crate::queryplanner::serialized_plan::SerializedPlan;
 use crate::remotefs::RemoteFs;
 use crate::store::compaction::CompactionService;
 use crate::store::ChunkDataStore;
+use crate::streaming::StreamingService;
+use crate::util::tls::{NetworkStream, NodeTls};
 use crate::CubeError;
 use arrow::datatypes::SchemaRef;
 use arrow::error::ArrowError;
@@ -47,9 +53,7 @@ use log::{debug, error, info, warn};
 use mockall::automock;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, HashSet};
-use std::hash::{Hash, Hasher};
 use std::path::Path;
 use std::sync::Weak;
 use std::sync::{Arc, Mutex};
@@ -94,6 +98,9 @@ pub trait Cluster: DIService + Send + Sync {
 
     async fn available_nodes(&self) -> Result<Vec<String>, CubeError>;
 
+    /// Membership and liveness of select workers as seen by this node.
+    async fn nodes_status(&self) -> Result<Vec<NodeStatus>, CubeError>;
+
     fn server_name(&self) -> &str;
 
     async fn warmup_download(&self, node_name: &str, remote_path: String) -> Result<(), CubeError>;
@@ -108,6 +115,16 @@ pub trait Cluster: DIService + Send + Sync {
 
     fn node_name_by_partitions(&self, partition_ids: &[u64]) -> String;
 
+    /// All workers serving partitions in the order of preference, first one is the same as
+    /// [Cluster::node_name_by_partitions].
+    fn node_names_by_partitions(&self, partition_ids: &[u64]) -> Vec<String>;
+
+    /// Number of selects this node is currently waiting for on `node_name`.
+    fn active_selects(&self, node_name: &str) -> usize;
+
+    /// Selects that take longer are also sent to another replica.
+    fn hedged_select_timeout(&self) -> Option<Duration>;
+
     async fn node_name_for_import(
         &self,
         table_id: u64,
@@ -135,6 +152,7 @@ pub struct ClusterImpl {
     chunk_store: Arc<dyn ChunkDataStore>,
     compaction_service: Arc<dyn CompactionService>,
     import_service: Arc<dyn ImportService>,
+    streaming_service: Arc<dyn StreamingService>,
     cluster_transport: Arc<dyn ClusterTransport>,
     connect_timeout: Duration,
     server_name: String,
@@ -159,6 +177,11 @@ pub struct ClusterImpl {
     stop_token: CancellationToken,
     close_worker_socket_tx: watch::Sender<bool>,
     close_worker_socket_rx: RwLock<watch::Receiver<bool>>,
+    routing: Mutex<Arc<WorkerRouting>>,
+    membership: Mutex<Membership>,
+    active_selects: Arc<Mutex<HashMap<String, usize>>>,
+    meta_store_transport: Option<Arc<dyn MetaStoreTransport>>,
+    tls: Arc<NodeTls>,
 }
 
 crate::di_service!(ClusterImpl, [Cluster]);
@@ -203,11 +226,20 @@ fn proc_handler() {
     );
 }
 
+/// Node that imports `location` into the table, see [Cluster::node_name_for_import].
+pub fn import_node_name(config: &dyn ConfigObj, table_id: u64, location: &str) -> String {
+    match pick_worker(&(table_id, location), config.select_workers()) {
+        Some(node) => node.to_string(),
+        None => config.server_name().to_string(),
+    }
+}
+
 struct JobRunner {
     meta_store: Arc<dyn MetaStore>,
     chunk_store: Arc<dyn ChunkDataStore>,
     compaction_service: Arc<dyn CompactionService>,
     import_service: Arc<dyn ImportService>,
+    streaming_service: Arc<dyn StreamingService>,
     server_name: String,
     notify: Arc<Notify>,
     jobs_enabled: Arc<RwLock<bool>>,
@@ -250,6 +282,7 @@ impl Cluster for ClusterImpl {
         node_name: &str,
         plan_node: SerializedPlan,
     ) -> Result<Vec<RecordBatch>, CubeError> {
+        let _active = self.start_select(node_name);
         let response = self
             .send_or_process_locally(node_name, NetworkMessage::Select(plan_node))
             .await?;
@@ -266,10 +299,11 @@ impl Cluster for ClusterImpl {
         node_name: &str,
         plan: SerializedPlan,
     ) -> Result<SendableRecordBatchStream, CubeError> {
+        let active = self.start_select(node_name);
         self.this
             .upgrade()
             .unwrap()
-            .run_select_stream_impl(node_name, plan)
+            .run_select_stream_impl(node_name, plan, active)
             .await
     }
 
@@ -277,6 +311,10 @@ impl Cluster for ClusterImpl {
         Ok(vec![self.server_name.to_string()])
     }
 
+    async fn nodes_status(&self) -> Result<Vec<NodeStatus>, CubeError> {
+        Ok(self.node_statuses())
+    }
+
     fn server_name(&self) -> &str {
         self.server_name.as_str()
     }
@@ -299,16 +337,38 @@ impl Cluster for ClusterImpl {
     }
 
     fn node_name_by_partitions(&self, partition_ids: &[u64]) -> String {
-        let workers = self.config_obj.select_workers();
-        if workers.is_empty() {
-            return self.server_name.to_string();
+        self.node_names_by_partitions(partition_ids)
+            .into_iter()
+            .next()
+            .unwrap()
+    }
+
+    fn node_names_by_partitions(&self, partition_ids: &[u64]) -> Vec<String> {
+        let nodes = self.routing().healthy_replicas_for(
+            partition_ids,
+            self.config_obj.partition_replication_factor(),
+            |n| self.is_node_healthy(n),
+        );
+        if nodes.is_empty() {
+            return vec![self.server_name.to_string()];
         }
+        nodes
+    }
 
-        let mut hasher = DefaultHasher::new();
-        for p in partition_ids.iter() {
-            p.hash(&mut hasher);
+    fn active_selects(&self, node_name: &str) -> usize {
+        self.active_selects
+            .lock()
+            .unwrap()
+            .get(node_name)
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    fn hedged_select_timeout(&self) -> Option<Duration> {
+        match self.config_obj.hedged_select_timeout_ms() {
+            0 => None,
+            t => Some(Duration::from_millis(t)),
         }
-        workers[(hasher.finish() % workers.len() as u64) as usize].clone()
     }
 
     async fn node_name_for_import(
@@ -316,14 +376,11 @@ impl Cluster for ClusterImpl {
         table_id: u64,
         location: &str,
     ) -> Result<String, CubeError> {
-        let workers = self.config_obj.select_workers();
-        if workers.is_empty() {
-            return Ok(self.server_name.to_string());
-        }
-        let mut hasher = DefaultHasher::new();
-        table_id.hash(&mut hasher);
-        location.hash(&mut hasher);
-        Ok(workers[(hasher.finish() % workers.len() as u64) as usize].to_string())
+        Ok(import_node_name(
+            self.config_obj.as_ref(),
+            table_id,
+            location,
+        ))
     }
 
     async fn warmup_partition(
@@ -331,14 +388,16 @@ impl Cluster for ClusterImpl {
         partition: IdRow<Partition>,
         chunks: Vec<IdRow<Chunk>>,
     ) -> Result<(), CubeError> {
-        let node_name = self.node_name_by_partitions(&[partition.get_id()]);
+        let node_names = self.node_names_by_partitions(&[partition.get_id()]);
         let mut futures = Vec::new();
-        if let Some(name) = partition.get_row().get_full_name(partition.get_id()) {
-            futures.push(self.warmup_download(&node_name, name));
-        }
-        for chunk in chunks.iter() {
-            let name = chunk.get_row().get_full_name(chunk.get_id());
-            futures.push(self.warmup_download(&node_name, name));
+        for node_name in node_names.iter() {
+            if let Some(name) = partition.get_row().get_full_name(partition.get_id()) {
+                futures.push(self.warmup_download(node_name, name));
+            }
+            for chunk in chunks.iter() {
+                let name = chunk.get_row().get_full_name(chunk.get_id());
+                futures.push(self.warmup_download(node_name, name));
+            }
         }
         join_all(futures)
             .await
@@ -382,6 +441,9 @@ impl Cluster for ClusterImpl {
             NetworkMessage::NotifyJobListenersSuccess => {
                 panic!("NotifyJobListenersSuccess sent to worker")
             }
+            NetworkMessage::NodeHeartBeat(_) | NetworkMessage::NodeHeartBeatResult(_) => {
+                panic!("NodeHeartBeat sent to worker")
+            }
             NetworkMessage::SelectStart(..)
             | NetworkMessage::SelectResultSchema(..)
             | NetworkMessage::SelectResultBatch(..) => {
@@ -397,6 +459,9 @@ impl Cluster for ClusterImpl {
                 let res = server.invoke_method(method_call).await;
                 NetworkMessage::MetaStoreCallResult(res)
             }
+            NetworkMessage::NodeHeartBeat(node_name) => {
+                NetworkMessage::NodeHeartBeatResult(self.process_heart_beat(&node_name))
+            }
             x => panic!("Unexpected message: {:?}", x),
         }
     }
@@ -605,6 +670,25 @@ impl JobRunner {
                     Self::fail_job_row_key(job);
                 }
             }
+            JobType::StreamIngestion => {
+                if let RowKey::Table(TableId::Tables, _) = job.row_reference() {
+                    // Ingestion runs in long-lived loops, the job only starts or stops them.
+                    let streaming_service = self.streaming_service.clone();
+                    cube_ext::spawn(async move { streaming_service.reconcile().await }).await??
+                } else {
+                    Self::fail_job_row_key(job);
+                }
+            }
+            JobType::IndexBuild => {
+                if let RowKey::Table(TableId::Indexes, index_id) = job.row_reference() {
+                    let chunk_store = self.chunk_store.clone();
+                    let index_id = *index_id;
+                    cube_ext::spawn(async move { chunk_store.build_index(index_id).await })
+                        .await??
+                } else {
+                    Self::fail_job_row_key(job);
+                }
+            }
         }
         Ok(())
     }
@@ -614,7 +698,36 @@ impl JobRunner {
     }
 }
 
+/// Tracks a select sent to a node until it is dropped, see [Cluster::active_selects].
+/// Streamed selects keep it until the stream is dropped.
+struct ActiveSelect {
+    active_selects: Arc<Mutex<HashMap<String, usize>>>,
+    node_name: String,
+}
+
+impl Drop for ActiveSelect {
+    fn drop(&mut self) {
+        let mut active_selects = self.active_selects.lock().unwrap();
+        if let Some(count) = active_selects.get_mut(&self.node_name) {
+            *count -= 1;
+        }
+    }
+}
+
 impl ClusterImpl {
+    fn start_select(&self, node_name: &str) -> ActiveSelect {
+        *self
+            .active_selects
+            .lock()
+            .unwrap()
+            .entry(node_name.to_string())
+            .or_default() += 1;
+        ActiveSelect {
+            active_selects: self.active_selects.clone(),
+            node_name: node_name.to_string(),
+        }
+    }
+
     pub fn new(
         server_name: String,
         server_addresses: Vec<String>,
@@ -624,12 +737,21 @@ impl ClusterImpl {
         compaction_service: Arc<dyn CompactionService>,
         meta_store: Arc<dyn MetaStore>,
         import_service: Arc<dyn ImportService>,
+        streaming_service: Arc<dyn StreamingService>,
         config_obj: Arc<dyn ConfigObj>,
         query_executor: Arc<dyn QueryExecutor>,
         meta_store_sender: Sender<MetaStoreEvent>,
         cluster_transport: Arc<dyn ClusterTransport>,
+        meta_store_transport: Option<Arc<dyn MetaStoreTransport>>,
+        tls: Arc<NodeTls>,
     ) -> Arc<ClusterImpl> {
         let (close_worker_socket_tx, close_worker_socket_rx) = watch::channel(false);
+        let routing = WorkerRouting::new(config_obj.select_workers().clone());
+        let membership = Membership::new(
+            config_obj.select_workers(),
+            Duration::from_secs(config_obj.heart_beat_timeout()),
+            Utc::now(),
+        );
         Arc::new_cyclic(|this| ClusterImpl {
             this: this.clone(),
             server_name,
@@ -639,6 +761,7 @@ impl ClusterImpl {
             chunk_store,
             compaction_service,
             import_service,
+            streaming_service,
             meta_store,
             cluster_transport,
             job_notify: Arc::new(Notify::new()),
@@ -651,6 +774,11 @@ impl ClusterImpl {
             stop_token: CancellationToken::new(),
             close_worker_socket_tx,
             close_worker_socket_rx: RwLock::new(close_worker_socket_rx),
+            routing: Mutex::new(Arc::new(routing)),
+            membership: Mutex::new(membership),
+            active_selects: Arc::new(Mutex::new(HashMap::new())),
+            meta_store_transport,
+            tls,
         })
     }
 
@@ -685,6 +813,20 @@ impl ClusterImpl {
             ));
         }
 
+        let cluster = self.this.upgrade().unwrap();
+        futures.push(cube_ext::spawn(async move {
+            ack_error!(cluster.wait_rebalance().await);
+        }));
+        let cluster = self.this.upgrade().unwrap();
+        futures.push(cube_ext::spawn(async move {
+            cluster.wait_membership_loop().await;
+        }));
+
+        let streaming_service = self.streaming_service.clone();
+        futures.push(cube_ext::spawn(async move {
+            streaming_service.wait_processing_loops().await;
+        }));
+
         for _ in 0..self.config_obj.job_runners_count() {
             // TODO number of job event loops
             let job_runner = JobRunner {
@@ -692,6 +834,7 @@ impl ClusterImpl {
                 chunk_store: self.chunk_store.clone(),
                 compaction_service: self.compaction_service.clone(),
                 import_service: self.import_service.clone(),
+                streaming_service: self.streaming_service.clone(),
                 server_name: self.server_name.clone(),
                 notify: self.job_notify.clone(),
                 jobs_enabled: self.jobs_enabled.clone(),
@@ -720,6 +863,8 @@ impl ClusterImpl {
             pool.stop_workers().await?;
         }
 
+        self.streaming_service.stop_processing_loops().await;
+
         self.close_worker_socket_tx.send(true)?;
         self.stop_token.cancel();
         Ok(())
@@ -752,7 +897,7 @@ impl ClusterImpl {
             cluster.clone(),
             on_socket_bound,
             async move |c, mut socket| {
-                let m = match NetworkMessage::receive(&mut socket).await {
+                let m = match NetworkMessage::receive(socket.as_mut()).await {
                     Ok(m) => m,
                     Err(e) => {
                         error!("Network error: {}", e);
@@ -762,7 +907,7 @@ impl ClusterImpl {
 
                 if !m.is_streaming_request() {
                     let response = c.process_message_on_worker(m).await;
-                    if let Err(e) = response.send(&mut socket).await {
+                    if let Err(e) = response.send(socket.as_mut()).await {
                         error!("Network error: {}", e);
                         return;
                     }
@@ -770,7 +915,7 @@ impl ClusterImpl {
                     let mut p = c.start_stream_on_worker(m).await;
                     loop {
                         let (response, finished) = p.next().await;
-                        match response.maybe_send(&mut socket).await {
+                        match response.maybe_send(socket.as_mut()).await {
                             // All ok, continue streaming.
                             Ok(true) => {}
                             // Connection closed by client, stop streaming.
@@ -816,7 +961,11 @@ impl ClusterImpl {
         address: &str,
         cluster: Arc<ClusterImpl>,
         on_socket_bound: oneshot::Sender<()>,
-        process_fn: impl Fn(Arc<ClusterImpl>, TcpStream) -> F + Send + Sync + Clone + 'static,
+        process_fn: impl Fn(Arc<ClusterImpl>, Box<dyn NetworkStream>) -> F
+            + Send
+            + Sync
+            + Clone
+            + 'static,
     ) -> Result<(), CubeError> {
         let listener = TcpListener::bind(address.clone()).await?;
         let _ = on_socket_bound.send(());
@@ -847,6 +996,13 @@ impl ClusterImpl {
             let process_fn_to_move = process_fn.clone();
 
             cube_ext::spawn(async move {
+                let socket = match cluster_to_move.tls.accept(socket).await {
+                    Ok(socket) => socket,
+                    Err(e) => {
+                        error!("Network error: {}", e);
+                        return;
+                    }
+                };
                 process_fn_to_move(cluster_to_move, socket).await;
             });
         }
@@ -868,7 +1024,7 @@ impl ClusterImpl {
                 let cluster = c.clone();
                 let process_fn = process_fn.clone();
                 async move {
-                    let request = NetworkMessage::receive(&mut socket).await;
+                    let request = NetworkMessage::receive(socket.as_mut()).await;
                     let response;
                     match request {
                         Ok(m) => response = process_fn(cluster.clone(), m).await,
@@ -878,7 +1034,7 @@ impl ClusterImpl {
                         }
                     }
 
-                    if let Err(e) = response.send(&mut socket).await {
+                    if let Err(e) = response.send(socket.as_mut()).await {
                         error!("Network error: {}", e)
                     }
                 }
@@ -1086,6 +1242,7 @@ impl ClusterImpl {
         self: &Arc<Self>,
         node_name: &str,
         plan: SerializedPlan,
+        active: ActiveSelect,
     ) -> Result<SendableRecordBatchStream, CubeError> {
         let init_message = NetworkMessage::SelectStart(plan);
         let mut c = self.call_streaming(node_name, init_message).await?;
@@ -1098,6 +1255,7 @@ impl ClusterImpl {
             connection: Some(c),
             pending: Mutex::new(None),
             finished: false,
+            _active: active,
         }));
 
         type ConnPtr = Box<dyn WorkerConnection>;
@@ -1115,6 +1273,7 @@ impl ClusterImpl {
                 >,
             >,
             finished: bool,
+            _active: ActiveSelect,
         }
 
         impl Stream for SelectStream {
@@ -1216,7 +1375,10 @@ impl ClusterImpl {
         log::debug!("Got {} partitions, running the warmup", partitions.len());
 
         for (p, chunks) in partitions {
-            if self.node_name_by_partitions(&[p.partition_id]) != self.server_name {
+            if !self
+                .node_names_by_partitions(&[p.partition_id])
+                .contains(&self.server_name)
+            {
                 continue;
             }
             if let Some(file) = partition_file_name(p.parent_partition_id, p.partition_id) {
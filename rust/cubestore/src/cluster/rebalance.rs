@@ -0,0 +1,317 @@
+//! Assignment of partitions to select workers.
+//!
+//! Partitions are assigned with rendezvous hashing, so adding or removing a worker only moves
+//! the partitions owned by that worker. When `select_workers` changes, the router downloads moved
+//! partitions to their new owners before switching the routing to them. The applied worker list
+//! is kept in [APPLIED_WORKERS_FILE] on the remote fs, so workers route the same way as the
+//! router while rebalancing is in progress.
+use crate::ack_error;
+use crate::cluster::{Cluster, ClusterImpl};
+use crate::config::is_router;
+use crate::metastore::chunks::chunk_file_name;
+use crate::metastore::partition::partition_file_name;
+use crate::remotefs::RemoteFs;
+use crate::CubeError;
+use futures::future::join_all;
+use futures_timer::Delay;
+use log::info;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::fs;
+
+pub const APPLIED_WORKERS_FILE: &str = "select-workers-current";
+
+fn weight<K: Hash + ?Sized>(key: &K, worker: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    worker.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Picks the worker with the highest hash weight for `key`.
+pub fn pick_worker<'a, K: Hash + ?Sized>(key: &K, workers: &'a [String]) -> Option<&'a String> {
+    workers.iter().max_by_key(|w| weight(key, w))
+}
+
+/// Workers ordered by their hash weight for `key`, highest first.
+pub fn rank_workers<'a, K: Hash + ?Sized>(key: &K, workers: &'a [String]) -> Vec<&'a String> {
+    let mut ranked = workers.iter().collect::<Vec<_>>();
+    ranked.sort_by_key(|w| std::cmp::Reverse(weight(key, w)));
+    ranked
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkerRouting {
+    workers: Vec<String>,
+    /// Workers used before `select_workers` changed. Set until rebalancing is finished.
+    previous: Option<Vec<String>>,
+}
+
+impl WorkerRouting {
+    pub fn new(workers: Vec<String>) -> Self {
+        Self {
+            workers,
+            previous: None,
+        }
+    }
+
+    pub fn rebalancing(previous: Vec<String>, workers: Vec<String>) -> Self {
+        Self {
+            workers,
+            previous: Some(previous),
+        }
+    }
+
+    pub fn is_rebalancing(&self) -> bool {
+        self.previous.is_some()
+    }
+
+    /// Keeps routing to the previous owner while it's still in the cluster.
+    pub fn node_for<K: Hash + ?Sized>(&self, key: &K) -> Option<&String> {
+        self.replicas_for(key, 1).into_iter().next()
+    }
+
+    /// First `replication_factor` workers serving `key`. Previous owners that are still in the
+    /// cluster keep their place while rebalancing.
+    pub fn replicas_for<K: Hash + ?Sized>(
+        &self,
+        key: &K,
+        replication_factor: usize,
+    ) -> Vec<&String> {
+        let mut candidates = Vec::new();
+        if let Some(previous) = &self.previous {
+            candidates.extend(
+                rank_workers(key, previous)
+                    .into_iter()
+                    .filter(|w| self.workers.contains(w)),
+            );
+        }
+        candidates.extend(rank_workers(key, &self.workers));
+
+        let mut replicas: Vec<&String> = Vec::new();
+        for c in candidates {
+            if replication_factor <= replicas.len() {
+                break;
+            }
+            if !replicas.contains(&c) {
+                replicas.push(c);
+            }
+        }
+        replicas
+    }
+
+    /// Like [WorkerRouting::replicas_for], but healthy replicas go first. Falls back to the next
+    /// healthy worker if all replicas are unhealthy.
+    pub fn healthy_replicas_for<K: Hash + ?Sized>(
+        &self,
+        key: &K,
+        replication_factor: usize,
+        is_healthy: impl Fn(&str) -> bool,
+    ) -> Vec<String> {
+        let replicas = self.replicas_for(key, replication_factor);
+        let (mut result, unhealthy): (Vec<&String>, Vec<&String>) =
+            replicas.into_iter().partition(|w| is_healthy(w));
+        if result.is_empty() {
+            result.extend(
+                rank_workers(key, &self.workers)
+                    .into_iter()
+                    .find(|w| is_healthy(w)),
+            );
+        }
+        result.extend(unhealthy);
+        result.into_iter().cloned().collect()
+    }
+}
+
+fn same_workers(a: &[String], b: &[String]) -> bool {
+    let mut a = a.to_vec();
+    let mut b = b.to_vec();
+    a.sort();
+    b.sort();
+    a == b
+}
+
+pub async fn read_applied_workers(
+    remote_fs: &dyn RemoteFs,
+) -> Result<Option<Vec<String>>, CubeError> {
+    if remote_fs.list(APPLIED_WORKERS_FILE).await?.is_empty() {
+        return Ok(None);
+    }
+    let local = remote_fs.local_file(APPLIED_WORKERS_FILE).await?;
+    if fs::metadata(&local).await.is_ok() {
+        fs::remove_file(&local).await?;
+    }
+    let local = remote_fs.download_file(APPLIED_WORKERS_FILE).await?;
+    Ok(Some(serde_json::from_slice(&fs::read(local).await?)?))
+}
+
+pub async fn write_applied_workers(
+    remote_fs: &dyn RemoteFs,
+    workers: &[String],
+) -> Result<(), CubeError> {
+    let temp = remote_fs.temp_upload_path(APPLIED_WORKERS_FILE).await?;
+    fs::write(&temp, serde_json::to_vec(workers)?).await?;
+    remote_fs.upload_file(&temp, APPLIED_WORKERS_FILE).await
+}
+
+impl ClusterImpl {
+    pub(crate) fn routing(&self) -> Arc<WorkerRouting> {
+        self.routing.lock().unwrap().clone()
+    }
+
+    fn set_routing(&self, routing: WorkerRouting) {
+        *self.routing.lock().unwrap() = Arc::new(routing);
+    }
+
+    /// Moves routing to the configured `select_workers`. Router warms up moved partitions and
+    /// publishes the new worker list, workers wait for it to be published.
+    pub async fn wait_rebalance(&self) -> Result<(), CubeError> {
+        let workers = self.config_obj.select_workers().clone();
+        if workers.is_empty() {
+            return Ok(());
+        }
+        let applied = read_applied_workers(self.remote_fs.as_ref()).await?;
+        let previous = match applied {
+            Some(previous) if same_workers(&previous, &workers) => return Ok(()),
+            p => p,
+        };
+
+        if is_router(self.config_obj.as_ref()) {
+            if let Some(previous) = previous {
+                let routing = WorkerRouting::rebalancing(previous, workers.clone());
+                self.set_routing(routing.clone());
+                if self.config_obj.enable_rebalance_warmup() {
+                    if !self.rebalance_warmup(&routing).await? {
+                        return Ok(());
+                    }
+                }
+            }
+            write_applied_workers(self.remote_fs.as_ref(), &workers).await?;
+            info!("Routing partitions to workers: {:?}", workers);
+        } else if let Some(previous) = previous {
+            self.set_routing(WorkerRouting::rebalancing(previous, workers.clone()));
+            loop {
+                tokio::select! {
+                    _ = self.stop_token.cancelled() => return Ok(()),
+                    _ = Delay::new(Duration::from_secs(10)) => {}
+                }
+                if let Some(applied) = read_applied_workers(self.remote_fs.as_ref()).await? {
+                    if same_workers(&applied, &workers) {
+                        break;
+                    }
+                }
+            }
+        }
+        self.set_routing(WorkerRouting::new(workers));
+        Ok(())
+    }
+
+    /// Returns `false` if warmup was cancelled.
+    async fn rebalance_warmup(&self, routing: &WorkerRouting) -> Result<bool, CubeError> {
+        let target = WorkerRouting::new(routing.workers.clone());
+        let replication_factor = self.config_obj.partition_replication_factor();
+        let partitions = self.meta_store.get_warmup_partitions().await?;
+        info!(
+            "Rebalancing {} partitions to workers: {:?}",
+            partitions.len(),
+            routing.workers
+        );
+        let mut moved = 0;
+        for (p, chunks) in partitions {
+            if self.stop_token.is_cancelled() {
+                log::debug!("Rebalancing warmup cancelled");
+                return Ok(false);
+            }
+            let ids = [p.partition_id];
+            let current = routing.replicas_for(&ids[..], replication_factor);
+            let new_owners = target
+                .replicas_for(&ids[..], replication_factor)
+                .into_iter()
+                .filter(|n| !current.contains(n))
+                .collect::<Vec<_>>();
+            if new_owners.is_empty() {
+                continue;
+            }
+            let files = partition_file_name(p.parent_partition_id, p.partition_id)
+                .into_iter()
+                .chain(chunks.into_iter().map(chunk_file_name))
+                .collect::<Vec<_>>();
+            // Compaction might remove files in the meantime, so errors are not fatal.
+            for r in join_all(new_owners.iter().flat_map(|n| {
+                files
+                    .iter()
+                    .map(move |f| self.warmup_download(n, f.to_string()))
+            }))
+            .await
+            {
+                ack_error!(r);
+            }
+            moved += 1;
+        }
+        info!("Rebalancing warmup finished: {} partitions moved", moved);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workers(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("worker-{}", i)).collect()
+    }
+
+    #[test]
+    fn adding_worker_moves_only_its_partitions() {
+        let before = WorkerRouting::new(workers(4));
+        let after = WorkerRouting::new(workers(5));
+        let mut moved = 0;
+        for id in 0..1000u64 {
+            let old = before.node_for(&[id][..]).unwrap();
+            let new = after.node_for(&[id][..]).unwrap();
+            if old != new {
+                assert_eq!(new, "worker-4");
+                moved += 1;
+            }
+        }
+        assert!(100 < moved && moved < 300, "moved {}", moved);
+    }
+
+    #[test]
+    fn replicas() {
+        let routing = WorkerRouting::new(workers(5));
+        for id in 0..100u64 {
+            let replicas = routing.replicas_for(&[id][..], 3);
+            assert_eq!(replicas.len(), 3);
+            assert_eq!(replicas[0], routing.node_for(&[id][..]).unwrap());
+            assert!(replicas[1] != replicas[0] && replicas[2] != replicas[1]);
+
+            let healthy = routing.healthy_replicas_for(&[id][..], 3, |w| w != replicas[0]);
+            assert_eq!(healthy[0], *replicas[1]);
+            assert_eq!(healthy[2], *replicas[0]);
+        }
+        assert_eq!(routing.replicas_for(&[1u64][..], 10).len(), 5);
+    }
+
+    #[test]
+    fn rebalancing_keeps_previous_owners() {
+        let routing = WorkerRouting::rebalancing(workers(4), workers(5));
+        let previous = WorkerRouting::new(workers(4));
+        for id in 0..100u64 {
+            assert_eq!(routing.node_for(&[id][..]), previous.node_for(&[id][..]));
+        }
+
+        // Partitions of removed workers go to their new owners right away.
+        let routing = WorkerRouting::rebalancing(workers(4), workers(3));
+        let target = WorkerRouting::new(workers(3));
+        for id in 0..100u64 {
+            let node = routing.node_for(&[id][..]).unwrap();
+            assert_ne!(node, "worker-3");
+            if previous.node_for(&[id][..]).unwrap() == "worker-3" {
+                assert_eq!(Some(node), target.node_for(&[id][..]));
+            }
+        }
+    }
+}
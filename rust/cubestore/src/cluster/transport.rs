@@ -1,8 +1,10 @@
 use crate::cluster::message::NetworkMessage;
 use crate::config::injection::DIService;
 use crate::config::ConfigObj;
+use crate::util::tls::{NetworkStream, NodeTls};
 use crate::CubeError;
 use async_trait::async_trait;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::net::TcpStream;
@@ -59,28 +61,29 @@ impl dyn ClusterTransport {
 
 pub struct ClusterTransportImpl {
     config: Arc<dyn ConfigObj>,
+    tls: Arc<NodeTls>,
 }
 
 crate::di_service!(ClusterTransportImpl, [ClusterTransport]);
 
 impl ClusterTransportImpl {
-    pub fn new(config: Arc<dyn ConfigObj>) -> Arc<Self> {
-        Arc::new(Self { config })
+    pub fn new(config: Arc<dyn ConfigObj>, tls: Arc<NodeTls>) -> Arc<Self> {
+        Arc::new(Self { config, tls })
     }
 }
 
 struct Connection {
-    stream: TcpStream,
+    stream: Box<dyn NetworkStream>,
 }
 
 #[async_trait]
 impl WorkerConnection for Connection {
     async fn maybe_send(&mut self, m: NetworkMessage) -> Result<bool, CubeError> {
-        m.maybe_send(&mut self.stream).await
+        m.maybe_send(self.stream.as_mut()).await
     }
 
     async fn maybe_receive(&mut self) -> Result<Option<NetworkMessage>, CubeError> {
-        NetworkMessage::maybe_receive(&mut self.stream).await
+        NetworkMessage::maybe_receive(self.stream.as_mut()).await
     }
 }
 
@@ -96,6 +99,7 @@ impl ClusterTransport for ClusterTransportImpl {
         )
         .await?
         .map_err(|e| CubeError::internal(format!("Can't connect to {}: {}", worker_node, e)))?;
+        let stream = self.tls.connect(&worker_node, stream).await?;
         Ok(Box::new(Connection { stream }))
     }
 }
@@ -107,32 +111,90 @@ pub trait MetaStoreTransport: DIService {
 
 pub struct MetaStoreTransportImpl {
     config: Arc<dyn ConfigObj>,
+    tls: Arc<NodeTls>,
+    addresses: Vec<String>,
+    /// Index of the address in [addresses] that accepted the last call.
+    current: AtomicUsize,
 }
 
 crate::di_service!(MetaStoreTransportImpl, [MetaStoreTransport]);
 
 impl MetaStoreTransportImpl {
-    pub fn new(config: Arc<dyn ConfigObj>) -> Arc<Self> {
-        Arc::new(Self { config })
+    /// Connects to the metastore on `metastore_remote_address` and fails over to the
+    /// `metastore_standby_addresses` when it is unreachable.
+    pub fn new(config: Arc<dyn ConfigObj>, tls: Arc<NodeTls>) -> Arc<Self> {
+        let addresses = config
+            .metastore_remote_address()
+            .iter()
+            .chain(config.metastore_standby_addresses().iter())
+            .cloned()
+            .collect();
+        Self::with_addresses(config, tls, addresses)
+    }
+
+    pub fn with_addresses(
+        config: Arc<dyn ConfigObj>,
+        tls: Arc<NodeTls>,
+        addresses: Vec<String>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            config,
+            tls,
+            addresses,
+            current: AtomicUsize::new(0),
+        })
+    }
+
+    /// Connects to the metastore that accepted the last call or to the next one that accepts
+    /// the connection. Standby metastores do not listen until they are promoted, so the first
+    /// address to accept a connection is the one that currently leads.
+    async fn connect(&self) -> Result<Box<dyn NetworkStream>, CubeError> {
+        if self.addresses.is_empty() {
+            return Err(CubeError::internal(
+                "Meta store remote addr is not defined".to_string(),
+            ));
+        }
+        let start = self.current.load(Ordering::Acquire);
+        let mut last_error = None;
+        for i in 0..self.addresses.len() {
+            let index = (start + i) % self.addresses.len();
+            let address = &self.addresses[index];
+            let stream = tokio::time::timeout(
+                Duration::from_secs(self.config.connection_timeout()),
+                TcpStream::connect(address.to_string()),
+            )
+            .await;
+            match stream {
+                Ok(Ok(stream)) => {
+                    if index != start {
+                        log::warn!(
+                            "Meta store on {} is unreachable, switching to {}",
+                            self.addresses[start],
+                            address
+                        );
+                        self.current.store(index, Ordering::Release);
+                    }
+                    return self.tls.connect(address, stream).await;
+                }
+                Ok(Err(e)) => {
+                    last_error = Some(CubeError::internal(format!(
+                        "Can't connect to meta store on {}: {}",
+                        address, e
+                    )))
+                }
+                Err(e) => last_error = Some(e.into()),
+            }
+        }
+        Err(last_error.unwrap())
     }
 }
 
 #[async_trait]
 impl MetaStoreTransport for MetaStoreTransportImpl {
     async fn meta_store_call(&self, m: NetworkMessage) -> Result<NetworkMessage, CubeError> {
-        let meta_remote_addr = self
-            .config
-            .metastore_remote_address()
-            .as_ref()
-            .expect("Meta store remote addr is not defined")
-            .to_string();
-        let mut stream = tokio::time::timeout(
-            Duration::from_secs(self.config.connection_timeout()),
-            TcpStream::connect(meta_remote_addr),
-        )
-        .await??;
-        m.send(&mut stream).await?;
-        let message = NetworkMessage::receive(&mut stream).await?;
+        let mut stream = self.connect().await?;
+        m.send(stream.as_mut()).await?;
+        let message = NetworkMessage::receive(stream.as_mut()).await?;
         Ok(message)
     }
 }
@@ -23,12 +23,14 @@ use crate::scheduler::SchedulerImpl;
 use crate::sql::{SqlService, SqlServiceImpl};
 use crate::store::compaction::{CompactionService, CompactionServiceImpl};
 use crate::store::{ChunkDataStore, ChunkStore, WALDataStore, WALStore};
+use crate::streaming::{LogBroker, StreamingService, StreamingServiceImpl};
 use crate::telemetry::{start_track_event_loop, stop_track_event_loop};
+use crate::util::tls::NodeTls;
 use crate::CubeError;
 use datafusion::cube_ext;
 use futures::future::join_all;
 use log::Level;
-use log::{debug, error};
+use log::{debug, error, info};
 use mockall::automock;
 use rocksdb::{Options, DB};
 use simple_logger::SimpleLogger;
@@ -83,43 +85,38 @@ impl CubeServices {
 
     async fn spawn_processing_loops(&self) -> Result<Vec<LoopHandle>, CubeError> {
         let mut futures = Vec::new();
-        let cluster = self.cluster.clone();
-        futures.push(cube_ext::spawn(async move {
-            cluster.wait_processing_loops().await
-        }));
         let remote_fs = self.remote_fs.clone();
         futures.push(cube_ext::spawn(async move {
             QueueRemoteFs::wait_processing_loops(remote_fs.clone()).await
         }));
         if !self.cluster.is_select_worker() {
             let rocks_meta_store = self.rocks_meta_store.clone().unwrap();
-            futures.push(cube_ext::spawn(async move {
-                RocksMetaStore::wait_upload_loop(rocks_meta_store).await;
-                Ok(())
-            }));
-            let cluster = self.cluster.clone();
-            let (started_tx, started_rx) = tokio::sync::oneshot::channel();
-            futures.push(cube_ext::spawn(async move {
-                ClusterImpl::listen_on_metastore_port(cluster, started_tx).await
-            }));
-            started_rx.await?;
-
-            let scheduler = self.scheduler.clone();
-            futures.extend(SchedulerImpl::spawn_processing_loops(scheduler));
-
-            if self.injector.has_service_typed::<MySqlServer>().await {
-                let mysql_server = self.injector.get_service_typed::<MySqlServer>().await;
+            if rocks_meta_store.is_standby() {
+                let leader = self
+                    .injector
+                    .get_service::<dyn MetaStore>("leader_meta_store")
+                    .await;
+                let replicating_store = rocks_meta_store.clone();
                 futures.push(cube_ext::spawn(async move {
-                    mysql_server.processing_loop().await
+                    RocksMetaStore::wait_replication_loop(replicating_store, leader).await;
+                    Ok(())
                 }));
-            }
-            if self.injector.has_service_typed::<HttpServer>().await {
-                let http_server = self.injector.get_service_typed::<HttpServer>().await;
-                futures.push(cube_ext::spawn(
-                    async move { http_server.run_server().await },
-                ));
+                let services = self.clone();
+                futures.push(cube_ext::spawn(async move {
+                    if !rocks_meta_store.wait_for_promotion().await {
+                        return Ok(());
+                    }
+                    info!("Starting router processing loops after meta store failover");
+                    Self::wait_loops(services.spawn_router_loops().await?).await
+                }));
+            } else {
+                futures.extend(self.spawn_router_loops().await?);
             }
         } else {
+            let cluster = self.cluster.clone();
+            futures.push(cube_ext::spawn(async move {
+                cluster.wait_processing_loops().await
+            }));
             let cluster = self.cluster.clone();
             let (started_tx, started_rx) = tokio::sync::oneshot::channel();
             futures.push(cube_ext::spawn(async move {
@@ -140,6 +137,63 @@ impl CubeServices {
         Ok(futures)
     }
 
+    /// Loops of the router that owns the meta store. Standby routers start these only after
+    /// they are promoted.
+    async fn spawn_router_loops(&self) -> Result<Vec<LoopHandle>, CubeError> {
+        let mut futures = Vec::new();
+        let cluster = self.cluster.clone();
+        futures.push(cube_ext::spawn(async move {
+            cluster.wait_processing_loops().await
+        }));
+        let rocks_meta_store = self.rocks_meta_store.clone().unwrap();
+        let gc_meta_store = rocks_meta_store.clone();
+        let patterns_meta_store = rocks_meta_store.clone();
+        futures.push(cube_ext::spawn(async move {
+            RocksMetaStore::wait_upload_loop(rocks_meta_store).await;
+            Ok(())
+        }));
+        futures.push(cube_ext::spawn(async move {
+            RocksMetaStore::wait_ingested_batch_gc_loop(gc_meta_store).await;
+            Ok(())
+        }));
+        futures.push(cube_ext::spawn(async move {
+            RocksMetaStore::wait_query_patterns_flush_loop(patterns_meta_store).await;
+            Ok(())
+        }));
+        let demoted_meta_store = self.rocks_meta_store.clone().unwrap();
+        let services = self.clone();
+        futures.push(cube_ext::spawn(async move {
+            if demoted_meta_store.wait_for_demotion().await {
+                error!("Stopping after another node took over the meta store");
+                services.stop_processing_loops().await?;
+            }
+            Ok(())
+        }));
+        let cluster = self.cluster.clone();
+        let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+        futures.push(cube_ext::spawn(async move {
+            ClusterImpl::listen_on_metastore_port(cluster, started_tx).await
+        }));
+        started_rx.await?;
+
+        let scheduler = self.scheduler.clone();
+        futures.extend(SchedulerImpl::spawn_processing_loops(scheduler));
+
+        if self.injector.has_service_typed::<MySqlServer>().await {
+            let mysql_server = self.injector.get_service_typed::<MySqlServer>().await;
+            futures.push(cube_ext::spawn(async move {
+                mysql_server.processing_loop().await
+            }));
+        }
+        if self.injector.has_service_typed::<HttpServer>().await {
+            let http_server = self.injector.get_service_typed::<HttpServer>().await;
+            futures.push(cube_ext::spawn(
+                async move { http_server.run_server().await },
+            ));
+        }
+        Ok(futures)
+    }
+
     pub async fn stop_processing_loops(&self) -> Result<(), CubeError> {
         #[cfg(not(target_os = "windows"))]
         self.cluster.stop_processing_loops().await?;
@@ -200,6 +254,41 @@ pub fn validate_config(c: &dyn ConfigObj) -> ValidationMessages {
             "Router node cannot use remote metastore. Try removing CUBESTORE_META_ADDR".to_string(),
         );
     }
+    if c.metastore_leader_address().is_some() {
+        if !is_router(c) {
+            errors.push(
+                "Worker node cannot run a standby metastore. Try removing CUBESTORE_META_LEADER_ADDR"
+                    .to_string(),
+            );
+        }
+        if c.metastore_bind_address().is_none() {
+            errors.push(
+                "Standby metastore requires CUBESTORE_META_PORT to accept calls after failover"
+                    .to_string(),
+            );
+        }
+    }
+    if c.partition_replication_factor() == 0 {
+        errors.push("CUBESTORE_REPLICATION_FACTOR must be at least 1".to_string());
+    } else if c.select_workers().len() < c.partition_replication_factor() {
+        warnings.push(format!(
+            "CUBESTORE_REPLICATION_FACTOR is {}, but there are only {} workers",
+            c.partition_replication_factor(),
+            c.select_workers().len()
+        ));
+    }
+    if c.tls_cert_path().is_some() != c.tls_key_path().is_some() {
+        errors.push("CUBESTORE_TLS_CERT and CUBESTORE_TLS_KEY must be set together".to_string());
+    }
+    if c.tls_mutual_auth() && (c.tls_cert_path().is_none() || c.tls_ca_path().is_none()) {
+        errors.push(
+            "CUBESTORE_TLS_MUTUAL_AUTH requires CUBESTORE_TLS_CERT, CUBESTORE_TLS_KEY and CUBESTORE_TLS_CA"
+                .to_string(),
+        );
+    } else if let Err(e) = NodeTls::new(c) {
+        // Certificates and keys must be readable and valid.
+        errors.push(e.message);
+    }
     if !is_router(c) && !c.select_workers().contains(c.server_name()) {
         warnings.push(format!("Current worker '{}' is missing in CUBESTORE_WORKERS. Please check CUBESTORE_SERVER_NAME and CUBESTORE_WORKERS variables", c.server_name()));
     }
@@ -292,6 +381,12 @@ pub trait ConfigObj: DIService {
 
     fn metastore_remote_address(&self) -> &Option<String>;
 
+    fn metastore_standby_addresses(&self) -> &Vec<String>;
+
+    fn metastore_leader_address(&self) -> &Option<String>;
+
+    fn metastore_failover_timeout(&self) -> u64;
+
     fn download_concurrency(&self) -> u64;
 
     fn upload_concurrency(&self) -> u64;
@@ -310,9 +405,31 @@ pub trait ConfigObj: DIService {
 
     fn enable_startup_warmup(&self) -> bool;
 
+    fn enable_rebalance_warmup(&self) -> bool;
+
+    fn heart_beat_interval(&self) -> u64;
+
+    fn heart_beat_timeout(&self) -> u64;
+
+    fn partition_replication_factor(&self) -> usize;
+
+    fn hedged_select_timeout_ms(&self) -> u64;
+
+    fn tls_cert_path(&self) -> &Option<String>;
+
+    fn tls_key_path(&self) -> &Option<String>;
+
+    fn tls_ca_path(&self) -> &Option<String>;
+
+    fn tls_mutual_auth(&self) -> bool;
+
     fn malloc_trim_every_secs(&self) -> u64;
 
     fn max_cached_queries(&self) -> usize;
+
+    fn broadcast_join_max_rows(&self) -> u64;
+
+    fn ingested_batch_ttl_secs(&self) -> u64;
 }
 
 #[derive(Debug, Clone)]
@@ -335,6 +452,12 @@ pub struct ConfigObjImpl {
     pub worker_bind_address: Option<String>,
     pub metastore_bind_address: Option<String>,
     pub metastore_remote_address: Option<String>,
+    /// Standby metastores workers fail over to when [metastore_remote_address] is unreachable.
+    pub metastore_standby_addresses: Vec<String>,
+    /// When set, this node runs as a hot-standby replica of the metastore on this address.
+    pub metastore_leader_address: Option<String>,
+    /// Seconds without reaching the leader before the standby promotes itself.
+    pub metastore_failover_timeout: u64,
     pub upload_concurrency: u64,
     pub download_concurrency: u64,
     pub connection_timeout: u64,
@@ -343,8 +466,35 @@ pub struct ConfigObjImpl {
     pub upload_to_remote: bool,
     pub enable_topk: bool,
     pub enable_startup_warmup: bool,
+    /// Download partitions to their new owners before routing to them when `select_workers`
+    /// changes.
+    pub enable_rebalance_warmup: bool,
+    /// Seconds between worker heart beats sent to the router.
+    pub heart_beat_interval: u64,
+    /// Workers without heart beats for this number of seconds are considered unhealthy.
+    pub heart_beat_timeout: u64,
+    /// Number of workers serving each partition.
+    pub partition_replication_factor: usize,
+    /// Selects running longer are also sent to another replica. Zero disables hedging.
+    pub hedged_select_timeout_ms: u64,
+    /// PEM certificate chain served on the worker, metastore and HTTP ports. TLS is enabled when
+    /// it is set together with [tls_key_path].
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
+    /// PEM certificates trusted when nodes connect to each other.
+    pub tls_ca_path: Option<String>,
+    /// Nodes present their certificate to each other and the worker and metastore ports reject
+    /// clients without a certificate signed by [tls_ca_path].
+    pub tls_mutual_auth: bool,
     pub malloc_trim_every_secs: u64,
     pub max_cached_queries: usize,
+    /// Joins without an index sorted on the join keys send the side with at most this number of
+    /// rows to every task of the other side. Larger sides are shuffled into buckets of about
+    /// this number of rows.
+    pub broadcast_join_max_rows: u64,
+    /// Seconds to remember ids of ingested batches for. Batches re-submitted later are applied
+    /// again.
+    pub ingested_batch_ttl_secs: u64,
 }
 
 crate::di_service!(ConfigObjImpl, [ConfigObj]);
@@ -411,6 +561,18 @@ impl ConfigObj for ConfigObjImpl {
         &self.metastore_remote_address
     }
 
+    fn metastore_standby_addresses(&self) -> &Vec<String> {
+        &self.metastore_standby_addresses
+    }
+
+    fn metastore_leader_address(&self) -> &Option<String> {
+        &self.metastore_leader_address
+    }
+
+    fn metastore_failover_timeout(&self) -> u64 {
+        self.metastore_failover_timeout
+    }
+
     fn download_concurrency(&self) -> u64 {
         self.download_concurrency
     }
@@ -446,12 +608,56 @@ impl ConfigObj for ConfigObjImpl {
     fn enable_startup_warmup(&self) -> bool {
         self.enable_startup_warmup
     }
+
+    fn enable_rebalance_warmup(&self) -> bool {
+        self.enable_rebalance_warmup
+    }
+
+    fn heart_beat_interval(&self) -> u64 {
+        self.heart_beat_interval
+    }
+
+    fn heart_beat_timeout(&self) -> u64 {
+        self.heart_beat_timeout
+    }
+
+    fn partition_replication_factor(&self) -> usize {
+        self.partition_replication_factor
+    }
+
+    fn hedged_select_timeout_ms(&self) -> u64 {
+        self.hedged_select_timeout_ms
+    }
+
+    fn tls_cert_path(&self) -> &Option<String> {
+        &self.tls_cert_path
+    }
+
+    fn tls_key_path(&self) -> &Option<String> {
+        &self.tls_key_path
+    }
+
+    fn tls_ca_path(&self) -> &Option<String> {
+        &self.tls_ca_path
+    }
+
+    fn tls_mutual_auth(&self) -> bool {
+        self.tls_mutual_auth
+    }
     fn malloc_trim_every_secs(&self) -> u64 {
         self.malloc_trim_every_secs
     }
     fn max_cached_queries(&self) -> usize {
         self.max_cached_queries
     }
+
+    fn broadcast_join_max_rows(&self) -> u64 {
+        self.broadcast_join_max_rows
+    }
+
+    fn ingested_batch_ttl_secs(&self) -> u64 {
+        self.ingested_batch_ttl_secs
+    }
 }
 
 lazy_static! {
@@ -552,6 +758,12 @@ impl Config {
                 metastore_bind_address: env_optparse::<u16>("CUBESTORE_META_PORT")
                     .map(|v| format!("0.0.0.0:{}", v)),
                 metastore_remote_address: env::var("CUBESTORE_META_ADDR").ok(),
+                metastore_standby_addresses: env::var("CUBESTORE_META_STANDBY_ADDR")
+                    .ok()
+                    .map(|v| v.split(",").map(|s| s.to_string()).collect())
+                    .unwrap_or(Vec::new()),
+                metastore_leader_address: env::var("CUBESTORE_META_LEADER_ADDR").ok(),
+                metastore_failover_timeout: env_parse("CUBESTORE_META_FAILOVER_TIMEOUT", 30),
                 upload_concurrency: env_parse("CUBESTORE_MAX_ACTIVE_UPLOADS", 4),
                 download_concurrency: env_parse("CUBESTORE_MAX_ACTIVE_DOWNLOADS", 8),
                 max_ingestion_data_frames: env_parse("CUBESTORE_MAX_DATA_FRAMES", 4),
@@ -564,8 +776,19 @@ impl Config {
                 upload_to_remote: !env::var("CUBESTORE_NO_UPLOAD").ok().is_some(),
                 enable_topk: env_bool("CUBESTORE_ENABLE_TOPK", true),
                 enable_startup_warmup: env_bool("CUBESTORE_STARTUP_WARMUP", true),
+                enable_rebalance_warmup: env_bool("CUBESTORE_REBALANCE_WARMUP", true),
+                heart_beat_interval: env_parse("CUBESTORE_HEART_BEAT_INTERVAL", 5),
+                heart_beat_timeout: env_parse("CUBESTORE_HEART_BEAT_TIMEOUT", 30),
+                partition_replication_factor: env_parse("CUBESTORE_REPLICATION_FACTOR", 1),
+                hedged_select_timeout_ms: env_parse("CUBESTORE_HEDGED_SELECT_TIMEOUT_MS", 0),
+                tls_cert_path: env::var("CUBESTORE_TLS_CERT").ok(),
+                tls_key_path: env::var("CUBESTORE_TLS_KEY").ok(),
+                tls_ca_path: env::var("CUBESTORE_TLS_CA").ok(),
+                tls_mutual_auth: env_bool("CUBESTORE_TLS_MUTUAL_AUTH", false),
                 malloc_trim_every_secs: env_parse("CUBESTORE_MALLOC_TRIM_EVERY_SECS", 30),
                 max_cached_queries: env_parse("CUBESTORE_MAX_CACHED_QUERIES", 10_000),
+                broadcast_join_max_rows: env_parse("CUBESTORE_BROADCAST_JOIN_MAX_ROWS", 1_000_000),
+                ingested_batch_ttl_secs: env_parse("CUBESTORE_INGESTED_BATCH_TTL", 24 * 60 * 60),
             }),
         }
     }
@@ -599,6 +822,9 @@ impl Config {
                 worker_bind_address: None,
                 metastore_bind_address: None,
                 metastore_remote_address: None,
+                metastore_standby_addresses: Vec::new(),
+                metastore_leader_address: None,
+                metastore_failover_timeout: 30,
                 upload_concurrency: 4,
                 download_concurrency: 8,
                 max_ingestion_data_frames: 4,
@@ -608,8 +834,19 @@ impl Config {
                 upload_to_remote: true,
                 enable_topk: true,
                 enable_startup_warmup: true,
+                enable_rebalance_warmup: true,
+                heart_beat_interval: 5,
+                heart_beat_timeout: 30,
+                partition_replication_factor: 1,
+                hedged_select_timeout_ms: 0,
+                tls_cert_path: None,
+                tls_key_path: None,
+                tls_ca_path: None,
+                tls_mutual_auth: false,
                 malloc_trim_every_secs: 0,
                 max_cached_queries: 10_000,
+                broadcast_join_max_rows: 1_000_000,
+                ingested_batch_ttl_secs: 24 * 60 * 60,
             }),
         }
     }
@@ -669,7 +906,7 @@ impl Config {
             }
         }
         {
-            let services = self.configure().await;
+            let services = self.configure().await.unwrap();
             services.start_processing_loops().await.unwrap();
 
             // Should be long enough even for CI.
@@ -778,7 +1015,7 @@ impl Config {
         self.injector.clone()
     }
 
-    pub async fn configure_injector(&self) {
+    pub async fn configure_injector(&self) -> Result<(), CubeError> {
         self.configure_remote_fs().await;
 
         self.injector
@@ -793,16 +1030,25 @@ impl Config {
         let (event_sender, _) = broadcast::channel(10000); // TODO config
         let event_sender_to_move = event_sender.clone();
 
+        // Created here rather than lazily to fail on startup with invalid certificates.
+        let tls = NodeTls::new(self.config_obj.as_ref())?;
+        self.injector
+            .register_typed::<NodeTls, _, _, _>(async move |_| tls)
+            .await;
+
         self.injector
             .register_typed::<dyn ClusterTransport, _, _, _>(async move |i| {
-                ClusterTransportImpl::new(i.get_service_typed().await)
+                ClusterTransportImpl::new(i.get_service_typed().await, i.get_service_typed().await)
             })
             .await;
 
         if let Some(_) = self.config_obj.metastore_remote_address() {
             self.injector
                 .register_typed::<dyn MetaStoreTransport, _, _, _>(async move |i| {
-                    MetaStoreTransportImpl::new(i.get_service_typed().await)
+                    MetaStoreTransportImpl::new(
+                        i.get_service_typed().await,
+                        i.get_service_typed().await,
+                    )
                 })
                 .await;
         }
@@ -815,6 +1061,25 @@ impl Config {
                 })
                 .await;
         } else {
+            // Leader is known only after the meta store has read the leader lease on startup.
+            self.injector
+                .register("leader_meta_store", async move |i| {
+                    let leader = i
+                        .get_service_typed::<RocksMetaStore>()
+                        .await
+                        .leader_address()
+                        .await;
+                    let transport =
+                        ClusterMetaStoreClient::new(MetaStoreTransportImpl::with_addresses(
+                            i.get_service_typed().await,
+                            i.get_service_typed().await,
+                            leader.into_iter().collect(),
+                        ));
+                    let arc: Arc<dyn DIService> = Arc::new(MetaStoreRpcClient::new(transport));
+                    arc
+                })
+                .await;
+
             let path = self.meta_store_path().to_str().unwrap().to_string();
             self.injector
                 .register_typed_with_default::<dyn MetaStore, RocksMetaStore, _, _>(
@@ -891,6 +1156,34 @@ impl Config {
             })
             .await;
 
+        // Tests register a stand-in broker beforehand.
+        if !self.injector.has_service_typed::<dyn LogBroker>().await {
+            #[cfg(feature = "kafka")]
+            self.injector
+                .register_typed::<dyn LogBroker, _, _, _>(async move |_| {
+                    crate::streaming::kafka::KafkaLogBroker::new()
+                })
+                .await;
+            #[cfg(not(feature = "kafka"))]
+            self.injector
+                .register_typed::<dyn LogBroker, _, _, _>(async move |_| {
+                    crate::streaming::DisabledLogBroker::new()
+                })
+                .await;
+        }
+
+        self.injector
+            .register_typed::<dyn StreamingService, _, _, _>(async move |i| {
+                StreamingServiceImpl::new(
+                    i.get_service_typed().await,
+                    i.get_service_typed().await,
+                    i.get_service_typed().await,
+                    i.get_service_typed().await,
+                    i.get_service_typed().await,
+                )
+            })
+            .await;
+
         self.injector
             .register_typed::<dyn QueryPlanner, _, _, _>(async move |i| {
                 QueryPlannerImpl::new(i.get_service_typed().await, i.get_service_typed().await)
@@ -921,8 +1214,15 @@ impl Config {
                     i.get_service_typed().await,
                     i.get_service_typed().await,
                     i.get_service_typed().await,
+                    i.get_service_typed().await,
                     cluster_meta_store_sender,
                     i.get_service_typed().await,
+                    if i.has_service_typed::<dyn MetaStoreTransport>().await {
+                        Some(i.get_service_typed().await)
+                    } else {
+                        None
+                    },
+                    i.get_service_typed().await,
                 )
             })
             .await;
@@ -975,25 +1275,28 @@ impl Config {
                             .to_string(),
                         i.get_service_typed().await,
                         i.get_service_typed().await,
+                        i.get_service_typed().await,
                     )
                 })
                 .await;
 
             self.injector
                 .register_typed::<HttpServer, _, _, _>(async move |i| {
+                    let config = i.get_service_typed::<dyn ConfigObj>().await;
                     HttpServer::new(
-                        i.get_service_typed::<dyn ConfigObj>()
-                            .await
-                            .http_bind_address()
-                            .as_ref()
-                            .unwrap()
-                            .to_string(),
+                        config.http_bind_address().as_ref().unwrap().to_string(),
                         i.get_service_typed().await,
                         i.get_service_typed().await,
+                        config
+                            .tls_cert_path()
+                            .clone()
+                            .zip(config.tls_key_path().clone()),
                     )
                 })
                 .await;
         }
+
+        Ok(())
     }
 
     pub async fn cube_services(&self) -> CubeServices {
@@ -1012,9 +1315,9 @@ impl Config {
         }
     }
 
-    pub async fn configure(&self) -> CubeServices {
-        self.configure_injector().await;
-        self.cube_services().await
+    pub async fn configure(&self) -> Result<CubeServices, CubeError> {
+        self.configure_injector().await?;
+        Ok(self.cube_services().await)
     }
 
     pub fn configure_worker_services() {
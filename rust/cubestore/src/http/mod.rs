@@ -17,7 +17,7 @@ use crate::util::WorkerLoop;
 use crate::CubeError;
 use async_std::fs::File;
 use datafusion::cube_ext;
-use futures::{AsyncWriteExt, SinkExt, Stream, StreamExt};
+use futures::{AsyncWriteExt, FutureExt, SinkExt, Stream, StreamExt};
 use hex::ToHex;
 use http_auth_basic::Credentials;
 use log::error;
@@ -38,6 +38,7 @@ pub struct HttpServer {
     bind_address: String,
     sql_service: Arc<dyn SqlService>,
     auth: Arc<dyn SqlAuthService>,
+    tls_paths: Option<(/*cert*/ String, /*key*/ String)>,
     worker_loop: WorkerLoop,
     cancel_token: CancellationToken,
 }
@@ -68,11 +69,13 @@ impl HttpServer {
         bind_address: String,
         auth: Arc<dyn SqlAuthService>,
         sql_service: Arc<dyn SqlService>,
+        tls_paths: Option<(String, String)>,
     ) -> Arc<Self> {
         Arc::new(Self {
             bind_address,
             auth,
             sql_service,
+            tls_paths,
             worker_loop: WorkerLoop::new("HttpServer message processing"),
             cancel_token: CancellationToken::new(),
         })
@@ -224,8 +227,9 @@ impl HttpServer {
             },
         );
         let cancel_token = self.cancel_token.clone();
-        let (_, server_future) = warp::serve(query_route.or(upload_route).recover(
-            |err: Rejection| async move {
+        let routes = query_route
+            .or(upload_route)
+            .recover(|err: Rejection| async move {
                 let mut obj = HashMap::new();
                 if let Some(ws_error) = err.find::<CubeRejection>() {
                     match ws_error {
@@ -247,9 +251,24 @@ impl HttpServer {
                 } else {
                     Err(err)
                 }
-            },
-        ))
-        .bind_with_graceful_shutdown(addr, async move { cancel_token.cancelled().await });
+            });
+        let shutdown = async move { cancel_token.cancelled().await };
+        let server_future = match &self.tls_paths {
+            Some((cert_path, key_path)) => {
+                info!("Http Server uses TLS");
+                warp::serve(routes)
+                    .tls()
+                    .cert_path(cert_path)
+                    .key_path(key_path)
+                    .bind_with_graceful_shutdown(addr, shutdown)
+                    .1
+                    .boxed()
+            }
+            None => warp::serve(routes)
+                .bind_with_graceful_shutdown(addr, shutdown)
+                .1
+                .boxed(),
+        };
         let _ = tokio::join!(process_loop, server_future);
 
         Ok(())
@@ -455,6 +474,13 @@ impl HttpMessage {
                                         &HttpColumnValueArgs { string_value },
                                     )
                                 }
+                                TableValue::Date(v) => {
+                                    let string_value = Some(builder.create_string(&v.to_string()));
+                                    HttpColumnValue::create(
+                                        &mut builder,
+                                        &HttpColumnValueArgs { string_value },
+                                    )
+                                }
                                 TableValue::Timestamp(v) => {
                                     let string_value = Some(builder.create_string(&v.to_string()));
                                     HttpColumnValue::create(
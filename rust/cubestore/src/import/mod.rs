@@ -16,6 +16,7 @@ use futures::future::join_all;
 use futures::{Stream, StreamExt};
 use itertools::Itertools;
 use mockall::automock;
+use num::BigInt;
 use num::ToPrimitive;
 use pin_project_lite::pin_project;
 use tempfile::TempPath;
@@ -32,7 +33,7 @@ use crate::metastore::table::Table;
 use crate::metastore::{is_valid_plain_binary_hll, HllFlavour, IdRow};
 use crate::metastore::{Column, ColumnType, ImportFormat, MetaStore};
 use crate::remotefs::RemoteFs;
-use crate::sql::timestamp_from_string;
+use crate::sql::{date_from_string, timestamp_from_string};
 use crate::store::ChunkDataStore;
 use crate::table::data::{append_row, create_array_builders};
 use crate::table::{Row, TableValue};
@@ -112,47 +113,7 @@ impl ImportFormat {
                         } else {
                             row.insert(
                                 mapping_insert_indices[i],
-                                match column.get_column_type() {
-                                    ColumnType::String => {
-                                        TableValue::String(value_buf.take_string())
-                                    }
-                                    ColumnType::Int => value
-                                        .parse()
-                                        .map(|v| TableValue::Int(v))
-                                        .unwrap_or(TableValue::Null),
-                                    t @ ColumnType::Decimal { .. } => {
-                                        TableValue::Decimal(parse_decimal(
-                                            value,
-                                            u8::try_from(t.target_scale()).unwrap(),
-                                        )?)
-                                    }
-                                    ColumnType::Bytes => TableValue::Bytes(base64::decode(value)?),
-                                    ColumnType::HyperLogLog(HllFlavour::Snowflake) => {
-                                        let hll = HllSketch::read_snowflake(value)?;
-                                        TableValue::Bytes(hll.write())
-                                    }
-                                    ColumnType::HyperLogLog(HllFlavour::Postgres) => {
-                                        let data = base64::decode(value)?;
-                                        let hll = HllSketch::read_hll_storage_spec(&data)?;
-                                        TableValue::Bytes(hll.write())
-                                    }
-                                    ColumnType::HyperLogLog(
-                                        f @ (HllFlavour::Airlift | HllFlavour::ZetaSketch),
-                                    ) => {
-                                        let data = base64::decode(value)?;
-                                        is_valid_plain_binary_hll(&data, *f)?;
-                                        TableValue::Bytes(data)
-                                    }
-                                    ColumnType::Timestamp => {
-                                        TableValue::Timestamp(timestamp_from_string(value)?)
-                                    }
-                                    ColumnType::Float => {
-                                        TableValue::Float(OrdF64(value.parse::<f64>()?))
-                                    }
-                                    ColumnType::Boolean => {
-                                        TableValue::Boolean(value.to_lowercase() == "true")
-                                    }
-                                },
+                                parse_table_value(column, value_buf)?,
                             );
                         }
 
@@ -166,24 +127,77 @@ impl ImportFormat {
     }
 }
 
+/// Converts a non-empty textual value into a value of the column type.
+pub(crate) fn parse_table_value(
+    column: &Column,
+    value_buf: MaybeOwnedStr,
+) -> Result<TableValue, CubeError> {
+    let value = value_buf.as_ref();
+    Ok(match column.get_column_type() {
+        ColumnType::String => TableValue::String(value_buf.take_string()),
+        ColumnType::Int => value
+            .parse()
+            .map(|v| TableValue::Int(v))
+            .unwrap_or(TableValue::Null),
+        t @ ColumnType::Decimal { .. } => TableValue::Decimal(parse_decimal(
+            value,
+            u8::try_from(t.target_scale()).unwrap(),
+        )?),
+        ColumnType::Bytes => TableValue::Bytes(base64::decode(value)?),
+        ColumnType::HyperLogLog(HllFlavour::Snowflake) => {
+            let hll = HllSketch::read_snowflake(value)?;
+            TableValue::Bytes(hll.write())
+        }
+        ColumnType::HyperLogLog(HllFlavour::Postgres) => {
+            let data = base64::decode(value)?;
+            let hll = HllSketch::read_hll_storage_spec(&data)?;
+            TableValue::Bytes(hll.write())
+        }
+        ColumnType::HyperLogLog(f @ (HllFlavour::Airlift | HllFlavour::ZetaSketch)) => {
+            let data = base64::decode(value)?;
+            is_valid_plain_binary_hll(&data, *f)?;
+            TableValue::Bytes(data)
+        }
+        ColumnType::Timestamp => TableValue::Timestamp(timestamp_from_string(value)?),
+        ColumnType::Float => TableValue::Float(OrdF64(value.parse::<f64>()?)),
+        ColumnType::Boolean => TableValue::Boolean(value.to_lowercase() == "true"),
+        ColumnType::Int32 => value
+            .parse::<i32>()
+            .map(|v| TableValue::Int(v as i64))
+            .unwrap_or(TableValue::Null),
+        ColumnType::Date => TableValue::Date(date_from_string(value)?),
+        t @ ColumnType::Decimal128 { .. } => TableValue::Decimal(parse_decimal_i128(
+            value,
+            u8::try_from(t.target_scale()).unwrap(),
+        )?),
+    })
+}
+
 pub(crate) fn parse_decimal(value: &str, scale: u8) -> Result<Decimal, CubeError> {
+    match scaled_decimal(value, scale)?.to_i64() {
+        Some(d) => Ok(Decimal::new(d)),
+        None => Err(precision_loss_error(value, scale)),
+    }
+}
+
+pub(crate) fn parse_decimal_i128(value: &str, scale: u8) -> Result<Decimal, CubeError> {
+    match scaled_decimal(value, scale)?.to_i128() {
+        Some(d) => Ok(Decimal::new_i128(d)),
+        None => Err(precision_loss_error(value, scale)),
+    }
+}
+
+fn scaled_decimal(value: &str, scale: u8) -> Result<BigInt, CubeError> {
     // TODO: parse into Decimal directly.
     let bd = BigDecimal::from_str_radix(value, 10)?;
-    let raw_value = match bd
-        .with_scale(scale as i64)
-        .into_bigint_and_exponent()
-        .0
-        .to_i64()
-    {
-        Some(d) => d,
-        None => {
-            return Err(CubeError::user(format!(
-                "cannot represent '{}' with scale {} without loosing precision",
-                value, scale
-            )))
-        }
-    };
-    Ok(Decimal::new(raw_value))
+    Ok(bd.with_scale(scale as i64).into_bigint_and_exponent().0)
+}
+
+fn precision_loss_error(value: &str, scale: u8) -> CubeError {
+    CubeError::user(format!(
+        "cannot represent '{}' with scale {} without loosing precision",
+        value, scale
+    ))
 }
 
 struct CsvLineParser<'a> {
@@ -436,6 +450,18 @@ impl ImportServiceImpl {
         format: ImportFormat,
         location: &str,
     ) -> Result<(), CubeError> {
+        // Locations of a table are imported only once, e.g. when import jobs are retried.
+        let mut ingestion = Ingestion::new(
+            self.meta_store.clone(),
+            self.chunk_store.clone(),
+            self.limits.clone(),
+            table.clone(),
+            Some(table.get_row().location_batch_id(location)),
+        );
+        if ingestion.is_batch_ingested().await? {
+            return Ok(());
+        }
+
         let temp_dir = self.config_obj.data_dir().join("tmp");
         tokio::fs::create_dir_all(temp_dir.clone()).await?;
 
@@ -450,13 +476,6 @@ impl ImportServiceImpl {
             )
             .await?;
 
-        let mut ingestion = Ingestion::new(
-            self.meta_store.clone(),
-            self.chunk_store.clone(),
-            self.limits.clone(),
-            table.clone(),
-        );
-
         let finish = |builders: Vec<Box<dyn ArrayBuilder>>| {
             builders.into_iter().map(|mut b| b.finish()).collect_vec()
         };
@@ -548,14 +567,17 @@ impl ImportService for ImportServiceImpl {
     }
 }
 
-/// Handles row-based data ingestion, e.g. on CSV import and SQL insert.
+/// Handles row-based data ingestion, e.g. on CSV import, SQL insert and streaming.
+/// Data with a batch id is activated all at once and only if the batch was not ingested before.
 pub struct Ingestion {
     meta_store: Arc<dyn MetaStore>,
     chunk_store: Arc<dyn ChunkDataStore>,
     limits: Arc<ConcurrencyLimits>,
     table: IdRow<Table>,
+    batch_id: Option<String>,
 
-    partition_jobs: Vec<JoinHandle<Result<(), CubeError>>>,
+    /// Return ids of chunks awaiting activation, which are only deferred for batches.
+    partition_jobs: Vec<JoinHandle<Result<Vec<u64>, CubeError>>>,
 }
 
 impl Ingestion {
@@ -564,16 +586,30 @@ impl Ingestion {
         chunk_store: Arc<dyn ChunkDataStore>,
         limits: Arc<ConcurrencyLimits>,
         table: IdRow<Table>,
+        batch_id: Option<String>,
     ) -> Ingestion {
         Ingestion {
             meta_store,
             chunk_store,
             limits,
             table,
+            batch_id,
             partition_jobs: Vec::new(),
         }
     }
 
+    /// True if the batch of this ingestion was already ingested, so the data can be skipped.
+    pub async fn is_batch_ingested(&self) -> Result<bool, CubeError> {
+        match &self.batch_id {
+            None => Ok(false),
+            Some(batch_id) => Ok(self
+                .meta_store
+                .get_ingested_batch(self.table.get_id(), batch_id.clone())
+                .await?
+                .is_some()),
+        }
+    }
+
     pub async fn queue_data_frame(&mut self, rows: Vec<ArrayRef>) -> Result<(), CubeError> {
         let active_data_frame = self.limits.acquire_data_frame().await?;
 
@@ -581,6 +617,7 @@ impl Ingestion {
         let chunk_store = self.chunk_store.clone();
         let columns = self.table.get_row().get_columns().clone().clone();
         let table_id = self.table.get_id();
+        let activate = self.batch_id.is_none();
         self.partition_jobs.push(cube_ext::spawn(async move {
             let new_chunks = chunk_store.partition_data(table_id, rows, &columns).await?;
             std::mem::drop(active_data_frame);
@@ -592,15 +629,35 @@ impl Ingestion {
                 .into_iter()
                 .map(|c| Ok(c??.get_id()))
                 .collect();
-            meta_store.activate_chunks(table_id, new_chunk_ids?).await
+            if !activate {
+                return new_chunk_ids;
+            }
+            meta_store.activate_chunks(table_id, new_chunk_ids?).await?;
+            Ok(Vec::new())
         }));
 
         Ok(())
     }
 
     pub async fn wait_completion(self) -> Result<(), CubeError> {
+        let mut chunk_ids = Vec::new();
         for j in self.partition_jobs {
-            j.await??;
+            chunk_ids.extend(j.await??);
+        }
+
+        if let Some(batch_id) = self.batch_id {
+            let table_id = self.table.get_id();
+            if !self
+                .meta_store
+                .activate_batch_chunks(table_id, batch_id.clone(), chunk_ids)
+                .await?
+            {
+                log::info!(
+                    "Skipped batch {} already ingested into table {}",
+                    batch_id,
+                    table_id
+                );
+            }
         }
 
         Ok(())
@@ -21,6 +21,7 @@ use crate::metastore::TableId;
 use crate::remotefs::queue::RemoteFsOpResult;
 use arrow::error::ArrowError;
 use cubehll::HllError;
+use cubemysql::BindError;
 use cubezetasketch::ZetaError;
 use flexbuffers::{DeserializationError, ReaderError};
 use log::SetLoggerError;
@@ -50,6 +51,7 @@ pub mod remotefs;
 pub mod scheduler;
 pub mod sql;
 pub mod store;
+pub mod streaming;
 pub mod sys;
 pub mod table;
 pub mod telemetry;
@@ -344,6 +346,13 @@ impl From<serde_json::Error> for CubeError {
     }
 }
 
+#[cfg(feature = "kafka")]
+impl From<rdkafka::error::KafkaError> for CubeError {
+    fn from(v: rdkafka::error::KafkaError) -> Self {
+        CubeError::from_error(v)
+    }
+}
+
 impl From<PoisonError<std::sync::MutexGuard<'_, std::collections::HashMap<TableId, u64>>>>
     for CubeError
 {
@@ -372,6 +381,12 @@ impl From<hex::FromHexError> for CubeError {
     }
 }
 
+impl From<BindError> for CubeError {
+    fn from(v: BindError) -> Self {
+        CubeError::user(v.message)
+    }
+}
+
 impl From<HllError> for CubeError {
     fn from(v: HllError) -> Self {
         return CubeError::from_error(v);
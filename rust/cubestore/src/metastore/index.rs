@@ -1,5 +1,6 @@
 use super::{
-    BaseRocksSecondaryIndex, Column, Index, IndexId, RocksSecondaryIndex, RocksTable, TableId,
+    BaseRocksSecondaryIndex, Column, Index, IndexBuildSources, IndexId, RocksSecondaryIndex,
+    RocksTable, TableId,
 };
 use crate::metastore::{IdRow, MetaStoreEvent};
 use crate::{rocks_table_impl, CubeError};
@@ -26,9 +27,15 @@ impl Index {
             table_id,
             columns,
             sort_key_size,
+            is_ready: true,
+            build_sources: None,
         })
     }
 
+    pub fn is_ready_default() -> bool {
+        true
+    }
+
     pub fn table_id(&self) -> u64 {
         return self.table_id;
     }
@@ -49,6 +56,29 @@ impl Index {
     pub fn sort_key_size(&self) -> u64 {
         self.sort_key_size
     }
+
+    pub fn is_ready(&self) -> bool {
+        self.is_ready
+    }
+
+    /// Set while the index is being built.
+    pub fn build_sources(&self) -> Option<&IndexBuildSources> {
+        self.build_sources.as_ref()
+    }
+
+    pub fn building(&self, sources: IndexBuildSources) -> Index {
+        let mut i = self.clone();
+        i.is_ready = false;
+        i.build_sources = Some(sources);
+        i
+    }
+
+    pub fn built(&self) -> Index {
+        let mut i = self.clone();
+        i.is_ready = true;
+        i.build_sources = None;
+        i
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
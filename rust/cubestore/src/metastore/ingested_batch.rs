@@ -0,0 +1,111 @@
+use super::{BaseRocksSecondaryIndex, IndexId, RocksSecondaryIndex, RocksTable, TableId};
+use crate::base_rocks_secondary_index;
+use crate::metastore::{IdRow, MetaStoreEvent};
+use crate::rocks_table_impl;
+use byteorder::{BigEndian, WriteBytesExt};
+use chrono::{DateTime, Utc};
+use rocksdb::DB;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::io::{Cursor, Write};
+
+/// Client-supplied id of data ingested into a table. Data with the same batch id is ingested
+/// only once.
+#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
+pub struct IngestedBatch {
+    table_id: u64,
+    batch_id: String,
+    chunk_ids: Vec<u64>,
+    created_at: DateTime<Utc>,
+}
+
+impl IngestedBatch {
+    pub fn new(table_id: u64, batch_id: String, chunk_ids: Vec<u64>) -> IngestedBatch {
+        IngestedBatch {
+            table_id,
+            batch_id,
+            chunk_ids,
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn table_id(&self) -> u64 {
+        self.table_id
+    }
+
+    pub fn batch_id(&self) -> &String {
+        &self.batch_id
+    }
+
+    /// Chunks activated when the batch was ingested.
+    pub fn chunk_ids(&self) -> &Vec<u64> {
+        &self.chunk_ids
+    }
+
+    pub fn created_at(&self) -> &DateTime<Utc> {
+        &self.created_at
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum IngestedBatchRocksIndex {
+    TableID = 1,
+    BatchID,
+}
+
+base_rocks_secondary_index!(IngestedBatch, IngestedBatchRocksIndex);
+
+rocks_table_impl!(
+    IngestedBatch,
+    IngestedBatchRocksTable,
+    TableId::IngestedBatches,
+    {
+        vec![
+            Box::new(IngestedBatchRocksIndex::TableID),
+            Box::new(IngestedBatchRocksIndex::BatchID),
+        ]
+    }
+);
+
+#[derive(Hash, Clone, Debug)]
+pub enum IngestedBatchIndexKey {
+    ByTable(u64),
+    ByBatch(u64, String),
+}
+
+impl RocksSecondaryIndex<IngestedBatch, IngestedBatchIndexKey> for IngestedBatchRocksIndex {
+    fn typed_key_by(&self, row: &IngestedBatch) -> IngestedBatchIndexKey {
+        match self {
+            IngestedBatchRocksIndex::TableID => IngestedBatchIndexKey::ByTable(row.table_id),
+            IngestedBatchRocksIndex::BatchID => {
+                IngestedBatchIndexKey::ByBatch(row.table_id, row.batch_id.clone())
+            }
+        }
+    }
+
+    fn key_to_bytes(&self, key: &IngestedBatchIndexKey) -> Vec<u8> {
+        match key {
+            IngestedBatchIndexKey::ByTable(table_id) => {
+                let mut buf = Vec::with_capacity(8);
+                buf.write_u64::<BigEndian>(*table_id).unwrap();
+                buf
+            }
+            IngestedBatchIndexKey::ByBatch(table_id, batch_id) => {
+                let mut buf = Cursor::new(Vec::new());
+                buf.write_u64::<BigEndian>(*table_id).unwrap();
+                buf.write_all(batch_id.as_bytes()).unwrap();
+                buf.into_inner()
+            }
+        }
+    }
+
+    fn is_unique(&self) -> bool {
+        match self {
+            IngestedBatchRocksIndex::TableID => false,
+            IngestedBatchRocksIndex::BatchID => true,
+        }
+    }
+
+    fn get_id(&self) -> IndexId {
+        *self as IndexId
+    }
+}
@@ -1,8 +1,11 @@
 pub mod chunks;
 pub mod index;
+pub mod ingested_batch;
 pub mod job;
 pub mod listener;
 pub mod partition;
+pub mod query_pattern;
+pub mod replication;
 pub mod schema;
 pub mod table;
 pub mod wal;
@@ -24,8 +27,16 @@ use crate::config::injection::DIService;
 use crate::config::{Config, ConfigObj};
 use crate::metastore::chunks::{ChunkIndexKey, ChunkRocksIndex};
 use crate::metastore::index::IndexIndexKey;
+use crate::metastore::ingested_batch::{
+    IngestedBatch, IngestedBatchIndexKey, IngestedBatchRocksIndex, IngestedBatchRocksTable,
+};
 use crate::metastore::job::{Job, JobIndexKey, JobRocksIndex, JobRocksTable, JobStatus};
 use crate::metastore::partition::PartitionIndexKey;
+use crate::metastore::query_pattern::{
+    QueryPattern, QueryPatternIndexKey, QueryPatternKind, QueryPatternRocksIndex,
+    QueryPatternRocksTable,
+};
+use crate::metastore::replication::ReplicationUpdate;
 use crate::metastore::table::{TableIndexKey, TablePath};
 use crate::metastore::wal::{WALIndexKey, WALRocksIndex};
 use crate::remotefs::{LocalDirRemoteFs, RemoteFs};
@@ -66,6 +77,7 @@ use table::Table;
 use table::{TableRocksIndex, TableRocksTable};
 use tokio::fs::File;
 use tokio::sync::broadcast::Sender;
+use tokio::sync::watch;
 use wal::WALRocksTable;
 
 #[macro_export]
@@ -231,6 +243,20 @@ impl DataFrameValue<String> for Vec<Column> {
     }
 }
 
+impl DataFrameValue<String> for Option<IndexBuildSources> {
+    fn value(v: &Self) -> String {
+        v.as_ref()
+            .map(|v| {
+                format!(
+                    "{} partitions, {} chunks",
+                    v.partition_ids.len(),
+                    v.chunk_ids.len()
+                )
+            })
+            .unwrap_or("NULL".to_string())
+    }
+}
+
 impl DataFrameValue<String> for Option<String> {
     fn value(v: &Self) -> String {
         v.as_ref()
@@ -263,6 +289,14 @@ impl DataFrameValue<String> for Option<ImportFormat> {
     }
 }
 
+impl DataFrameValue<String> for Option<StreamSource> {
+    fn value(v: &Self) -> String {
+        v.as_ref()
+            .map(|v| format!("{:?}://{}/{} ({:?})", v.kind, v.brokers, v.topic, v.format))
+            .unwrap_or("NULL".to_string())
+    }
+}
+
 impl DataFrameValue<String> for Option<u64> {
     fn value(v: &Self) -> String {
         v.as_ref()
@@ -288,6 +322,7 @@ impl DataFrameValue<String> for Option<Row> {
                             TableValue::Boolean(b) => format!("{:?}", b),
                             TableValue::Decimal(v) => format!("{}", v.raw_value()),
                             TableValue::Float(v) => format!("{}", v),
+                            TableValue::Date(d) => format!("{:?}", d),
                         })
                         .join(", ")
                 )
@@ -327,9 +362,20 @@ pub enum ColumnType {
     Bytes,
     HyperLogLog(HllFlavour), // HLL Sketches, compatible with presto.
     Timestamp,
-    Decimal { scale: i32, precision: i32 },
+    Decimal {
+        scale: i32,
+        precision: i32,
+    },
     Float,
     Boolean,
+    Int32,
+    /// Days since the Unix epoch.
+    Date,
+    /// Decimals with precision above 18, stored as 128-bit integers with the exact scale.
+    Decimal128 {
+        scale: i32,
+        precision: i32,
+    },
 }
 
 impl ColumnType {
@@ -342,6 +388,7 @@ impl ColumnType {
                     *scale
                 }
             }
+            ColumnType::Decimal128 { scale, .. } => *scale,
             x => panic!("target_scale called on {:?}", x),
         }
     }
@@ -400,6 +447,30 @@ impl From<&Column> for parquet::schema::types::Type {
                     .build()
                     .unwrap()
             }
+            ColumnType::Int32 => {
+                types::Type::primitive_type_builder(&column.get_name(), Type::INT32)
+                    .with_converted_type(ConvertedType::INT_32)
+                    .with_repetition(Repetition::OPTIONAL)
+                    .build()
+                    .unwrap()
+            }
+            ColumnType::Date => {
+                types::Type::primitive_type_builder(&column.get_name(), Type::INT32)
+                    .with_converted_type(ConvertedType::DATE)
+                    .with_repetition(Repetition::OPTIONAL)
+                    .build()
+                    .unwrap()
+            }
+            ColumnType::Decimal128 { scale, precision } => {
+                types::Type::primitive_type_builder(&column.get_name(), Type::FIXED_LEN_BYTE_ARRAY)
+                    .with_length(16)
+                    .with_converted_type(ConvertedType::DECIMAL)
+                    .with_precision(*precision)
+                    .with_scale(*scale)
+                    .with_repetition(Repetition::OPTIONAL)
+                    .build()
+                    .unwrap()
+            }
         }
     }
 }
@@ -432,6 +503,11 @@ impl<'a> Into<Field> for &'a Column {
                 ColumnType::Bytes => DataType::Binary,
                 ColumnType::HyperLogLog(_) => DataType::Binary,
                 ColumnType::Float => DataType::Float64,
+                ColumnType::Int32 => DataType::Int32,
+                ColumnType::Date => DataType::Date32,
+                ColumnType::Decimal128 { scale, precision } => {
+                    DataType::Decimal(precision as usize, scale as usize)
+                }
             },
             true,
         )
@@ -454,6 +530,11 @@ impl fmt::Display for Column {
             ColumnType::HyperLogLog(HllFlavour::Postgres) => "HLL_POSTGRES".to_string(),
             ColumnType::HyperLogLog(HllFlavour::Snowflake) => "HLL_SNOWFLAKE".to_string(),
             ColumnType::Float => "FLOAT".to_string(),
+            ColumnType::Int32 => "INT32".to_string(),
+            ColumnType::Date => "DATE".to_string(),
+            ColumnType::Decimal128 { scale, precision } => {
+                format!("DECIMAL({}, {})", precision, scale)
+            }
         };
         f.write_fmt(format_args!("{} {}", self.name, column_type))
     }
@@ -464,6 +545,26 @@ pub enum ImportFormat {
     CSV,
 }
 
+/// A log, e.g. a Kafka topic, that is continuously ingested into a table.
+#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
+pub struct StreamSource {
+    pub kind: StreamSourceKind,
+    pub brokers: String,
+    pub topic: String,
+    pub format: StreamFormat,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
+pub enum StreamSourceKind {
+    Kafka,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
+pub enum StreamFormat {
+    /// Each record is a JSON object with column names as keys.
+    JSON,
+}
+
 data_frame_from! {
 #[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
 pub struct Schema {
@@ -477,7 +578,12 @@ pub struct Index {
     name: String,
     table_id: u64,
     columns: Vec<Column>,
-    sort_key_size: u64
+    sort_key_size: u64,
+    /// Indexes created on tables with data are not used by queries until built.
+    #[serde(default="Index::is_ready_default")]
+    is_ready: bool,
+    #[serde(default)]
+    build_sources: Option<IndexBuildSources>
 }
 }
 
@@ -487,6 +593,14 @@ pub struct IndexDef {
     pub columns: Vec<String>,
 }
 
+/// Data of the default index written before a new index was created. Data written later goes
+/// to all indexes of the table.
+#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
+pub struct IndexBuildSources {
+    pub partition_ids: Vec<u64>,
+    pub chunk_ids: Vec<u64>,
+}
+
 data_frame_from! {
 #[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
 pub struct Partition {
@@ -682,6 +796,8 @@ pub trait MetaStore: DIService + Send + Sync {
         columns: Vec<Column>,
         locations: Option<Vec<String>>,
         import_format: Option<ImportFormat>,
+        stream_source: Option<StreamSource>,
+        batch_id: Option<String>,
         indexes: Vec<IndexDef>,
         is_ready: bool,
     ) -> Result<IdRow<Table>, CubeError>;
@@ -695,6 +811,15 @@ pub trait MetaStore: DIService + Send + Sync {
     async fn get_tables(&self) -> Result<Vec<IdRow<Table>>, CubeError>;
     async fn get_tables_with_path(&self) -> Result<Arc<Vec<TablePath>>, CubeError>;
     async fn drop_table(&self, table_id: u64) -> Result<IdRow<Table>, CubeError>;
+    /// Exchanges the schemas and names of two ready tables in a single write. Queries planned
+    /// before the swap keep reading data of the table they were planned with.
+    async fn swap_tables(
+        &self,
+        schema_name: String,
+        table_name: String,
+        other_schema_name: String,
+        other_table_name: String,
+    ) -> Result<(), CubeError>;
 
     fn partition_table(&self) -> PartitionMetaStoreTable;
     async fn create_partition(&self, partition: Partition) -> Result<IdRow<Partition>, CubeError>;
@@ -722,7 +847,15 @@ pub trait MetaStore: DIService + Send + Sync {
         index_def: IndexDef,
     ) -> Result<IdRow<Index>, CubeError>;
     async fn get_default_index(&self, table_id: u64) -> Result<IdRow<Index>, CubeError>;
+    /// Includes indexes which are still being built.
     async fn get_table_indexes(&self, table_id: u64) -> Result<Vec<IdRow<Index>>, CubeError>;
+    /// Activates chunks with data copied from [IndexBuildSources] and makes the index available
+    /// to queries.
+    async fn index_built(
+        &self,
+        index_id: u64,
+        uploaded_chunk_ids: Vec<u64>,
+    ) -> Result<IdRow<Index>, CubeError>;
     async fn get_active_partitions_by_index_id(
         &self,
         index_id: u64,
@@ -768,8 +901,25 @@ pub trait MetaStore: DIService + Send + Sync {
         table_id: u64,
         uploaded_chunk_ids: Vec<u64>,
     ) -> Result<(), CubeError>;
+    /// Activates chunks like [activate_chunks] and records `batch_id` as ingested into the table.
+    /// If the batch was already ingested, deletes the chunks instead and returns false.
+    async fn activate_batch_chunks(
+        &self,
+        table_id: u64,
+        batch_id: String,
+        uploaded_chunk_ids: Vec<u64>,
+    ) -> Result<bool, CubeError>;
     async fn delete_chunk(&self, chunk_id: u64) -> Result<IdRow<Chunk>, CubeError>;
 
+    async fn get_ingested_batch(
+        &self,
+        table_id: u64,
+        batch_id: String,
+    ) -> Result<Option<IdRow<IngestedBatch>>, CubeError>;
+    /// Forgets batches ingested before `created_before`, they can be ingested again afterwards.
+    async fn delete_ingested_batches(&self, created_before: DateTime<Utc>)
+        -> Result<(), CubeError>;
+
     async fn create_wal(&self, table_id: u64, row_count: usize) -> Result<IdRow<WAL>, CubeError>;
     async fn get_wal(&self, wal_id: u64) -> Result<IdRow<WAL>, CubeError>;
     async fn delete_wal(&self, wal_id: u64) -> Result<(), CubeError>;
@@ -786,12 +936,25 @@ pub trait MetaStore: DIService + Send + Sync {
     async fn update_status(&self, job_id: u64, status: JobStatus) -> Result<IdRow<Job>, CubeError>;
     async fn update_heart_beat(&self, job_id: u64) -> Result<IdRow<Job>, CubeError>;
 
+    /// Returns only indexes that are ready to be queried.
     async fn get_tables_with_indexes(
         &self,
         table_name: Vec<(String, String)>,
     ) -> Result<Vec<(IdRow<Schema>, IdRow<Table>, Vec<IdRow<Index>>)>, CubeError>;
 
+    /// Adds queries of `patterns` to the stored ones with the same table, kind and columns.
+    /// Patterns are persisted in the background and are visible to [get_query_patterns] right
+    /// away.
+    async fn record_query_patterns(&self, patterns: Vec<QueryPattern>) -> Result<(), CubeError>;
+    async fn get_query_patterns(&self) -> Result<Vec<IdRow<QueryPattern>>, CubeError>;
+
     async fn debug_dump(&self, out_path: String) -> Result<(), CubeError>;
+
+    /// Used by standby routers to follow the leader, see [replication].
+    async fn get_replication_updates(
+        &self,
+        since_seq: Option<u64>,
+    ) -> Result<ReplicationUpdate, CubeError>;
 }
 
 /// Information required to produce partition name on remote fs.
@@ -812,16 +975,20 @@ pub enum MetaStoreEvent {
 
     UpdateChunk(IdRow<Chunk>, IdRow<Chunk>),
     UpdateIndex(IdRow<Index>, IdRow<Index>),
+    UpdateIngestedBatch(IdRow<IngestedBatch>, IdRow<IngestedBatch>),
     UpdateJob(IdRow<Job>, IdRow<Job>),
     UpdatePartition(IdRow<Partition>, IdRow<Partition>),
+    UpdateQueryPattern(IdRow<QueryPattern>, IdRow<QueryPattern>),
     UpdateSchema(IdRow<Schema>, IdRow<Schema>),
     UpdateTable(IdRow<Table>, IdRow<Table>),
     UpdateWAL(IdRow<WAL>, IdRow<WAL>),
 
     DeleteChunk(IdRow<Chunk>),
     DeleteIndex(IdRow<Index>),
+    DeleteIngestedBatch(IdRow<IngestedBatch>),
     DeleteJob(IdRow<Job>),
     DeletePartition(IdRow<Partition>),
+    DeleteQueryPattern(IdRow<QueryPattern>),
     DeleteSchema(IdRow<Schema>),
     DeleteTable(IdRow<Table>),
     DeleteWAL(IdRow<WAL>),
@@ -930,7 +1097,9 @@ enum_from_primitive! {
         Partitions = 0x0400,
         Chunks = 0x0500,
         WALs = 0x0600,
-        Jobs = 0x0700
+        Jobs = 0x0700,
+        QueryPatterns = 0x0800,
+        IngestedBatches = 0x0900
     }
 }
 
@@ -961,8 +1130,21 @@ pub struct RocksMetaStore {
     last_upload_seq: Arc<RwLock<u64>>,
     last_check_seq: Arc<RwLock<u64>>,
     upload_loop: Arc<WorkerLoop>,
+    ingested_batch_gc_loop: Arc<WorkerLoop>,
+    /// Query patterns recorded since the last flush, merged by table, kind and columns.
+    pending_query_patterns: Arc<Mutex<HashMap<(u64, QueryPatternKind, Vec<String>), QueryPattern>>>,
+    query_patterns_flush_loop: Arc<WorkerLoop>,
     config: Arc<dyn ConfigObj>,
     cached_tables: Arc<Mutex<Option<Arc<Vec<TablePath>>>>>,
+    standby_tx: Arc<watch::Sender<bool>>,
+    standby_rx: watch::Receiver<bool>,
+    replicated_seq: Arc<RwLock<Option<u64>>>,
+    last_leader_contact: Arc<RwLock<SystemTime>>,
+    replication_loop: Arc<WorkerLoop>,
+    /// Epoch of the [replication::LeaderLease] this node holds.
+    leader_epoch: Arc<RwLock<Option<u64>>>,
+    lease_checked: Arc<RwLock<SystemTime>>,
+    leader_address: Arc<RwLock<Option<String>>>,
 }
 
 trait BaseRocksSecondaryIndex<T>: Debug {
@@ -1533,13 +1715,10 @@ impl RocksMetaStore {
         remote_fs: Arc<dyn RemoteFs>,
         config: Arc<dyn ConfigObj>,
     ) -> RocksMetaStore {
-        let mut opts = Options::default();
-        opts.create_if_missing(true);
-        opts.set_prefix_extractor(rocksdb::SliceTransform::create_fixed_prefix(13));
-        opts.set_merge_operator_associative("meta_store merge", meta_store_merge);
-
-        let db = DB::open(&opts, path).unwrap();
+        let db = DB::open(&Self::db_options(), path).unwrap();
         let db_arc = Arc::new(db);
+        let (standby_tx, standby_rx) = watch::channel(config.metastore_leader_address().is_some());
+        let leader_address = config.metastore_leader_address().clone();
 
         let meta_store = RocksMetaStore {
             db: Arc::new(RwLock::new(db_arc.clone())),
@@ -1552,12 +1731,31 @@ impl RocksMetaStore {
             last_upload_seq: Arc::new(RwLock::new(db_arc.latest_sequence_number())),
             last_check_seq: Arc::new(RwLock::new(db_arc.latest_sequence_number())),
             upload_loop: Arc::new(WorkerLoop::new("Meta Store Upload")),
+            ingested_batch_gc_loop: Arc::new(WorkerLoop::new("Ingested Batch GC")),
+            pending_query_patterns: Arc::new(Mutex::new(HashMap::new())),
+            query_patterns_flush_loop: Arc::new(WorkerLoop::new("Query Patterns Flush")),
             config,
             cached_tables: Arc::new(Mutex::new(None)),
+            standby_tx: Arc::new(standby_tx),
+            standby_rx,
+            replicated_seq: Arc::new(RwLock::new(None)),
+            last_leader_contact: Arc::new(RwLock::new(SystemTime::now())),
+            replication_loop: Arc::new(WorkerLoop::new("Meta Store Replication")),
+            leader_epoch: Arc::new(RwLock::new(None)),
+            lease_checked: Arc::new(RwLock::new(SystemTime::now())),
+            leader_address: Arc::new(RwLock::new(leader_address)),
         };
         meta_store
     }
 
+    fn db_options() -> Options {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.set_prefix_extractor(rocksdb::SliceTransform::create_fixed_prefix(13));
+        opts.set_merge_operator_associative("meta_store merge", meta_store_merge);
+        opts
+    }
+
     pub fn new(
         path: impl AsRef<Path>,
         remote_fs: Arc<dyn RemoteFs>,
@@ -1570,6 +1768,25 @@ impl RocksMetaStore {
         path: impl AsRef<Path>,
         remote_fs: Arc<dyn RemoteFs>,
         config: Arc<dyn ConfigObj>,
+    ) -> Result<Arc<RocksMetaStore>, CubeError> {
+        let meta_store = match Self::latest_replica_path(path.as_ref()).await? {
+            Some(replica_path) => {
+                info!(
+                    "Using meta store replica in {}",
+                    replica_path.as_os_str().to_string_lossy()
+                );
+                Self::new(replica_path, remote_fs, config)
+            }
+            None => Self::open_from_remote(path, remote_fs, config).await?,
+        };
+        meta_store.init_leadership().await?;
+        Ok(meta_store)
+    }
+
+    async fn open_from_remote(
+        path: impl AsRef<Path>,
+        remote_fs: Arc<dyn RemoteFs>,
+        config: Arc<dyn ConfigObj>,
     ) -> Result<Arc<RocksMetaStore>, CubeError> {
         if !fs::metadata(path.as_ref()).await.is_ok() {
             let re = Regex::new(r"^metastore-(\d+)").unwrap();
@@ -1665,6 +1882,7 @@ impl RocksMetaStore {
             + 'static,
         R: Send + 'static,
     {
+        self.check_leadership(false).await?;
         let db = acquire_lock("meta store write", self.db.write()).await?;
         let db_span = warn_long("metastore write operation", Duration::from_millis(100));
         let mem_seq = MemorySequence {
@@ -1717,11 +1935,77 @@ impl RocksMetaStore {
             .await;
     }
 
+    /// Forgets ingested batches older than [ConfigObj::ingested_batch_ttl_secs].
+    pub async fn wait_ingested_batch_gc_loop(meta_store: Arc<Self>) {
+        meta_store
+            .ingested_batch_gc_loop
+            .process(
+                meta_store.clone(),
+                async move |_| Ok(Delay::new(Duration::from_secs(60)).await),
+                async move |m, _| {
+                    let ttl = chrono::Duration::seconds(m.config.ingested_batch_ttl_secs() as i64);
+                    m.delete_ingested_batches(Utc::now() - ttl).await
+                },
+            )
+            .await;
+    }
+
+    /// Persists query patterns recorded by [MetaStore::record_query_patterns].
+    pub async fn wait_query_patterns_flush_loop(meta_store: Arc<Self>) {
+        meta_store
+            .query_patterns_flush_loop
+            .process(
+                meta_store.clone(),
+                async move |_| Ok(Delay::new(Duration::from_secs(10)).await),
+                async move |m, _| m.flush_query_patterns().await,
+            )
+            .await;
+    }
+
+    async fn flush_query_patterns(&self) -> Result<(), CubeError> {
+        let patterns = std::mem::take(&mut *self.pending_query_patterns.lock().unwrap());
+        if patterns.is_empty() {
+            return Ok(());
+        }
+        self.write_operation(move |db_ref, batch_pipe| {
+            let tables = TableRocksTable::new(db_ref.clone());
+            let table = QueryPatternRocksTable::new(db_ref);
+            for (_, p) in patterns {
+                // The table might have been dropped since.
+                if tables.get_row(p.table_id())?.is_none() {
+                    continue;
+                }
+                let existing = table.get_rows_by_index(
+                    &QueryPatternIndexKey::Pattern(p.table_id(), p.kind(), p.columns().clone()),
+                    &QueryPatternRocksIndex::Pattern,
+                )?;
+                match existing.into_iter().next() {
+                    Some(e) => {
+                        table.update_with_fn(e.get_id(), |row| row.merge(&p), batch_pipe)?;
+                    }
+                    None => {
+                        table.insert(p, batch_pipe)?;
+                    }
+                }
+            }
+            Ok(())
+        })
+        .await
+    }
+
     pub async fn stop_processing_loops(&self) {
         self.upload_loop.stop();
+        self.ingested_batch_gc_loop.stop();
+        self.query_patterns_flush_loop.stop();
+        self.replication_loop.stop();
     }
 
     pub async fn run_upload(&self) -> Result<(), CubeError> {
+        if self.is_standby() {
+            trace!("Persisting meta store snapshot: skipped in standby mode");
+            return Ok(());
+        }
+        self.check_leadership(true).await?;
         let time = SystemTime::now();
         info!("Persisting meta store snapshot");
         let last_check_seq = self.last_check_seq().await;
@@ -1784,21 +2068,26 @@ impl RocksMetaStore {
         Ok(())
     }
 
-    async fn upload_check_point(&self) -> Result<(), CubeError> {
+    /// Returns the remote path of the uploaded checkpoint along with the last sequence number
+    /// it contains.
+    async fn upload_check_point(&self) -> Result<(String, u64), CubeError> {
         let mut check_point_time = self.last_checkpoint_time.write().await;
         let remote_fs = self.remote_fs.clone();
 
-        let (remote_path, checkpoint_path) = {
+        let (remote_path, checkpoint_path, seq) = {
             let db = acquire_lock("meta store upload checkpoint", self.db.write())
                 .await?
                 .clone();
             *check_point_time = SystemTime::now();
-            RocksMetaStore::prepare_checkpoint(db, &check_point_time).await?
+            let seq = db.latest_sequence_number();
+            let (remote_path, checkpoint_path) =
+                RocksMetaStore::prepare_checkpoint(db, &check_point_time).await?;
+            (remote_path, checkpoint_path, seq)
         };
 
-        RocksMetaStore::upload_checkpoint(remote_fs, remote_path, checkpoint_path).await?;
+        RocksMetaStore::upload_checkpoint(remote_fs, remote_path.clone(), checkpoint_path).await?;
         self.write_completed_notify.notify_waiters();
-        Ok(())
+        Ok((remote_path, seq))
     }
 
     async fn last_upload_seq(&self) -> u64 {
@@ -2012,6 +2301,7 @@ impl RocksMetaStore {
         table_cols: &Vec<Column>,
         table_id: &IdRow<Table>,
         index_def: IndexDef,
+        build_sources: Option<IndexBuildSources>,
     ) -> Result<IdRow<Index>, CubeError> {
         if let Some(not_found) = index_def
             .columns
@@ -2050,12 +2340,15 @@ impl RocksMetaStore {
         }
         assert_eq!(index_columns.len(), table_cols.len());
 
-        let index = Index::try_new(
+        let mut index = Index::try_new(
             index_def.name,
             table_id.get_id(),
             index_columns,
             sorted_key_size,
         )?;
+        if let Some(sources) = build_sources {
+            index = index.building(sources);
+        }
         let index_id = rocks_index.insert(index, batch_pipe)?;
         let partition = Partition::new(index_id.id, None, None);
         let _ = rocks_partition.insert(partition, batch_pipe)?;
@@ -2107,6 +2400,76 @@ impl RocksMetaStore {
     }
 
     // Must be run under write_operation(). Returns activated row count.
+    /// Sources of indexes being built can't be merged with other data until they are copied,
+    /// otherwise the new index would miss or duplicate rows.
+    fn check_not_index_build_sources(
+        db_ref: DbTableRef,
+        partition_ids: &[u64],
+        chunk_ids: &[u64],
+    ) -> Result<(), CubeError> {
+        for index in IndexRocksTable::new(db_ref).all_rows()? {
+            if let Some(sources) = index.get_row().build_sources() {
+                if partition_ids
+                    .iter()
+                    .any(|p| sources.partition_ids.contains(p))
+                    || chunk_ids.iter().any(|c| sources.chunk_ids.contains(c))
+                {
+                    return Err(CubeError::internal(format!(
+                        "Can't swap partitions ({}) and chunks ({}) used to build index {}",
+                        partition_ids.iter().join(", "),
+                        chunk_ids.iter().join(", "),
+                        index.get_id()
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Repartitioned chunks hold exactly the rows of the chunks they replace, so indexes being
+    /// built can copy them instead.
+    fn replace_index_build_chunks(
+        db_ref: DbTableRef,
+        batch_pipe: &mut BatchPipe,
+        deactivated_ids: &[u64],
+        new_ids: &[u64],
+    ) -> Result<(), CubeError> {
+        let indexes = IndexRocksTable::new(db_ref);
+        for index in indexes.all_rows()? {
+            let sources = match index.get_row().build_sources() {
+                Some(s) => s,
+                None => continue,
+            };
+            let (replaced, kept): (Vec<u64>, Vec<u64>) = deactivated_ids
+                .iter()
+                .cloned()
+                .partition(|id| sources.chunk_ids.contains(id));
+            if replaced.is_empty() {
+                continue;
+            }
+            if !kept.is_empty() {
+                return Err(CubeError::internal(format!(
+                    "Can't swap chunks ({}) partially used to build index {}",
+                    deactivated_ids.iter().join(", "),
+                    index.get_id()
+                )));
+            }
+            let mut chunk_ids = sources
+                .chunk_ids
+                .iter()
+                .filter(|id| !replaced.contains(id))
+                .cloned()
+                .collect_vec();
+            chunk_ids.extend_from_slice(new_ids);
+            let sources = IndexBuildSources {
+                partition_ids: sources.partition_ids.clone(),
+                chunk_ids,
+            };
+            indexes.update_with_fn(index.get_id(), |i| i.building(sources), batch_pipe)?;
+        }
+        Ok(())
+    }
+
     fn activate_chunks_impl(
         db_ref: DbTableRef,
         batch_pipe: &mut BatchPipe,
@@ -2281,6 +2644,8 @@ impl MetaStore for RocksMetaStore {
         columns: Vec<Column>,
         locations: Option<Vec<String>>,
         import_format: Option<ImportFormat>,
+        stream_source: Option<StreamSource>,
+        batch_id: Option<String>,
         indexes: Vec<IndexDef>,
         is_ready: bool,
     ) -> Result<IdRow<Table>, CubeError> {
@@ -2299,6 +2664,8 @@ impl MetaStore for RocksMetaStore {
                 columns,
                 locations,
                 import_format,
+                stream_source,
+                batch_id,
                 is_ready,
             );
             let table_id = rocks_table.insert(table, batch_pipe)?;
@@ -2310,6 +2677,7 @@ impl MetaStore for RocksMetaStore {
                     &table_columns,
                     &table_id,
                     index_def,
+                    None,
                 )?;
             }
             let def_index_columns = table_columns
@@ -2329,6 +2697,7 @@ impl MetaStore for RocksMetaStore {
                     name: "default".to_string(),
                     columns: def_index_columns,
                 },
+                None,
             )?;
 
             Ok(table_id)
@@ -2344,6 +2713,63 @@ impl MetaStore for RocksMetaStore {
         .await
     }
 
+    async fn swap_tables(
+        &self,
+        schema_name: String,
+        table_name: String,
+        other_schema_name: String,
+        other_table_name: String,
+    ) -> Result<(), CubeError> {
+        self.write_operation(move |db_ref, batch_pipe| {
+            let table = get_table_impl(db_ref.clone(), schema_name.clone(), table_name.clone())?;
+            let other = get_table_impl(
+                db_ref.clone(),
+                other_schema_name.clone(),
+                other_table_name.clone(),
+            )?;
+            if table.get_id() == other.get_id() {
+                return Err(CubeError::user(format!(
+                    "Can't swap table {}.{} with itself",
+                    schema_name, table_name
+                )));
+            }
+            for (t, schema_name, table_name) in &[
+                (&table, &schema_name, &table_name),
+                (&other, &other_schema_name, &other_table_name),
+            ] {
+                if !t.get_row().is_ready() {
+                    return Err(CubeError::user(format!(
+                        "Table {}.{} is not ready",
+                        schema_name, table_name
+                    )));
+                }
+            }
+
+            // Secondary index keys include row ids, so names can be exchanged in one batch.
+            let rocks_table = TableRocksTable::new(db_ref);
+            rocks_table.update(
+                table.get_id(),
+                table.get_row().update_path(
+                    other.get_row().get_schema_id(),
+                    other.get_row().get_table_name().clone(),
+                ),
+                table.get_row(),
+                batch_pipe,
+            )?;
+            rocks_table.update(
+                other.get_id(),
+                other.get_row().update_path(
+                    table.get_row().get_schema_id(),
+                    table.get_row().get_table_name().clone(),
+                ),
+                other.get_row(),
+                batch_pipe,
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
     async fn get_table(
         &self,
         schema_name: String,
@@ -2397,7 +2823,7 @@ impl MetaStore for RocksMetaStore {
             let tables_table = TableRocksTable::new(db_ref.clone());
             let indexes_table = IndexRocksTable::new(db_ref.clone());
             let partitions_table = PartitionRocksTable::new(db_ref.clone());
-            let chunks_table = ChunkRocksTable::new(db_ref);
+            let chunks_table = ChunkRocksTable::new(db_ref.clone());
 
             let indexes = indexes_table
                 .get_rows_by_index(&IndexIndexKey::TableId(table_id), &IndexRocksIndex::TableID)?;
@@ -2418,6 +2844,20 @@ impl MetaStore for RocksMetaStore {
                 }
                 indexes_table.delete(index.get_id(), batch_pipe)?;
             }
+            let query_patterns = QueryPatternRocksTable::new(db_ref.clone());
+            for p in query_patterns.get_rows_by_index(
+                &QueryPatternIndexKey::ByTable(table_id),
+                &QueryPatternRocksIndex::TableID,
+            )? {
+                query_patterns.delete(p.get_id(), batch_pipe)?;
+            }
+            let ingested_batches = IngestedBatchRocksTable::new(db_ref.clone());
+            for b in ingested_batches.get_rows_by_index(
+                &IngestedBatchIndexKey::ByTable(table_id),
+                &IngestedBatchRocksIndex::TableID,
+            )? {
+                ingested_batches.delete(b.get_id(), batch_pipe)?;
+            }
             Ok(tables_table.delete(table_id, batch_pipe)?)
         })
         .await
@@ -2493,6 +2933,11 @@ impl MetaStore for RocksMetaStore {
             new_active.iter().join(", ")
         );
         self.write_operation(move |db_ref, batch_pipe| {
+            Self::check_not_index_build_sources(
+                db_ref.clone(),
+                &current_active,
+                &compacted_chunk_ids,
+            )?;
             let table = PartitionRocksTable::new(db_ref.clone());
             let chunk_table = ChunkRocksTable::new(db_ref.clone());
 
@@ -2616,13 +3061,38 @@ impl MetaStore for RocksMetaStore {
                 rocks_schema,
             )?;
 
-            if *table.get_row().has_data() {
-                return Err(CubeError::user(format!(
-                    "Can't create '{}' index because '{}' table already has data",
-                    index_def.name,
-                    table.get_row().get_table_name()
-                )));
-            }
+            // Existing data is copied from the default index by a job. Data written after this
+            // point goes to the new index directly.
+            let build_sources = if *table.get_row().has_data() {
+                let rocks_chunk = ChunkRocksTable::new(db_ref.clone());
+                let default_index = get_default_index_impl(db_ref.clone(), table.get_id())?;
+                let partitions = rocks_partition
+                    .get_rows_by_index(
+                        &PartitionIndexKey::ByIndexId(default_index.get_id()),
+                        &PartitionRocksIndex::IndexId,
+                    )?
+                    .into_iter()
+                    .filter(|p| p.get_row().is_active())
+                    .collect_vec();
+                let mut chunk_ids = Vec::new();
+                for p in partitions.iter() {
+                    chunk_ids.extend(
+                        Self::chunks_by_partitioned_with_non_repartitioned(
+                            p.get_id(),
+                            &rocks_chunk,
+                            &rocks_partition,
+                        )?
+                        .into_iter()
+                        .map(|c| c.get_id()),
+                    );
+                }
+                Some(IndexBuildSources {
+                    partition_ids: partitions.into_iter().map(|p| p.get_id()).collect(),
+                    chunk_ids: chunk_ids.into_iter().unique().collect(),
+                })
+            } else {
+                None
+            };
 
             Ok(RocksMetaStore::add_index(
                 batch_pipe,
@@ -2631,6 +3101,7 @@ impl MetaStore for RocksMetaStore {
                 table.get_row().get_columns(),
                 &table,
                 index_def,
+                build_sources,
             )?)
         })
         .await
@@ -2650,6 +3121,23 @@ impl MetaStore for RocksMetaStore {
         .await
     }
 
+    async fn index_built(
+        &self,
+        index_id: u64,
+        uploaded_chunk_ids: Vec<u64>,
+    ) -> Result<IdRow<Index>, CubeError> {
+        trace!(
+            "Index {} built, activating chunks ({})",
+            index_id,
+            uploaded_chunk_ids.iter().join(", ")
+        );
+        self.write_operation(move |db_ref, batch_pipe| {
+            Self::activate_chunks_impl(db_ref.clone(), batch_pipe, &uploaded_chunk_ids)?;
+            Ok(IndexRocksTable::new(db_ref).update_with_fn(index_id, |i| i.built(), batch_pipe)?)
+        })
+        .await
+    }
+
     async fn get_active_partitions_by_index_id(
         &self,
         index_id: u64,
@@ -2880,6 +3368,45 @@ impl MetaStore for RocksMetaStore {
         Ok(())
     }
 
+    async fn activate_batch_chunks(
+        &self,
+        table_id: u64,
+        batch_id: String,
+        uploaded_chunk_ids: Vec<u64>,
+    ) -> Result<bool, CubeError> {
+        trace!(
+            "Activating chunks ({}) of batch {}",
+            uploaded_chunk_ids.iter().join(", "),
+            batch_id
+        );
+        self.write_operation(move |db_ref, batch_pipe| {
+            let batches = IngestedBatchRocksTable::new(db_ref.clone());
+            let existing = batches.get_rows_by_index(
+                &IngestedBatchIndexKey::ByBatch(table_id, batch_id.clone()),
+                &IngestedBatchRocksIndex::BatchID,
+            )?;
+            if !existing.is_empty() {
+                let chunks = ChunkRocksTable::new(db_ref);
+                for id in uploaded_chunk_ids {
+                    chunks.delete(id, batch_pipe)?;
+                }
+                return Ok(false);
+            }
+            TableRocksTable::new(db_ref.clone()).update_with_fn(
+                table_id,
+                |t| t.update_has_data(true),
+                batch_pipe,
+            )?;
+            Self::activate_chunks_impl(db_ref, batch_pipe, &uploaded_chunk_ids)?;
+            batches.insert(
+                IngestedBatch::new(table_id, batch_id, uploaded_chunk_ids),
+                batch_pipe,
+            )?;
+            Ok(true)
+        })
+        .await
+    }
+
     async fn swap_chunks(
         &self,
         deactivate_ids: Vec<u64>,
@@ -2891,6 +3418,12 @@ impl MetaStore for RocksMetaStore {
             uploaded_ids.iter().join(", ")
         );
         self.write_operation(move |db_ref, batch_pipe| {
+            Self::replace_index_build_chunks(
+                db_ref.clone(),
+                batch_pipe,
+                &deactivate_ids,
+                &uploaded_ids,
+            )?;
             let table = ChunkRocksTable::new(db_ref.clone());
             let mut deactivated_row_count = 0;
             let mut activated_row_count = 0;
@@ -2932,6 +3465,39 @@ impl MetaStore for RocksMetaStore {
         .await
     }
 
+    async fn get_ingested_batch(
+        &self,
+        table_id: u64,
+        batch_id: String,
+    ) -> Result<Option<IdRow<IngestedBatch>>, CubeError> {
+        self.read_operation(move |db_ref| {
+            Ok(IngestedBatchRocksTable::new(db_ref)
+                .get_rows_by_index(
+                    &IngestedBatchIndexKey::ByBatch(table_id, batch_id),
+                    &IngestedBatchRocksIndex::BatchID,
+                )?
+                .into_iter()
+                .next())
+        })
+        .await
+    }
+
+    async fn delete_ingested_batches(
+        &self,
+        created_before: DateTime<Utc>,
+    ) -> Result<(), CubeError> {
+        self.write_operation(move |db_ref, batch_pipe| {
+            let batches = IngestedBatchRocksTable::new(db_ref);
+            for b in batches.all_rows()? {
+                if *b.get_row().created_at() < created_before {
+                    batches.delete(b.get_id(), batch_pipe)?;
+                }
+            }
+            Ok(())
+        })
+        .await
+    }
+
     fn chunks_table(&self) -> ChunkMetaStoreTable {
         ChunkMetaStoreTable {
             rocks_meta_store: self.clone(),
@@ -3091,10 +3657,14 @@ impl MetaStore for RocksMetaStore {
                     .get_row_or_not_found(table.get_row().get_schema_id())?;
 
                 let mut indexes;
-                indexes = IndexRocksTable::new(db.clone()).get_rows_by_index(
-                    &IndexIndexKey::TableId(table.get_id()),
-                    &IndexRocksIndex::TableID,
-                )?;
+                indexes = IndexRocksTable::new(db.clone())
+                    .get_rows_by_index(
+                        &IndexIndexKey::TableId(table.get_id()),
+                        &IndexRocksIndex::TableID,
+                    )?
+                    .into_iter()
+                    .filter(|i| i.get_row().is_ready())
+                    .collect_vec();
                 indexes.insert(0, get_default_index_impl(db.clone(), table.get_id())?);
 
                 r.push((schema, table, indexes))
@@ -3104,6 +3674,27 @@ impl MetaStore for RocksMetaStore {
         .await
     }
 
+    async fn record_query_patterns(&self, patterns: Vec<QueryPattern>) -> Result<(), CubeError> {
+        // Recorded on every select, so writes are batched by the flush loop.
+        let mut pending = self.pending_query_patterns.lock().unwrap();
+        for p in patterns {
+            let key = (p.table_id(), p.kind(), p.columns().clone());
+            match pending.get_mut(&key) {
+                Some(m) => *m = m.merge(&p),
+                None => {
+                    pending.insert(key, p);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_query_patterns(&self) -> Result<Vec<IdRow<QueryPattern>>, CubeError> {
+        self.flush_query_patterns().await?;
+        self.read_operation(|db_ref| QueryPatternRocksTable::new(db_ref).all_rows())
+            .await
+    }
+
     async fn debug_dump(&self, out_path: String) -> Result<(), CubeError> {
         self.read_operation(|db| {
             let mut e =
@@ -3112,6 +3703,13 @@ impl MetaStore for RocksMetaStore {
         })
         .await
     }
+
+    async fn get_replication_updates(
+        &self,
+        since_seq: Option<u64>,
+    ) -> Result<ReplicationUpdate, CubeError> {
+        self.replication_updates_since(since_seq).await
+    }
 }
 
 fn get_table_impl(
@@ -3429,6 +4027,8 @@ mod tests {
                     columns.clone(),
                     None,
                     None,
+                    None,
+                    None,
                     vec![],
                     true,
                 )
@@ -3444,6 +4044,8 @@ mod tests {
                     columns.clone(),
                     None,
                     None,
+                    None,
+                    None,
                     vec![],
                     true
                 )
@@ -3480,7 +4082,7 @@ mod tests {
             let _ = fs::remove_dir_all(config.local_dir());
             let _ = fs::remove_dir_all(config.remote_dir());
 
-            let services = config.configure().await;
+            let services = config.configure().await.unwrap();
             services.start_processing_loops().await.unwrap();
             services
                 .meta_store
@@ -3527,7 +4129,7 @@ mod tests {
         {
             let config = Config::test("cold_start_test");
 
-            let services2 = config.configure().await;
+            let services2 = config.configure().await.unwrap();
             services2
                 .meta_store
                 .get_schema("foo1".to_string())
@@ -3548,6 +4150,123 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn standby_replication() {
+        let config = Config::test("standby_replication");
+        let leader_path = env::current_dir().unwrap().join("test-standby-leader");
+        let standby_path = env::current_dir().unwrap().join("test-standby-replica");
+        let remote_store_path = env::current_dir().unwrap().join("test-standby-remote");
+        let _ = fs::remove_dir_all(leader_path.clone());
+        let _ = fs::remove_dir_all(standby_path.clone());
+        let _ = fs::remove_dir_all(remote_store_path.clone());
+
+        let leader_config = config.update_config(|mut c| {
+            c.server_name = "leader:9999".to_string();
+            c
+        });
+        let standby_config = config.update_config(|mut c| {
+            c.server_name = "standby:9999".to_string();
+            c.metastore_leader_address = Some("leader:9999".to_string());
+            c.metastore_failover_timeout = 1;
+            c
+        });
+        {
+            let leader = RocksMetaStore::new(
+                leader_path.join("metastore").as_path(),
+                LocalDirRemoteFs::new(Some(remote_store_path.clone()), leader_path.clone()),
+                leader_config.config_obj(),
+            );
+            leader.init_leadership().await.unwrap();
+            assert!(!leader.is_standby());
+            let standby = RocksMetaStore::new(
+                standby_path.join("metastore").as_path(),
+                LocalDirRemoteFs::new(Some(remote_store_path.clone()), standby_path.clone()),
+                standby_config.config_obj(),
+            );
+            standby.init_leadership().await.unwrap();
+            assert!(standby.is_standby());
+            assert_eq!(
+                standby.leader_address().await,
+                Some("leader:9999".to_string())
+            );
+            assert!(standby
+                .create_schema("foo".to_string(), false)
+                .await
+                .is_err());
+
+            leader
+                .create_schema("foo".to_string(), false)
+                .await
+                .unwrap();
+            // First pull is served from a checkpoint.
+            standby.replicate_from(leader.as_ref()).await.unwrap();
+            standby.get_schema("foo".to_string()).await.unwrap();
+
+            // Following pulls tail the write log.
+            leader
+                .create_schema("bar".to_string(), false)
+                .await
+                .unwrap();
+            standby.replicate_from(leader.as_ref()).await.unwrap();
+            standby.get_schema("bar".to_string()).await.unwrap();
+            assert!(standby.is_standby());
+
+            // Leader stops serving updates and the standby takes over once timeout passes.
+            leader.standby_tx.send(true).unwrap();
+            assert!(standby.replicate_from(leader.as_ref()).await.is_err());
+            assert!(standby.is_standby());
+            Delay::new(Duration::from_millis(1100)).await;
+            standby.replicate_from(leader.as_ref()).await.unwrap();
+            assert!(!standby.is_standby());
+            assert!(standby.wait_for_promotion().await);
+
+            standby
+                .create_schema("baz".to_string(), false)
+                .await
+                .unwrap();
+            assert_eq!(standby.get_schemas().await.unwrap().len(), 3);
+
+            // Former leader comes back and finds a newer epoch before its next upload.
+            leader.standby_tx.send(false).unwrap();
+            assert!(leader.run_upload().await.is_err());
+            assert!(leader.is_standby());
+            assert!(leader.wait_for_demotion().await);
+            assert!(leader
+                .create_schema("qux".to_string(), false)
+                .await
+                .is_err());
+        }
+        {
+            // Restarted former leader follows the promoted node.
+            let leader = RocksMetaStore::load_from_remote(
+                leader_path.join("metastore").as_path(),
+                LocalDirRemoteFs::new(Some(remote_store_path.clone()), leader_path.clone()),
+                leader_config.config_obj(),
+            )
+            .await
+            .unwrap();
+            assert!(leader.is_standby());
+            assert_eq!(
+                leader.leader_address().await,
+                Some("standby:9999".to_string())
+            );
+
+            // Restarted promoted node keeps leading from its replica.
+            let standby = RocksMetaStore::load_from_remote(
+                standby_path.join("metastore").as_path(),
+                LocalDirRemoteFs::new(Some(remote_store_path.clone()), standby_path.clone()),
+                standby_config.config_obj(),
+            )
+            .await
+            .unwrap();
+            assert!(!standby.is_standby());
+            assert_eq!(standby.get_schemas().await.unwrap().len(), 3);
+        }
+        let _ = fs::remove_dir_all(leader_path.clone());
+        let _ = fs::remove_dir_all(standby_path.clone());
+        let _ = fs::remove_dir_all(remote_store_path.clone());
+    }
+
     #[tokio::test]
     async fn discard_logs() {
         {
@@ -3556,7 +4275,7 @@ mod tests {
             let _ = fs::remove_dir_all(config.local_dir());
             let _ = fs::remove_dir_all(config.remote_dir());
 
-            let services = config.configure().await;
+            let services = config.configure().await.unwrap();
             services.start_processing_loops().await.unwrap();
             services
                 .meta_store
@@ -3629,7 +4348,7 @@ mod tests {
         {
             let config = Config::test("discard_logs");
 
-            let services2 = config.configure().await;
+            let services2 = config.configure().await.unwrap();
             services2
                 .meta_store
                 .get_schema("foo1".to_string())
@@ -3645,4 +4364,205 @@ mod tests {
             fs::remove_dir_all(config.remote_dir()).unwrap();
         }
     }
+
+    #[tokio::test]
+    async fn ingested_batches_test() {
+        let config = Config::test("ingested_batches_test");
+        let store_path = env::current_dir()
+            .unwrap()
+            .join("test-ingested-batches-local");
+        let remote_store_path = env::current_dir()
+            .unwrap()
+            .join("test-ingested-batches-remote");
+        let _ = fs::remove_dir_all(store_path.clone());
+        let _ = fs::remove_dir_all(remote_store_path.clone());
+        let remote_fs = LocalDirRemoteFs::new(Some(remote_store_path.clone()), store_path.clone());
+        {
+            let meta_store = RocksMetaStore::new(
+                store_path.join("metastore").as_path(),
+                remote_fs,
+                config.config_obj(),
+            );
+            meta_store
+                .create_schema("foo".to_string(), false)
+                .await
+                .unwrap();
+            let table = meta_store
+                .create_table(
+                    "foo".to_string(),
+                    "boo".to_string(),
+                    vec![Column::new("col1".to_string(), ColumnType::Int, 0)],
+                    None,
+                    None,
+                    None,
+                    None,
+                    vec![],
+                    true,
+                )
+                .await
+                .unwrap();
+            let index = meta_store.get_table_indexes(table.get_id()).await.unwrap();
+            let partition = meta_store
+                .get_active_partitions_by_index_id(index[0].get_id())
+                .await
+                .unwrap();
+            let partition_id = partition[0].get_id();
+
+            let c1 = meta_store.create_chunk(partition_id, 10).await.unwrap();
+            assert!(meta_store
+                .activate_batch_chunks(table.get_id(), "b".to_string(), vec![c1.get_id()])
+                .await
+                .unwrap());
+
+            // Chunks of the re-submitted batch are dropped.
+            let c2 = meta_store.create_chunk(partition_id, 10).await.unwrap();
+            assert!(!meta_store
+                .activate_batch_chunks(table.get_id(), "b".to_string(), vec![c2.get_id()])
+                .await
+                .unwrap());
+            let chunks = meta_store
+                .get_chunks_by_partition(partition_id, true)
+                .await
+                .unwrap();
+            assert_eq!(chunks.len(), 1);
+            assert!(chunks[0].get_row().uploaded());
+            assert_eq!(
+                meta_store
+                    .get_ingested_batch(table.get_id(), "b".to_string())
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .get_row()
+                    .chunk_ids(),
+                &vec![c1.get_id()]
+            );
+
+            meta_store
+                .delete_ingested_batches(Utc::now() + chrono::Duration::seconds(1))
+                .await
+                .unwrap();
+            assert!(meta_store
+                .get_ingested_batch(table.get_id(), "b".to_string())
+                .await
+                .unwrap()
+                .is_none());
+        }
+        let _ = fs::remove_dir_all(store_path.clone());
+        let _ = fs::remove_dir_all(remote_store_path.clone());
+    }
+
+    #[tokio::test]
+    async fn index_build_sources_test() {
+        let config = Config::test("index_build_sources_test");
+        let store_path = env::current_dir()
+            .unwrap()
+            .join("test-index-build-sources-local");
+        let remote_store_path = env::current_dir()
+            .unwrap()
+            .join("test-index-build-sources-remote");
+        let _ = fs::remove_dir_all(store_path.clone());
+        let _ = fs::remove_dir_all(remote_store_path.clone());
+        let remote_fs = LocalDirRemoteFs::new(Some(remote_store_path.clone()), store_path.clone());
+        {
+            let meta_store = RocksMetaStore::new(
+                store_path.join("metastore").as_path(),
+                remote_fs,
+                config.config_obj(),
+            );
+            meta_store
+                .create_schema("foo".to_string(), false)
+                .await
+                .unwrap();
+            let table = meta_store
+                .create_table(
+                    "foo".to_string(),
+                    "boo".to_string(),
+                    vec![Column::new("col1".to_string(), ColumnType::Int, 0)],
+                    None,
+                    None,
+                    None,
+                    None,
+                    vec![],
+                    true,
+                )
+                .await
+                .unwrap();
+            let default_index = meta_store.get_default_index(table.get_id()).await.unwrap();
+            let partition = meta_store
+                .get_active_partitions_by_index_id(default_index.get_id())
+                .await
+                .unwrap();
+            let partition_id = partition[0].get_id();
+            let c1 = meta_store.create_chunk(partition_id, 10).await.unwrap();
+            meta_store
+                .activate_chunks(table.get_id(), vec![c1.get_id()])
+                .await
+                .unwrap();
+
+            let index = meta_store
+                .create_index(
+                    "foo".to_string(),
+                    "boo".to_string(),
+                    IndexDef {
+                        name: "by_col1".to_string(),
+                        columns: vec!["col1".to_string()],
+                    },
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                index.get_row().build_sources(),
+                Some(&IndexBuildSources {
+                    partition_ids: vec![partition_id],
+                    chunk_ids: vec![c1.get_id()],
+                })
+            );
+
+            // Sources can't be compacted while the index is being built.
+            let new_partition = meta_store
+                .create_partition(Partition::new(default_index.get_id(), None, None))
+                .await
+                .unwrap();
+            assert!(meta_store
+                .swap_active_partitions(
+                    vec![partition_id],
+                    vec![new_partition.get_id()],
+                    vec![c1.get_id()],
+                    vec![(10, (None, None))],
+                )
+                .await
+                .is_err());
+
+            // Repartitioned chunks replace the sources.
+            let c2 = meta_store.create_chunk(partition_id, 10).await.unwrap();
+            meta_store
+                .swap_chunks(vec![c1.get_id()], vec![c2.get_id()])
+                .await
+                .unwrap();
+            let index = meta_store.get_index(index.get_id()).await.unwrap();
+            assert_eq!(
+                index.get_row().build_sources(),
+                Some(&IndexBuildSources {
+                    partition_ids: vec![partition_id],
+                    chunk_ids: vec![c2.get_id()],
+                })
+            );
+
+            meta_store
+                .index_built(index.get_id(), vec![])
+                .await
+                .unwrap();
+            meta_store
+                .swap_active_partitions(
+                    vec![partition_id],
+                    vec![new_partition.get_id()],
+                    vec![c2.get_id()],
+                    vec![(10, (None, None))],
+                )
+                .await
+                .unwrap();
+        }
+        let _ = fs::remove_dir_all(store_path.clone());
+        let _ = fs::remove_dir_all(remote_store_path.clone());
+    }
 }
@@ -0,0 +1,147 @@
+use super::{BaseRocksSecondaryIndex, IndexId, RocksSecondaryIndex, RocksTable, TableId};
+use crate::base_rocks_secondary_index;
+use crate::metastore::{IdRow, MetaStoreEvent};
+use crate::rocks_table_impl;
+use byteorder::{BigEndian, WriteBytesExt};
+use chrono::{DateTime, Utc};
+use rocksdb::DB;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::io::{Cursor, Write};
+
+/// Part of a query that could be served by an index sorted on its columns.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
+pub enum QueryPatternKind {
+    Filter,
+    GroupBy,
+    Join,
+}
+
+fn get_query_pattern_kind_index(k: QueryPatternKind) -> u32 {
+    match k {
+        QueryPatternKind::Filter => 1,
+        QueryPatternKind::GroupBy => 2,
+        QueryPatternKind::Join => 3,
+    }
+}
+
+/// Columns of a table used by queries that no index was sorted on.
+#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
+pub struct QueryPattern {
+    table_id: u64,
+    kind: QueryPatternKind,
+    columns: Vec<String>,
+    query_count: u64,
+    last_seen: DateTime<Utc>,
+}
+
+impl QueryPattern {
+    pub fn new(table_id: u64, kind: QueryPatternKind, columns: Vec<String>) -> QueryPattern {
+        QueryPattern {
+            table_id,
+            kind,
+            columns,
+            query_count: 1,
+            last_seen: Utc::now(),
+        }
+    }
+
+    pub fn table_id(&self) -> u64 {
+        self.table_id
+    }
+
+    pub fn kind(&self) -> QueryPatternKind {
+        self.kind
+    }
+
+    pub fn columns(&self) -> &Vec<String> {
+        &self.columns
+    }
+
+    pub fn query_count(&self) -> u64 {
+        self.query_count
+    }
+
+    pub fn last_seen(&self) -> &DateTime<Utc> {
+        &self.last_seen
+    }
+
+    /// Adds queries of `other`, which must have the same table, kind and columns.
+    pub fn merge(&self, other: &QueryPattern) -> QueryPattern {
+        QueryPattern {
+            table_id: self.table_id,
+            kind: self.kind,
+            columns: self.columns.clone(),
+            query_count: self.query_count + other.query_count,
+            last_seen: self.last_seen.max(other.last_seen),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum QueryPatternRocksIndex {
+    TableID = 1,
+    Pattern,
+}
+
+base_rocks_secondary_index!(QueryPattern, QueryPatternRocksIndex);
+
+rocks_table_impl!(
+    QueryPattern,
+    QueryPatternRocksTable,
+    TableId::QueryPatterns,
+    {
+        vec![
+            Box::new(QueryPatternRocksIndex::TableID),
+            Box::new(QueryPatternRocksIndex::Pattern),
+        ]
+    }
+);
+
+#[derive(Hash, Clone, Debug)]
+pub enum QueryPatternIndexKey {
+    ByTable(u64),
+    Pattern(u64, QueryPatternKind, Vec<String>),
+}
+
+impl RocksSecondaryIndex<QueryPattern, QueryPatternIndexKey> for QueryPatternRocksIndex {
+    fn typed_key_by(&self, row: &QueryPattern) -> QueryPatternIndexKey {
+        match self {
+            QueryPatternRocksIndex::TableID => QueryPatternIndexKey::ByTable(row.table_id),
+            QueryPatternRocksIndex::Pattern => {
+                QueryPatternIndexKey::Pattern(row.table_id, row.kind, row.columns.clone())
+            }
+        }
+    }
+
+    fn key_to_bytes(&self, key: &QueryPatternIndexKey) -> Vec<u8> {
+        match key {
+            QueryPatternIndexKey::ByTable(table_id) => {
+                let mut buf = Vec::with_capacity(8);
+                buf.write_u64::<BigEndian>(*table_id).unwrap();
+                buf
+            }
+            QueryPatternIndexKey::Pattern(table_id, kind, columns) => {
+                let mut buf = Cursor::new(Vec::new());
+                buf.write_u64::<BigEndian>(*table_id).unwrap();
+                buf.write_u32::<BigEndian>(get_query_pattern_kind_index(*kind))
+                    .unwrap();
+                for c in columns {
+                    buf.write_u64::<BigEndian>(c.len() as u64).unwrap();
+                    buf.write_all(c.as_bytes()).unwrap();
+                }
+                buf.into_inner()
+            }
+        }
+    }
+
+    fn is_unique(&self) -> bool {
+        match self {
+            QueryPatternRocksIndex::TableID => false,
+            QueryPatternRocksIndex::Pattern => true,
+        }
+    }
+
+    fn get_id(&self) -> IndexId {
+        *self as IndexId
+    }
+}
@@ -0,0 +1,436 @@
+//! Hot-standby replication of the meta store.
+//!
+//! A standby router pulls RocksDB write batches from the leader through
+//! [MetaStore::get_replication_updates] and applies them to its local copy. When the leader
+//! can't serve a contiguous range of updates, it uploads a fresh checkpoint and the standby
+//! reloads from it. If the leader stays unreachable for `metastore_failover_timeout` seconds the
+//! standby promotes itself and starts accepting writes.
+//!
+//! The current leader is recorded in remote storage as a [LeaderLease]. Each node that becomes
+//! the leader, on startup or by promotion, bumps its epoch. The leader checks the lease before
+//! every upload and at least once per lease interval before writes, and stops accepting writes
+//! once another node took over. Nodes that start while someone else holds the lease follow that
+//! node as standbys.
+use crate::metastore::{MetaStore, RocksMetaStore, WriteBatchContainer, WriteBatchEntry};
+use crate::remotefs::RemoteFs;
+use crate::util::lock::acquire_lock;
+use crate::CubeError;
+use datafusion::cube_ext;
+use futures_timer::Delay;
+use log::{error, info, warn};
+use rocksdb::{WriteBatchIterator, DB};
+use serde::{Deserialize, Serialize};
+use std::cmp::max;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::fs;
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub enum ReplicationUpdate {
+    /// Writes applied on the leader after the requested sequence number, up to `last_seq`.
+    Batches {
+        entries: Vec<WriteBatchEntry>,
+        last_seq: u64,
+    },
+    /// The leader can't serve writes since the requested sequence number. The checkpoint
+    /// uploaded to `remote_path` contains all writes up to `last_seq`.
+    Checkpoint { remote_path: String, last_seq: u64 },
+}
+
+/// Remote file holding the [LeaderLease].
+const LEADER_LEASE_FILE: &str = "metastore-leader";
+
+/// Meta store leader as recorded in remote storage.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct LeaderLease {
+    pub epoch: u64,
+    /// Meta store address of the leader, its `server_name`.
+    pub address: String,
+}
+
+impl RocksMetaStore {
+    pub fn is_standby(&self) -> bool {
+        *self.standby_rx.borrow()
+    }
+
+    /// Address of the leader this standby follows.
+    pub async fn leader_address(&self) -> Option<String> {
+        self.leader_address.read().await.clone()
+    }
+
+    /// Decides whether this node leads or follows on startup. A node that isn't the holder of
+    /// the current lease follows the holder, otherwise a configured standby follows
+    /// `metastore_leader_address` and everyone else takes the lease.
+    pub(crate) async fn init_leadership(&self) -> Result<(), CubeError> {
+        if !self.config.upload_to_remote() {
+            return Ok(());
+        }
+        let leader = match Self::read_leader_lease(self.remote_fs.as_ref()).await? {
+            Some(lease) if &lease.address != self.config.server_name() => Some(lease.address),
+            Some(_) => None,
+            None => self.config.metastore_leader_address().clone(),
+        };
+        match leader {
+            Some(address) => {
+                info!("Meta store follows leader on {}", address);
+                *self.leader_address.write().await = Some(address);
+                self.standby_tx.send(true)?;
+            }
+            None => {
+                self.acquire_leadership().await?;
+                self.standby_tx.send(false)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolves to `true` once this leader lost its lease and `false` if uploads were stopped
+    /// before that.
+    pub async fn wait_for_demotion(&self) -> bool {
+        let mut standby = self.standby_rx.clone();
+        loop {
+            if *standby.borrow() {
+                return true;
+            }
+            tokio::select! {
+                changed = standby.changed() => {
+                    if changed.is_err() {
+                        return false;
+                    }
+                }
+                _ = self.upload_loop.wait_stopped() => {
+                    return *standby.borrow();
+                }
+            }
+        }
+    }
+
+    /// Fails if this node doesn't lead the meta store anymore. The lease is read from remote
+    /// storage at most once per lease interval unless `force` is set.
+    pub(crate) async fn check_leadership(&self, force: bool) -> Result<(), CubeError> {
+        if self.is_standby() {
+            return Err(CubeError::internal(
+                "Meta store is in standby mode and doesn't accept writes".to_string(),
+            ));
+        }
+        let epoch = match *self.leader_epoch.read().await {
+            Some(epoch) => epoch,
+            // Leadership wasn't initialized, e.g. uploads to remote storage are disabled.
+            None => return Ok(()),
+        };
+        let lease_interval =
+            Duration::from_secs(max(1, self.config.metastore_failover_timeout() / 3));
+        if !force && self.lease_checked.read().await.elapsed()? < lease_interval {
+            return Ok(());
+        }
+        match Self::read_leader_lease(self.remote_fs.as_ref()).await? {
+            Some(lease) if lease.epoch != epoch => {
+                error!(
+                    "Meta store leadership moved to {} in epoch {}, this node stops accepting \
+                     writes. Restart it to follow the new leader",
+                    lease.address, lease.epoch
+                );
+                *self.leader_address.write().await = Some(lease.address.clone());
+                self.standby_tx.send(true)?;
+                Err(CubeError::internal(format!(
+                    "Meta store leadership moved to {}",
+                    lease.address
+                )))
+            }
+            _ => {
+                *self.lease_checked.write().await = SystemTime::now();
+                Ok(())
+            }
+        }
+    }
+
+    async fn acquire_leadership(&self) -> Result<(), CubeError> {
+        let current = Self::read_leader_lease(self.remote_fs.as_ref()).await?;
+        let lease = LeaderLease {
+            epoch: current.map(|l| l.epoch).unwrap_or(0) + 1,
+            address: self.config.server_name().clone(),
+        };
+        let upload_path = self.remote_fs.temp_upload_path(LEADER_LEASE_FILE).await?;
+        fs::write(&upload_path, serde_json::to_vec(&lease)?).await?;
+        self.remote_fs
+            .upload_file(&upload_path, LEADER_LEASE_FILE)
+            .await?;
+        // Remote storage has no compare-and-swap, read the lease back to detect another node
+        // taking over at the same time.
+        if Self::read_leader_lease(self.remote_fs.as_ref()).await? != Some(lease.clone()) {
+            return Err(CubeError::internal(format!(
+                "Meta store leadership in epoch {} was taken by another node",
+                lease.epoch
+            )));
+        }
+        *self.leader_epoch.write().await = Some(lease.epoch);
+        *self.lease_checked.write().await = SystemTime::now();
+        *self.leader_address.write().await = None;
+        info!("Meta store leads in epoch {}", lease.epoch);
+        Ok(())
+    }
+
+    async fn read_leader_lease(remote_fs: &dyn RemoteFs) -> Result<Option<LeaderLease>, CubeError> {
+        if remote_fs.list(LEADER_LEASE_FILE).await?.is_empty() {
+            return Ok(None);
+        }
+        let local_file = remote_fs.local_file(LEADER_LEASE_FILE).await?;
+        if fs::metadata(&local_file).await.is_ok() {
+            fs::remove_file(&local_file).await?;
+        }
+        let local_file = remote_fs.download_file(LEADER_LEASE_FILE).await?;
+        Ok(Some(serde_json::from_slice(&fs::read(&local_file).await?)?))
+    }
+
+    /// Resolves to `true` once this standby was promoted to leader and `false` if replication
+    /// was stopped before that.
+    pub async fn wait_for_promotion(&self) -> bool {
+        let mut standby = self.standby_rx.clone();
+        loop {
+            if !*standby.borrow() {
+                return true;
+            }
+            tokio::select! {
+                changed = standby.changed() => {
+                    if changed.is_err() {
+                        return false;
+                    }
+                }
+                _ = self.replication_loop.wait_stopped() => {
+                    return !*standby.borrow();
+                }
+            }
+        }
+    }
+
+    pub async fn wait_replication_loop(meta_store: Arc<Self>, leader: Arc<dyn MetaStore>) {
+        if !meta_store.is_standby() {
+            return;
+        }
+        *meta_store.last_leader_contact.write().await = SystemTime::now();
+        meta_store
+            .replication_loop
+            .process(
+                meta_store.clone(),
+                async move |_| Ok(Delay::new(Duration::from_secs(1)).await),
+                move |m, _| {
+                    let leader = leader.clone();
+                    async move { m.replicate_from(leader.as_ref()).await }
+                },
+            )
+            .await;
+    }
+
+    pub(crate) async fn replication_updates_since(
+        &self,
+        since_seq: Option<u64>,
+    ) -> Result<ReplicationUpdate, CubeError> {
+        if self.is_standby() {
+            return Err(CubeError::internal(
+                "Meta store in standby mode can't serve replication updates".to_string(),
+            ));
+        }
+        if let Some(since_seq) = since_seq {
+            let db = acquire_lock("meta store replication", self.db.read())
+                .await?
+                .clone();
+            let updates = cube_ext::spawn_blocking(move || -> Result<_, CubeError> {
+                let mut container = WriteBatchContainer::new();
+                let mut last_seq = since_seq;
+                for (seq, batch) in db.get_updates_since(since_seq + 1)? {
+                    let batch_last_seq = seq + batch.len() as u64 - 1;
+                    if batch_last_seq <= last_seq {
+                        continue;
+                    }
+                    if seq > last_seq + 1 {
+                        // Write ahead log has been truncated past the requested position.
+                        return Ok(None);
+                    }
+                    batch.iterate(&mut container);
+                    last_seq = batch_last_seq;
+                }
+                Ok(Some((container.entries, last_seq)))
+            })
+            .await?;
+            match updates {
+                Ok(Some((entries, last_seq))) => {
+                    return Ok(ReplicationUpdate::Batches { entries, last_seq })
+                }
+                Ok(None) => {}
+                Err(e) => warn!(
+                    "Can't read meta store updates since {}, falling back to checkpoint: {}",
+                    since_seq, e
+                ),
+            }
+        }
+        if !self.config.upload_to_remote() {
+            return Err(CubeError::internal(
+                "Meta store replication requires uploads to remote storage".to_string(),
+            ));
+        }
+        self.check_leadership(true).await?;
+        let (remote_path, last_seq) = self.upload_check_point().await?;
+        Ok(ReplicationUpdate::Checkpoint {
+            remote_path,
+            last_seq,
+        })
+    }
+
+    pub(crate) async fn replicate_from(&self, leader: &dyn MetaStore) -> Result<(), CubeError> {
+        if !self.is_standby() {
+            self.replication_loop.stop();
+            return Ok(());
+        }
+        let failover_timeout = Duration::from_secs(self.config.metastore_failover_timeout());
+        let since_seq = *self.replicated_seq.read().await;
+        let update =
+            tokio::time::timeout(failover_timeout, leader.get_replication_updates(since_seq))
+                .await
+                .map_err(|e| CubeError::from(e))
+                .and_then(|r| r);
+        match update {
+            Ok(update) => {
+                *self.last_leader_contact.write().await = SystemTime::now();
+                self.apply_replication_update(update).await
+            }
+            Err(e) => {
+                let last_contact = *self.last_leader_contact.read().await;
+                if last_contact.elapsed()? < failover_timeout {
+                    return Err(e);
+                }
+                warn!(
+                    "Meta store leader hasn't responded for {:?}, promoting standby: {}",
+                    failover_timeout, e
+                );
+                self.promote_to_leader().await
+            }
+        }
+    }
+
+    async fn apply_replication_update(&self, update: ReplicationUpdate) -> Result<(), CubeError> {
+        match update {
+            ReplicationUpdate::Batches { entries, last_seq } => {
+                if !entries.is_empty() {
+                    let db = acquire_lock("meta store replication", self.db.write()).await?;
+                    db.write(WriteBatchContainer { entries }.write_batch())?;
+                    mem::drop(db);
+                    self.invalidate_caches();
+                }
+                *self.replicated_seq.write().await = Some(last_seq);
+            }
+            ReplicationUpdate::Checkpoint {
+                remote_path,
+                last_seq,
+            } => {
+                info!("Reloading standby meta store from {}", remote_path);
+                self.reload_from_checkpoint(&remote_path).await?;
+                *self.replicated_seq.write().await = Some(last_seq);
+            }
+        }
+        Ok(())
+    }
+
+    async fn reload_from_checkpoint(&self, remote_path: &str) -> Result<(), CubeError> {
+        let mut db = acquire_lock("meta store replication reload", self.db.write()).await?;
+        let old_path = db.path().to_path_buf();
+        let replica_path = old_path.with_file_name(format!(
+            "metastore-replica-{}",
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_millis()
+        ));
+        RocksMetaStore::download_checkpoint(self.remote_fs.as_ref(), remote_path, &replica_path)
+            .await?;
+        let options = RocksMetaStore::db_options();
+        let path_to_open = replica_path.clone();
+        let new_db = cube_ext::spawn_blocking(move || DB::open(&options, path_to_open)).await??;
+        *db = Arc::new(new_db);
+        mem::drop(db);
+
+        self.seq_store.lock()?.clear();
+        self.invalidate_caches();
+        if Self::is_replica_path(&old_path) {
+            if let Err(e) = fs::remove_dir_all(&old_path).await {
+                warn!(
+                    "Can't remove stale meta store replica {:?}: {}",
+                    old_path, e
+                );
+            }
+        }
+        Ok(())
+    }
+
+    async fn promote_to_leader(&self) -> Result<(), CubeError> {
+        {
+            let db = acquire_lock("meta store promotion", self.db.read()).await?;
+            let seq = db.latest_sequence_number();
+            *self.last_upload_seq.write().await = seq;
+            *self.last_check_seq.write().await = seq;
+        }
+        if self.config.upload_to_remote() {
+            self.acquire_leadership().await?;
+        }
+        self.standby_tx.send(false)?;
+        if self.config.upload_to_remote() {
+            // New writes are uploaded as logs relative to the latest checkpoint so it should
+            // include everything replicated so far.
+            self.upload_check_point().await?;
+        }
+        info!("Standby meta store promoted to leader");
+        self.replication_loop.stop();
+        Ok(())
+    }
+
+    /// Standbys reload checkpoints into `metastore-replica-*` directories next to `path`. Returns
+    /// the latest of them so a restarted node doesn't fall back to the data it had before.
+    pub(crate) async fn latest_replica_path(path: &Path) -> Result<Option<PathBuf>, CubeError> {
+        let parent = match path.parent() {
+            Some(parent) if fs::metadata(parent).await.is_ok() => parent,
+            _ => return Ok(None),
+        };
+        let mut latest: Option<(u128, PathBuf)> = None;
+        let mut entries = fs::read_dir(parent).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let millis = entry
+                .file_name()
+                .to_str()
+                .and_then(|n| n.strip_prefix("metastore-replica-"))
+                .and_then(|m| m.parse::<u128>().ok());
+            if let Some(millis) = millis {
+                if latest.as_ref().map(|(l, _)| *l < millis).unwrap_or(true) {
+                    latest = Some((millis, entry.path()));
+                }
+            }
+        }
+        Ok(latest.map(|(_, path)| path))
+    }
+
+    fn is_replica_path(path: &Path) -> bool {
+        path.file_name()
+            .and_then(|n| n.to_str())
+            .map(|n| n.starts_with("metastore-replica-"))
+            .unwrap_or(false)
+    }
+
+    pub(crate) async fn download_checkpoint(
+        remote_fs: &dyn crate::remotefs::RemoteFs,
+        remote_path: &str,
+        target_dir: &Path,
+    ) -> Result<(), CubeError> {
+        let to_load = remote_fs.list(&format!("{}/", remote_path)).await?;
+        fs::create_dir_all(target_dir).await?;
+        for file in to_load.iter() {
+            remote_fs.download_file(file).await?;
+            let local = remote_fs.local_file(file).await?;
+            let path = Path::new(&local);
+            fs::copy(
+                path,
+                PathBuf::from(target_dir).join(path.file_name().unwrap().to_str().unwrap()),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+}
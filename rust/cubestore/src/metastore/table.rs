@@ -5,7 +5,7 @@ use super::{DataFrameValue, TableValue};
 use crate::base_rocks_secondary_index;
 use crate::data_frame_from;
 use crate::format_table_value;
-use crate::metastore::{IdRow, ImportFormat, MetaStoreEvent, Schema};
+use crate::metastore::{IdRow, ImportFormat, MetaStoreEvent, Schema, StreamSource};
 use crate::rocks_table_impl;
 use crate::store::DataFrame;
 use crate::table::Row;
@@ -31,7 +31,13 @@ pub struct Table {
     #[serde(default="Table::is_ready_default")]
     is_ready: bool,
     #[serde(default)]
-    created_at: Option<DateTime<Utc>>
+    created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    stream_source: Option<StreamSource>,
+    /// Set by `WITH (batch_id = '...')`. Creating the table again with the same batch id is a
+    /// no-op and imports of its locations are recorded under this id.
+    #[serde(default)]
+    batch_id: Option<String>
 }
 }
 
@@ -56,6 +62,8 @@ impl Table {
         columns: Vec<Column>,
         locations: Option<Vec<String>>,
         import_format: Option<ImportFormat>,
+        stream_source: Option<StreamSource>,
+        batch_id: Option<String>,
         is_ready: bool,
     ) -> Table {
         Table {
@@ -67,6 +75,8 @@ impl Table {
             has_data: false,
             is_ready,
             created_at: Some(Utc::now()),
+            stream_source,
+            batch_id,
         }
     }
     pub fn get_columns(&self) -> &Vec<Column> {
@@ -81,6 +91,25 @@ impl Table {
         &self.import_format
     }
 
+    pub fn stream_source(&self) -> &Option<StreamSource> {
+        &self.stream_source
+    }
+
+    pub fn batch_id(&self) -> &Option<String> {
+        &self.batch_id
+    }
+
+    /// Id the import of `location` is recorded under to import it only once. That's the location
+    /// itself unless the table has an explicit batch id, which is then suffixed with the position
+    /// of the location.
+    pub fn location_batch_id(&self, location: &str) -> String {
+        let position = self.locations.iter().flatten().position(|l| l == location);
+        match (&self.batch_id, position) {
+            (Some(batch_id), Some(position)) => format!("{}:{}", batch_id, position),
+            _ => location.to_string(),
+        }
+    }
+
     pub fn locations(&self) -> Option<Vec<&String>> {
         self.locations.as_ref().map(|l| l.iter().collect())
     }
@@ -109,6 +138,13 @@ impl Table {
         table
     }
 
+    pub fn update_path(&self, schema_id: u64, table_name: String) -> Self {
+        let mut table = self.clone();
+        table.schema_id = schema_id;
+        table.table_name = table_name;
+        table
+    }
+
     pub fn is_ready_default() -> bool {
         true
     }
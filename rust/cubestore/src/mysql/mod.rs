@@ -1,59 +1,44 @@
 use crate::config::processing_loop::ProcessingLoop;
+use crate::mysql::prepared_statement::PreparedStatement;
+use crate::sql::parser::CubeStoreParser;
 use crate::sql::{SqlQueryContext, SqlService};
 use crate::table::TableValue;
 use crate::util::time_span::warn_long;
+use crate::util::tls::NodeTls;
 use crate::{metastore, CubeError};
 use async_trait::async_trait;
+use cubemysql::{bind_params, param_to_sql, placeholder_positions};
 use datafusion::cube_ext;
 use hex::ToHex;
-use log::{error, info, warn};
+use log::{error, info, trace, warn};
 use msql_srv::*;
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::io;
 use std::sync::Arc;
 use std::time::Duration;
 use std::time::SystemTime;
-use tokio::net::TcpListener;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::{watch, RwLock};
+use tokio_rustls::TlsAcceptor;
+
+pub mod prepared_statement;
 
 struct Backend {
     sql_service: Arc<dyn SqlService>,
     auth: Arc<dyn SqlAuthService>,
     user: Option<String>,
+    statements: HashMap<u32, PreparedStatement>,
+    next_statement_id: u32,
 }
 
-#[async_trait]
-impl<W: io::Write + Send> AsyncMysqlShim<W> for Backend {
-    type Error = io::Error;
-
-    async fn on_prepare<'a>(
-        &'a mut self,
-        _query: &'a str,
-        info: StatementMetaWriter<'a, W>,
-    ) -> Result<(), Self::Error> {
-        info.reply(42, &[], &[])
-    }
-
-    async fn on_execute<'a>(
-        &'a mut self,
-        _id: u32,
-        _params: ParamParser<'a>,
-        results: QueryResultWriter<'a, W>,
-    ) -> Result<(), Self::Error> {
-        results.completed(0, 0)
-    }
-
-    async fn on_close<'a>(&'a mut self, _stmt: u32)
-    where
-        W: 'async_trait,
-    {
-    }
-
-    async fn on_query<'a>(
-        &'a mut self,
-        query: &'a str,
-        results: QueryResultWriter<'a, W>,
-    ) -> Result<(), Self::Error> {
+impl Backend {
+    async fn run_query<W: io::Write + Send>(
+        &self,
+        query: &str,
+        results: QueryResultWriter<'_, W>,
+    ) -> Result<(), io::Error> {
         let start = SystemTime::now();
         let res = self
             .sql_service
@@ -75,25 +60,7 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for Backend {
         }
         let _s = warn_long("sending query results", Duration::from_millis(100));
         let data_frame = res.unwrap();
-        let columns = data_frame
-            .get_columns()
-            .iter()
-            .map(|c| Column {
-                table: "result".to_string(), // TODO
-                column: c.get_name().to_string(),
-                coltype: match c.get_column_type() {
-                    metastore::ColumnType::String => ColumnType::MYSQL_TYPE_STRING,
-                    metastore::ColumnType::Timestamp => ColumnType::MYSQL_TYPE_STRING,
-                    metastore::ColumnType::Int => ColumnType::MYSQL_TYPE_LONGLONG,
-                    metastore::ColumnType::Decimal { .. } => ColumnType::MYSQL_TYPE_DECIMAL,
-                    metastore::ColumnType::Boolean => ColumnType::MYSQL_TYPE_STRING,
-                    metastore::ColumnType::Bytes => ColumnType::MYSQL_TYPE_STRING,
-                    metastore::ColumnType::HyperLogLog(_) => ColumnType::MYSQL_TYPE_STRING,
-                    metastore::ColumnType::Float => ColumnType::MYSQL_TYPE_STRING,
-                },
-                colflags: ColumnFlags::empty(),
-            })
-            .collect::<Vec<_>>();
+        let columns = mysql_columns(data_frame.get_columns());
 
         let mut rw = results.start(&columns)?;
         for row in data_frame.get_rows().iter() {
@@ -101,6 +68,7 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for Backend {
                 match value {
                     TableValue::String(s) => rw.write_col(s)?,
                     TableValue::Timestamp(s) => rw.write_col(s.to_string())?,
+                    TableValue::Date(d) => rw.write_col(d.to_string())?,
                     TableValue::Int(i) => rw.write_col(i)?,
                     TableValue::Decimal(v) => {
                         let scale = u8::try_from(
@@ -131,6 +99,136 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for Backend {
         Ok(())
     }
 
+    /// Plans `query` with empty strings in place of its parameters to describe the result.
+    /// Queries which plan only with actual values are described on execute, but they must parse.
+    async fn describe_statement(
+        &self,
+        query: &str,
+        params: usize,
+    ) -> Result<Vec<metastore::Column>, CubeError> {
+        let placeholder_query = bind_params(query, &vec!["''".to_string(); params])?;
+        let context = SqlQueryContext {
+            user: self.user.clone(),
+        };
+        match self
+            .sql_service
+            .describe_query(context, &placeholder_query)
+            .await
+        {
+            Ok(columns) => Ok(columns),
+            Err(e) => {
+                CubeStoreParser::new(&placeholder_query.replace("\\'", "''"))?.parse_statement()?;
+                trace!("Unable to describe prepared statement: {}", e);
+                Ok(vec![])
+            }
+        }
+    }
+}
+
+fn mysql_columns(columns: &[metastore::Column]) -> Vec<Column> {
+    columns
+        .iter()
+        .map(|c| Column {
+            table: "result".to_string(), // TODO
+            column: c.get_name().to_string(),
+            coltype: match c.get_column_type() {
+                metastore::ColumnType::String => ColumnType::MYSQL_TYPE_STRING,
+                metastore::ColumnType::Timestamp => ColumnType::MYSQL_TYPE_STRING,
+                metastore::ColumnType::Int => ColumnType::MYSQL_TYPE_LONGLONG,
+                metastore::ColumnType::Decimal { .. } => ColumnType::MYSQL_TYPE_DECIMAL,
+                metastore::ColumnType::Boolean => ColumnType::MYSQL_TYPE_STRING,
+                metastore::ColumnType::Bytes => ColumnType::MYSQL_TYPE_STRING,
+                metastore::ColumnType::HyperLogLog(_) => ColumnType::MYSQL_TYPE_STRING,
+                metastore::ColumnType::Float => ColumnType::MYSQL_TYPE_STRING,
+                metastore::ColumnType::Int32 => ColumnType::MYSQL_TYPE_LONG,
+                metastore::ColumnType::Date => ColumnType::MYSQL_TYPE_STRING,
+                metastore::ColumnType::Decimal128 { .. } => ColumnType::MYSQL_TYPE_DECIMAL,
+            },
+            colflags: ColumnFlags::empty(),
+        })
+        .collect()
+}
+
+#[async_trait]
+impl<W: io::Write + Send> AsyncMysqlShim<W> for Backend {
+    type Error = io::Error;
+
+    async fn on_prepare<'a>(
+        &'a mut self,
+        query: &'a str,
+        info: StatementMetaWriter<'a, W>,
+    ) -> Result<(), Self::Error> {
+        let params = placeholder_positions(query).len();
+        let columns = match self.describe_statement(query, params).await {
+            Ok(columns) => columns,
+            Err(e) => {
+                error!("Error during preparing {}: {}", query, e);
+                return info.error(ErrorKind::ER_PARSE_ERROR, e.message.as_bytes());
+            }
+        };
+
+        let id = self.next_statement_id;
+        self.next_statement_id += 1;
+        self.statements.insert(
+            id,
+            PreparedStatement {
+                query: query.to_string(),
+                params,
+            },
+        );
+
+        let params = (0..params)
+            .map(|_| Column {
+                table: "".to_string(),
+                column: "?".to_string(),
+                coltype: ColumnType::MYSQL_TYPE_VAR_STRING,
+                colflags: ColumnFlags::empty(),
+            })
+            .collect::<Vec<_>>();
+        info.reply(id, &params, &mysql_columns(&columns))
+    }
+
+    async fn on_execute<'a>(
+        &'a mut self,
+        id: u32,
+        params: ParamParser<'a>,
+        results: QueryResultWriter<'a, W>,
+    ) -> Result<(), Self::Error> {
+        let statement = match self.statements.get(&id) {
+            Some(statement) => statement,
+            None => {
+                return results.error(
+                    ErrorKind::ER_UNKNOWN_STMT_HANDLER,
+                    format!("Unknown prepared statement: {}", id).as_bytes(),
+                )
+            }
+        };
+
+        let values = params.into_iter().map(param_to_sql).collect::<Vec<_>>();
+        match bind_params(&statement.query, &values) {
+            Ok(query) => self.run_query(&query, results).await,
+            Err(e) => {
+                error!("Error during executing {}: {}", statement.query, e);
+                results.error(ErrorKind::ER_WRONG_ARGUMENTS, e.message.as_bytes())
+            }
+        }
+    }
+
+    async fn on_close<'a>(&'a mut self, stmt: u32)
+    where
+        W: 'async_trait,
+    {
+        self.statements.remove(&stmt);
+    }
+
+    async fn on_query<'a>(
+        &'a mut self,
+        query: &'a str,
+        results: QueryResultWriter<'a, W>,
+    ) -> Result<(), Self::Error> {
+        self.run_query(query, results).await
+    }
+
     async fn on_auth<'a>(&'a mut self, user: Vec<u8>) -> Result<Option<Vec<u8>>, Self::Error>
     where
         W: 'async_trait,
@@ -152,6 +250,7 @@ pub struct MySqlServer {
     address: String,
     sql_service: Arc<dyn SqlService>,
     auth: Arc<dyn SqlAuthService>,
+    tls: Arc<NodeTls>,
     close_socket_rx: RwLock<watch::Receiver<bool>>,
     close_socket_tx: watch::Sender<bool>,
 }
@@ -188,17 +287,30 @@ impl ProcessingLoop for MySqlServer {
 
             let sql_service = self.sql_service.clone();
             let auth = self.auth.clone();
+            let acceptor = self.tls.sql_acceptor().cloned();
             cube_ext::spawn(async move {
-                if let Err(e) = AsyncMysqlIntermediary::run_on(
-                    Backend {
-                        sql_service,
-                        auth,
-                        user: None,
-                    },
-                    socket,
-                )
-                .await
-                {
+                let backend = Backend {
+                    sql_service,
+                    auth,
+                    user: None,
+                    statements: HashMap::new(),
+                    next_statement_id: 1,
+                };
+                let res = match acceptor {
+                    Some(acceptor) => {
+                        let (server_side, relay_side) = tokio::io::duplex(TLS_RELAY_BUFFER);
+                        let relay = cube_ext::spawn(relay_tls(socket, acceptor, relay_side));
+                        let res = AsyncMysqlIntermediary::run_on(backend, server_side).await;
+                        match relay.await {
+                            Ok(Err(e)) => warn!("TLS error on MySQL connection: {}", e),
+                            Err(e) => error!("TLS relay for MySQL connection failed: {}", e),
+                            Ok(Ok(())) => {}
+                        }
+                        res
+                    }
+                    None => AsyncMysqlIntermediary::run_on(backend, socket).await,
+                };
+                if let Err(e) = res {
                     error!("Error during processing MySQL connection: {}", e);
                 }
             });
@@ -216,18 +328,102 @@ impl MySqlServer {
         address: String,
         sql_service: Arc<dyn SqlService>,
         auth: Arc<dyn SqlAuthService>,
+        tls: Arc<NodeTls>,
     ) -> Arc<Self> {
         let (close_socket_tx, close_socket_rx) = watch::channel(false);
         Arc::new(Self {
             address,
             sql_service,
             auth,
+            tls,
             close_socket_rx: RwLock::new(close_socket_rx),
             close_socket_tx,
         })
     }
 }
 
+const TLS_RELAY_BUFFER: usize = 64 * 1024;
+const CLIENT_SSL: u32 = 0x0800;
+const SSL_REQUEST_LEN: usize = 32;
+const ER_SECURE_TRANSPORT_REQUIRED: u16 = 3159;
+
+/// MySQL clients switch to TLS in the middle of the handshake: the server greeting advertises
+/// `CLIENT_SSL`, the client answers with a short SSLRequest packet and both sides continue over
+/// TLS. msql-srv only speaks plaintext, so this relays `socket` to `server_side` and does the
+/// upgrade on the way. Clients that don't ask for TLS are rejected.
+async fn relay_tls<S: AsyncRead + AsyncWrite + Unpin>(
+    mut socket: TcpStream,
+    acceptor: TlsAcceptor,
+    mut server_side: S,
+) -> Result<(), CubeError> {
+    let mut greeting = read_packet(&mut server_side).await?;
+    advertise_ssl(&mut greeting)?;
+    socket.write_all(&greeting).await?;
+
+    let request = read_packet(&mut socket).await?;
+    let capabilities = request
+        .get(4..8)
+        .map(|f| u32::from_le_bytes([f[0], f[1], f[2], f[3]]))
+        .unwrap_or(0);
+    if request.len() != 4 + SSL_REQUEST_LEN || capabilities & CLIENT_SSL == 0 {
+        socket
+            .write_all(&error_packet(
+                request[3].wrapping_add(1),
+                ER_SECURE_TRANSPORT_REQUIRED,
+                "Connections using insecure transport are prohibited",
+            ))
+            .await?;
+        return Err(CubeError::user(
+            "Client didn't request TLS on MySQL port".to_string(),
+        ));
+    }
+
+    let mut tls_stream = acceptor
+        .accept(socket)
+        .await
+        .map_err(|e| CubeError::internal(format!("TLS handshake failed: {}", e)))?;
+    tokio::io::copy_bidirectional(&mut tls_stream, &mut server_side).await?;
+    Ok(())
+}
+
+async fn read_packet<R: AsyncRead + Unpin>(r: &mut R) -> Result<Vec<u8>, CubeError> {
+    let mut packet = vec![0; 4];
+    r.read_exact(&mut packet).await?;
+    let len = u32::from_le_bytes([packet[0], packet[1], packet[2], 0]) as usize;
+    packet.resize(4 + len, 0);
+    r.read_exact(&mut packet[4..]).await?;
+    Ok(packet)
+}
+
+/// Sets `CLIENT_SSL` in the lower capability flags of a protocol 10 handshake packet.
+fn advertise_ssl(greeting: &mut [u8]) -> Result<(), CubeError> {
+    let payload = &mut greeting[4..];
+    let version_end = payload
+        .iter()
+        .skip(1)
+        .position(|b| *b == 0)
+        .ok_or_else(|| CubeError::internal("Malformed MySQL greeting".to_string()))?
+        + 1;
+    // Version terminator, connection id, first part of auth data and filler.
+    let flags = version_end + 1 + 4 + 8 + 1;
+    if payload.get(0) != Some(&10) || payload.len() < flags + 2 {
+        return Err(CubeError::internal("Malformed MySQL greeting".to_string()));
+    }
+    payload[flags + 1] |= (CLIENT_SSL >> 8) as u8;
+    Ok(())
+}
+
+fn error_packet(seq: u8, code: u16, message: &str) -> Vec<u8> {
+    let mut payload = vec![0xff];
+    payload.extend_from_slice(&code.to_le_bytes());
+    payload.extend_from_slice(b"#HY000");
+    payload.extend_from_slice(message.as_bytes());
+    let mut packet = (payload.len() as u32).to_le_bytes()[..3].to_vec();
+    packet.push(seq);
+    packet.extend(payload);
+    packet
+}
+
 #[async_trait]
 pub trait SqlAuthService: Send + Sync {
     async fn authenticate(&self, user: Option<String>) -> Result<Option<String>, CubeError>;
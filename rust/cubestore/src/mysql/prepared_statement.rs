@@ -0,0 +1,6 @@
+//! Server-side prepared statements, see [cubemysql] for binding their parameters.
+
+pub struct PreparedStatement {
+    pub query: String,
+    pub params: usize,
+}
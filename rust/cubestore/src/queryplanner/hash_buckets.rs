@@ -0,0 +1,198 @@
+use arrow::array::{ArrayRef, BooleanArray};
+use arrow::compute::filter_record_batch;
+use arrow::datatypes::SchemaRef;
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::DataFusionError;
+use datafusion::physical_plan::group_scalar::GroupByScalar;
+use datafusion::physical_plan::hash_aggregate::create_group_by_values;
+use datafusion::physical_plan::{
+    ExecutionPlan, OptimizerHints, Partitioning, RecordBatchStream, SendableRecordBatchStream,
+};
+use futures::task::{Context, Poll};
+use futures::{Stream, StreamExt};
+use itertools::Itertools;
+use smallvec::{smallvec, SmallVec};
+use std::any::Any;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Keeps rows of the input whose hash of `columns` falls into `bucket` out of `buckets`. Inputs
+/// of shuffled hash joins are read whole by a task per bucket, which then joins only the rows of
+/// its bucket. Equal keys have equal hashes on both sides, so each match is found by exactly one
+/// task.
+#[derive(Debug)]
+pub struct HashBucketExec {
+    pub input: Arc<dyn ExecutionPlan>,
+    /// Indices of the key columns in the input.
+    pub columns: Vec<usize>,
+    pub buckets: u64,
+    pub bucket: u64,
+}
+
+/// Bucket of the key in `row` of `keys`, the same for all nodes running the same version.
+fn hash_bucket(
+    keys: &[ArrayRef],
+    row: usize,
+    buckets: u64,
+    key: &mut SmallVec<[GroupByScalar; 2]>,
+) -> Result<u64, DataFusionError> {
+    create_group_by_values(keys, row, key)?;
+    // Not randomized unlike the default hasher of `HashMap`.
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    Ok(hasher.finish() % buckets)
+}
+
+fn filter_bucket(
+    batch: RecordBatch,
+    columns: &[usize],
+    buckets: u64,
+    bucket: u64,
+) -> Result<RecordBatch, ArrowError> {
+    let keys = columns
+        .iter()
+        .map(|c| batch.column(*c).clone())
+        .collect_vec();
+    let mut key = smallvec![GroupByScalar::Int8(0); keys.len()];
+    let mut in_bucket = Vec::with_capacity(batch.num_rows());
+    for row in 0..batch.num_rows() {
+        let b = hash_bucket(&keys, row, buckets, &mut key)
+            .map_err(|e| ArrowError::ComputeError(e.to_string()))?;
+        in_bucket.push(b == bucket);
+    }
+    filter_record_batch(&batch, &BooleanArray::from(in_bucket))
+}
+
+#[async_trait]
+impl ExecutionPlan for HashBucketExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        self.input.output_partitioning()
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        assert_eq!(children.len(), 1);
+        Ok(Arc::new(HashBucketExec {
+            input: children.into_iter().next().unwrap(),
+            columns: self.columns.clone(),
+            buckets: self.buckets,
+            bucket: self.bucket,
+        }))
+    }
+
+    fn output_hints(&self) -> OptimizerHints {
+        // Filtering keeps the order and the single value columns.
+        self.input.output_hints()
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<SendableRecordBatchStream, DataFusionError> {
+        let input = self.input.execute(partition).await?;
+        let (columns, buckets, bucket) = (self.columns.clone(), self.buckets, self.bucket);
+        Ok(Box::pin(HashBucketStream {
+            schema: self.schema(),
+            inner: Box::pin(
+                input.map(move |batch| filter_bucket(batch?, &columns, buckets, bucket)),
+            ),
+        }))
+    }
+}
+
+struct HashBucketStream {
+    schema: SchemaRef,
+    inner: Pin<Box<dyn Stream<Item = Result<RecordBatch, ArrowError>> + Send>>,
+}
+
+impl Stream for HashBucketStream {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+impl RecordBatchStream for HashBucketStream {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Int64Array, StringArray};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::physical_plan::collect;
+    use datafusion::physical_plan::memory::MemoryExec;
+
+    #[tokio::test]
+    async fn buckets_split_rows() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("k", DataType::Int64, true),
+            Field::new("v", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(vec![
+                    Some(1),
+                    Some(2),
+                    Some(3),
+                    None,
+                    Some(1),
+                    Some(5),
+                ])),
+                Arc::new(StringArray::from(vec!["a", "b", "c", "d", "e", "f"])),
+            ],
+        )
+        .unwrap();
+        let input = Arc::new(MemoryExec::try_new(&vec![vec![batch]], schema, None).unwrap());
+
+        let mut rows = Vec::new();
+        for bucket in 0..3 {
+            let exec = HashBucketExec {
+                input: input.clone(),
+                columns: vec![0],
+                buckets: 3,
+                bucket,
+            };
+            let mut keys = Vec::new();
+            for b in collect(Arc::new(exec)).await.unwrap() {
+                let k = b.column(0).as_any().downcast_ref::<Int64Array>().unwrap();
+                let v = b.column(1).as_any().downcast_ref::<StringArray>().unwrap();
+                for i in 0..b.num_rows() {
+                    keys.push(if k.is_null(i) { None } else { Some(k.value(i)) });
+                    rows.push(v.value(i).to_string());
+                }
+            }
+            // Equal keys always end up in the same bucket.
+            if keys.contains(&Some(1)) {
+                assert_eq!(keys.iter().filter(|k| **k == Some(1)).count(), 2);
+            }
+        }
+
+        // Every row is in exactly one bucket.
+        rows.sort();
+        assert_eq!(rows, vec!["a", "b", "c", "d", "e", "f"]);
+    }
+}
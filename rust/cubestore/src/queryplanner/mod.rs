@@ -1,14 +1,18 @@
+mod hash_buckets;
 pub mod hll;
 mod optimizations;
 mod partition_filter;
 mod planning;
 pub mod pretty_printers;
 pub mod query_executor;
+mod range_join;
 pub mod serialized_plan;
 mod topk;
 pub use topk::MIN_TOPK_STREAM_ROWS;
 mod coalesce;
 mod now;
+mod strings;
+mod time_zones;
 pub mod udfs;
 
 use crate::config::injection::DIService;
@@ -16,9 +20,11 @@ use crate::config::ConfigObj;
 use crate::metastore::table::{Table, TablePath};
 use crate::metastore::{IdRow, MetaStore, MetaStoreTable};
 use crate::queryplanner::now::MaterializeNow;
-use crate::queryplanner::planning::{choose_index_ext, ClusterSendNode};
+use crate::queryplanner::planning::{choose_index_ext, unindexed_query_patterns, ClusterSendNode};
 use crate::queryplanner::query_executor::{batch_to_dataframe, ClusterSendExec};
 use crate::queryplanner::serialized_plan::SerializedPlan;
+use crate::queryplanner::strings::RewriteEscapedLike;
+use crate::queryplanner::time_zones::ResolveTimeZones;
 use crate::queryplanner::topk::ClusterAggregateTopK;
 use crate::queryplanner::udfs::aggregate_udf_by_kind;
 use crate::queryplanner::udfs::{scalar_udf_by_kind, CubeAggregateUDFKind, CubeScalarUDFKind};
@@ -43,7 +49,7 @@ use datafusion::sql::parser::Statement;
 use datafusion::sql::planner::{ContextProvider, SqlToRel};
 use datafusion::{cube_ext, datasource::TableProvider, prelude::ExecutionContext};
 use itertools::Itertools;
-use log::{debug, trace};
+use log::{debug, error, trace};
 use mockall::automock;
 use serde_derive::{Deserialize, Serialize};
 use smallvec::alloc::fmt::Formatter;
@@ -91,15 +97,25 @@ impl QueryPlanner for QueryPlannerImpl {
         trace!("Logical Plan: {:#?}", &logical_plan);
 
         let plan = if SerializedPlan::is_data_select_query(&logical_plan) {
-            let (logical_plan, index_snapshots) = choose_index_ext(
+            let (chosen_plan, index_snapshots) = choose_index_ext(
                 &logical_plan,
                 &self.meta_store.as_ref(),
                 self.config.enable_topk(),
+                self.config.broadcast_join_max_rows(),
             )
             .await?;
-            let partitions = extract_partitions(&logical_plan)?;
+            let patterns = unindexed_query_patterns(&logical_plan, &index_snapshots)?;
+            if !patterns.is_empty() {
+                let meta_store = self.meta_store.clone();
+                cube_ext::spawn(async move {
+                    if let Err(e) = meta_store.record_query_patterns(patterns).await {
+                        error!("Error while recording query patterns: {}", e);
+                    }
+                });
+            }
+            let partitions = extract_partitions(&chosen_plan)?;
             QueryPlan::Select(
-                SerializedPlan::try_new(logical_plan, index_snapshots).await?,
+                SerializedPlan::try_new(chosen_plan, index_snapshots).await?,
                 partitions,
             )
         } else {
@@ -140,7 +156,10 @@ impl QueryPlannerImpl {
 impl QueryPlannerImpl {
     async fn execution_context(&self) -> Result<Arc<ExecutionContext>, CubeError> {
         Ok(Arc::new(ExecutionContext::with_config(
-            ExecutionConfig::new().add_optimizer_rule(Arc::new(MaterializeNow {})),
+            ExecutionConfig::new()
+                .add_optimizer_rule(Arc::new(MaterializeNow {}))
+                .add_optimizer_rule(Arc::new(ResolveTimeZones {}))
+                .add_optimizer_rule(Arc::new(RewriteEscapedLike {})),
         )))
     }
 }
@@ -200,7 +219,16 @@ impl ContextProvider for MetaStoreSchemaProvider {
         let name = TablePath {
             table: IdRow::new(
                 u64::MAX,
-                Table::new(table.to_string(), u64::MAX, Vec::new(), None, None, false),
+                Table::new(
+                    table.to_string(),
+                    u64::MAX,
+                    Vec::new(),
+                    None,
+                    None,
+                    None,
+                    None,
+                    false,
+                ),
             ),
             schema: Arc::new(IdRow::new(0, metastore::Schema::new(schema.to_string()))),
         };
@@ -245,6 +273,14 @@ impl ContextProvider for MetaStoreSchemaProvider {
             "unix_timestamp" | "UNIX_TIMESTAMP" => CubeScalarUDFKind::UnixTimestamp,
             "date_add" | "DATE_ADD" => CubeScalarUDFKind::DateAdd,
             "date_sub" | "DATE_SUB" => CubeScalarUDFKind::DateSub,
+            "substring" | "SUBSTRING" => CubeScalarUDFKind::Substring,
+            "substring_index" | "SUBSTRING_INDEX" => CubeScalarUDFKind::SubstringIndex,
+            "locate" | "LOCATE" => CubeScalarUDFKind::Locate,
+            "instr" | "INSTR" => CubeScalarUDFKind::Instr,
+            "regexp_like" | "REGEXP_LIKE" => CubeScalarUDFKind::RegexpLike,
+            "regexp_substr" | "REGEXP_SUBSTR" => CubeScalarUDFKind::RegexpSubstr,
+            "regexp_replace" | "REGEXP_REPLACE" => CubeScalarUDFKind::RegexpReplace,
+            "split_part" | "SPLIT_PART" => CubeScalarUDFKind::SplitPart,
             _ => return None,
         };
         return Some(Arc::new(scalar_udf_by_kind(kind).descriptor()));
@@ -1,6 +1,8 @@
 use crate::cluster::Cluster;
 use crate::queryplanner::optimizations::distributed_partial_aggregate::push_aggregate_to_workers;
 use crate::queryplanner::optimizations::prefer_inplace_aggregates::try_switch_to_inplace_aggregates;
+use crate::queryplanner::optimizations::sorted_range_joins::try_use_sorted_range_join_input;
+use crate::queryplanner::optimizations::sorted_windows::try_skip_window_sort;
 use crate::queryplanner::planning::CubeExtensionPlanner;
 use crate::queryplanner::serialized_plan::SerializedPlan;
 use datafusion::error::DataFusionError;
@@ -14,6 +16,8 @@ use std::sync::Arc;
 mod distributed_partial_aggregate;
 mod prefer_inplace_aggregates;
 pub mod rewrite_plan;
+mod sorted_range_joins;
+mod sorted_windows;
 
 pub struct CubeQueryPlanner {
     cluster: Option<Arc<dyn Cluster>>,
@@ -60,5 +64,8 @@ fn finalize_physical_plan(
     p: Arc<dyn ExecutionPlan>,
 ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
     let p = rewrite_physical_plan(p.as_ref(), &mut |p| try_switch_to_inplace_aggregates(p))?;
-    rewrite_physical_plan(p.as_ref(), &mut |p| push_aggregate_to_workers(p))
+    let p = rewrite_physical_plan(p.as_ref(), &mut |p| try_skip_window_sort(p))?;
+    let p = rewrite_physical_plan(p.as_ref(), &mut |p| push_aggregate_to_workers(p))?;
+    // Goes last, other rewrites do not preserve the order of inputs.
+    rewrite_physical_plan(p.as_ref(), &mut |p| try_use_sorted_range_join_input(p))
 }
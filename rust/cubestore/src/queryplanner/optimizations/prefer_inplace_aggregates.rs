@@ -45,7 +45,7 @@ pub fn try_switch_to_inplace_aggregates(
 }
 
 /// Attempts to provide **some** grouping in the results, but no particular one is guaranteed.
-fn try_regroup_columns(
+pub fn try_regroup_columns(
     p: Arc<dyn ExecutionPlan>,
 ) -> datafusion::error::Result<Arc<dyn ExecutionPlan>> {
     if p.as_any().is::<HashAggregateExec>() {
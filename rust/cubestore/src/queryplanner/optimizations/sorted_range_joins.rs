@@ -0,0 +1,43 @@
+use crate::queryplanner::optimizations::prefer_inplace_aggregates::try_regroup_columns;
+use crate::queryplanner::range_join::RangeJoinAggExec;
+use datafusion::error::DataFusionError;
+use datafusion::physical_plan::expressions::Column;
+use datafusion::physical_plan::sort::SortExec;
+use datafusion::physical_plan::ExecutionPlan;
+use std::sync::Arc;
+
+/// Removes the sort of the right input of range joins when it is already sorted by time, e.g.
+/// because partitions of the chosen index can be merged in the time order.
+pub fn try_use_sorted_range_join_input(
+    p: Arc<dyn ExecutionPlan>,
+) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+    let join;
+    if let Some(j) = p.as_any().downcast_ref::<RangeJoinAggExec>() {
+        join = j;
+    } else {
+        return Ok(p);
+    }
+    let sort;
+    if let Some(s) = join.right.as_any().downcast_ref::<SortExec>() {
+        sort = s;
+    } else {
+        return Ok(p);
+    }
+    let time;
+    if let Some(c) = join.time.as_any().downcast_ref::<Column>() {
+        time = c.index();
+    } else {
+        return Ok(p);
+    }
+
+    let input = try_regroup_columns(sort.input().clone())?;
+    if input.output_partitioning().partition_count() != 1 {
+        return Ok(p);
+    }
+    match input.output_hints().sort_order {
+        Some(order) if order.first() == Some(&time) => {
+            p.with_new_children(vec![join.left.clone(), input])
+        }
+        _ => Ok(p),
+    }
+}
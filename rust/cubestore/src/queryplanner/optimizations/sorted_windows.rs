@@ -0,0 +1,48 @@
+use crate::queryplanner::optimizations::prefer_inplace_aggregates::try_regroup_columns;
+use datafusion::error::DataFusionError;
+use datafusion::physical_plan::expressions::Column;
+use datafusion::physical_plan::sort::SortExec;
+use datafusion::physical_plan::windows::WindowAggExec;
+use datafusion::physical_plan::ExecutionPlan;
+use std::sync::Arc;
+
+/// Removes the sort below window functions when the input is already sorted by the partition and
+/// order keys, e.g. because the chosen index is sorted on them.
+pub fn try_skip_window_sort(
+    p: Arc<dyn ExecutionPlan>,
+) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+    if !p.as_any().is::<WindowAggExec>() {
+        return Ok(p);
+    }
+    let children = p.children();
+    if children.len() != 1 {
+        return Ok(p);
+    }
+    let sort;
+    if let Some(s) = children[0].as_any().downcast_ref::<SortExec>() {
+        sort = s;
+    } else {
+        return Ok(p);
+    }
+
+    // Indexes are sorted in ascending order with nulls first.
+    let mut sort_columns = Vec::with_capacity(sort.expr().len());
+    for e in sort.expr() {
+        if e.options.descending || !e.options.nulls_first {
+            return Ok(p);
+        }
+        match e.expr.as_any().downcast_ref::<Column>() {
+            Some(c) => sort_columns.push(c.index()),
+            None => return Ok(p),
+        }
+    }
+
+    let input = try_regroup_columns(sort.input().clone())?;
+    if input.output_partitioning().partition_count() != 1 {
+        return Ok(p);
+    }
+    match input.output_hints().sort_order {
+        Some(order) if order.starts_with(&sort_columns) => p.with_new_children(vec![input]),
+        _ => Ok(p),
+    }
+}
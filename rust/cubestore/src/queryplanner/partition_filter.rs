@@ -1,4 +1,5 @@
-use crate::table::{cmp_same_types, TableValue};
+use crate::sql::date_from_string;
+use crate::table::{cmp_same_types, DateValue, TableValue};
 use arrow::datatypes::{DataType, Schema};
 use datafusion::logical_plan::{Column, Expr, Operator};
 use datafusion::scalar::ScalarValue;
@@ -350,6 +351,7 @@ impl Builder<'_> {
         match t {
             t if Self::is_signed_int(t) => Some(TableValue::Int(i64::min_value())),
             DataType::Utf8 => Some(TableValue::String("".to_string())),
+            DataType::Date32 => Some(TableValue::Date(DateValue::new(i32::min_value()))),
             _ => None,
             // TODO: more data types
         }
@@ -358,6 +360,9 @@ impl Builder<'_> {
     fn try_minus_one(mut v: TableValue) -> TableValue {
         match &mut v {
             TableValue::Int(i) if *i != i64::min_value() => *i -= 1,
+            TableValue::Date(d) if d.days_since_epoch() != i32::min_value() => {
+                *d = DateValue::new(d.days_since_epoch() - 1)
+            }
             _ => (),
         }
         v
@@ -366,6 +371,9 @@ impl Builder<'_> {
     fn try_plus_one(mut v: TableValue) -> TableValue {
         match &mut v {
             TableValue::Int(i) if *i != i64::max_value() => *i += 1,
+            TableValue::Date(d) if d.days_since_epoch() != i32::max_value() => {
+                *d = DateValue::new(d.days_since_epoch() + 1)
+            }
             _ => (),
         }
         v
@@ -379,6 +387,7 @@ impl Builder<'_> {
             t if Self::is_signed_int(t) => Self::extract_signed_int(v),
             DataType::Boolean => Self::extract_bool(v),
             DataType::Utf8 => Self::extract_string(v),
+            DataType::Date32 => Self::extract_date(v),
             _ => None,
             // TODO: more data types
         }
@@ -418,6 +427,23 @@ impl Builder<'_> {
         Some(TableValue::String(s.unwrap()))
     }
 
+    fn extract_date(v: &ScalarValue) -> Option<TableValue> {
+        let d = match v {
+            ScalarValue::Date32(v) => DateValue::new(v.unwrap()),
+            ScalarValue::Utf8(s) | ScalarValue::LargeUtf8(s) => {
+                match date_from_string(s.as_ref().unwrap()) {
+                    Ok(d) => d,
+                    Err(_) => {
+                        log::error!("could not convert string to date: {}", s.as_ref().unwrap());
+                        return None;
+                    }
+                }
+            }
+            _ => return None, // TODO: casts.
+        };
+        Some(TableValue::Date(d))
+    }
+
     fn extract_signed_int(v: &ScalarValue) -> Option<TableValue> {
         let ival = match v {
             ScalarValue::Int8(v) => v.unwrap() as i64,
@@ -621,6 +647,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_dates() {
+        let s = schema(&[("a", DataType::Date32)]);
+        let extract = |sql| PartitionFilter::extract(&s, &[parse(sql, &s)]);
+
+        assert_eq!(
+            extract("a = '2021-01-01'").min_max,
+            vec![MinMaxCondition {
+                min: vec![Some(TableValue::Date(DateValue::new(18628)))],
+                max: vec![Some(TableValue::Date(DateValue::new(18628)))],
+            }]
+        );
+        assert_eq!(
+            extract("a < '2021-01-01'").min_max,
+            vec![MinMaxCondition {
+                min: vec![None],
+                max: vec![Some(TableValue::Date(DateValue::new(18627)))],
+            }]
+        );
+    }
+
     #[test]
     fn test_bools() {
         let s = schema(&[("a", DataType::Boolean)]);
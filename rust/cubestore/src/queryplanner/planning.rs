@@ -22,9 +22,12 @@ use std::sync::Arc;
 
 use arrow::datatypes::{Field, SchemaRef};
 use async_trait::async_trait;
+use datafusion::cube_ext::joinagg::CrossJoinAgg;
 use datafusion::error::DataFusionError;
 use datafusion::execution::context::ExecutionContextState;
-use datafusion::logical_plan::{DFSchemaRef, Expr, LogicalPlan, UserDefinedLogicalNode};
+use datafusion::logical_plan::{
+    DFSchemaRef, Expr, JoinType, LogicalPlan, Operator, UserDefinedLogicalNode,
+};
 use datafusion::physical_plan::empty::EmptyExec;
 use datafusion::physical_plan::planner::ExtensionPlanner;
 use datafusion::physical_plan::{
@@ -35,12 +38,16 @@ use flatbuffers::bitflags::_core::fmt::Formatter;
 use itertools::Itertools;
 
 use crate::cluster::Cluster;
+use crate::metastore::query_pattern::{QueryPattern, QueryPatternKind};
 use crate::metastore::table::{Table, TablePath};
 use crate::metastore::{Chunk, IdRow, Index, MetaStore, Partition, Schema};
 use crate::queryplanner::optimizations::rewrite_plan::{rewrite_plan, PlanRewriter};
 use crate::queryplanner::partition_filter::PartitionFilter;
 use crate::queryplanner::query_executor::{ClusterSendExec, CubeTable};
-use crate::queryplanner::serialized_plan::{IndexSnapshot, PartitionSnapshot, SerializedPlan};
+use crate::queryplanner::range_join::plan_range_join_agg;
+use crate::queryplanner::serialized_plan::{
+    IndexSnapshot, JoinDistribution, PartitionSnapshot, SerializedPlan,
+};
 use crate::queryplanner::topk::{materialize_topk, plan_topk, ClusterAggregateTopK};
 use crate::queryplanner::CubeTableLogical;
 use crate::CubeError;
@@ -50,13 +57,14 @@ pub async fn choose_index(
     p: &LogicalPlan,
     metastore: &dyn PlanIndexStore,
 ) -> Result<(LogicalPlan, Vec<IndexSnapshot>), DataFusionError> {
-    choose_index_ext(p, metastore, true).await
+    choose_index_ext(p, metastore, true, 1_000_000).await
 }
 
 pub async fn choose_index_ext(
     p: &LogicalPlan,
     metastore: &dyn PlanIndexStore,
     enable_topk: bool,
+    broadcast_join_max_rows: u64,
 ) -> Result<(LogicalPlan, Vec<IndexSnapshot>), DataFusionError> {
     // Prepare information to choose the index.
     let mut collector = CollectConstraints::default();
@@ -95,6 +103,7 @@ pub async fn choose_index_ext(
     {
         i.partitions = pick_partitions(i, c, ps)?
     }
+    choose_join_distributions(&mut indices, &collector, broadcast_join_max_rows)?;
 
     // We have enough information to finalize the logical plan.
     let mut r = ChooseIndex {
@@ -108,6 +117,39 @@ pub async fn choose_index_ext(
     Ok((plan, indices))
 }
 
+/// Finds parts of the query that no index in `indices`, as chosen by [choose_index_ext], is sorted
+/// on. These are recorded in the metastore to recommend new indices.
+pub fn unindexed_query_patterns(
+    p: &LogicalPlan,
+    indices: &[IndexSnapshot],
+) -> Result<Vec<QueryPattern>, DataFusionError> {
+    let mut collector = CollectConstraints::default();
+    rewrite_plan(p, &None, &mut collector)?;
+    assert_eq!(collector.constraints.len(), indices.len());
+
+    let mut patterns = Vec::new();
+    for (c, i) in collector.constraints.iter().zip(indices) {
+        let table_id = c.table.table.get_id();
+        if let (Some(sort), None) = (&c.sort_on, &i.sort_on) {
+            let kind = if sort.required {
+                QueryPatternKind::Join
+            } else {
+                QueryPatternKind::GroupBy
+            };
+            patterns.push(QueryPattern::new(table_id, kind, sort.sort_on.clone()));
+        }
+        let filter_columns = filter_columns(&c.filters);
+        if !filter_columns.is_empty() && !prunes_by_filter(&i.index, &filter_columns) {
+            patterns.push(QueryPattern::new(
+                table_id,
+                QueryPatternKind::Filter,
+                filter_columns,
+            ));
+        }
+    }
+    Ok(patterns)
+}
+
 #[async_trait]
 pub trait PlanIndexStore: Send + Sync {
     async fn get_tables_with_indexes(
@@ -141,6 +183,15 @@ impl<'a> PlanIndexStore for &'a dyn MetaStore {
 struct SortColumns {
     sort_on: Vec<String>,
     required: bool,
+    join: Option<JoinInput>,
+}
+
+/// Side of the join, identified by its position in [CollectConstraints::joins], a table scan is
+/// a direct input of.
+#[derive(Clone, Copy, PartialEq)]
+struct JoinInput {
+    join: usize,
+    left: bool,
 }
 
 struct IndexConstraints {
@@ -150,9 +201,18 @@ struct IndexConstraints {
     filters: Vec<Expr>,
 }
 
+impl IndexConstraints {
+    fn join(&self) -> Option<JoinInput> {
+        self.sort_on.as_ref().and_then(|s| s.join)
+    }
+}
+
 #[derive(Default)]
 struct CollectConstraints {
     constraints: Vec<IndexConstraints>,
+    joins: Vec<JoinType>,
+    /// Joins whose right side is not visited yet, innermost last.
+    pending_right_joins: Vec<usize>,
 }
 
 impl PlanRewriter for CollectConstraints {
@@ -202,11 +262,21 @@ impl PlanRewriter for CollectConstraints {
                     Some(Some(SortColumns {
                         sort_on: sort_on.into_iter().map(|c| c.unwrap()).collect(),
                         required: false,
+                        join: None,
                     }))
                 } else {
                     Some(None)
                 }
             }
+            LogicalPlan::Window { window_expr, .. } => {
+                // Input sorted by the partition and the order keys avoids sorting for the window.
+                let sort_on = window_sort_columns(window_expr).map(|cols| SortColumns {
+                    sort_on: cols,
+                    required: false,
+                    join: None,
+                });
+                Some(sort_on)
+            }
             _ => None,
         }
     }
@@ -217,14 +287,21 @@ impl PlanRewriter for CollectConstraints {
         _: &Option<SortColumns>,
     ) -> Option<Option<SortColumns>> {
         let join_on;
-        if let LogicalPlan::Join { on, .. } = join {
+        if let LogicalPlan::Join { on, join_type, .. } = join {
             join_on = on;
+            self.joins.push(*join_type);
         } else {
             panic!("expected join node");
         }
+        let join_id = self.joins.len() - 1;
+        self.pending_right_joins.push(join_id);
         Some(Some(SortColumns {
             sort_on: join_on.iter().map(|(l, _)| l.name.clone()).collect(),
             required: true,
+            join: Some(JoinInput {
+                join: join_id,
+                left: true,
+            }),
         }))
     }
 
@@ -239,13 +316,64 @@ impl PlanRewriter for CollectConstraints {
         } else {
             panic!("expected join node");
         }
+        let join_id = self
+            .pending_right_joins
+            .pop()
+            .expect("left side of join must be visited first");
         Some(Some(SortColumns {
             sort_on: join_on.iter().map(|(_, r)| r.name.clone()).collect(),
             required: true,
+            join: Some(JoinInput {
+                join: join_id,
+                left: false,
+            }),
         }))
     }
 }
 
+/// Columns of `PARTITION BY` followed by `ORDER BY` if all window functions use the same ones,
+/// all are plain columns and ordering is ascending with nulls first, i.e. the order of indexes.
+fn window_sort_columns(window_expr: &[Expr]) -> Option<Vec<String>> {
+    let mut keys = None;
+    for e in window_expr {
+        let e = match e {
+            Expr::Alias(e, _) => e.as_ref(),
+            e => e,
+        };
+        let (partition_by, order_by) = match e {
+            Expr::WindowFunction {
+                partition_by,
+                order_by,
+                ..
+            } => (partition_by, order_by),
+            _ => return None,
+        };
+        let mut cols = Vec::with_capacity(partition_by.len() + order_by.len());
+        for p in partition_by {
+            match p {
+                Expr::Column(c) => cols.push(c.name.clone()),
+                _ => return None,
+            }
+        }
+        for o in order_by {
+            match o {
+                Expr::Sort {
+                    expr: box Expr::Column(c),
+                    asc: true,
+                    nulls_first: true,
+                } => cols.push(c.name.clone()),
+                _ => return None,
+            }
+        }
+        match &keys {
+            None => keys = Some(cols),
+            Some(k) if k != &cols => return None,
+            Some(_) => {}
+        }
+    }
+    keys.filter(|k| !k.is_empty())
+}
+
 struct ChooseIndex<'a> {
     next_index: usize,
     chosen_indices: &'a [IndexSnapshot],
@@ -325,6 +453,7 @@ async fn pick_index(
 ) -> Result<IndexSnapshot, DataFusionError> {
     let sort_on = c.sort_on.as_ref().map(|sc| (&sc.sort_on, sc.required));
 
+    let filter_columns = filter_columns(&c.filters);
     let mut indices = indices.into_iter();
     let default_index = indices.next().expect("no default index");
     let (index, sort_on) = if let Some(projection_column_indices) = &c.projection {
@@ -362,22 +491,12 @@ async fn pick_index(
                     .fold_options(0, |a, b| a + b);
                 score.map(|s| (i, s))
             })
-            .min_by_key(|(_, s)| *s)
+            // Prefer indices that can prune partitions with filters.
+            .min_by_key(|(i, s)| (!prunes_by_filter(i, &filter_columns), *s))
         {
             (index, sort_on)
         } else {
-            if let Some((join_on_columns, true)) = sort_on.as_ref() {
-                let table_name = c.table.table_name();
-                return Err(DataFusionError::Plan(format!(
-                    "Can't find index to join table {} on {}. Consider creating index: CREATE INDEX {}_{} ON {} ({})",
-                    table_name,
-                    join_on_columns.join(", "),
-                    table.get_row().get_table_name(),
-                    join_on_columns.join("_"),
-                    table_name,
-                    join_on_columns.join(", ")
-                )));
-            }
+            // Joins without a sorted index run as hash joins, see [choose_join_distributions].
             (default_index, None)
         }
     } else {
@@ -399,9 +518,217 @@ async fn pick_index(
             schema: Arc::new(schema),
         },
         sort_on: sort_on.map(|(cols, _)| cols.clone()),
+        join_distribution: None,
     })
 }
 
+/// Columns compared with constants in conjunctions of `filters`, in order of appearance.
+fn filter_columns(filters: &[Expr]) -> Vec<String> {
+    fn collect(e: &Expr, columns: &mut Vec<String>) {
+        match e {
+            Expr::BinaryExpr {
+                left,
+                op: Operator::And,
+                right,
+            } => {
+                collect(left, columns);
+                collect(right, columns);
+            }
+            Expr::BinaryExpr {
+                left: box Expr::Column(c),
+                op,
+                right: box Expr::Literal(_),
+            }
+            | Expr::BinaryExpr {
+                left: box Expr::Literal(_),
+                op,
+                right: box Expr::Column(c),
+            } if matches!(
+                op,
+                Operator::Eq | Operator::Lt | Operator::LtEq | Operator::Gt | Operator::GtEq
+            ) =>
+            {
+                columns.push(c.name.clone())
+            }
+            Expr::InList {
+                expr: box Expr::Column(c),
+                negated: false,
+                ..
+            }
+            | Expr::Between {
+                expr: box Expr::Column(c),
+                negated: false,
+                ..
+            } => columns.push(c.name.clone()),
+            _ => {}
+        }
+    }
+
+    let mut columns = Vec::new();
+    for f in filters {
+        collect(f, &mut columns);
+    }
+    columns.into_iter().unique().collect()
+}
+
+/// Partitions of an index are split by its first sort column, so only filters on that column can
+/// prune them.
+fn prunes_by_filter(index: &IdRow<Index>, filter_columns: &[String]) -> bool {
+    match index.get_row().get_columns().first() {
+        Some(c) => filter_columns.iter().any(|f| f == c.get_name()),
+        None => false,
+    }
+}
+
+fn missing_join_index_error(c: &IndexConstraints, reason: &str) -> DataFusionError {
+    let table_name = c.table.table_name();
+    let join_on_columns = c
+        .sort_on
+        .as_ref()
+        .map(|s| s.sort_on.clone())
+        .unwrap_or_default();
+    DataFusionError::Plan(format!(
+        "Can't find index to join table {} on {}, {}. Consider creating index: CREATE INDEX {}_{} ON {} ({})",
+        table_name,
+        join_on_columns.join(", "),
+        reason,
+        c.table.table.get_row().get_table_name(),
+        join_on_columns.join("_"),
+        table_name,
+        join_on_columns.join(", ")
+    ))
+}
+
+/// Joins with an input not sorted on the join keys run as hash joins on workers. A side with at
+/// most `broadcast_join_max_rows` rows, estimated from partition row counts, is sent whole to
+/// every task of the other side. Otherwise both sides are shuffled by the join keys: each task
+/// reads them whole, but only joins rows of its bucket of key hashes. There are enough buckets
+/// for the smaller side to have at most `broadcast_join_max_rows` rows per bucket.
+fn choose_join_distributions(
+    indices: &mut [IndexSnapshot],
+    collector: &CollectConstraints,
+    broadcast_join_max_rows: u64,
+) -> Result<(), DataFusionError> {
+    let mut shuffled = false;
+    for (join, join_type) in collector.joins.iter().enumerate() {
+        let side = |left: bool| {
+            collector
+                .constraints
+                .iter()
+                .positions(|c| c.join() == Some(JoinInput { join, left }))
+                .collect_vec()
+        };
+        let (left, right) = (side(true), side(false));
+        if left
+            .iter()
+            .chain(right.iter())
+            .all(|i| indices[*i].sort_on.is_some())
+        {
+            continue;
+        }
+        let unsorted = left
+            .iter()
+            .chain(right.iter())
+            .find(|i| indices[**i].sort_on.is_none())
+            .map(|i| &collector.constraints[*i])
+            .unwrap();
+
+        let partition_ids = |side: &[usize]| {
+            side.iter()
+                .flat_map(|i| indices[*i].partitions.iter().map(|p| p.partition.get_id()))
+                .collect::<HashSet<_>>()
+        };
+        if !partition_ids(&left).is_disjoint(&partition_ids(&right)) {
+            return Err(missing_join_index_error(
+                unsorted,
+                "both sides read the same partitions",
+            ));
+        }
+
+        // Sides which are other joins or aggregates have no row estimate.
+        let rows = |side: &[usize]| {
+            if side.is_empty() {
+                None
+            } else {
+                Some(
+                    side.iter()
+                        .map(|i| indices[*i].estimated_row_count())
+                        .sum::<u64>(),
+                )
+            }
+        };
+        let (left_rows, right_rows) = (rows(&left), rows(&right));
+        // The preserved side of an outer join must not be repeated in several tasks.
+        let candidates = match join_type {
+            JoinType::Inner => vec![(&left, left_rows), (&right, right_rows)]
+                .into_iter()
+                .sorted_by_key(|(_, rows)| rows.unwrap_or(u64::MAX))
+                .collect(),
+            JoinType::Left | JoinType::Semi | JoinType::Anti => vec![(&right, right_rows)],
+            JoinType::Right => vec![(&left, left_rows)],
+            _ => vec![],
+        };
+        let broadcast = candidates
+            .into_iter()
+            .find(|(_, rows)| matches!(rows, Some(r) if *r <= broadcast_join_max_rows));
+
+        if let Some((side, _)) = broadcast {
+            for i in side.iter() {
+                indices[*i].join_distribution = Some(JoinDistribution::Broadcast);
+            }
+        } else if !left.is_empty()
+            && !right.is_empty()
+            && partition_ids(&left).len() <= 1
+            && partition_ids(&right).len() <= 1
+        {
+            // Both sides are joined whole by a single task.
+        } else if let (Some(left_rows), Some(right_rows)) = (left_rows, right_rows) {
+            // Tasks have a single bucket for all joins, which only works for the keys of one.
+            if shuffled {
+                return Err(missing_join_index_error(
+                    unsorted,
+                    "only one join without a small side can be shuffled per query",
+                ));
+            }
+            let max_rows = broadcast_join_max_rows.max(1);
+            let buckets = ((left_rows.min(right_rows) + max_rows - 1) / max_rows).max(2);
+            for i in left.iter().chain(right.iter()) {
+                let columns = match &collector.constraints[*i].sort_on {
+                    Some(s) if !s.sort_on.is_empty() => s.sort_on.clone(),
+                    _ => {
+                        return Err(missing_join_index_error(
+                            unsorted,
+                            "join keys of a side to shuffle are unknown",
+                        ))
+                    }
+                };
+                indices[*i].join_distribution =
+                    Some(JoinDistribution::Shuffle { columns, buckets });
+            }
+            shuffled = true;
+        } else {
+            return Err(missing_join_index_error(
+                unsorted,
+                &format!(
+                    "no side of the join is known to have at most {} rows to send to every node \
+                     and a side that is not a table can't be shuffled",
+                    broadcast_join_max_rows
+                ),
+            ));
+        }
+    }
+    // Every task of a shuffled join reads all partitions, other tables would be read repeatedly.
+    if shuffled {
+        if let Some(i) = indices.iter().position(|i| i.join_distribution.is_none()) {
+            return Err(missing_join_index_error(
+                &collector.constraints[i],
+                "tables outside of a shuffled join must be small enough to send to every node",
+            ));
+        }
+    }
+    Ok(())
+}
+
 fn pick_partitions(
     i: &IndexSnapshot,
     c: &IndexConstraints,
@@ -519,6 +846,7 @@ fn pull_up_cluster_send(mut p: LogicalPlan) -> Result<LogicalPlan, DataFusionErr
         LogicalPlan::Extension { .. } => return Ok(p),
         // These nodes collect results from multiple partitions, return unchanged.
         LogicalPlan::Aggregate { .. }
+        | LogicalPlan::Window { .. }
         | LogicalPlan::Sort { .. }
         | LogicalPlan::Limit { .. }
         | LogicalPlan::Skip { .. }
@@ -580,7 +908,7 @@ fn pull_up_cluster_send(mut p: LogicalPlan) -> Result<LogicalPlan, DataFusionErr
             *left = lsend.input.clone();
             *right = rsend.input.clone();
         }
-        LogicalPlan::Window { .. } | LogicalPlan::CrossJoin { .. } => {
+        LogicalPlan::CrossJoin { .. } => {
             return Err(DataFusionError::Internal(
                 "unsupported operation".to_string(),
             ))
@@ -623,6 +951,9 @@ impl ExtensionPlanner for CubeExtensionPlanner {
             assert_eq!(inputs.len(), 1);
             let input = inputs.into_iter().next().unwrap();
             Ok(Some(plan_topk(planner, self, topk, input.clone(), state)?))
+        } else if let Some(j) = node.as_any().downcast_ref::<CrossJoinAgg>() {
+            assert_eq!(inputs.len(), 2);
+            plan_range_join_agg(planner, j, inputs[0].clone(), inputs[1].clone(), state)
         } else {
             Ok(None)
         }
@@ -747,10 +1078,14 @@ pub mod tests {
     use itertools::Itertools;
     use pretty_assertions::assert_eq;
 
+    use crate::metastore::query_pattern::QueryPatternKind;
     use crate::metastore::table::{Table, TablePath};
     use crate::metastore::{Chunk, Column, ColumnType, IdRow, Index, Partition, Schema};
-    use crate::queryplanner::planning::{choose_index, PlanIndexStore};
+    use crate::queryplanner::planning::{
+        choose_index, choose_index_ext, unindexed_query_patterns, PlanIndexStore,
+    };
     use crate::queryplanner::pretty_printers::PPOptions;
+    use crate::queryplanner::query_executor::ClusterSendExec;
     use crate::queryplanner::{pretty_printers, CubeTableLogical};
     use crate::sql::parser::{CubeStoreParser, Statement};
     use crate::CubeError;
@@ -823,6 +1158,184 @@ pub mod tests {
                                   \n        Filter\
                                   \n          Scan c1, source: CubeTable(index: default:0:[]:sort_on[customer_id]), fields: [customer_id, customer_name]\
                                   \n      Scan c2, source: CubeTable(index: by_city:1:[]:sort_on[customer_city]), fields: [customer_name, customer_city]");
+
+        // Should prefer an index that can prune partitions by filters.
+        let plan = initial_plan(
+            "SELECT * FROM s.Customers WHERE customer_city = 1",
+            &indices,
+        );
+        let plan = choose_index(&plan, &indices).await.unwrap().0;
+        assert_eq!(
+            pretty_printers::pp_plan(&plan),
+            "ClusterSend, indices: [[1]]\
+           \n  Projection, [s.Customers.customer_id, s.Customers.customer_name, s.Customers.customer_city, s.Customers.customer_registered_date]\
+           \n    Filter\
+           \n      Scan s.Customers, source: CubeTable(index: by_city:1:[]), fields: *"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_choose_index_for_window() {
+        let indices = default_indices();
+        // Should prefer the index sorted by partition and order keys.
+        let plan = initial_plan(
+            "SELECT order_id, SUM(order_amount) OVER (PARTITION BY order_customer ORDER BY order_id) \
+             FROM s.Orders",
+            &indices,
+        );
+        let plan = choose_index(&plan, &indices).await.unwrap().0;
+        let pp = pretty_printers::pp_plan(&plan);
+        assert!(pp.starts_with("Projection"), "{}", pp);
+        assert!(pp.contains("\n  Window"), "{}", pp);
+        assert!(pp.contains("by_customer:3"), "{}", pp);
+
+        // Window over expressions cannot use index ordering.
+        let plan = initial_plan(
+            "SELECT order_id, SUM(order_amount) OVER (PARTITION BY order_customer + 1) \
+             FROM s.Orders",
+            &indices,
+        );
+        let plan = choose_index(&plan, &indices).await.unwrap().0;
+        let pp = pretty_printers::pp_plan(&plan);
+        assert!(pp.contains("default:2"), "{}", pp);
+    }
+
+    #[tokio::test]
+    pub async fn test_unindexed_query_patterns() {
+        async fn patterns(sql: &str) -> Vec<(u64, QueryPatternKind, Vec<String>)> {
+            let indices = default_indices();
+            let plan = initial_plan(sql, &indices);
+            let (_, snapshots) = choose_index(&plan, &indices).await.unwrap();
+            unindexed_query_patterns(&plan, &snapshots)
+                .unwrap()
+                .into_iter()
+                .map(|p| (p.table_id(), p.kind(), p.columns().clone()))
+                .collect_vec()
+        }
+        let cols = |cs: &[&str]| cs.iter().map(|c| c.to_string()).collect_vec();
+
+        assert_eq!(
+            patterns("SELECT * FROM s.Customers WHERE customer_city = 1").await,
+            vec![]
+        );
+        assert_eq!(
+            patterns(
+                "SELECT * FROM s.Customers \
+                 WHERE customer_name = 1 AND customer_registered_date > 2"
+            )
+            .await,
+            vec![(
+                0,
+                QueryPatternKind::Filter,
+                cols(&["customer_name", "customer_registered_date"])
+            )]
+        );
+        assert_eq!(
+            patterns("SELECT customer_name, count(*) FROM s.Customers GROUP BY customer_name")
+                .await,
+            vec![(0, QueryPatternKind::GroupBy, cols(&["customer_name"]))]
+        );
+        assert_eq!(
+            patterns(
+                "SELECT order_id, customer_name \
+                 FROM s.Orders \
+                 JOIN s.Customers ON order_customer = customer_id"
+            )
+            .await,
+            vec![]
+        );
+        assert_eq!(
+            patterns(
+                "SELECT order_id, customer_name \
+                 FROM s.Orders \
+                 JOIN s.Customers ON order_amount = customer_registered_date"
+            )
+            .await,
+            vec![
+                (1, QueryPatternKind::Join, cols(&["order_amount"])),
+                (
+                    0,
+                    QueryPatternKind::Join,
+                    cols(&["customer_registered_date"])
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_join_distribution() {
+        let mut indices = default_indices();
+        // Customers (index 0) have a single small partition, Orders (index 2) have two.
+        for (index_id, rows) in &[(0, 10), (2, 1000), (2, 1000)] {
+            indices.partitions.push(
+                Partition::new(*index_id, None, None)
+                    .update_min_max_and_row_count(None, None, *rows),
+            );
+        }
+        let plan = initial_plan(
+            "SELECT order_id, customer_name \
+             FROM s.Orders \
+             JOIN s.Customers ON order_amount = customer_registered_date",
+            &indices,
+        );
+
+        // The smaller side is broadcast to tasks of the larger one.
+        let (broadcast, snapshots) = choose_index(&plan, &indices).await.unwrap();
+        assert_eq!(pretty_printers::pp_plan(&broadcast), "ClusterSend, indices: [[2], [0]]\
+                                  \n  Projection, [s.Orders.order_id, s.Customers.customer_name]\
+                                  \n    Join on: [#s.Orders.order_amount = #s.Customers.customer_registered_date]\
+                                  \n      Scan s.Orders, source: CubeTable(index: default:2:[1, 2]), fields: [order_id, order_amount]\
+                                  \n      Scan s.Customers, source: CubeTable(index: default:0:[0]:broadcast), fields: [customer_name, customer_registered_date]");
+        let tasks = ClusterSendExec::logical_partitions(
+            &snapshots.into_iter().map(|s| vec![s]).collect_vec(),
+        );
+        assert_eq!(
+            tasks
+                .iter()
+                .map(|ps| ps.iter().map(|p| p.get_id()).collect_vec())
+                .collect_vec(),
+            vec![vec![1, 0], vec![2, 0]]
+        );
+
+        // Both sides are shuffled by the join keys when neither is small enough. The smaller side
+        // has 10 rows, so 2 buckets keep at most 5 of them in each task.
+        let (shuffle, snapshots) = choose_index_ext(&plan, &indices, true, 5).await.unwrap();
+        assert_eq!(pretty_printers::pp_plan(&shuffle), "ClusterSend, indices: [[2], [0]]\
+                                  \n  Projection, [s.Orders.order_id, s.Customers.customer_name]\
+                                  \n    Join on: [#s.Orders.order_amount = #s.Customers.customer_registered_date]\
+                                  \n      Scan s.Orders, source: CubeTable(index: default:2:[1, 2]:shuffle[2]), fields: [order_id, order_amount]\
+                                  \n      Scan s.Customers, source: CubeTable(index: default:0:[0]:shuffle[2]), fields: [customer_name, customer_registered_date]");
+        let snapshots = snapshots.into_iter().map(|s| vec![s]).collect_vec();
+        assert_eq!(ClusterSendExec::join_buckets(&snapshots), Some(2));
+        let tasks = ClusterSendExec::logical_partitions(&snapshots);
+        assert_eq!(
+            tasks
+                .iter()
+                .map(|ps| ps.iter().map(|p| p.get_id()).collect_vec())
+                .collect_vec(),
+            vec![vec![1, 2, 0]]
+        );
+
+        // Only the right side of a left join can be broadcast, the large one is shuffled.
+        let plan = initial_plan(
+            "SELECT order_id, customer_name \
+             FROM s.Customers \
+             LEFT JOIN s.Orders ON order_amount = customer_registered_date",
+            &indices,
+        );
+        let (shuffle, _) = choose_index_ext(&plan, &indices, true, 100).await.unwrap();
+        let shuffle = pretty_printers::pp_plan(&shuffle);
+        assert!(shuffle.contains("CubeTable(index: default:0:[0]:shuffle[2])"));
+        assert!(shuffle.contains("CubeTable(index: default:2:[1, 2]:shuffle[2])"));
+
+        // Self-joins without an index read the same partitions on both sides.
+        let plan = initial_plan(
+            "SELECT c1.customer_name \
+             FROM s.Customers c1 \
+             JOIN s.Customers c2 ON c1.customer_name = c2.customer_city",
+            &indices,
+        );
+        assert!(choose_index(&plan, &indices).await.is_err());
     }
 
     #[tokio::test]
@@ -982,6 +1495,8 @@ pub mod tests {
             customers_cols.clone(),
             None,
             None,
+            None,
+            None,
             true,
         ));
         i.indices.push(
@@ -1007,6 +1522,8 @@ pub mod tests {
             orders_cols.clone(),
             None,
             None,
+            None,
+            None,
             true,
         ));
         i.indices.push(
@@ -1034,6 +1551,8 @@ pub mod tests {
             int_columns(&["product_id", "product_name"]),
             None,
             None,
+            None,
+            None,
             true,
         ));
 
@@ -14,9 +14,11 @@ use datafusion::physical_plan::sort::SortExec;
 use datafusion::physical_plan::ExecutionPlan;
 use itertools::{repeat_n, Itertools};
 
+use crate::queryplanner::hash_buckets::HashBucketExec;
 use crate::queryplanner::planning::{ClusterSendNode, WorkerExec};
 use crate::queryplanner::query_executor::{ClusterSendExec, CubeTable, CubeTableExec};
-use crate::queryplanner::serialized_plan::IndexSnapshot;
+use crate::queryplanner::range_join::RangeJoinAggExec;
+use crate::queryplanner::serialized_plan::{IndexSnapshot, JoinDistribution};
 use crate::queryplanner::topk::ClusterAggregateTopK;
 use crate::queryplanner::topk::{AggregateTopKExec, SortColumn};
 use crate::queryplanner::CubeTableLogical;
@@ -27,6 +29,7 @@ use datafusion::physical_plan::expressions::Column;
 use datafusion::physical_plan::merge::MergeExec;
 use datafusion::physical_plan::projection::ProjectionExec;
 use datafusion::physical_plan::union::UnionExec;
+use datafusion::physical_plan::windows::WindowAggExec;
 
 #[derive(Default, Clone, Copy)]
 pub struct PPOptions {
@@ -181,7 +184,13 @@ pub fn pp_plan_ext(p: &LogicalPlan, opts: &PPOptions) -> String {
                         panic!("unknown extension node");
                     }
                 }
-                LogicalPlan::Window { .. } | LogicalPlan::CrossJoin { .. } => {
+                LogicalPlan::Window { window_expr, .. } => {
+                    self.output += "Window";
+                    if self.opts.show_aggregations {
+                        self.output += &format!(", exprs: {:?}", window_expr)
+                    }
+                }
+                LogicalPlan::CrossJoin { .. } => {
                     panic!("unsupported logical plan node")
                 }
             }
@@ -211,6 +220,11 @@ fn pp_index(index: &IndexSnapshot) -> String {
     if let Some(so) = &index.sort_on {
         r += &format!(":sort_on[{}]", so.join(", "))
     }
+    match &index.join_distribution {
+        Some(JoinDistribution::Broadcast) => r += ":broadcast",
+        Some(JoinDistribution::Shuffle { buckets, .. }) => r += &format!(":shuffle[{}]", buckets),
+        None => {}
+    }
     r
 }
 
@@ -248,7 +262,13 @@ fn pp_phys_plan_indented(p: &dyn ExecutionPlan, indent: usize, o: &PPOptions, ou
         // Do not show children of ClusterSend. This is a hack to avoid rewriting all tests.
         return;
     }
-    for c in p.children() {
+    let mut children = p.children();
+    if p.as_any().is::<RangeJoinAggExec>() {
+        // Do not show the left input of range joins, it is a series of constant ranges in
+        // rolling window queries.
+        children.remove(0);
+    }
+    for c in children {
         pp_phys_plan_indented(c.as_ref(), indent + 2, o, out);
     }
 
@@ -374,8 +394,17 @@ fn pp_phys_plan_indented(p: &dyn ExecutionPlan, indent: usize, o: &PPOptions, ou
             if o.show_aggregations {
                 *out += &format!(", aggs: {:?}", j.agg_expr)
             }
+        } else if let Some(j) = a.downcast_ref::<RangeJoinAggExec>() {
+            *out += &format!("RangeJoinAgg, on: {}", j);
+            if o.show_aggregations {
+                *out += &format!(", aggs: {:?}", j.agg_expr)
+            }
+        } else if let Some(b) = a.downcast_ref::<HashBucketExec>() {
+            *out += &format!("HashBucket, {} of {}", b.bucket, b.buckets);
         } else if let Some(_) = a.downcast_ref::<UnionExec>() {
             *out += "Union";
+        } else if let Some(_) = a.downcast_ref::<WindowAggExec>() {
+            *out += "Window";
         } else {
             panic!("unhandled ExecutionPlan: {:?}", p);
         }
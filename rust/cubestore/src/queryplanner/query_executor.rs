@@ -2,19 +2,22 @@ use crate::cluster::Cluster;
 use crate::config::injection::DIService;
 use crate::metastore::table::Table;
 use crate::metastore::{Column, ColumnType, IdRow, Index, Partition};
+use crate::queryplanner::hash_buckets::HashBucketExec;
 use crate::queryplanner::optimizations::CubeQueryPlanner;
 use crate::queryplanner::planning::get_worker_plan;
-use crate::queryplanner::serialized_plan::{IndexSnapshot, SerializedPlan};
+use crate::queryplanner::serialized_plan::{IndexSnapshot, JoinDistribution, SerializedPlan};
 use crate::store::DataFrame;
 use crate::table::{Row, TableValue, TimestampValue};
 use crate::{app_metrics, CubeError};
 use arrow::array::{
-    make_array, Array, ArrayRef, BinaryArray, BooleanArray, Float64Array, Int64Array,
-    Int64Decimal0Array, Int64Decimal10Array, Int64Decimal1Array, Int64Decimal2Array,
-    Int64Decimal3Array, Int64Decimal4Array, Int64Decimal5Array, MutableArrayData, StringArray,
-    TimestampMicrosecondArray, TimestampNanosecondArray, UInt64Array,
+    make_array, Array, ArrayRef, BinaryArray, BooleanArray, Date32Array, DecimalArray,
+    Float64Array, Int32Array, Int64Array, Int64Decimal0Array, Int64Decimal10Array,
+    Int64Decimal1Array, Int64Decimal2Array, Int64Decimal3Array, Int64Decimal4Array,
+    Int64Decimal5Array, MutableArrayData, StringArray, TimestampMicrosecondArray,
+    TimestampNanosecondArray, UInt64Array,
 };
 use arrow::datatypes::{DataType, Schema, SchemaRef, TimeUnit};
+use arrow::error::ArrowError;
 use arrow::ipc::reader::StreamReader;
 use arrow::ipc::writer::MemStreamWriter;
 use arrow::record_batch::RecordBatch;
@@ -34,18 +37,23 @@ use datafusion::physical_plan::merge_sort::MergeSortExec;
 use datafusion::physical_plan::parquet::ParquetExec;
 use datafusion::physical_plan::projection::ProjectionExec;
 use datafusion::physical_plan::{
-    collect, ExecutionPlan, OptimizerHints, Partitioning, PhysicalExpr, SendableRecordBatchStream,
+    collect, ExecutionPlan, OptimizerHints, Partitioning, PhysicalExpr, RecordBatchStream,
+    SendableRecordBatchStream,
 };
+use futures::stream::FuturesUnordered;
+use futures::task::{Context, Poll};
+use futures::{Future, Stream, StreamExt};
 use itertools::Itertools;
 use log::{debug, error, trace, warn};
 use mockall::automock;
 use serde_derive::{Deserialize, Serialize};
 use std::any::Any;
 use std::cmp::min;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::{Debug, Formatter};
 use std::io::Cursor;
 use std::iter::FromIterator;
+use std::pin::Pin;
 use std::sync::Arc;
 use std::time::SystemTime;
 use tracing::{instrument, Instrument};
@@ -247,6 +255,7 @@ pub struct CubeTable {
     index_snapshot: IndexSnapshot,
     remote_to_local_names: HashMap<String, String>,
     worker_partition_ids: HashSet<u64>,
+    worker_join_bucket: Option<u64>,
     schema: SchemaRef,
 }
 
@@ -271,6 +280,7 @@ impl CubeTable {
             schema,
             remote_to_local_names,
             worker_partition_ids,
+            worker_join_bucket: None,
         })
     }
 
@@ -279,10 +289,12 @@ impl CubeTable {
         &self,
         remote_to_local_names: HashMap<String, String>,
         worker_partition_ids: HashSet<u64>,
+        worker_join_bucket: Option<u64>,
     ) -> CubeTable {
         let mut t = self.clone();
         t.remote_to_local_names = remote_to_local_names;
         t.worker_partition_ids = worker_partition_ids;
+        t.worker_join_bucket = worker_join_bucket;
         t
     }
 
@@ -434,6 +446,24 @@ impl CubeTable {
             Arc::new(MergeExec::new(read_data))
         };
 
+        let plan: Arc<dyn ExecutionPlan> = match (
+            self.index_snapshot.join_distribution(),
+            self.worker_join_bucket,
+        ) {
+            (Some(JoinDistribution::Shuffle { columns, buckets }), Some(bucket)) => {
+                Arc::new(HashBucketExec {
+                    input: plan,
+                    columns: columns
+                        .iter()
+                        .map(|c| schema.index_of(c))
+                        .collect::<Result<Vec<_>, _>>()?,
+                    buckets: *buckets,
+                    bucket,
+                })
+            }
+            _ => plan,
+        };
+
         Ok(plan)
     }
 
@@ -553,6 +583,10 @@ impl ExecutionPlan for CubeTableExec {
 pub struct ClusterSendExec {
     schema: SchemaRef,
     pub partitions: Vec<(/*node*/ String, /*partition_id*/ Vec<u64>)>,
+    /// Other workers serving the same partitions, tried when the main node fails or is slow.
+    pub replicas: Vec<Vec<String>>,
+    /// Buckets of shuffled join inputs for each of `partitions`.
+    pub join_buckets: Vec<Option<u64>>,
     /// Never executed, only stored to allow consistent optimization on router and worker.
     pub input_for_optimizations: Arc<dyn ExecutionPlan>,
     pub cluster: Arc<dyn Cluster>,
@@ -570,10 +604,19 @@ impl ClusterSendExec {
         use_streaming: bool,
     ) -> Self {
         let partitions = Self::logical_partitions(&union_snapshots);
-        let partitions = Self::assign_nodes(cluster.as_ref(), partitions);
+        let tasks = match Self::join_buckets(&union_snapshots) {
+            None => partitions.into_iter().map(|ps| (ps, None)).collect_vec(),
+            Some(buckets) => partitions
+                .into_iter()
+                .flat_map(|ps| (0..buckets).map(move |b| (ps.clone(), Some(b))))
+                .collect_vec(),
+        };
+        let (partitions, replicas, join_buckets) = Self::assign_nodes(cluster.as_ref(), tasks);
         Self {
             schema,
             partitions,
+            replicas,
+            join_buckets,
             cluster,
             serialized_plan,
             input_for_optimizations,
@@ -582,37 +625,134 @@ impl ClusterSendExec {
     }
 
     pub fn logical_partitions(snapshots: &[Vec<IndexSnapshot>]) -> Vec<Vec<IdRow<Partition>>> {
-        let to_multiply = snapshots
-            .iter()
-            .map(|union| {
-                union
-                    .iter()
-                    .flat_map(|index| index.partitions().iter().map(|p| p.partition().clone()))
-                    .collect::<Vec<_>>()
-            })
-            .collect::<Vec<_>>();
+        let partitions_of = |union: &Vec<IndexSnapshot>| {
+            union
+                .iter()
+                .flat_map(|index| index.partitions().iter().map(|p| p.partition().clone()))
+                .collect::<Vec<_>>()
+        };
+        // Broadcast and shuffled join inputs are read whole by every task.
+        let (broadcast, to_multiply): (Vec<_>, Vec<_>) = snapshots.iter().partition(|union| {
+            !union.is_empty() && union.iter().all(|s| s.join_distribution().is_some())
+        });
+        let broadcast = broadcast.into_iter().flat_map(partitions_of).collect_vec();
+        if to_multiply.is_empty() && !broadcast.is_empty() {
+            // All inputs are shuffled, a single task per bucket reads them.
+            return vec![broadcast];
+        }
         let partitions = to_multiply
             .into_iter()
+            .map(partitions_of)
             .multi_cartesian_product()
+            .map(|mut ps| {
+                ps.extend(broadcast.iter().cloned());
+                ps
+            })
             .collect::<Vec<Vec<_>>>();
         partitions
     }
 
+    /// Number of buckets of shuffled join inputs, every task runs once per bucket.
+    pub fn join_buckets(snapshots: &[Vec<IndexSnapshot>]) -> Option<u64> {
+        snapshots
+            .iter()
+            .flatten()
+            .filter_map(|s| match s.join_distribution() {
+                Some(JoinDistribution::Shuffle { buckets, .. }) => Some(*buckets),
+                _ => None,
+            })
+            .max()
+    }
+
     fn assign_nodes(
         c: &dyn Cluster,
-        logical: Vec<Vec<IdRow<Partition>>>,
-    ) -> Vec<(String, Vec<u64>)> {
-        let mut m: HashMap<String, Vec<u64>> = HashMap::new();
-        for ps in &logical {
+        logical: Vec<(Vec<IdRow<Partition>>, Option<u64>)>,
+    ) -> (Vec<(String, Vec<u64>)>, Vec<Vec<String>>, Vec<Option<u64>>) {
+        // Subqueries assigned to nodes by this query so far.
+        let mut assigned: HashMap<String, usize> = HashMap::new();
+        // Tasks of different join buckets are never merged.
+        let mut m: HashMap<(String, Vec<String>, Option<u64>), (Vec<String>, Vec<u64>)> =
+            HashMap::new();
+        for (ps, bucket) in &logical {
             let ids = ps.iter().map(|p| p.get_id()).collect_vec();
-            m.entry(c.node_name_by_partitions(&ids))
-                .or_default()
+            let mut replicas = c.node_names_by_partitions(&ids);
+            if replicas.is_empty() {
+                replicas.push(c.server_name().to_string());
+            }
+            // Least loaded replica wins, ties are resolved in the order of preference.
+            let (i, _) = replicas
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, n)| c.active_selects(n) + assigned.get(*n).cloned().unwrap_or(0))
+                .unwrap();
+            let node = replicas.remove(i);
+            *assigned.entry(node.clone()).or_default() += 1;
+
+            let mut replica_set = replicas.clone();
+            replica_set.sort();
+            m.entry((node, replica_set, *bucket))
+                .or_insert_with(|| (replicas, Vec::new()))
+                .1
                 .extend(ids)
         }
 
         let mut r = m.into_iter().collect_vec();
         r.sort_unstable_by(|l, r| l.0.cmp(&r.0));
-        r
+        let mut partitions = Vec::with_capacity(r.len());
+        let mut replicas = Vec::with_capacity(r.len());
+        let mut buckets = Vec::with_capacity(r.len());
+        for ((node, _, bucket), (node_replicas, ids)) in r {
+            partitions.push((node, ids));
+            replicas.push(node_replicas);
+            buckets.push(bucket);
+        }
+        (partitions, replicas, buckets)
+    }
+
+    /// The main node of `partition` followed by its replicas.
+    fn replica_nodes(&self, partition: usize) -> impl Iterator<Item = String> + '_ {
+        std::iter::once(self.partitions[partition].0.clone())
+            .chain(self.replicas[partition].iter().cloned())
+    }
+
+    /// Runs `f` on the main node of `partition`. Moves on to the next replica if it fails or
+    /// doesn't respond within the hedging timeout. The first successful response wins.
+    async fn run_on_replicas<T, F, Fut>(&self, partition: usize, f: F) -> Result<T, CubeError>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<T, CubeError>>,
+    {
+        let mut pending = self.replica_nodes(partition).collect::<VecDeque<_>>();
+        let hedge_after = self.cluster.hedged_select_timeout();
+
+        let mut running = FuturesUnordered::new();
+        running.push(f(pending.pop_front().unwrap()));
+        loop {
+            let hedge = async {
+                match hedge_after {
+                    Some(t) => tokio::time::sleep(t).await,
+                    None => futures::future::pending().await,
+                }
+            };
+            tokio::select! {
+                r = running.next() => match r.expect("no running selects") {
+                    Ok(r) => return Ok(r),
+                    Err(e) => {
+                        if let Some(node) = pending.pop_front() {
+                            warn!("Select failed, retrying on {}: {}", node, e);
+                            running.push(f(node));
+                        } else if running.is_empty() {
+                            return Err(e);
+                        }
+                    }
+                },
+                _ = hedge, if !pending.is_empty() => {
+                    let node = pending.pop_front().unwrap();
+                    debug!("Select is slow, sending hedged request to {}", node);
+                    running.push(f(node));
+                }
+            }
+        }
     }
 
     pub fn with_changed_schema(
@@ -623,6 +763,8 @@ impl ClusterSendExec {
         ClusterSendExec {
             schema,
             partitions: self.partitions.clone(),
+            replicas: self.replicas.clone(),
+            join_buckets: self.join_buckets.clone(),
             cluster: self.cluster.clone(),
             serialized_plan: self.serialized_plan.clone(),
             input_for_optimizations,
@@ -660,6 +802,8 @@ impl ExecutionPlan for ClusterSendExec {
         Ok(Arc::new(ClusterSendExec {
             schema: self.schema.clone(),
             partitions: self.partitions.clone(),
+            replicas: self.replicas.clone(),
+            join_buckets: self.join_buckets.clone(),
             cluster: self.cluster.clone(),
             serialized_plan: self.serialized_plan.clone(),
             input_for_optimizations,
@@ -676,14 +820,39 @@ impl ExecutionPlan for ClusterSendExec {
         &self,
         partition: usize,
     ) -> Result<SendableRecordBatchStream, DataFusionError> {
-        let (node_name, ids) = &self.partitions[partition];
+        let (_, ids) = &self.partitions[partition];
         let plan = self
             .serialized_plan
-            .with_partition_id_to_execute(HashSet::from_iter(ids.iter().cloned()));
+            .with_partition_id_to_execute(HashSet::from_iter(ids.iter().cloned()))
+            .with_join_bucket_to_execute(self.join_buckets[partition]);
+        let cluster = &self.cluster;
         if self.use_streaming {
-            Ok(self.cluster.run_select_stream(node_name, plan).await?)
+            let (node, stream) = self
+                .run_on_replicas(partition, |node| {
+                    let plan = plan.clone();
+                    async move {
+                        let stream = cluster.run_select_stream(&node, plan).await?;
+                        Ok((node, stream))
+                    }
+                })
+                .await?;
+            let fallbacks = self
+                .replica_nodes(partition)
+                .filter(|n| *n != node)
+                .collect();
+            Ok(Box::pin(ReplicaFailoverStream::new(
+                stream,
+                fallbacks,
+                self.cluster.clone(),
+                plan,
+            )))
         } else {
-            let record_batches = self.cluster.run_select(node_name, plan).await?;
+            let record_batches = self
+                .run_on_replicas(partition, |node| {
+                    let plan = plan.clone();
+                    async move { cluster.run_select(&node, plan).await }
+                })
+                .await?;
             // TODO .to_schema_ref()
             let memory_exec = MemoryExec::try_new(&vec![record_batches], self.schema(), None)?;
             memory_exec.execute(0).await
@@ -691,6 +860,94 @@ impl ExecutionPlan for ClusterSendExec {
     }
 }
 
+/// Streams a partition from a worker. If the worker fails before sending any batches, the select
+/// is sent to the next replica. Failures after the first batch are returned as is, retrying them
+/// would send the same rows twice.
+struct ReplicaFailoverStream {
+    schema: SchemaRef,
+    inner: Pin<Box<dyn Stream<Item = Result<RecordBatch, ArrowError>> + Send>>,
+}
+
+struct ReplicaFailoverState {
+    stream: SendableRecordBatchStream,
+    fallbacks: VecDeque<String>,
+    sent_batches: bool,
+    cluster: Arc<dyn Cluster>,
+    plan: SerializedPlan,
+}
+
+impl ReplicaFailoverStream {
+    fn new(
+        stream: SendableRecordBatchStream,
+        fallbacks: VecDeque<String>,
+        cluster: Arc<dyn Cluster>,
+        plan: SerializedPlan,
+    ) -> ReplicaFailoverStream {
+        let schema = stream.schema();
+        let state = ReplicaFailoverState {
+            stream,
+            fallbacks,
+            sent_batches: false,
+            cluster,
+            plan,
+        };
+        let inner = futures::stream::unfold(Some(state), |state| async move {
+            let mut state = state?;
+            loop {
+                let error = match state.stream.next().await {
+                    Some(Ok(batch)) => {
+                        state.sent_batches = true;
+                        return Some((Ok(batch), Some(state)));
+                    }
+                    None => return None,
+                    Some(Err(e)) => e,
+                };
+                if state.sent_batches {
+                    return Some((Err(error), None));
+                }
+
+                let mut error: CubeError = error.into();
+                loop {
+                    let node = match state.fallbacks.pop_front() {
+                        Some(node) => node,
+                        None => return Some((Err(error.into()), None)),
+                    };
+                    warn!("Select stream failed, retrying on {}: {}", node, error);
+                    match state
+                        .cluster
+                        .run_select_stream(&node, state.plan.clone())
+                        .await
+                    {
+                        Ok(stream) => {
+                            state.stream = stream;
+                            break;
+                        }
+                        Err(e) => error = e,
+                    }
+                }
+            }
+        });
+        ReplicaFailoverStream {
+            schema,
+            inner: Box::pin(inner),
+        }
+    }
+}
+
+impl Stream for ReplicaFailoverStream {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+impl RecordBatchStream for ReplicaFailoverStream {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
 impl fmt::Debug for ClusterSendExec {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
         f.write_fmt(format_args!(
@@ -744,6 +1001,12 @@ macro_rules! convert_array_cast_native {
     ($V: expr, (Decimal)) => {{
         crate::util::decimal::Decimal::new($V)
     }};
+    ($V: expr, (Decimal128)) => {{
+        crate::util::decimal::Decimal::new_i128($V)
+    }};
+    ($V: expr, (Date)) => {{
+        crate::table::DateValue::new($V)
+    }};
     ($V: expr, $T: ty) => {{
         $V as $T
     }};
@@ -792,6 +1055,13 @@ pub fn batch_to_dataframe(batches: &Vec<RecordBatch>) -> Result<DataFrame, CubeE
             match array.data_type() {
                 DataType::UInt64 => convert_array!(array, num_rows, rows, UInt64Array, Int, i64),
                 DataType::Int64 => convert_array!(array, num_rows, rows, Int64Array, Int, i64),
+                DataType::Int32 => convert_array!(array, num_rows, rows, Int32Array, Int, i64),
+                DataType::Date32 => {
+                    convert_array!(array, num_rows, rows, Date32Array, Date, (Date))
+                }
+                DataType::Decimal(_, _) => {
+                    convert_array!(array, num_rows, rows, DecimalArray, Decimal, (Decimal128))
+                }
                 DataType::Float64 => {
                     let a = array.as_any().downcast_ref::<Float64Array>().unwrap();
                     for i in 0..num_rows {
@@ -926,10 +1196,15 @@ pub fn arrow_to_column_type(arrow_type: DataType) -> Result<ColumnType, CubeErro
             scale: scale as i32,
             precision: 18,
         }),
+        DataType::Decimal(precision, scale) => Ok(ColumnType::Decimal128 {
+            scale: scale as i32,
+            precision: precision as i32,
+        }),
+        DataType::Date32 => Ok(ColumnType::Date),
         DataType::Boolean => Ok(ColumnType::Boolean),
+        DataType::Int32 => Ok(ColumnType::Int32),
         DataType::Int8
         | DataType::Int16
-        | DataType::Int32
         | DataType::Int64
         | DataType::UInt8
         | DataType::UInt16
@@ -0,0 +1,678 @@
+use arrow::array::{new_null_array, Array, ArrayRef, PrimitiveArray, UInt32Array};
+use arrow::datatypes::{
+    ArrowPrimitiveType, DataType, Int32Type, Int64Type, SchemaRef, TimeUnit,
+    TimestampNanosecondType,
+};
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::cube_ext::joinagg::CrossJoinAgg;
+use datafusion::error::DataFusionError;
+use datafusion::execution::context::ExecutionContextState;
+use datafusion::logical_plan::{Column, Expr, Operator};
+use datafusion::physical_plan::expressions::PhysicalSortExpr;
+use datafusion::physical_plan::group_scalar::GroupByScalar;
+use datafusion::physical_plan::hash_aggregate::{
+    create_accumulators, create_group_by_values, AccumulatorSet,
+};
+use datafusion::physical_plan::memory::MemoryExec;
+use datafusion::physical_plan::sort::{SortExec, SortOptions};
+use datafusion::physical_plan::{
+    collect, AggregateExpr, ExecutionPlan, OptimizerHints, Partitioning, PhysicalExpr,
+    PhysicalPlanner, SendableRecordBatchStream,
+};
+use datafusion::scalar::ScalarValue;
+use futures::StreamExt;
+use itertools::Itertools;
+use smallvec::{smallvec, SmallVec};
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+/// Joins a series of time ranges on the left with rows of the right input that fall into them
+/// and aggregates the matched rows, i.e. computes rolling windows. Has the semantics of
+/// [CrossJoinAgg] over a left join, but streams the right input sorted by time once instead of
+/// evaluating the join condition for each pair of rows. The right input is aggregated by
+/// segments between range boundaries, ranges merge the aggregates of their segments.
+#[derive(Debug)]
+pub struct RangeJoinAggExec {
+    pub left: Arc<dyn ExecutionPlan>,
+    pub right: Arc<dyn ExecutionPlan>,
+    /// Evaluated on the right input.
+    pub time: Arc<dyn PhysicalExpr>,
+    /// Evaluated on the left input.
+    pub lower: Option<RangeBound>,
+    /// Evaluated on the left input.
+    pub upper: Option<RangeBound>,
+    pub group_expr: Vec<GroupExpr>,
+    /// Evaluated on the right input.
+    pub agg_expr: Vec<Arc<dyn AggregateExpr>>,
+    pub schema: SchemaRef,
+}
+
+#[derive(Debug, Clone)]
+pub struct RangeBound {
+    pub expr: Arc<dyn PhysicalExpr>,
+    pub inclusive: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum GroupExpr {
+    Left(Arc<dyn PhysicalExpr>),
+    Right(Arc<dyn PhysicalExpr>),
+}
+
+/// Returns `None` if the join condition is not a range over a single time column of the right
+/// input or the expressions cannot be split between inputs. [CrossJoinAgg] is planned by
+/// DataFusion in that case.
+pub fn plan_range_join_agg(
+    planner: &dyn PhysicalPlanner,
+    node: &CrossJoinAgg,
+    left: Arc<dyn ExecutionPlan>,
+    right: Arc<dyn ExecutionPlan>,
+    ctx: &ExecutionContextState,
+) -> Result<Option<Arc<dyn ExecutionPlan>>, DataFusionError> {
+    let left_schema = left.schema();
+    let right_schema = right.schema();
+    let on_left = |e: &Expr| {
+        planner
+            .create_physical_expr(e, node.join.left.schema(), &left_schema, ctx)
+            .ok()
+    };
+    let on_right = |e: &Expr| {
+        planner
+            .create_physical_expr(e, node.join.right.schema(), &right_schema, ctx)
+            .ok()
+    };
+
+    let mut conditions = Vec::new();
+    conjuncts(&node.join.on, &mut conditions);
+    let mut time: Option<(&Column, Arc<dyn PhysicalExpr>)> = None;
+    let mut lower = None;
+    let mut upper = None;
+    for c in conditions {
+        let (l, op, r) = match c {
+            Expr::BinaryExpr { left, op, right } => (left.as_ref(), *op, right.as_ref()),
+            _ => return Ok(None),
+        };
+        let (l_column, r_column) = match (l, r) {
+            (Expr::Column(l), Expr::Column(r)) => (l, r),
+            _ => return Ok(None),
+        };
+        // Normalize to `<time> <op> <bound>`.
+        let (column, t, op, b) = match (on_right(l), on_left(r), on_left(l), on_right(r)) {
+            (Some(t), Some(b), None, None) => (l_column, t, op, b),
+            (None, None, Some(b), Some(t)) => (r_column, t, flip(op), b),
+            _ => return Ok(None),
+        };
+        match &time {
+            Some((prev, _)) if *prev != column => return Ok(None),
+            Some(_) => {}
+            None => time = Some((column, t)),
+        }
+        let (bound, inclusive) = match op {
+            Operator::Lt => (&mut upper, false),
+            Operator::LtEq => (&mut upper, true),
+            Operator::Gt => (&mut lower, false),
+            Operator::GtEq => (&mut lower, true),
+            _ => return Ok(None),
+        };
+        if bound.is_some() {
+            return Ok(None);
+        }
+        *bound = Some(RangeBound { expr: b, inclusive });
+    }
+    let time = match time {
+        Some((_, t)) => t,
+        None => return Ok(None),
+    };
+    let time_type = time.data_type(&right_schema)?;
+    if !is_time_type(&time_type) {
+        return Ok(None);
+    }
+    for b in lower.iter().chain(upper.iter()) {
+        if b.expr.data_type(&left_schema)? != time_type {
+            return Ok(None);
+        }
+    }
+
+    let mut group_expr = Vec::with_capacity(node.group_expr.len());
+    for e in &node.group_expr {
+        if let Some(l) = on_left(e) {
+            group_expr.push(GroupExpr::Left(l))
+        } else if let Some(r) = on_right(e) {
+            group_expr.push(GroupExpr::Right(r))
+        } else {
+            return Ok(None);
+        }
+    }
+    let mut agg_expr = Vec::with_capacity(node.agg_expr.len());
+    for e in &node.agg_expr {
+        match planner.create_aggregate_expr(e, node.join.right.schema(), &right_schema, ctx) {
+            Ok(a) => agg_expr.push(a),
+            Err(_) => return Ok(None),
+        }
+    }
+
+    // The sort is removed later if the input is already sorted by time.
+    let right = Arc::new(SortExec::try_new(
+        vec![PhysicalSortExpr {
+            expr: time.clone(),
+            options: SortOptions {
+                descending: false,
+                nulls_first: true,
+            },
+        }],
+        right,
+    )?);
+    Ok(Some(Arc::new(RangeJoinAggExec {
+        left,
+        right,
+        time,
+        lower,
+        upper,
+        group_expr,
+        agg_expr,
+        schema: node.schema.to_schema_ref(),
+    })))
+}
+
+fn conjuncts<'a>(e: &'a Expr, out: &mut Vec<&'a Expr>) {
+    match e {
+        Expr::BinaryExpr {
+            left,
+            op: Operator::And,
+            right,
+        } => {
+            conjuncts(left, out);
+            conjuncts(right, out);
+        }
+        e => out.push(e),
+    }
+}
+
+fn flip(op: Operator) -> Operator {
+    match op {
+        Operator::Lt => Operator::Gt,
+        Operator::LtEq => Operator::GtEq,
+        Operator::Gt => Operator::Lt,
+        Operator::GtEq => Operator::LtEq,
+        op => op,
+    }
+}
+
+fn is_time_type(t: &DataType) -> bool {
+    matches!(
+        t,
+        DataType::Int32 | DataType::Int64 | DataType::Timestamp(TimeUnit::Nanosecond, _)
+    )
+}
+
+impl RangeJoinAggExec {
+    async fn join_and_aggregate(&self) -> Result<RecordBatch, DataFusionError> {
+        let left = collect_batch(self.left.clone()).await?;
+        let ranges = self.ranges(&left)?;
+        // Ranges split the time axis into segments, each range covers consecutive segments.
+        let boundaries = ranges
+            .iter()
+            .flatten()
+            .flat_map(|(start, end)| vec![*start, *end])
+            .sorted()
+            .dedup()
+            .collect_vec();
+
+        let mut right_groups = RightGroups::new(self, &boundaries);
+        if self.right.output_partitioning().partition_count() != 1 {
+            return Err(DataFusionError::Internal(
+                "right input of range join must have a single partition".to_string(),
+            ));
+        }
+        let mut right = self.right.execute(0).await?;
+        while let Some(batch) = right.next().await {
+            if !right_groups.add_batch(&batch?)? {
+                // The remaining rows are past all ranges.
+                break;
+            }
+        }
+
+        let left_values = self
+            .group_expr
+            .iter()
+            .map(|e| match e {
+                GroupExpr::Left(e) => Ok(Some(e.evaluate(&left)?.into_array(left.num_rows()))),
+                GroupExpr::Right(_) => Ok(None),
+            })
+            .collect::<Result<Vec<_>, DataFusionError>>()?;
+        let segments = ranges
+            .iter()
+            .map(|r| {
+                r.map(|(start, end)| {
+                    let segment = |b| boundaries.binary_search(&b).unwrap();
+                    (segment(start), segment(end))
+                })
+            })
+            .collect_vec();
+        // Visit left rows in the order of their ranges, so windows mostly slide forward.
+        let mut left_order = (0..left.num_rows()).collect_vec();
+        left_order.sort_by_key(|l| segments[*l]);
+
+        let mut windows = right_groups
+            .groups
+            .iter()
+            .map(|_| SlidingWindow::new(&self.agg_expr))
+            .collect::<Result<Vec<_>, DataFusionError>>()?;
+        let mut output = vec![Vec::new(); self.schema.fields().len()];
+        for l in left_order {
+            let mut matched = false;
+            if let Some((from, to)) = segments[l] {
+                for (g, window) in right_groups.groups.iter().zip(&mut windows) {
+                    let start = g.segments.partition_point(|(s, _)| *s < from);
+                    let end = g.segments.partition_point(|(s, _)| *s < to).max(start);
+                    if start == end {
+                        continue;
+                    }
+                    let accs = window.aggregate(&g.segments, start, end, &self.agg_expr)?;
+                    self.write_row(
+                        &left_values,
+                        l,
+                        Some(g.values.as_slice()),
+                        &accs,
+                        &mut output,
+                    )?;
+                    matched = true;
+                }
+            }
+            if !matched {
+                // Like in a left join, a left row without matches produces a single row with
+                // nulls in place of the right group columns.
+                let accs = create_accumulators(&self.agg_expr)?;
+                self.write_row(&left_values, l, None, &accs, &mut output)?;
+            }
+        }
+
+        if output[0].is_empty() {
+            return Ok(RecordBatch::new_empty(self.schema.clone()));
+        }
+        let columns = output
+            .iter()
+            .map(|c| arrow::compute::concat(&c.iter().map(|a| a.as_ref()).collect_vec()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+
+    /// Times matched by each left row as `[start, end)`, `None` if a bound is null.
+    fn ranges(&self, left: &RecordBatch) -> Result<Vec<Option<(i128, i128)>>, DataFusionError> {
+        let bound_values = |b: &Option<RangeBound>| -> Result<_, DataFusionError> {
+            match b {
+                None => Ok(None),
+                Some(b) => Ok(Some((
+                    time_values(&b.expr.evaluate(left)?.into_array(left.num_rows()))?,
+                    b.inclusive,
+                ))),
+            }
+        };
+        let lower = bound_values(&self.lower)?;
+        let upper = bound_values(&self.upper)?;
+        Ok((0..left.num_rows())
+            .map(|l| {
+                let start = match &lower {
+                    None => i128::MIN,
+                    Some((lo, inclusive)) => lo[l]? as i128 + if *inclusive { 0 } else { 1 },
+                };
+                let end = match &upper {
+                    None => i128::MAX,
+                    Some((hi, inclusive)) => hi[l]? as i128 + if *inclusive { 1 } else { 0 },
+                };
+                Some((start, end))
+            })
+            .collect())
+    }
+
+    fn write_row(
+        &self,
+        left_values: &[Option<ArrayRef>],
+        left_row: usize,
+        right_values: Option<&[ScalarValue]>,
+        accs: &AccumulatorSet,
+        output: &mut [Vec<ArrayRef>],
+    ) -> Result<(), DataFusionError> {
+        let mut right_values = right_values.map(|v| v.iter());
+        for (i, l) in left_values.iter().enumerate() {
+            let value = match (l, &mut right_values) {
+                (Some(a), _) => a.slice(left_row, 1),
+                (None, Some(r)) => r.next().unwrap().to_array_of_size(1),
+                (None, None) => new_null_array(self.schema.field(i).data_type(), 1),
+            };
+            output[i].push(value);
+        }
+        for (i, acc) in accs.iter().enumerate() {
+            output[left_values.len() + i].push(acc.evaluate()?.to_array_of_size(1));
+        }
+        Ok(())
+    }
+}
+
+impl Display for RangeJoinAggExec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(lo) = &self.lower {
+            let op = if lo.inclusive { "<=" } else { "<" };
+            write!(f, "{} {} ", lo.expr, op)?;
+        }
+        write!(f, "{}", self.time)?;
+        if let Some(hi) = &self.upper {
+            let op = if hi.inclusive { "<=" } else { "<" };
+            write!(f, " {} {}", op, hi.expr)?;
+        }
+        Ok(())
+    }
+}
+
+/// Aggregates of the right input by the right group columns and segments of the time axis.
+struct RightGroups<'a> {
+    join: &'a RangeJoinAggExec,
+    /// Sorted boundaries of segments, `boundaries[i]` starts the `i`-th segment.
+    boundaries: &'a [i128],
+    index: HashMap<SmallVec<[GroupByScalar; 2]>, usize>,
+    groups: Vec<RightGroup>,
+    /// Segment of the last added row.
+    segment: usize,
+    last_time: Option<i64>,
+    key: SmallVec<[GroupByScalar; 2]>,
+}
+
+struct RightGroup {
+    /// Values of the right group columns.
+    values: Vec<ScalarValue>,
+    /// Aggregates of the segments that have rows of this group, ordered by segment.
+    segments: Vec<(usize, AccumulatorSet)>,
+}
+
+impl RightGroups<'a> {
+    fn new(join: &'a RangeJoinAggExec, boundaries: &'a [i128]) -> RightGroups<'a> {
+        let num_keys = join
+            .group_expr
+            .iter()
+            .filter(|e| matches!(e, GroupExpr::Right(_)))
+            .count();
+        RightGroups {
+            join,
+            boundaries,
+            index: HashMap::new(),
+            groups: Vec::new(),
+            segment: 0,
+            last_time: None,
+            key: smallvec![GroupByScalar::Int8(0); num_keys],
+        }
+    }
+
+    /// Returns false when the batch is past the last segment, i.e. no more rows can match.
+    fn add_batch(&mut self, batch: &RecordBatch) -> Result<bool, DataFusionError> {
+        if self.boundaries.is_empty() {
+            return Ok(false);
+        }
+        let join = self.join;
+        let num_rows = batch.num_rows();
+        let times = time_values(&join.time.evaluate(batch)?.into_array(num_rows))?;
+        let keys = join
+            .group_expr
+            .iter()
+            .filter_map(|e| match e {
+                GroupExpr::Right(e) => Some(e),
+                GroupExpr::Left(_) => None,
+            })
+            .map(|e| Ok(e.evaluate(batch)?.into_array(num_rows)))
+            .collect::<Result<Vec<_>, DataFusionError>>()?;
+        let args = join
+            .agg_expr
+            .iter()
+            .map(|a| {
+                a.expressions()
+                    .iter()
+                    .map(|e| Ok(e.evaluate(batch)?.into_array(num_rows)))
+                    .collect::<Result<Vec<_>, DataFusionError>>()
+            })
+            .collect::<Result<Vec<_>, DataFusionError>>()?;
+
+        // Rows of the current segment by group.
+        let mut pending: HashMap<usize, Vec<u32>> = HashMap::new();
+        for row in 0..num_rows {
+            // Null times never match.
+            let time = match times[row] {
+                Some(t) => t,
+                None => continue,
+            };
+            if self.last_time.map_or(false, |last| time < last) {
+                return Err(DataFusionError::Internal(
+                    "right input of range join is not sorted by time".to_string(),
+                ));
+            }
+            self.last_time = Some(time);
+
+            let time = time as i128;
+            if time < self.boundaries[0] {
+                continue;
+            }
+            if *self.boundaries.last().unwrap() <= time {
+                self.update(&mut pending, &args)?;
+                return Ok(false);
+            }
+            while self.boundaries[self.segment + 1] <= time {
+                self.update(&mut pending, &args)?;
+                self.segment += 1;
+            }
+
+            create_group_by_values(&keys, row, &mut self.key)?;
+            let group = match self.index.get(&self.key) {
+                Some(g) => *g,
+                None => {
+                    let g = self.groups.len();
+                    self.groups.push(RightGroup {
+                        values: keys
+                            .iter()
+                            .map(|k| ScalarValue::try_from_array(k, row))
+                            .collect::<Result<Vec<_>, DataFusionError>>()?,
+                        segments: Vec::new(),
+                    });
+                    self.index.insert(self.key.clone(), g);
+                    g
+                }
+            };
+            pending.entry(group).or_default().push(row as u32);
+        }
+        self.update(&mut pending, &args)?;
+        Ok(true)
+    }
+
+    /// Adds [pending] rows to the aggregates of the current segment.
+    fn update(
+        &mut self,
+        pending: &mut HashMap<usize, Vec<u32>>,
+        args: &[Vec<ArrayRef>],
+    ) -> Result<(), DataFusionError> {
+        for (group, rows) in pending.drain() {
+            let segments = &mut self.groups[group].segments;
+            if segments.last().map(|(s, _)| *s) != Some(self.segment) {
+                segments.push((self.segment, create_accumulators(&self.join.agg_expr)?));
+            }
+            let accs = &mut segments.last_mut().unwrap().1;
+            let rows = UInt32Array::from(rows);
+            for (acc, args) in accs.iter_mut().zip(args) {
+                acc.update_batch(
+                    &args
+                        .iter()
+                        .map(|a| arrow::compute::take(a.as_ref(), &rows, None))
+                        .collect::<Result<Vec<_>, _>>()?,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Aggregates a window of consecutive segments that moves forward. Uses two stacks: [front]
+/// holds the aggregates of `[i, mid)` for each `i` in `[start, mid)`, the last one is for
+/// `start`, and [back] holds the aggregate of `[mid, end)`. Each segment is merged into a
+/// constant number of aggregates on average, windows are never re-aggregated from scratch.
+struct SlidingWindow {
+    start: usize,
+    mid: usize,
+    end: usize,
+    front: Vec<AccumulatorSet>,
+    back: AccumulatorSet,
+}
+
+impl SlidingWindow {
+    fn new(agg_expr: &[Arc<dyn AggregateExpr>]) -> Result<SlidingWindow, DataFusionError> {
+        Ok(SlidingWindow {
+            start: 0,
+            mid: 0,
+            end: 0,
+            front: Vec::new(),
+            back: create_accumulators(agg_expr)?,
+        })
+    }
+
+    /// Aggregate of `segments[start..end]`.
+    fn aggregate(
+        &mut self,
+        segments: &[(usize, AccumulatorSet)],
+        start: usize,
+        end: usize,
+        agg_expr: &[Arc<dyn AggregateExpr>],
+    ) -> Result<AccumulatorSet, DataFusionError> {
+        if start < self.start || end < self.end || self.end < start {
+            // Can only slide forward over adjacent segments, start over.
+            self.start = start;
+            self.mid = start;
+            self.end = start;
+            self.front.clear();
+            self.back.iter_mut().for_each(|a| a.reset());
+        }
+        for (_, s) in &segments[self.end..end] {
+            merge(&mut self.back, s)?;
+        }
+        self.end = end;
+        if self.mid < start {
+            // Front is exhausted, move everything to it.
+            self.front.clear();
+            for (_, s) in segments[start..end].iter().rev() {
+                let mut accs = create_accumulators(agg_expr)?;
+                merge(&mut accs, s)?;
+                if let Some(prev) = self.front.last() {
+                    merge(&mut accs, prev)?;
+                }
+                self.front.push(accs);
+            }
+            self.back.iter_mut().for_each(|a| a.reset());
+            self.mid = end;
+        } else {
+            for _ in self.start..start {
+                self.front.pop();
+            }
+        }
+        self.start = start;
+
+        let mut r = create_accumulators(agg_expr)?;
+        if let Some(f) = self.front.last() {
+            merge(&mut r, f)?;
+        }
+        merge(&mut r, &self.back)?;
+        Ok(r)
+    }
+}
+
+fn merge(to: &mut AccumulatorSet, from: &AccumulatorSet) -> Result<(), DataFusionError> {
+    for (t, f) in to.iter_mut().zip(from.iter()) {
+        t.merge(&f.state()?)?;
+    }
+    Ok(())
+}
+
+fn time_values(a: &ArrayRef) -> Result<Vec<Option<i64>>, DataFusionError> {
+    fn values<T: ArrowPrimitiveType>(a: &ArrayRef) -> Vec<Option<i64>>
+    where
+        T::Native: Into<i64>,
+    {
+        let a = a.as_any().downcast_ref::<PrimitiveArray<T>>().unwrap();
+        (0..a.len())
+            .map(|i| {
+                if a.is_null(i) {
+                    None
+                } else {
+                    Some(a.value(i).into())
+                }
+            })
+            .collect()
+    }
+    match a.data_type() {
+        DataType::Int32 => Ok(values::<Int32Type>(a)),
+        DataType::Int64 => Ok(values::<Int64Type>(a)),
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => Ok(values::<TimestampNanosecondType>(a)),
+        t => Err(DataFusionError::Internal(format!(
+            "unexpected type of range join column: {:?}",
+            t
+        ))),
+    }
+}
+
+async fn collect_batch(p: Arc<dyn ExecutionPlan>) -> Result<RecordBatch, DataFusionError> {
+    let schema = p.schema();
+    let batches = collect(p).await?;
+    if batches.is_empty() {
+        return Ok(RecordBatch::new_empty(schema));
+    }
+    Ok(RecordBatch::concat(&schema, &batches)?)
+}
+
+#[async_trait]
+impl ExecutionPlan for RangeJoinAggExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(1)
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.left.clone(), self.right.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        assert_eq!(children.len(), 2);
+        let mut children = children.into_iter();
+        Ok(Arc::new(RangeJoinAggExec {
+            left: children.next().unwrap(),
+            right: children.next().unwrap(),
+            time: self.time.clone(),
+            lower: self.lower.clone(),
+            upper: self.upper.clone(),
+            group_expr: self.group_expr.clone(),
+            agg_expr: self.agg_expr.clone(),
+            schema: self.schema.clone(),
+        }))
+    }
+
+    fn output_hints(&self) -> OptimizerHints {
+        OptimizerHints::default()
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<SendableRecordBatchStream, DataFusionError> {
+        assert_eq!(partition, 0);
+        let batch = self.join_and_aggregate().await?;
+        let schema = batch.schema();
+        MemoryExec::try_new(&vec![vec![batch]], schema, None)?
+            .execute(0)
+            .await
+    }
+}
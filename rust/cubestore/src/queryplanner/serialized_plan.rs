@@ -14,17 +14,19 @@ use datafusion::cube_ext::alias::LogicalAlias;
 use datafusion::cube_ext::join::SkewedLeftCrossJoin;
 use datafusion::cube_ext::joinagg::CrossJoinAgg;
 use datafusion::cube_ext::rolling::RollingWindowAggregate;
-use datafusion::logical_plan::window_frames::WindowFrameBound;
+use datafusion::logical_plan::window_frames::{WindowFrame, WindowFrameBound, WindowFrameUnits};
 use datafusion::logical_plan::{
     Column, DFSchemaRef, Expr, JoinConstraint, JoinType, LogicalPlan, Operator, Partitioning,
     PlanVisitor,
 };
+use datafusion::physical_plan::window_functions::{BuiltInWindowFunction, WindowFunction};
 use datafusion::physical_plan::{aggregates, functions};
 use datafusion::scalar::ScalarValue;
 use serde_derive::{Deserialize, Serialize};
 use sqlparser::ast::RollingOffset;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
+use std::str::FromStr;
 use std::sync::Arc;
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -32,6 +34,8 @@ pub struct SerializedPlan {
     logical_plan: Arc<SerializedLogicalPlan>,
     schema_snapshot: Arc<SchemaSnapshot>,
     partition_ids_to_execute: HashSet<u64>,
+    /// Bucket of shuffled join inputs this task joins, see [JoinDistribution::Shuffle].
+    join_bucket_to_execute: Option<u64>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -45,6 +49,18 @@ pub struct IndexSnapshot {
     pub index: IdRow<Index>,
     pub partitions: Vec<PartitionSnapshot>,
     pub sort_on: Option<Vec<String>>,
+    /// Set for inputs of hash joins, which run when no index is sorted on the join keys.
+    pub join_distribution: Option<JoinDistribution>,
+}
+
+/// How partitions of a hash join input are assigned to worker tasks.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub enum JoinDistribution {
+    /// All partitions of this side go to every task of the other side.
+    Broadcast,
+    /// All partitions of both sides go to each of `buckets` tasks, which only read the rows with
+    /// the hash of the join key `columns` in its bucket.
+    Shuffle { columns: Vec<String>, buckets: u64 },
 }
 
 impl IndexSnapshot {
@@ -67,6 +83,24 @@ impl IndexSnapshot {
     pub fn sort_on(&self) -> Option<&Vec<String>> {
         self.sort_on.as_ref()
     }
+
+    pub fn join_distribution(&self) -> Option<&JoinDistribution> {
+        self.join_distribution.as_ref()
+    }
+
+    /// Rows in the snapshot partitions and their chunks.
+    pub fn estimated_row_count(&self) -> u64 {
+        self.partitions
+            .iter()
+            .map(|p| {
+                p.partition.get_row().main_table_row_count()
+                    + p.chunks
+                        .iter()
+                        .map(|c| c.get_row().get_row_count())
+                        .sum::<u64>()
+            })
+            .sum()
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -140,6 +174,11 @@ pub enum SerializedLogicalPlan {
         n: usize,
         input: Arc<SerializedLogicalPlan>,
     },
+    Window {
+        input: Arc<SerializedLogicalPlan>,
+        window_expr: Vec<SerializedExpr>,
+        schema: DFSchemaRef,
+    },
     Repartition {
         input: Arc<SerializedLogicalPlan>,
         partitioning_scheme: SerializePartitioning,
@@ -203,6 +242,7 @@ impl SerializedLogicalPlan {
         &self,
         remote_to_local_names: &HashMap<String, String>,
         worker_partition_ids: &HashSet<u64>,
+        join_bucket: Option<u64>,
     ) -> Result<LogicalPlan, CubeError> {
         Ok(match self {
             SerializedLogicalPlan::Projection {
@@ -211,12 +251,20 @@ impl SerializedLogicalPlan {
                 schema,
             } => LogicalPlan::Projection {
                 expr: expr.iter().map(|e| e.expr()).collect(),
-                input: Arc::new(input.logical_plan(remote_to_local_names, worker_partition_ids)?),
+                input: Arc::new(input.logical_plan(
+                    remote_to_local_names,
+                    worker_partition_ids,
+                    join_bucket,
+                )?),
                 schema: schema.clone(),
             },
             SerializedLogicalPlan::Filter { predicate, input } => LogicalPlan::Filter {
                 predicate: predicate.expr(),
-                input: Arc::new(input.logical_plan(remote_to_local_names, worker_partition_ids)?),
+                input: Arc::new(input.logical_plan(
+                    remote_to_local_names,
+                    worker_partition_ids,
+                    join_bucket,
+                )?),
             },
             SerializedLogicalPlan::Aggregate {
                 input,
@@ -226,12 +274,20 @@ impl SerializedLogicalPlan {
             } => LogicalPlan::Aggregate {
                 group_expr: group_expr.iter().map(|e| e.expr()).collect(),
                 aggr_expr: aggr_expr.iter().map(|e| e.expr()).collect(),
-                input: Arc::new(input.logical_plan(remote_to_local_names, worker_partition_ids)?),
+                input: Arc::new(input.logical_plan(
+                    remote_to_local_names,
+                    worker_partition_ids,
+                    join_bucket,
+                )?),
                 schema: schema.clone(),
             },
             SerializedLogicalPlan::Sort { expr, input } => LogicalPlan::Sort {
                 expr: expr.iter().map(|e| e.expr()).collect(),
-                input: Arc::new(input.logical_plan(remote_to_local_names, worker_partition_ids)?),
+                input: Arc::new(input.logical_plan(
+                    remote_to_local_names,
+                    worker_partition_ids,
+                    join_bucket,
+                )?),
             },
             SerializedLogicalPlan::Union {
                 inputs,
@@ -241,7 +297,11 @@ impl SerializedLogicalPlan {
                 inputs: inputs
                     .iter()
                     .map(|p| -> Result<LogicalPlan, CubeError> {
-                        Ok(p.logical_plan(remote_to_local_names, worker_partition_ids)?)
+                        Ok(p.logical_plan(
+                            remote_to_local_names,
+                            worker_partition_ids,
+                            join_bucket,
+                        )?)
                     })
                     .collect::<Result<Vec<_>, _>>()?,
                 schema: schema.clone(),
@@ -261,6 +321,7 @@ impl SerializedLogicalPlan {
                     SerializedTableSource::CubeTable(v) => Arc::new(v.to_worker_table(
                         remote_to_local_names.clone(),
                         worker_partition_ids.clone(),
+                        join_bucket,
                     )),
                 },
                 projection: projection.clone(),
@@ -277,11 +338,32 @@ impl SerializedLogicalPlan {
             },
             SerializedLogicalPlan::Limit { n, input } => LogicalPlan::Limit {
                 n: *n,
-                input: Arc::new(input.logical_plan(remote_to_local_names, worker_partition_ids)?),
+                input: Arc::new(input.logical_plan(
+                    remote_to_local_names,
+                    worker_partition_ids,
+                    join_bucket,
+                )?),
             },
             SerializedLogicalPlan::Skip { n, input } => LogicalPlan::Skip {
                 n: *n,
-                input: Arc::new(input.logical_plan(remote_to_local_names, worker_partition_ids)?),
+                input: Arc::new(input.logical_plan(
+                    remote_to_local_names,
+                    worker_partition_ids,
+                    join_bucket,
+                )?),
+            },
+            SerializedLogicalPlan::Window {
+                input,
+                window_expr,
+                schema,
+            } => LogicalPlan::Window {
+                input: Arc::new(input.logical_plan(
+                    remote_to_local_names,
+                    worker_partition_ids,
+                    join_bucket,
+                )?),
+                window_expr: exprs(&window_expr),
+                schema: schema.clone(),
             },
             SerializedLogicalPlan::Join {
                 left,
@@ -291,8 +373,16 @@ impl SerializedLogicalPlan {
                 join_constraint,
                 schema,
             } => LogicalPlan::Join {
-                left: Arc::new(left.logical_plan(remote_to_local_names, worker_partition_ids)?),
-                right: Arc::new(right.logical_plan(remote_to_local_names, worker_partition_ids)?),
+                left: Arc::new(left.logical_plan(
+                    remote_to_local_names,
+                    worker_partition_ids,
+                    join_bucket,
+                )?),
+                right: Arc::new(right.logical_plan(
+                    remote_to_local_names,
+                    worker_partition_ids,
+                    join_bucket,
+                )?),
                 on: on.clone(),
                 join_type: join_type.clone(),
                 join_constraint: *join_constraint,
@@ -302,7 +392,11 @@ impl SerializedLogicalPlan {
                 input,
                 partitioning_scheme,
             } => LogicalPlan::Repartition {
-                input: Arc::new(input.logical_plan(remote_to_local_names, worker_partition_ids)?),
+                input: Arc::new(input.logical_plan(
+                    remote_to_local_names,
+                    worker_partition_ids,
+                    join_bucket,
+                )?),
                 partitioning_scheme: match partitioning_scheme {
                     SerializePartitioning::RoundRobinBatch(s) => Partitioning::RoundRobinBatch(*s),
                     SerializePartitioning::Hash(e, s) => {
@@ -316,13 +410,21 @@ impl SerializedLogicalPlan {
                 schema,
             } => LogicalPlan::Extension {
                 node: Arc::new(LogicalAlias {
-                    input: input.logical_plan(remote_to_local_names, worker_partition_ids)?,
+                    input: input.logical_plan(
+                        remote_to_local_names,
+                        worker_partition_ids,
+                        join_bucket,
+                    )?,
                     alias: alias.clone(),
                     schema: schema.clone(),
                 }),
             },
             SerializedLogicalPlan::ClusterSend { input, snapshots } => ClusterSendNode {
-                input: Arc::new(input.logical_plan(remote_to_local_names, worker_partition_ids)?),
+                input: Arc::new(input.logical_plan(
+                    remote_to_local_names,
+                    worker_partition_ids,
+                    join_bucket,
+                )?),
                 snapshots: snapshots.clone(),
             }
             .into_plan(),
@@ -336,7 +438,11 @@ impl SerializedLogicalPlan {
                 snapshots,
             } => ClusterAggregateTopK {
                 limit: *limit,
-                input: Arc::new(input.logical_plan(remote_to_local_names, worker_partition_ids)?),
+                input: Arc::new(input.logical_plan(
+                    remote_to_local_names,
+                    worker_partition_ids,
+                    join_bucket,
+                )?),
                 group_expr: group_expr.iter().map(|e| e.expr()).collect(),
                 aggregate_expr: aggregate_expr.iter().map(|e| e.expr()).collect(),
                 order_by: sort_columns.clone(),
@@ -351,8 +457,16 @@ impl SerializedLogicalPlan {
                 join_schema,
             } => LogicalPlan::Extension {
                 node: Arc::new(SkewedLeftCrossJoin {
-                    left: left.logical_plan(remote_to_local_names, worker_partition_ids)?,
-                    right: right.logical_plan(remote_to_local_names, worker_partition_ids)?,
+                    left: left.logical_plan(
+                        remote_to_local_names,
+                        worker_partition_ids,
+                        join_bucket,
+                    )?,
+                    right: right.logical_plan(
+                        remote_to_local_names,
+                        worker_partition_ids,
+                        join_bucket,
+                    )?,
                     on: on.expr(),
                     schema: join_schema.clone(),
                 }),
@@ -368,8 +482,16 @@ impl SerializedLogicalPlan {
             } => LogicalPlan::Extension {
                 node: Arc::new(CrossJoinAgg {
                     join: SkewedLeftCrossJoin {
-                        left: left.logical_plan(remote_to_local_names, worker_partition_ids)?,
-                        right: right.logical_plan(remote_to_local_names, worker_partition_ids)?,
+                        left: left.logical_plan(
+                            remote_to_local_names,
+                            worker_partition_ids,
+                            join_bucket,
+                        )?,
+                        right: right.logical_plan(
+                            remote_to_local_names,
+                            worker_partition_ids,
+                            join_bucket,
+                        )?,
                         on: on.expr(),
                         schema: join_schema.clone(),
                     },
@@ -392,7 +514,11 @@ impl SerializedLogicalPlan {
             } => LogicalPlan::Extension {
                 node: Arc::new(RollingWindowAggregate {
                     schema: schema.clone(),
-                    input: input.logical_plan(remote_to_local_names, worker_partition_ids)?,
+                    input: input.logical_plan(
+                        remote_to_local_names,
+                        worker_partition_ids,
+                        join_bucket,
+                    )?,
                     dimension: dimension.clone(),
                     from: from.expr(),
                     to: to.expr(),
@@ -477,9 +603,31 @@ pub enum SerializedExpr {
         list: Vec<SerializedExpr>,
         negated: bool,
     },
+    WindowFunction {
+        fun: SerializedWindowFunction,
+        args: Vec<SerializedExpr>,
+        partition_by: Vec<SerializedExpr>,
+        order_by: Vec<SerializedExpr>,
+        window_frame: Option<SerializedWindowFrame>,
+    },
     Wildcard,
 }
 
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub enum SerializedWindowFunction {
+    AggregateFunction(aggregates::AggregateFunction),
+    /// Name of the [BuiltInWindowFunction], e.g. `ROW_NUMBER`.
+    BuiltInWindowFunction(String),
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct SerializedWindowFrame {
+    /// `ROWS` if true, `RANGE` otherwise.
+    rows: bool,
+    start_bound: WindowFrameBound,
+    end_bound: WindowFrameBound,
+}
+
 impl SerializedExpr {
     fn expr(&self) -> Expr {
         match self {
@@ -584,6 +732,35 @@ impl SerializedExpr {
                 list: list.iter().map(|e| e.expr()).collect(),
                 negated: *negated,
             },
+            SerializedExpr::WindowFunction {
+                fun,
+                args,
+                partition_by,
+                order_by,
+                window_frame,
+            } => Expr::WindowFunction {
+                fun: match fun {
+                    SerializedWindowFunction::AggregateFunction(f) => {
+                        WindowFunction::AggregateFunction(f.clone())
+                    }
+                    SerializedWindowFunction::BuiltInWindowFunction(f) => {
+                        WindowFunction::BuiltInWindowFunction(
+                            BuiltInWindowFunction::from_str(f).unwrap(),
+                        )
+                    }
+                },
+                args: exprs(&args),
+                partition_by: exprs(&partition_by),
+                order_by: exprs(&order_by),
+                window_frame: window_frame.as_ref().map(|f| WindowFrame {
+                    units: match f.rows {
+                        true => WindowFrameUnits::Rows,
+                        false => WindowFrameUnits::Range,
+                    },
+                    start_bound: f.start_bound.clone(),
+                    end_bound: f.end_bound.clone(),
+                }),
+            },
         }
     }
 }
@@ -603,6 +780,7 @@ impl SerializedPlan {
             logical_plan: Arc::new(serialized_logical_plan),
             schema_snapshot: Arc::new(SchemaSnapshot { index_snapshots }),
             partition_ids_to_execute: HashSet::new(),
+            join_bucket_to_execute: None,
         })
     }
 
@@ -611,6 +789,16 @@ impl SerializedPlan {
             logical_plan: self.logical_plan.clone(),
             schema_snapshot: self.schema_snapshot.clone(),
             partition_ids_to_execute,
+            join_bucket_to_execute: self.join_bucket_to_execute,
+        }
+    }
+
+    pub fn with_join_bucket_to_execute(&self, join_bucket_to_execute: Option<u64>) -> Self {
+        Self {
+            logical_plan: self.logical_plan.clone(),
+            schema_snapshot: self.schema_snapshot.clone(),
+            partition_ids_to_execute: self.partition_ids_to_execute.clone(),
+            join_bucket_to_execute,
         }
     }
 
@@ -622,8 +810,11 @@ impl SerializedPlan {
         &self,
         remote_to_local_names: &HashMap<String, String>,
     ) -> Result<LogicalPlan, CubeError> {
-        self.logical_plan
-            .logical_plan(remote_to_local_names, &self.partition_ids_to_execute())
+        self.logical_plan.logical_plan(
+            remote_to_local_names,
+            &self.partition_ids_to_execute(),
+            self.join_bucket_to_execute,
+        )
     }
 
     pub fn index_snapshots(&self) -> &Vec<IndexSnapshot> {
@@ -760,6 +951,15 @@ impl SerializedPlan {
                 input: Arc::new(Self::serialized_logical_plan(input)),
                 n: *n,
             },
+            LogicalPlan::Window {
+                input,
+                window_expr,
+                schema,
+            } => SerializedLogicalPlan::Window {
+                input: Arc::new(Self::serialized_logical_plan(input)),
+                window_expr: Self::serialized_exprs(window_expr),
+                schema: schema.clone(),
+            },
             LogicalPlan::CreateExternalTable { .. } => unimplemented!(),
             LogicalPlan::Explain { .. } => unimplemented!(),
             LogicalPlan::Extension { node } => {
@@ -869,7 +1069,7 @@ impl SerializedPlan {
                     ),
                 },
             },
-            LogicalPlan::Window { .. } | LogicalPlan::CrossJoin { .. } => {
+            LogicalPlan::CrossJoin { .. } => {
                 panic!("unsupported plan node")
             }
         }
@@ -990,7 +1190,34 @@ impl SerializedPlan {
                     RollingOffset::End => true,
                 },
             },
-            Expr::WindowFunction { .. } => panic!("window functions are not supported"),
+            Expr::WindowFunction {
+                fun,
+                args,
+                partition_by,
+                order_by,
+                window_frame,
+            } => SerializedExpr::WindowFunction {
+                fun: match fun {
+                    WindowFunction::AggregateFunction(f) => {
+                        SerializedWindowFunction::AggregateFunction(f.clone())
+                    }
+                    WindowFunction::BuiltInWindowFunction(f) => {
+                        SerializedWindowFunction::BuiltInWindowFunction(f.to_string())
+                    }
+                },
+                args: Self::serialized_exprs(args),
+                partition_by: Self::serialized_exprs(partition_by),
+                order_by: Self::serialized_exprs(order_by),
+                window_frame: window_frame.as_ref().map(|f| SerializedWindowFrame {
+                    rows: match f.units {
+                        WindowFrameUnits::Rows => true,
+                        WindowFrameUnits::Range => false,
+                        WindowFrameUnits::Groups => panic!("GROUPS frames are not supported"),
+                    },
+                    start_bound: f.start_bound.clone(),
+                    end_bound: f.end_bound.clone(),
+                }),
+            },
         }
     }
 
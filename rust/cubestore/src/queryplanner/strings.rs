@@ -0,0 +1,512 @@
+use crate::queryplanner::optimizations::rewrite_plan::{rewrite_plan, PlanRewriter};
+use crate::queryplanner::udfs::{scalar_udf_by_kind, CubeScalarUDFKind};
+use arrow::array::{
+    Array, ArrayRef, BooleanBuilder, Int64Array, Int64Builder, StringArray, StringBuilder,
+};
+use datafusion::error::DataFusionError;
+use datafusion::execution::context::ExecutionProps;
+use datafusion::logical_plan::{Expr, ExprRewriter, LogicalPlan, Operator};
+use datafusion::optimizer::optimizer::OptimizerRule;
+use datafusion::optimizer::utils::from_plan;
+use datafusion::physical_plan::ColumnarValue;
+use datafusion::scalar::ScalarValue;
+use itertools::Itertools;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// `SUBSTRING(str, pos[, len])`. Positions are 1-based, negative ones count from the end.
+pub fn substring(inputs: &[ColumnarValue]) -> Result<ColumnarValue, DataFusionError> {
+    let args = Args::new(inputs);
+    let mut r = StringBuilder::new(args.len);
+    for row in 0..args.len {
+        let len = if inputs.len() == 3 {
+            match args.int(2, row)? {
+                Some(l) => Some(l),
+                None => {
+                    r.append_null()?;
+                    continue;
+                }
+            }
+        } else {
+            None
+        };
+        match (args.str(0, row)?, args.int(1, row)?) {
+            (Some(s), Some(pos)) => r.append_value(&substring_str(s, pos, len))?,
+            _ => r.append_null()?,
+        }
+    }
+    args.finish(Arc::new(r.finish()))
+}
+
+/// `SUBSTRING_INDEX(str, delim, count)`. Part of `str` before the `count`-th occurrence of
+/// `delim`, or after the `-count`-th occurrence from the end if `count` is negative.
+pub fn substring_index(inputs: &[ColumnarValue]) -> Result<ColumnarValue, DataFusionError> {
+    let args = Args::new(inputs);
+    let mut r = StringBuilder::new(args.len);
+    for row in 0..args.len {
+        match (args.str(0, row)?, args.str(1, row)?, args.int(2, row)?) {
+            (Some(s), Some(delim), Some(count)) => {
+                r.append_value(substring_index_str(s, delim, count))?
+            }
+            _ => r.append_null()?,
+        }
+    }
+    args.finish(Arc::new(r.finish()))
+}
+
+/// `LOCATE(substr, str[, pos])`. 1-based position of `substr` in `str` at or after `pos`,
+/// 0 if there is none.
+pub fn locate(inputs: &[ColumnarValue]) -> Result<ColumnarValue, DataFusionError> {
+    let args = Args::new(inputs);
+    let mut r = Int64Builder::new(args.len);
+    for row in 0..args.len {
+        let pos = if inputs.len() == 3 {
+            args.int(2, row)?
+        } else {
+            Some(1)
+        };
+        match (args.str(0, row)?, args.str(1, row)?, pos) {
+            (Some(needle), Some(s), Some(pos)) => r.append_value(locate_str(needle, s, pos))?,
+            _ => r.append_null()?,
+        }
+    }
+    args.finish(Arc::new(r.finish()))
+}
+
+/// `INSTR(str, substr)`, same as `LOCATE(substr, str)`.
+pub fn instr(inputs: &[ColumnarValue]) -> Result<ColumnarValue, DataFusionError> {
+    let args = Args::new(inputs);
+    let mut r = Int64Builder::new(args.len);
+    for row in 0..args.len {
+        match (args.str(0, row)?, args.str(1, row)?) {
+            (Some(s), Some(needle)) => r.append_value(locate_str(needle, s, 1))?,
+            _ => r.append_null()?,
+        }
+    }
+    args.finish(Arc::new(r.finish()))
+}
+
+/// `REGEXP_LIKE(str, pattern)`. True if `pattern` matches any part of `str`.
+pub fn regexp_like(inputs: &[ColumnarValue]) -> Result<ColumnarValue, DataFusionError> {
+    let args = Args::new(inputs);
+    let mut regexes = RegexCache::default();
+    let mut r = BooleanBuilder::new(args.len);
+    for row in 0..args.len {
+        match (args.str(0, row)?, args.str(1, row)?) {
+            (Some(s), Some(p)) => r.append_value(regexes.get(p, "REGEXP_LIKE")?.is_match(s))?,
+            _ => r.append_null()?,
+        }
+    }
+    args.finish(Arc::new(r.finish()))
+}
+
+/// `REGEXP_SUBSTR(str, pattern)`. The first match of `pattern` in `str`, NULL if there is none.
+pub fn regexp_substr(inputs: &[ColumnarValue]) -> Result<ColumnarValue, DataFusionError> {
+    let args = Args::new(inputs);
+    let mut regexes = RegexCache::default();
+    let mut r = StringBuilder::new(args.len);
+    for row in 0..args.len {
+        match (args.str(0, row)?, args.str(1, row)?) {
+            (Some(s), Some(p)) => match regexes.get(p, "REGEXP_SUBSTR")?.find(s) {
+                Some(m) => r.append_value(m.as_str())?,
+                None => r.append_null()?,
+            },
+            _ => r.append_null()?,
+        }
+    }
+    args.finish(Arc::new(r.finish()))
+}
+
+/// `REGEXP_REPLACE(str, pattern, replacement)`. Replaces all matches of `pattern` in `str`.
+/// `$1` or `${name}` in `replacement` refer to capture groups.
+pub fn regexp_replace(inputs: &[ColumnarValue]) -> Result<ColumnarValue, DataFusionError> {
+    let args = Args::new(inputs);
+    let mut regexes = RegexCache::default();
+    let mut r = StringBuilder::new(args.len);
+    for row in 0..args.len {
+        match (args.str(0, row)?, args.str(1, row)?, args.str(2, row)?) {
+            (Some(s), Some(p), Some(replacement)) => r.append_value(
+                &regexes
+                    .get(p, "REGEXP_REPLACE")?
+                    .replace_all(s, replacement),
+            )?,
+            _ => r.append_null()?,
+        }
+    }
+    args.finish(Arc::new(r.finish()))
+}
+
+/// `SPLIT_PART(str, delim, n)`. The `n`-th field of `str` split by `delim`, counting from the
+/// end if `n` is negative. Empty string if there are fewer fields.
+pub fn split_part(inputs: &[ColumnarValue]) -> Result<ColumnarValue, DataFusionError> {
+    let args = Args::new(inputs);
+    let mut r = StringBuilder::new(args.len);
+    for row in 0..args.len {
+        match (args.str(0, row)?, args.str(1, row)?, args.int(2, row)?) {
+            (Some(s), Some(delim), Some(n)) => r.append_value(split_part_str(s, delim, n)?)?,
+            _ => r.append_null()?,
+        }
+    }
+    args.finish(Arc::new(r.finish()))
+}
+
+/// `LIKE(str, pattern)`. Unlike the DataFusion operator, `\` escapes `%`, `_` and itself.
+pub fn like(inputs: &[ColumnarValue]) -> Result<ColumnarValue, DataFusionError> {
+    let args = Args::new(inputs);
+    let mut regexes = RegexCache::default();
+    let mut r = BooleanBuilder::new(args.len);
+    for row in 0..args.len {
+        match (args.str(0, row)?, args.str(1, row)?) {
+            (Some(s), Some(p)) => {
+                r.append_value(regexes.get_with(p, "LIKE", like_to_regex)?.is_match(s))?
+            }
+            _ => r.append_null()?,
+        }
+    }
+    args.finish(Arc::new(r.finish()))
+}
+
+fn substring_str(s: &str, pos: i64, len: Option<i64>) -> String {
+    let n = s.chars().count() as i64;
+    let start = if 0 < pos {
+        pos - 1
+    } else if pos < 0 {
+        n + pos
+    } else {
+        return String::new();
+    };
+    if start < 0 || n <= start {
+        return String::new();
+    }
+    let take = match len {
+        None => n - start,
+        Some(l) if l <= 0 => return String::new(),
+        Some(l) => l.min(n - start),
+    };
+    s.chars().skip(start as usize).take(take as usize).collect()
+}
+
+fn substring_index_str<'a>(s: &'a str, delim: &str, count: i64) -> &'a str {
+    if count == 0 || delim.is_empty() {
+        return "";
+    }
+    if 0 < count {
+        match s.match_indices(delim).nth(count as usize - 1) {
+            Some((i, _)) => &s[..i],
+            None => s,
+        }
+    } else {
+        match s.rmatch_indices(delim).nth((-count) as usize - 1) {
+            Some((i, _)) => &s[i + delim.len()..],
+            None => s,
+        }
+    }
+}
+
+fn locate_str(needle: &str, s: &str, pos: i64) -> i64 {
+    if pos < 1 {
+        return 0;
+    }
+    let start = match s.char_indices().nth(pos as usize - 1) {
+        Some((i, _)) => i,
+        None => return 0,
+    };
+    match s[start..].find(needle) {
+        Some(i) => pos + s[start..start + i].chars().count() as i64,
+        None => 0,
+    }
+}
+
+fn split_part_str<'a>(s: &'a str, delim: &str, n: i64) -> Result<&'a str, DataFusionError> {
+    if n == 0 {
+        return Err(DataFusionError::Execution(
+            "Field position in `SPLIT_PART` must not be zero".to_string(),
+        ));
+    }
+    if delim.is_empty() {
+        return Ok(if n == 1 || n == -1 { s } else { "" });
+    }
+    let part = if 0 < n {
+        s.split(delim).nth(n as usize - 1)
+    } else {
+        s.rsplit(delim).nth((-n) as usize - 1)
+    };
+    Ok(part.unwrap_or(""))
+}
+
+fn like_to_regex(pattern: &str) -> String {
+    let mut r = String::with_capacity(pattern.len() + 6);
+    r.push_str("(?s)^");
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => r.push_str(".*"),
+            '_' => r.push('.'),
+            '\\' => match chars.next() {
+                Some(c) => r.push_str(&regex::escape(&c.to_string())),
+                // Trailing backslash matches itself.
+                None => r.push_str(r"\\"),
+            },
+            c => r.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    r.push('$');
+    r
+}
+
+/// Function arguments as arrays of the same length.
+struct Args {
+    arrays: Vec<ArrayRef>,
+    len: usize,
+    all_scalars: bool,
+}
+
+impl Args {
+    fn new(inputs: &[ColumnarValue]) -> Args {
+        let len = inputs
+            .iter()
+            .filter_map(|i| match i {
+                ColumnarValue::Array(a) => Some(a.len()),
+                ColumnarValue::Scalar(_) => None,
+            })
+            .next();
+        Args {
+            arrays: inputs
+                .iter()
+                .map(|i| i.clone().into_array(len.unwrap_or(1)))
+                .collect(),
+            len: len.unwrap_or(1),
+            all_scalars: len.is_none(),
+        }
+    }
+
+    fn str(&self, arg: usize, row: usize) -> Result<Option<&str>, DataFusionError> {
+        let a = self.arrays[arg]
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .ok_or_else(|| {
+                DataFusionError::Execution(format!("Argument {} must be a string", arg + 1))
+            })?;
+        Ok(if a.is_null(row) {
+            None
+        } else {
+            Some(a.value(row))
+        })
+    }
+
+    fn int(&self, arg: usize, row: usize) -> Result<Option<i64>, DataFusionError> {
+        let a = self.arrays[arg]
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .ok_or_else(|| {
+                DataFusionError::Execution(format!("Argument {} must be an integer", arg + 1))
+            })?;
+        Ok(if a.is_null(row) {
+            None
+        } else {
+            Some(a.value(row))
+        })
+    }
+
+    /// Scalar arguments must produce a scalar result.
+    fn finish(&self, r: ArrayRef) -> Result<ColumnarValue, DataFusionError> {
+        if self.all_scalars {
+            Ok(ColumnarValue::Scalar(ScalarValue::try_from_array(&r, 0)?))
+        } else {
+            Ok(ColumnarValue::Array(r))
+        }
+    }
+}
+
+/// Compiled regexes by pattern. Patterns come from literals or columns with few distinct
+/// values, so we start over once there are too many of them.
+#[derive(Default)]
+struct RegexCache {
+    regexes: HashMap<String, Regex>,
+}
+
+const MAX_CACHED_REGEXES: usize = 1024;
+
+impl RegexCache {
+    fn get(&mut self, pattern: &str, fun: &str) -> Result<&Regex, DataFusionError> {
+        self.get_with(pattern, fun, |p| p.to_string())
+    }
+
+    /// Compiles `to_regex(pattern)` the first time `pattern` is seen.
+    fn get_with(
+        &mut self,
+        pattern: &str,
+        fun: &str,
+        to_regex: impl FnOnce(&str) -> String,
+    ) -> Result<&Regex, DataFusionError> {
+        if !self.regexes.contains_key(pattern) {
+            let r = Regex::new(&to_regex(pattern)).map_err(|e| {
+                DataFusionError::Execution(format!("Invalid pattern in `{}`: {}", fun, e))
+            })?;
+            if MAX_CACHED_REGEXES <= self.regexes.len() {
+                self.regexes.clear();
+            }
+            self.regexes.insert(pattern.to_string(), r);
+        }
+        Ok(&self.regexes[pattern])
+    }
+}
+
+/// The DataFusion `LIKE` operator does not support escapes. Replace it with our `LIKE` function
+/// when the pattern is a literal that has any. Patterns computed from columns keep the
+/// DataFusion semantics.
+pub struct RewriteEscapedLike;
+impl OptimizerRule for RewriteEscapedLike {
+    fn optimize(
+        &self,
+        plan: &LogicalPlan,
+        _execution_props: &ExecutionProps,
+    ) -> Result<LogicalPlan, DataFusionError> {
+        return rewrite_plan(plan, &(), &mut Rewriter {});
+
+        #[derive(Clone)]
+        struct Rewriter {}
+        impl ExprRewriter for Rewriter {
+            fn mutate(&mut self, expr: Expr) -> Result<Expr, DataFusionError> {
+                match expr {
+                    Expr::BinaryExpr { left, op, right }
+                        if (op == Operator::Like || op == Operator::NotLike)
+                            && has_escapes(&right) =>
+                    {
+                        let like = Expr::ScalarUDF {
+                            fun: Arc::new(scalar_udf_by_kind(CubeScalarUDFKind::Like).descriptor()),
+                            args: vec![*left, *right],
+                        };
+                        if op == Operator::Like {
+                            Ok(like)
+                        } else {
+                            Ok(Expr::Not(Box::new(like)))
+                        }
+                    }
+                    _ => Ok(expr),
+                }
+            }
+        }
+
+        impl PlanRewriter for Rewriter {
+            type Context = ();
+
+            fn rewrite(&mut self, n: LogicalPlan, _: &()) -> Result<LogicalPlan, DataFusionError> {
+                let mut exprs = n.expressions();
+                for e in &mut exprs {
+                    *e = std::mem::replace(e, Expr::Wildcard).rewrite(self)?
+                }
+                from_plan(&n, &exprs, &n.inputs().into_iter().cloned().collect_vec())
+            }
+        }
+    }
+
+    fn name(&self) -> &str {
+        "rewrite_escaped_like"
+    }
+}
+
+fn has_escapes(pattern: &Expr) -> bool {
+    match pattern {
+        Expr::Literal(ScalarValue::Utf8(Some(p))) => p.contains('\\'),
+        Expr::Cast { expr, .. } | Expr::TryCast { expr, .. } => has_escapes(expr),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::logical_plan::Column;
+
+    #[test]
+    fn substrings() {
+        assert_eq!(substring_str("Quadratically", 5, None), "ratically");
+        assert_eq!(substring_str("Quadratically", 5, Some(6)), "ratica");
+        assert_eq!(substring_str("Sakila", -3, None), "ila");
+        assert_eq!(substring_str("Sakila", -5, Some(3)), "aki");
+        assert_eq!(substring_str("Sakila", 0, None), "");
+        assert_eq!(substring_str("Sakila", 7, None), "");
+        assert_eq!(substring_str("Sakila", -7, None), "");
+        assert_eq!(substring_str("Sakila", 2, Some(0)), "");
+        assert_eq!(substring_str("Привет", 2, Some(3)), "рив");
+
+        assert_eq!(substring_index_str("www.mysql.com", ".", 2), "www.mysql");
+        assert_eq!(substring_index_str("www.mysql.com", ".", -2), "mysql.com");
+        assert_eq!(
+            substring_index_str("www.mysql.com", ".", 5),
+            "www.mysql.com"
+        );
+        assert_eq!(substring_index_str("www.mysql.com", ".", 0), "");
+        assert_eq!(substring_index_str("a::b::c", "::", -1), "c");
+    }
+
+    #[test]
+    fn locates() {
+        assert_eq!(locate_str("bar", "foobarbar", 1), 4);
+        assert_eq!(locate_str("xbar", "foobar", 1), 0);
+        assert_eq!(locate_str("bar", "foobarbar", 5), 7);
+        assert_eq!(locate_str("bar", "foobarbar", 0), 0);
+        assert_eq!(locate_str("bar", "foobarbar", 10), 0);
+        assert_eq!(locate_str("т", "привет", 1), 6);
+    }
+
+    #[test]
+    fn split_parts() {
+        fn part<'a>(s: &'a str, delim: &str, n: i64) -> &'a str {
+            split_part_str(s, delim, n).unwrap()
+        }
+        assert_eq!(part("a,b,c", ",", 1), "a");
+        assert_eq!(part("a,b,c", ",", 3), "c");
+        assert_eq!(part("a,b,c", ",", 4), "");
+        assert_eq!(part("a,b,c", ",", -1), "c");
+        assert_eq!(part("a,b,c", ",", -3), "a");
+        assert_eq!(part("a,b,c", ",", -4), "");
+        assert_eq!(part("a::b", "::", 2), "b");
+        assert_eq!(part("abc", "", 1), "abc");
+        assert_eq!(part("abc", "", 2), "");
+        assert!(split_part_str("a,b", ",", 0).is_err());
+    }
+
+    #[test]
+    fn regex_cache() {
+        let mut cache = RegexCache::default();
+        for p in &["a+", "b+", "a+", "b+"] {
+            assert!(cache.get(p, "TEST").unwrap().is_match(&p[..1]));
+        }
+        assert_eq!(cache.regexes.len(), 2);
+        assert!(cache.get("(", "TEST").is_err());
+        assert!(cache
+            .get_with("10\\%", "TEST", like_to_regex)
+            .unwrap()
+            .is_match("10%"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(has_escapes(&Expr::Literal(ScalarValue::Utf8(Some(
+            "10\\%".to_string()
+        )))));
+        assert!(!has_escapes(&Expr::Literal(ScalarValue::Utf8(Some(
+            "10%".to_string()
+        )))));
+        assert!(!has_escapes(&Expr::Column(Column::from_name("pattern"))));
+    }
+
+    #[test]
+    fn like_patterns() {
+        let matches = |s: &str, p: &str| Regex::new(&like_to_regex(p)).unwrap().is_match(s);
+        assert!(matches("abc", "a%"));
+        assert!(matches("abc", "_b_"));
+        assert!(!matches("abc", "b%"));
+        assert!(matches("10%", r"10\%"));
+        assert!(!matches("100", r"10\%"));
+        assert!(matches("a_b", r"a\_b"));
+        assert!(!matches("axb", r"a\_b"));
+        assert!(matches(r"a\b", r"a\\b"));
+        assert!(matches("a.b*", "a.b*"));
+        assert!(!matches("axb", "a.b"));
+        assert!(matches("line\nbreak", "line%"));
+    }
+}
@@ -0,0 +1,108 @@
+use crate::queryplanner::optimizations::rewrite_plan::{rewrite_plan, PlanRewriter};
+use crate::queryplanner::udfs::{scalar_udf_by_kind, CubeScalarUDFKind};
+use datafusion::error::DataFusionError;
+use datafusion::execution::context::ExecutionProps;
+use datafusion::logical_plan::{Expr, ExprRewriter, LogicalPlan};
+use datafusion::optimizer::optimizer::OptimizerRule;
+use datafusion::optimizer::utils::from_plan;
+use datafusion::physical_plan::functions::BuiltinScalarFunction;
+use datafusion::scalar::ScalarValue;
+use itertools::Itertools;
+use std::sync::Arc;
+
+/// DataFusion resolves `convert_tz` and `date_trunc` to its built-ins before looking at our
+/// functions. The built-ins only know fixed offsets and lack quarters, so calls that need
+/// IANA time zones or quarters are replaced with `CONVERT_TZ` and `DATE_TRUNC` from [udfs].
+///
+/// [udfs]: crate::queryplanner::udfs
+pub struct ResolveTimeZones;
+impl OptimizerRule for ResolveTimeZones {
+    fn optimize(
+        &self,
+        plan: &LogicalPlan,
+        _execution_props: &ExecutionProps,
+    ) -> Result<LogicalPlan, DataFusionError> {
+        return rewrite_plan(plan, &(), &mut Rewriter {});
+
+        #[derive(Clone)]
+        struct Rewriter {}
+        impl ExprRewriter for Rewriter {
+            fn mutate(&mut self, expr: Expr) -> Result<Expr, DataFusionError> {
+                match expr {
+                    Expr::ScalarFunction {
+                        fun: BuiltinScalarFunction::ConvertTz,
+                        args,
+                    } if args.len() == 2
+                        && string_literal(&args[1]).map_or(false, |tz| !is_fixed_offset(tz)) =>
+                    {
+                        Ok(cube_udf(CubeScalarUDFKind::ConvertTz, args))
+                    }
+                    Expr::ScalarFunction {
+                        fun: BuiltinScalarFunction::DateTrunc,
+                        args,
+                    } if args.len() == 2
+                        && string_literal(&args[0])
+                            .map_or(false, |g| g.eq_ignore_ascii_case("quarter")) =>
+                    {
+                        Ok(cube_udf(CubeScalarUDFKind::DateTrunc, args))
+                    }
+                    _ => Ok(expr),
+                }
+            }
+        }
+
+        impl PlanRewriter for Rewriter {
+            type Context = ();
+
+            fn rewrite(&mut self, n: LogicalPlan, _: &()) -> Result<LogicalPlan, DataFusionError> {
+                let mut exprs = n.expressions();
+                for e in &mut exprs {
+                    *e = std::mem::replace(e, Expr::Wildcard).rewrite(self)?
+                }
+                from_plan(&n, &exprs, &n.inputs().into_iter().cloned().collect_vec())
+            }
+        }
+    }
+
+    fn name(&self) -> &str {
+        "resolve_time_zones"
+    }
+}
+
+fn cube_udf(kind: CubeScalarUDFKind, args: Vec<Expr>) -> Expr {
+    Expr::ScalarUDF {
+        fun: Arc::new(scalar_udf_by_kind(kind).descriptor()),
+        args,
+    }
+}
+
+fn string_literal(e: &Expr) -> Option<&str> {
+    match e {
+        Expr::Literal(ScalarValue::Utf8(Some(s))) => Some(s),
+        _ => None,
+    }
+}
+
+/// Offsets in the form of `+HH:MM` or `-HH:MM`.
+fn is_fixed_offset(tz: &str) -> bool {
+    let b = tz.as_bytes();
+    b.len() == 6
+        && (b[0] == b'+' || b[0] == b'-')
+        && b[1..3].iter().all(u8::is_ascii_digit)
+        && b[3] == b':'
+        && b[4..6].iter().all(u8::is_ascii_digit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_offsets() {
+        assert!(is_fixed_offset("+00:00"));
+        assert!(is_fixed_offset("-05:30"));
+        assert!(!is_fixed_offset("UTC"));
+        assert!(!is_fixed_offset("America/New_York"));
+        assert!(!is_fixed_offset("+5:00"));
+    }
+}
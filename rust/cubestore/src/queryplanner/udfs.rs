@@ -1,9 +1,14 @@
 use crate::queryplanner::coalesce::{coalesce, SUPPORTED_COALESCE_TYPES};
 use crate::queryplanner::hll::Hll;
+use crate::queryplanner::strings;
 use crate::CubeError;
 use arrow::array::{Array, BinaryArray, TimestampNanosecondArray, UInt64Builder};
 use arrow::datatypes::{DataType, IntervalUnit, TimeUnit};
-use chrono::{TimeZone, Utc};
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone,
+    Timelike, Utc,
+};
+use chrono_tz::Tz;
 use datafusion::cube_ext::datetime::{date_addsub_array, date_addsub_scalar};
 use datafusion::error::DataFusionError;
 use datafusion::physical_plan::functions::Signature;
@@ -24,6 +29,17 @@ pub enum CubeScalarUDFKind {
     UnixTimestamp,
     DateAdd,
     DateSub,
+    ConvertTz,
+    DateTrunc,
+    Substring,
+    SubstringIndex,
+    Locate,
+    Instr,
+    RegexpLike,
+    RegexpSubstr,
+    RegexpReplace,
+    SplitPart,
+    Like, // LIKE with escapes, see `RewriteEscapedLike`.
 }
 
 pub trait CubeScalarUDF {
@@ -40,6 +56,17 @@ pub fn scalar_udf_by_kind(k: CubeScalarUDFKind) -> Box<dyn CubeScalarUDF> {
         CubeScalarUDFKind::UnixTimestamp => Box::new(UnixTimestamp {}),
         CubeScalarUDFKind::DateAdd => Box::new(DateAddSub { is_add: true }),
         CubeScalarUDFKind::DateSub => Box::new(DateAddSub { is_add: false }),
+        CubeScalarUDFKind::ConvertTz => Box::new(ConvertTz {}),
+        CubeScalarUDFKind::DateTrunc => Box::new(DateTrunc {}),
+        k @ CubeScalarUDFKind::Substring
+        | k @ CubeScalarUDFKind::SubstringIndex
+        | k @ CubeScalarUDFKind::Locate
+        | k @ CubeScalarUDFKind::Instr
+        | k @ CubeScalarUDFKind::RegexpLike
+        | k @ CubeScalarUDFKind::RegexpSubstr
+        | k @ CubeScalarUDFKind::RegexpReplace
+        | k @ CubeScalarUDFKind::SplitPart
+        | k @ CubeScalarUDFKind::Like => Box::new(StringFunction { kind: k }),
     }
 }
 
@@ -63,6 +90,39 @@ pub fn scalar_kind_by_name(n: &str) -> Option<CubeScalarUDFKind> {
     if n == "DATE_SUB" {
         return Some(CubeScalarUDFKind::DateSub);
     }
+    if n == "CONVERT_TZ" {
+        return Some(CubeScalarUDFKind::ConvertTz);
+    }
+    if n == "DATE_TRUNC" {
+        return Some(CubeScalarUDFKind::DateTrunc);
+    }
+    if n == "SUBSTRING" {
+        return Some(CubeScalarUDFKind::Substring);
+    }
+    if n == "SUBSTRING_INDEX" {
+        return Some(CubeScalarUDFKind::SubstringIndex);
+    }
+    if n == "LOCATE" {
+        return Some(CubeScalarUDFKind::Locate);
+    }
+    if n == "INSTR" {
+        return Some(CubeScalarUDFKind::Instr);
+    }
+    if n == "REGEXP_LIKE" {
+        return Some(CubeScalarUDFKind::RegexpLike);
+    }
+    if n == "REGEXP_SUBSTR" {
+        return Some(CubeScalarUDFKind::RegexpSubstr);
+    }
+    if n == "REGEXP_REPLACE" {
+        return Some(CubeScalarUDFKind::RegexpReplace);
+    }
+    if n == "SPLIT_PART" {
+        return Some(CubeScalarUDFKind::SplitPart);
+    }
+    if n == "LIKE" {
+        return Some(CubeScalarUDFKind::Like);
+    }
     return None;
 }
 
@@ -207,6 +267,17 @@ impl DateAddSub {
                 DataType::Timestamp(TimeUnit::Nanosecond, None),
                 DataType::Interval(IntervalUnit::DayTime),
             ]),
+            // The third argument is a time zone, arithmetic is done in its local time.
+            Signature::Exact(vec![
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                DataType::Interval(IntervalUnit::YearMonth),
+                DataType::Utf8,
+            ]),
+            Signature::Exact(vec![
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                DataType::Interval(IntervalUnit::DayTime),
+                DataType::Utf8,
+            ]),
         ])
     }
 }
@@ -242,7 +313,7 @@ impl CubeScalarUDF for DateAddSub {
                 Ok(Arc::new(DataType::Timestamp(TimeUnit::Nanosecond, None)))
             }),
             fun: Arc::new(move |inputs| {
-                assert_eq!(inputs.len(), 2);
+                assert!(inputs.len() == 2 || inputs.len() == 3);
                 let interval = match &inputs[1] {
                     ColumnarValue::Scalar(i) => i.clone(),
                     _ => {
@@ -254,6 +325,14 @@ impl CubeScalarUDF for DateAddSub {
                         )));
                     }
                 };
+                if inputs.len() == 3 {
+                    let tz = time_zone_argument(&inputs[2], name)?;
+                    return map_timestamps(&inputs[0], name, |t| {
+                        let local = Utc.from_utc_datetime(&to_local(t, &tz));
+                        let r = date_addsub_scalar(local, interval.clone(), is_add)?;
+                        Ok(from_local(&r.naive_utc(), &tz).timestamp_nanos())
+                    });
+                }
                 match &inputs[0] {
                     ColumnarValue::Scalar(ScalarValue::TimestampNanosecond(None)) => Ok(
                         ColumnarValue::Scalar(ScalarValue::TimestampNanosecond(None)),
@@ -285,6 +364,253 @@ impl CubeScalarUDF for DateAddSub {
     }
 }
 
+/// Shifts UTC timestamps to the wall-clock time of an IANA time zone, e.g. `America/New_York`.
+/// Fixed offsets like `+03:00` are handled by the DataFusion built-in, see [ResolveTimeZones].
+///
+/// [ResolveTimeZones]: crate::queryplanner::time_zones::ResolveTimeZones
+struct ConvertTz {}
+impl ConvertTz {
+    fn signature() -> Signature {
+        Signature::Exact(vec![
+            DataType::Timestamp(TimeUnit::Nanosecond, None),
+            DataType::Utf8,
+        ])
+    }
+}
+impl CubeScalarUDF for ConvertTz {
+    fn kind(&self) -> CubeScalarUDFKind {
+        CubeScalarUDFKind::ConvertTz
+    }
+
+    fn name(&self) -> &str {
+        "CONVERT_TZ"
+    }
+
+    fn descriptor(&self) -> ScalarUDF {
+        return ScalarUDF {
+            name: self.name().to_string(),
+            signature: Self::signature(),
+            return_type: Arc::new(|_| {
+                Ok(Arc::new(DataType::Timestamp(TimeUnit::Nanosecond, None)))
+            }),
+            fun: Arc::new(|inputs| {
+                assert_eq!(inputs.len(), 2);
+                let tz = time_zone_argument(&inputs[1], "CONVERT_TZ")?;
+                map_timestamps(&inputs[0], "CONVERT_TZ", |t| {
+                    Ok(to_local(t, &tz).timestamp_nanos())
+                })
+            }),
+        };
+    }
+}
+
+/// Same as the DataFusion built-in `date_trunc`, but also supports quarters.
+/// Timestamps are truncated as is, so pass them through `CONVERT_TZ` to truncate in local time.
+struct DateTrunc {}
+impl DateTrunc {
+    fn signature() -> Signature {
+        Signature::Exact(vec![
+            DataType::Utf8,
+            DataType::Timestamp(TimeUnit::Nanosecond, None),
+        ])
+    }
+}
+impl CubeScalarUDF for DateTrunc {
+    fn kind(&self) -> CubeScalarUDFKind {
+        CubeScalarUDFKind::DateTrunc
+    }
+
+    fn name(&self) -> &str {
+        "DATE_TRUNC"
+    }
+
+    fn descriptor(&self) -> ScalarUDF {
+        return ScalarUDF {
+            name: self.name().to_string(),
+            signature: Self::signature(),
+            return_type: Arc::new(|_| {
+                Ok(Arc::new(DataType::Timestamp(TimeUnit::Nanosecond, None)))
+            }),
+            fun: Arc::new(|inputs| {
+                assert_eq!(inputs.len(), 2);
+                let granularity = match &inputs[0] {
+                    ColumnarValue::Scalar(ScalarValue::Utf8(Some(g))) => g.to_lowercase(),
+                    _ => {
+                        return Err(DataFusionError::Execution(
+                            "Granularity of `DATE_TRUNC` must be a non-null string literal"
+                                .to_string(),
+                        ))
+                    }
+                };
+                map_timestamps(&inputs[1], "DATE_TRUNC", |t| {
+                    Ok(truncate(&Utc.timestamp_nanos(t).naive_utc(), &granularity)?
+                        .timestamp_nanos())
+                })
+            }),
+        };
+    }
+}
+
+fn truncate(t: &NaiveDateTime, granularity: &str) -> Result<NaiveDateTime, DataFusionError> {
+    let d = t.date();
+    let start_of_month = |month| NaiveDate::from_ymd(d.year(), month, 1).and_hms(0, 0, 0);
+    Ok(match granularity {
+        "second" => d.and_hms(t.hour(), t.minute(), t.second()),
+        "minute" => d.and_hms(t.hour(), t.minute(), 0),
+        "hour" => d.and_hms(t.hour(), 0, 0),
+        "day" => d.and_hms(0, 0, 0),
+        "week" => (d - Duration::days(d.weekday().num_days_from_monday() as i64)).and_hms(0, 0, 0),
+        "month" => start_of_month(d.month()),
+        "quarter" => start_of_month(d.month0() / 3 * 3 + 1),
+        "year" => start_of_month(1),
+        _ => {
+            return Err(DataFusionError::Execution(format!(
+                "Unsupported granularity in `DATE_TRUNC`: {}",
+                granularity
+            )))
+        }
+    })
+}
+
+fn time_zone_argument(v: &ColumnarValue, fun: &str) -> Result<Tz, DataFusionError> {
+    match v {
+        ColumnarValue::Scalar(ScalarValue::Utf8(Some(tz))) => tz.parse::<Tz>().map_err(|e| {
+            DataFusionError::Execution(format!("Invalid time zone in `{}`: {}", fun, e))
+        }),
+        _ => Err(DataFusionError::Execution(format!(
+            "Time zone in `{}` must be a non-null string literal",
+            fun
+        ))),
+    }
+}
+
+/// Wall-clock time in `tz` at the UTC timestamp `t`.
+fn to_local(t: i64, tz: &Tz) -> NaiveDateTime {
+    Utc.timestamp_nanos(t).with_timezone(tz).naive_local()
+}
+
+/// Inverse of [to_local]. Times repeated by a DST transition resolve to the earlier instant,
+/// times skipped by it are shifted forward by the length of the gap.
+fn from_local(local: &NaiveDateTime, tz: &Tz) -> DateTime<Utc> {
+    match tz.from_local_datetime(local) {
+        LocalResult::Single(t) => t.with_timezone(&Utc),
+        LocalResult::Ambiguous(earliest, _) => earliest.with_timezone(&Utc),
+        LocalResult::None => {
+            // Use the offset in effect before the gap.
+            let offset = tz
+                .offset_from_utc_datetime(&(*local - Duration::days(1)))
+                .fix();
+            Utc.from_utc_datetime(&(*local - offset))
+        }
+    }
+}
+
+/// Applies `f` to the non-null values of a timestamp argument.
+fn map_timestamps(
+    v: &ColumnarValue,
+    fun: &str,
+    f: impl Fn(i64) -> Result<i64, DataFusionError>,
+) -> Result<ColumnarValue, DataFusionError> {
+    match v {
+        ColumnarValue::Scalar(ScalarValue::TimestampNanosecond(t)) => Ok(ColumnarValue::Scalar(
+            ScalarValue::TimestampNanosecond(t.map(&f).transpose()?),
+        )),
+        ColumnarValue::Array(a) if a.as_any().is::<TimestampNanosecondArray>() => {
+            let a = a
+                .as_any()
+                .downcast_ref::<TimestampNanosecondArray>()
+                .unwrap();
+            let r = a
+                .iter()
+                .map(|t| t.map(&f).transpose())
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(ColumnarValue::Array(Arc::new(
+                TimestampNanosecondArray::from_opt_vec(r, None),
+            )))
+        }
+        _ => Err(DataFusionError::Execution(format!(
+            "Timestamp argument of `{}` must be a timestamp",
+            fun
+        ))),
+    }
+}
+
+/// String functions that DataFusion lacks, implemented in [strings].
+///
+/// [strings]: crate::queryplanner::strings
+struct StringFunction {
+    kind: CubeScalarUDFKind,
+}
+impl StringFunction {
+    fn signature(&self) -> Signature {
+        let (s, i) = (DataType::Utf8, DataType::Int64);
+        match self.kind {
+            CubeScalarUDFKind::Substring => Signature::OneOf(vec![
+                Signature::Exact(vec![s.clone(), i.clone()]),
+                Signature::Exact(vec![s, i.clone(), i]),
+            ]),
+            CubeScalarUDFKind::SubstringIndex | CubeScalarUDFKind::SplitPart => {
+                Signature::Exact(vec![s.clone(), s, i])
+            }
+            CubeScalarUDFKind::RegexpReplace => Signature::Exact(vec![s.clone(), s.clone(), s]),
+            CubeScalarUDFKind::Locate => Signature::OneOf(vec![
+                Signature::Exact(vec![s.clone(), s.clone()]),
+                Signature::Exact(vec![s.clone(), s, i]),
+            ]),
+            _ => Signature::Exact(vec![s.clone(), s]),
+        }
+    }
+
+    fn return_type(&self) -> DataType {
+        match self.kind {
+            CubeScalarUDFKind::Locate | CubeScalarUDFKind::Instr => DataType::Int64,
+            CubeScalarUDFKind::RegexpLike | CubeScalarUDFKind::Like => DataType::Boolean,
+            _ => DataType::Utf8,
+        }
+    }
+}
+impl CubeScalarUDF for StringFunction {
+    fn kind(&self) -> CubeScalarUDFKind {
+        self.kind
+    }
+
+    fn name(&self) -> &str {
+        match self.kind {
+            CubeScalarUDFKind::Substring => "SUBSTRING",
+            CubeScalarUDFKind::SubstringIndex => "SUBSTRING_INDEX",
+            CubeScalarUDFKind::Locate => "LOCATE",
+            CubeScalarUDFKind::Instr => "INSTR",
+            CubeScalarUDFKind::RegexpLike => "REGEXP_LIKE",
+            CubeScalarUDFKind::RegexpSubstr => "REGEXP_SUBSTR",
+            CubeScalarUDFKind::RegexpReplace => "REGEXP_REPLACE",
+            CubeScalarUDFKind::SplitPart => "SPLIT_PART",
+            CubeScalarUDFKind::Like => "LIKE",
+            k => panic!("{:?} is not a string function", k),
+        }
+    }
+
+    fn descriptor(&self) -> ScalarUDF {
+        let return_type = Arc::new(self.return_type());
+        return ScalarUDF {
+            name: self.name().to_string(),
+            signature: self.signature(),
+            return_type: Arc::new(move |_| Ok(return_type.clone())),
+            fun: Arc::new(match self.kind {
+                CubeScalarUDFKind::Substring => strings::substring,
+                CubeScalarUDFKind::SubstringIndex => strings::substring_index,
+                CubeScalarUDFKind::Locate => strings::locate,
+                CubeScalarUDFKind::Instr => strings::instr,
+                CubeScalarUDFKind::RegexpLike => strings::regexp_like,
+                CubeScalarUDFKind::RegexpSubstr => strings::regexp_substr,
+                CubeScalarUDFKind::RegexpReplace => strings::regexp_replace,
+                CubeScalarUDFKind::SplitPart => strings::split_part,
+                CubeScalarUDFKind::Like => strings::like,
+                k => panic!("{:?} is not a string function", k),
+            }),
+        };
+    }
+}
+
 struct HllCardinality {}
 impl CubeScalarUDF for HllCardinality {
     fn kind(&self) -> CubeScalarUDFKind {
@@ -1,13 +1,14 @@
 use crate::cluster::Cluster;
 use crate::config::ConfigObj;
-use crate::metastore::job::{Job, JobType};
-use crate::metastore::{MetaStore, MetaStoreEvent, RowKey, TableId};
+use crate::metastore::job::{Job, JobStatus, JobType};
+use crate::metastore::{IdRow, MetaStore, MetaStoreEvent, RowKey, TableId};
 use crate::remotefs::RemoteFs;
 use crate::store::{ChunkStore, WALStore};
 use crate::CubeError;
 use datafusion::cube_ext;
 use flatbuffers::bitflags::_core::time::Duration;
 use log::error;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::broadcast::Receiver;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
@@ -25,6 +26,8 @@ pub struct SchedulerImpl {
     gc_loop: Mutex<DataGCLoop>,
     gc_sender: UnboundedSender<GCTimedTask>,
     config: Arc<dyn ConfigObj>,
+    /// Consecutive failures of retried jobs.
+    job_failures: Mutex<HashMap<(RowKey, JobType), u32>>,
 }
 
 crate::di_service!(SchedulerImpl, []);
@@ -50,6 +53,7 @@ impl SchedulerImpl {
             gc_loop: Mutex::new(gc_loop),
             gc_sender,
             config,
+            job_failures: Mutex::new(HashMap::new()),
         }
     }
 
@@ -159,11 +163,21 @@ impl SchedulerImpl {
                 }
             }
         }
+        if let MetaStoreEvent::Insert(TableId::Indexes, row_id) = event {
+            let index = self.meta_store.get_index(row_id).await?;
+            if !index.get_row().is_ready() {
+                self.schedule_index_build(row_id).await?;
+            }
+        }
         if let MetaStoreEvent::Insert(TableId::Tables, row_id) = event {
             let table = self.meta_store.get_table_by_id(row_id).await?;
             if let Some(locations) = table.get_row().locations() {
                 self.schedule_table_import(row_id, &locations).await?;
             }
+            if let Some(source) = table.get_row().stream_source() {
+                self.schedule_stream_ingestion(row_id, &source.topic)
+                    .await?;
+            }
         }
         if let MetaStoreEvent::Delete(TableId::WALs, row_id) = event {
             let file = self
@@ -172,16 +186,26 @@ impl SchedulerImpl {
                 .await?;
             tokio::fs::remove_file(file).await?;
         }
-        if let MetaStoreEvent::Delete(TableId::Chunks, row_id) = event {
-            self.remote_fs
-                .delete_file(ChunkStore::chunk_remote_path(row_id).as_str())
-                .await?
+        if let MetaStoreEvent::DeleteChunk(chunk) = &event {
+            let file_name = ChunkStore::chunk_remote_path(chunk.get_id());
+            if chunk.get_row().active() {
+                // Chunks of dropped tables, e.g. swapped out ones, can still be read by queries
+                // planned before the drop.
+                let deadline = Instant::now() + Duration::from_secs(self.config.not_used_timeout());
+                self.gc_sender
+                    .send(GCTimedTask(deadline, GCTask::RemoveRemoteFile(file_name)))?;
+            } else {
+                self.remote_fs.delete_file(file_name.as_str()).await?
+            }
         }
         if let MetaStoreEvent::DeletePartition(partition) = &event {
             // remove file only if partition is active otherwise it should be removed when it's deactivated
             if partition.get_row().is_active() {
                 if let Some(file_name) = partition.get_row().get_full_name(partition.get_id()) {
-                    self.remote_fs.delete_file(file_name.as_str()).await?;
+                    let deadline =
+                        Instant::now() + Duration::from_secs(self.config.not_used_timeout());
+                    self.gc_sender
+                        .send(GCTimedTask(deadline, GCTask::RemoveRemoteFile(file_name)))?;
                 }
             }
         }
@@ -199,6 +223,27 @@ impl SchedulerImpl {
                 }
             }
         }
+        if let MetaStoreEvent::DeleteTable(table) = &event {
+            if let Some(source) = table.get_row().stream_source() {
+                // Stops the ingestion loop and closes the consumer.
+                self.schedule_stream_ingestion(table.get_id(), &source.topic)
+                    .await?;
+            }
+        }
+        if let MetaStoreEvent::UpdateJob(_, job) = &event {
+            match job.get_row().status() {
+                JobStatus::Completed => {
+                    self.job_failures.lock().await.remove(&Self::job_key(job));
+                }
+                JobStatus::Error(_) | JobStatus::Timeout => match job.get_row().job_type() {
+                    JobType::StreamIngestion | JobType::IndexBuild => {
+                        self.retry_job(job).await?;
+                    }
+                    _ => {}
+                },
+                JobStatus::Scheduled(_) | JobStatus::ProcessingBy(_) => {}
+            }
+        }
         if let MetaStoreEvent::DeleteJob(job) = event {
             if let JobType::Repartition = job.get_row().job_type() {
                 if let RowKey::Table(TableId::Partitions, partition_id) =
@@ -223,6 +268,48 @@ impl SchedulerImpl {
         Ok(())
     }
 
+    fn job_key(job: &IdRow<Job>) -> (RowKey, JobType) {
+        (
+            job.get_row().row_reference().clone(),
+            job.get_row().job_type().clone(),
+        )
+    }
+
+    /// Reschedules a failed job after a delay that doubles with each consecutive failure.
+    async fn retry_job(&self, job: &IdRow<Job>) -> Result<(), CubeError> {
+        let failures = {
+            let mut job_failures = self.job_failures.lock().await;
+            let failures = job_failures.entry(Self::job_key(job)).or_insert(0);
+            *failures += 1;
+            *failures
+        };
+        let delay = Duration::from_secs(1 << (failures - 1).min(8));
+        self.meta_store.delete_job(job.get_id()).await?;
+        log::warn!("Retrying job in {:?}: {:?}", delay, job);
+        tokio::time::sleep(delay).await;
+
+        match (job.get_row().job_type(), job.get_row().row_reference()) {
+            (JobType::StreamIngestion, RowKey::Table(TableId::Tables, table_id)) => {
+                // The table might have been dropped in the meantime.
+                if let Ok(table) = self.meta_store.get_table_by_id(*table_id).await {
+                    if let Some(source) = table.get_row().stream_source() {
+                        self.schedule_stream_ingestion(*table_id, &source.topic)
+                            .await?;
+                    }
+                }
+            }
+            (JobType::IndexBuild, RowKey::Table(TableId::Indexes, index_id)) => {
+                if let Ok(index) = self.meta_store.get_index(*index_id).await {
+                    if !index.get_row().is_ready() {
+                        self.schedule_index_build(*index_id).await?;
+                    }
+                }
+            }
+            (job_type, row_key) => panic!("Unexpected job to retry: {:?} {:?}", job_type, row_key),
+        }
+        Ok(())
+    }
+
     async fn schedule_repartition(&self, partition_id: u64) -> Result<(), CubeError> {
         let node = self.cluster.node_name_by_partitions(&[partition_id]);
         let job = self
@@ -263,6 +350,46 @@ impl SchedulerImpl {
         Ok(())
     }
 
+    async fn schedule_stream_ingestion(&self, table_id: u64, topic: &str) -> Result<(), CubeError> {
+        let node = self.cluster.node_name_for_import(table_id, topic).await?;
+        let job = self
+            .meta_store
+            .add_job(Job::new(
+                RowKey::Table(TableId::Tables, table_id),
+                JobType::StreamIngestion,
+                node.to_string(),
+            ))
+            .await?;
+        if job.is_some() {
+            // TODO queue failover
+            self.cluster.notify_job_runner(node).await?;
+        }
+        Ok(())
+    }
+
+    async fn schedule_index_build(&self, index_id: u64) -> Result<(), CubeError> {
+        let partitions = self
+            .meta_store
+            .get_active_partitions_by_index_id(index_id)
+            .await?;
+        let node = self
+            .cluster
+            .node_name_by_partitions(&partitions.iter().map(|p| p.get_id()).collect::<Vec<_>>());
+        let job = self
+            .meta_store
+            .add_job(Job::new(
+                RowKey::Table(TableId::Indexes, index_id),
+                JobType::IndexBuild,
+                node.to_string(),
+            ))
+            .await?;
+        if job.is_some() {
+            // TODO queue failover
+            self.cluster.notify_job_runner(node).await?;
+        }
+        Ok(())
+    }
+
     async fn schedule_wal_to_process(&self, wal_id: u64) -> Result<(), CubeError> {
         let wal_node_name = self.cluster.server_name().to_string(); // TODO move to WAL
         let job = self
@@ -297,13 +424,28 @@ impl SchedulerImpl {
         Ok(())
     }
 
+    /// Warms up every replica of the partition. A failed replica doesn't stop the others, it
+    /// downloads the file on its first select instead. Fails only if no replica was warmed up.
     async fn schedule_partition_warmup(
         &self,
         partition_id: u64,
         path: String,
     ) -> Result<(), CubeError> {
-        let node_name = self.cluster.node_name_by_partitions(&[partition_id]);
-        self.cluster.warmup_download(&node_name, path).await
+        let mut last_error = None;
+        let mut warmed_up = false;
+        for node_name in self.cluster.node_names_by_partitions(&[partition_id]) {
+            match self.cluster.warmup_download(&node_name, path.clone()).await {
+                Ok(()) => warmed_up = true,
+                Err(e) => {
+                    error!("Warmup of {} on {} failed: {}", path, node_name, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        match last_error {
+            Some(e) if !warmed_up => Err(e),
+            _ => Ok(()),
+        }
     }
 }
 
@@ -315,7 +457,7 @@ enum GCTask {
     DeleteChunk(/*chunk_id*/ u64),
 }
 
-/// Cleans up deactivated partitions and chunks on remote fs.
+/// Cleans up deactivated partitions and chunks, and data of dropped tables on remote fs.
 /// Ensures enough time has passed that queries over those files finish.
 struct DataGCLoop {
     metastore: Arc<dyn MetaStore>,
@@ -12,7 +12,7 @@ use chrono::format::Item::{Fixed, Literal, Numeric, Space};
 use chrono::format::Numeric::{Day, Hour, Minute, Month, Second, Year};
 use chrono::format::Pad::Zero;
 use chrono::format::Parsed;
-use chrono::{ParseResult, Utc};
+use chrono::{NaiveDate, NaiveTime, ParseResult, TimeZone, Utc};
 use datafusion::cube_ext;
 use datafusion::physical_plan::ExecutionPlan;
 use datafusion::sql::parser::Statement as DFStatement;
@@ -40,17 +40,20 @@ use crate::config::injection::DIService;
 use crate::import::limits::ConcurrencyLimits;
 use crate::import::Ingestion;
 use crate::metastore::job::JobType;
+use crate::metastore::query_pattern::{QueryPattern, QueryPatternKind};
 use crate::metastore::{
     is_valid_plain_binary_hll, table::Table, HllFlavour, IdRow, ImportFormat, Index, IndexDef,
-    MetaStoreTable, RowKey, Schema, TableId,
+    MetaStoreTable, RowKey, Schema, StreamFormat, StreamSource, StreamSourceKind, TableId,
+};
+use crate::queryplanner::query_executor::{
+    arrow_to_column_type, batch_to_dataframe, QueryExecutor,
 };
-use crate::queryplanner::query_executor::{batch_to_dataframe, QueryExecutor};
 use crate::queryplanner::{QueryPlan, QueryPlanner};
 use crate::remotefs::RemoteFs;
 use crate::sql::cache::SqlResultCache;
 use crate::sql::parser::CubeStoreParser;
 use crate::store::ChunkDataStore;
-use crate::table::{data, Row, TableValue, TimestampValue};
+use crate::table::{data, DateValue, Row, TableValue, TimestampValue};
 use crate::util::decimal::Decimal;
 use crate::util::strings::path_to_string;
 use crate::CubeError;
@@ -74,6 +77,14 @@ pub trait SqlService: DIService + Send + Sync {
         query: &str,
     ) -> Result<Arc<DataFrame>, CubeError>;
 
+    /// Result columns of `query` from its plan, without running it. Empty for statements other
+    /// than selects.
+    async fn describe_query(
+        &self,
+        context: SqlQueryContext,
+        query: &str,
+    ) -> Result<Vec<Column>, CubeError>;
+
     /// Exposed only for tests. Worker plan created as if all partitions are on the same worker.
     async fn plan_query(&self, query: &str) -> Result<QueryPlans, CubeError>;
 
@@ -154,9 +165,45 @@ impl SqlServiceImpl {
         columns: &Vec<ColumnDef>,
         external: bool,
         locations: Option<Vec<String>>,
+        with_options: &Vec<SqlOption>,
         indexes: Vec<Statement>,
     ) -> Result<IdRow<Table>, CubeError> {
         let columns_to_set = convert_columns_type(columns)?;
+        let (stream_source, batch_id) = table_options(with_options)?;
+        if external && stream_source.is_some() {
+            return Err(CubeError::user(
+                "Table can't have both locations and a stream source".to_string(),
+            ));
+        }
+        if !external && batch_id.is_some() {
+            return Err(CubeError::user(
+                "Option 'batch_id' is only supported for tables with locations".to_string(),
+            ));
+        }
+        if let Some(batch_id) = &batch_id {
+            // Re-submitting a table with the same batch id, e.g. on retries, has no effect.
+            let existing = self
+                .db
+                .get_tables_with_path()
+                .await?
+                .iter()
+                .find(|t| {
+                    t.schema.get_row().get_name() == &schema_name
+                        && t.table.get_row().get_table_name() == &table_name
+                })
+                .map(|t| t.table.clone());
+            if let Some(table) = existing {
+                if table.get_row().batch_id().as_ref() == Some(batch_id) {
+                    if !table.get_row().is_ready() {
+                        return Err(CubeError::user(format!(
+                            "Batch '{}' of table {}.{} is still being imported",
+                            batch_id, schema_name, table_name
+                        )));
+                    }
+                    return Ok(table);
+                }
+            }
+        }
         let mut indexes_to_create = Vec::new();
         for index in indexes.iter() {
             if let Statement::CreateIndex { name, columns, .. } = index {
@@ -188,6 +235,8 @@ impl SqlServiceImpl {
                     columns_to_set,
                     None,
                     None,
+                    stream_source,
+                    None,
                     indexes_to_create,
                     true,
                 )
@@ -203,6 +252,8 @@ impl SqlServiceImpl {
                 columns_to_set,
                 locations,
                 Some(ImportFormat::CSV),
+                None,
+                batch_id,
                 indexes_to_create,
                 false,
             )
@@ -291,6 +342,7 @@ impl SqlServiceImpl {
         table_name: String,
         columns: &'a Vec<Ident>,
         data: &'a Vec<Vec<Expr>>,
+        batch_id: Option<String>,
     ) -> Result<u64, CubeError> {
         let table = self
             .db
@@ -319,7 +371,11 @@ impl SqlServiceImpl {
             self.chunk_store.clone(),
             self.limits.clone(),
             table.clone(),
+            batch_id,
         );
+        if ingestion.is_batch_ingested().await? {
+            return Ok(0);
+        }
         for rows_chunk in data.chunks(self.rows_per_chunk) {
             let rows = parse_chunk(rows_chunk, &real_col)?;
             ingestion.queue_data_frame(rows).await?;
@@ -328,6 +384,89 @@ impl SqlServiceImpl {
         Ok(data.len() as u64)
     }
 
+    async fn show_nodes(&self) -> Result<DataFrame, CubeError> {
+        let columns = vec![
+            Column::new("node_name".to_string(), ColumnType::String, 0),
+            Column::new("role".to_string(), ColumnType::String, 1),
+            Column::new("configured".to_string(), ColumnType::Boolean, 2),
+            Column::new("healthy".to_string(), ColumnType::Boolean, 3),
+            Column::new("last_heart_beat".to_string(), ColumnType::Timestamp, 4),
+        ];
+        let mut rows = vec![Row::new(vec![
+            TableValue::String(self.cluster.server_name().to_string()),
+            TableValue::String("router".to_string()),
+            TableValue::Boolean(true),
+            TableValue::Boolean(true),
+            TableValue::Null,
+        ])];
+        for node in self.cluster.nodes_status().await? {
+            if node.node_name == self.cluster.server_name() {
+                continue;
+            }
+            rows.push(Row::new(vec![
+                TableValue::String(node.node_name),
+                TableValue::String("worker".to_string()),
+                TableValue::Boolean(node.configured),
+                TableValue::Boolean(node.healthy),
+                node.last_heart_beat
+                    .map(|t| TableValue::Timestamp(TimestampValue::new(t.timestamp_nanos())))
+                    .unwrap_or(TableValue::Null),
+            ]));
+        }
+        Ok(DataFrame::new(columns, rows))
+    }
+
+    /// Lists query patterns recorded by the planner that no index, including ones that are still
+    /// being built, is sorted on. Most frequent patterns go first.
+    async fn show_index_recommendations(&self) -> Result<DataFrame, CubeError> {
+        let columns = vec![
+            Column::new("schema".to_string(), ColumnType::String, 0),
+            Column::new("table".to_string(), ColumnType::String, 1),
+            Column::new("kind".to_string(), ColumnType::String, 2),
+            Column::new("columns".to_string(), ColumnType::String, 3),
+            Column::new("queries".to_string(), ColumnType::Int, 4),
+            Column::new("last_seen".to_string(), ColumnType::Timestamp, 5),
+            Column::new("create_index".to_string(), ColumnType::String, 6),
+        ];
+        let tables = self.db.get_tables_with_path().await?;
+        let mut patterns = self.db.get_query_patterns().await?;
+        patterns.sort_by_key(|p| std::cmp::Reverse(p.get_row().query_count()));
+        let mut rows = Vec::new();
+        for p in patterns {
+            let p = p.get_row();
+            let table = match tables.iter().find(|t| t.table.get_id() == p.table_id()) {
+                Some(t) => t,
+                None => continue,
+            };
+            let indexes = self.db.get_table_indexes(p.table_id()).await?;
+            if indexes
+                .iter()
+                .any(|i| index_covers_query_pattern(i.get_row(), p))
+            {
+                continue;
+            }
+            let schema_name = table.schema.get_row().get_name();
+            let table_name = table.table.get_row().get_table_name();
+            rows.push(Row::new(vec![
+                TableValue::String(schema_name.clone()),
+                TableValue::String(table_name.clone()),
+                TableValue::String(format!("{:?}", p.kind())),
+                TableValue::String(p.columns().join(", ")),
+                TableValue::Int(p.query_count() as i64),
+                TableValue::Timestamp(TimestampValue::new(p.last_seen().timestamp_nanos())),
+                TableValue::String(format!(
+                    "CREATE INDEX {}_{} ON {}.{} ({})",
+                    table_name,
+                    p.columns().join("_"),
+                    schema_name,
+                    table_name,
+                    p.columns().join(", ")
+                )),
+            ]));
+        }
+        Ok(DataFrame::new(columns, rows))
+    }
+
     async fn dump_select_inputs(
         &self,
         query: &str,
@@ -434,13 +573,7 @@ impl SqlService for SqlServiceImpl {
         // trace!("AST is: {:?}", ast);
         match ast {
             CubeStoreStatement::Statement(Statement::ShowVariable { variable }) => {
-                if variable.len() != 1 {
-                    return Err(CubeError::user(format!(
-                        "Only one variable supported in SHOW, but got {}",
-                        variable.len()
-                    )));
-                }
-                match variable[0].value.to_lowercase() {
+                match variable.iter().map(|v| v.value.to_lowercase()).join(" ") {
                     s if s == "schemas" => {
                         Ok(Arc::new(DataFrame::from(self.db.get_schemas().await?)))
                     }
@@ -456,6 +589,10 @@ impl SqlService for SqlServiceImpl {
                     s if s == "partitions" => Ok(Arc::new(DataFrame::from(
                         self.db.partition_table().all_rows().await?,
                     ))),
+                    s if s == "nodes" => Ok(Arc::new(self.show_nodes().await?)),
+                    s if s == "index recommendations" => {
+                        Ok(Arc::new(self.show_index_recommendations().await?))
+                    }
                     x => Err(CubeError::user(format!("Unknown SHOW: {}", x))),
                 }
             }
@@ -476,6 +613,7 @@ impl SqlService for SqlServiceImpl {
                         name,
                         columns,
                         external,
+                        with_options,
                         ..
                     },
                 indexes,
@@ -498,6 +636,7 @@ impl SqlService for SqlServiceImpl {
                         &columns,
                         external,
                         locations,
+                        &with_options,
                         indexes,
                     )
                     .await?;
@@ -557,12 +696,37 @@ impl SqlService for SqlServiceImpl {
                 }
                 Ok(Arc::new(DataFrame::new(vec![], vec![])))
             }
-            CubeStoreStatement::Statement(Statement::Insert {
+            CubeStoreStatement::SwapTables {
                 table_name,
-                columns,
-                source,
-                ..
-            }) => {
+                other_table_name,
+            } => {
+                let (nv, other_nv) = (&table_name.0, &other_table_name.0);
+                if nv.len() != 2 || other_nv.len() != 2 {
+                    return Err(CubeError::user(format!(
+                        "Schema's name should be present in query (boo.table1). Your query was '{}'",
+                        query
+                    )));
+                }
+                self.db
+                    .swap_tables(
+                        nv[0].value.clone(),
+                        nv[1].value.clone(),
+                        other_nv[0].value.clone(),
+                        other_nv[1].value.clone(),
+                    )
+                    .await?;
+                Ok(Arc::new(DataFrame::new(vec![], vec![])))
+            }
+            CubeStoreStatement::Insert {
+                insert:
+                    Statement::Insert {
+                        table_name,
+                        columns,
+                        source,
+                        ..
+                    },
+                batch_id,
+            } => {
                 let data = if let SetExpr::Values(Values(data_series)) = &source.body {
                     data_series
                 } else {
@@ -579,8 +743,14 @@ impl SqlService for SqlServiceImpl {
                 let schema_name = &nv[0].value;
                 let table_name = &nv[1].value;
 
-                self.insert_data(schema_name.clone(), table_name.clone(), &columns, data)
-                    .await?;
+                self.insert_data(
+                    schema_name.clone(),
+                    table_name.clone(),
+                    &columns,
+                    data,
+                    batch_id,
+                )
+                .await?;
                 Ok(Arc::new(DataFrame::new(vec![], vec![])))
             }
             CubeStoreStatement::Statement(Statement::Query(q)) => {
@@ -637,6 +807,47 @@ impl SqlService for SqlServiceImpl {
         }
     }
 
+    async fn describe_query(
+        &self,
+        _context: SqlQueryContext,
+        query: &str,
+    ) -> Result<Vec<Column>, CubeError> {
+        if let Some(data_frame) = SqlServiceImpl::handle_workbench_queries(query) {
+            return Ok(data_frame.get_columns().clone());
+        }
+        let ast = {
+            let replaced_quote = query.replace("\\'", "''");
+            let mut parser = CubeStoreParser::new(&replaced_quote)?;
+            parser.parse_statement()?
+        };
+        let logical_plan = match ast {
+            CubeStoreStatement::Statement(Statement::Query(q)) => {
+                self.query_planner
+                    .logical_plan(DFStatement::Statement(Statement::Query(q)))
+                    .await?
+            }
+            _ => return Ok(vec![]),
+        };
+        let logical_plan = match logical_plan {
+            QueryPlan::Meta(logical_plan) => logical_plan,
+            // Same as the router plan, partitions aren't needed for the schema.
+            QueryPlan::Select(serialized, _) => serialized.logical_plan(&HashMap::new())?,
+        };
+        logical_plan
+            .schema()
+            .fields()
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                Ok(Column::new(
+                    field.name().clone(),
+                    arrow_to_column_type(field.data_type().clone())?,
+                    i,
+                ))
+            })
+            .collect()
+    }
+
     async fn plan_query(&self, q: &str) -> Result<QueryPlans, CubeError> {
         let ast = {
             let replaced_quote = q.replace("\\'", "''");
@@ -712,6 +923,91 @@ impl SqlService for SqlServiceImpl {
     }
 }
 
+/// Mirrors how the planner picks indices: joins and group by need the columns to be a prefix of the
+/// sort key in the same order, filters prune partitions only by the first sort column.
+fn index_covers_query_pattern(index: &Index, pattern: &QueryPattern) -> bool {
+    let sort_key = index
+        .get_columns()
+        .iter()
+        .take(index.sort_key_size() as usize)
+        .map(|c| c.get_name())
+        .collect_vec();
+    match pattern.kind() {
+        QueryPatternKind::Join | QueryPatternKind::GroupBy => {
+            pattern.columns().len() <= sort_key.len()
+                && pattern
+                    .columns()
+                    .iter()
+                    .zip(sort_key.iter())
+                    .all(|(p, i)| p == *i)
+        }
+        QueryPatternKind::Filter => sort_key
+            .first()
+            .map(|first| pattern.columns().contains(*first))
+            .unwrap_or(false),
+    }
+}
+
+/// Reads the batch id of imports from `WITH (batch_id = '...')` and the stream source from the
+/// remaining options.
+fn table_options(
+    options: &Vec<SqlOption>,
+) -> Result<(Option<StreamSource>, Option<String>), CubeError> {
+    let mut values = HashMap::new();
+    for o in options {
+        let value = match &o.value {
+            Value::SingleQuotedString(v) => v.to_string(),
+            v => {
+                return Err(CubeError::user(format!(
+                    "Expected string value for option '{}' but found: {}",
+                    o.name, v
+                )))
+            }
+        };
+        values.insert(o.name.value.to_lowercase(), value);
+    }
+    let batch_id = values.remove("batch_id");
+    Ok((stream_source_from_options(values)?, batch_id))
+}
+
+/// Reads `WITH (source = 'kafka', brokers = '...', topic = '...', format = 'json')`.
+fn stream_source_from_options(
+    mut values: HashMap<String, String>,
+) -> Result<Option<StreamSource>, CubeError> {
+    if values.is_empty() {
+        return Ok(None);
+    }
+    let mut take = |name: &str| {
+        values.remove(name).ok_or(CubeError::user(format!(
+            "Option '{}' is required for stream source",
+            name
+        )))
+    };
+    let kind = match take("source")?.to_lowercase().as_str() {
+        "kafka" => StreamSourceKind::Kafka,
+        s => return Err(CubeError::user(format!("Unsupported stream source: {}", s))),
+    };
+    let brokers = take("brokers")?;
+    let topic = take("topic")?;
+    let format = match values.remove("format") {
+        None => StreamFormat::JSON,
+        Some(f) if f.eq_ignore_ascii_case("json") => StreamFormat::JSON,
+        Some(f) => return Err(CubeError::user(format!("Unsupported stream format: {}", f))),
+    };
+    if let Some(name) = values.keys().next() {
+        return Err(CubeError::user(format!(
+            "Unexpected option for stream source: {}",
+            name
+        )));
+    }
+    Ok(Some(StreamSource {
+        kind,
+        brokers,
+        topic,
+        format,
+    }))
+}
+
 fn convert_columns_type(columns: &Vec<ColumnDef>) -> Result<Vec<Column>, CubeError> {
     let mut rolupdb_columns = Vec::new();
 
@@ -719,8 +1015,8 @@ fn convert_columns_type(columns: &Vec<ColumnDef>) -> Result<Vec<Column>, CubeErr
         let cube_col = Column::new(
             col.name.value.clone(),
             match &col.data_type {
-                DataType::Date
-                | DataType::Time
+                DataType::Date => ColumnType::Date,
+                DataType::Time
                 | DataType::Char(_)
                 | DataType::Varchar(_)
                 | DataType::Clob(_)
@@ -733,20 +1029,26 @@ fn convert_columns_type(columns: &Vec<ColumnDef>) -> Result<Vec<Column>, CubeErr
                 | DataType::Bytea
                 | DataType::Array(_) => ColumnType::Bytes,
                 DataType::Decimal(precision, scale) => {
-                    let mut precision = precision.unwrap_or(18);
-                    let mut scale = scale.unwrap_or(5);
-                    if precision > 18 {
-                        precision = 18;
-                    }
-                    if scale > 5 {
-                        scale = 10;
+                    let scale = scale.unwrap_or(5);
+                    let precision = precision.unwrap_or(18).max(scale);
+                    if precision > 38 {
+                        return Err(CubeError::user(format!(
+                            "Decimal precision of column '{}' is {}, at most 38 is supported",
+                            col.name.value, precision
+                        )));
                     }
-                    if scale > precision {
-                        precision = scale;
-                    }
-                    ColumnType::Decimal {
-                        precision: precision as i32,
-                        scale: scale as i32,
+                    // 64-bit decimals only have scales up to 5 and 10, others are stored as
+                    // 128-bit to keep the declared scale.
+                    if precision <= 18 && (scale <= 5 || scale == 10) {
+                        ColumnType::Decimal {
+                            precision: precision as i32,
+                            scale: scale as i32,
+                        }
+                    } else {
+                        ColumnType::Decimal128 {
+                            precision: precision as i32,
+                            scale: scale as i32,
+                        }
                     }
                 }
                 DataType::SmallInt | DataType::Int | DataType::BigInt | DataType::Interval => {
@@ -759,6 +1061,8 @@ fn convert_columns_type(columns: &Vec<ColumnDef>) -> Result<Vec<Column>, CubeErr
                     let custom_type_name = custom.to_string().to_lowercase();
                     match custom_type_name.as_str() {
                         "mediumint" => ColumnType::Int,
+                        // `int` stays 64-bit, 32-bit columns are declared explicitly
+                        "int32" => ColumnType::Int32,
                         "bytes" => ColumnType::Bytes,
                         "varbinary" => ColumnType::Bytes,
                         "hyperloglog" => ColumnType::HyperLogLog(HllFlavour::Airlift),
@@ -932,29 +1236,46 @@ fn extract_data<'a>(
                 builder.append_null()?;
                 return Ok(());
             }
-            let val_int = match cell {
-                Expr::Value(Value::Number(v, _)) | Expr::Value(Value::SingleQuotedString(v)) => {
-                    v.parse::<i64>()
-                }
-                Expr::UnaryOp {
-                    op: UnaryOperator::Minus,
-                    expr,
-                } => {
-                    if let Expr::Value(Value::Number(v, _)) = expr.as_ref() {
-                        v.parse::<i64>().map(|v| v * -1)
-                    } else {
-                        return Err(CubeError::user(format!("Can't parse int from, {:?}", cell)));
-                    }
+            builder.append_value(parse_int(cell)?)?;
+        }
+        ColumnType::Int32 => {
+            let builder = builder.as_any_mut().downcast_mut::<Int32Builder>().unwrap();
+            if is_null {
+                builder.append_null()?;
+                return Ok(());
+            }
+            let v = i32::try_from(parse_int(cell)?).map_err(|_| {
+                CubeError::user(format!("Int value is out of range for int32: {:?}", cell))
+            })?;
+            builder.append_value(v)?;
+        }
+        ColumnType::Date => {
+            let builder = builder
+                .as_any_mut()
+                .downcast_mut::<Date32Builder>()
+                .unwrap();
+            if is_null {
+                builder.append_null()?;
+                return Ok(());
+            }
+            match cell {
+                Expr::Value(Value::SingleQuotedString(v)) => {
+                    builder.append_value(date_from_string(v)?.days_since_epoch())?;
                 }
-                _ => return Err(CubeError::user(format!("Can't parse int from, {:?}", cell))),
-            };
-            if let Err(e) = val_int {
-                return Err(CubeError::user(format!(
-                    "Can't parse int from, {:?}: {}",
-                    cell, e
-                )));
+                x => return Err(CubeError::user(format!("Can't parse date from, {:?}", x))),
+            }
+        }
+        t @ ColumnType::Decimal128 { .. } => {
+            let builder = builder
+                .as_any_mut()
+                .downcast_mut::<DecimalBuilder>()
+                .unwrap();
+            if is_null {
+                builder.append_null()?;
+                return Ok(());
             }
-            builder.append_value(val_int.unwrap())?;
+            let scale = u8::try_from(t.target_scale()).unwrap();
+            builder.append_value(parse_decimal_i128(cell, scale)?.raw_value_i128())?;
         }
         t @ ColumnType::Decimal { .. } => {
             let scale = u8::try_from(t.target_scale()).unwrap();
@@ -1117,6 +1438,29 @@ pub fn timestamp_from_string(v: &str) -> Result<TimestampValue, CubeError> {
     Ok(TimestampValue::new(nanos))
 }
 
+/// Accepts dates as `YYYY-MM-DD` and timestamps at midnight UTC.
+pub fn date_from_string(v: &str) -> Result<DateValue, CubeError> {
+    let date = match NaiveDate::parse_from_str(v, "%Y-%m-%d") {
+        Ok(d) => d,
+        Err(_) => {
+            let ts = Utc.timestamp_nanos(timestamp_from_string(v)?.get_time_stamp());
+            if ts.time() != NaiveTime::from_hms(0, 0, 0) {
+                return Err(CubeError::user(format!(
+                    "Can't parse date, time must be midnight: {}",
+                    v
+                )));
+            }
+            ts.naive_utc().date()
+        }
+    };
+    let days_since_epoch = date
+        .signed_duration_since(NaiveDate::from_ymd(1970, 1, 1))
+        .num_days();
+    Ok(DateValue::new(i32::try_from(days_since_epoch).map_err(
+        |_| CubeError::user(format!("Date is out of range: {}", v)),
+    )?))
+}
+
 fn parse_time(s: &str, format: &[chrono::format::Item]) -> ParseResult<Parsed> {
     let mut p = Parsed::new();
     chrono::format::parse(&mut p, s, format.into_iter())?;
@@ -1138,6 +1482,42 @@ fn parse_float(cell: &Expr) -> Result<f64, CubeError> {
         ))),
     }
 }
+fn parse_int(cell: &Expr) -> Result<i64, CubeError> {
+    let val_int = match cell {
+        Expr::Value(Value::Number(v, _)) | Expr::Value(Value::SingleQuotedString(v)) => {
+            v.parse::<i64>()
+        }
+        Expr::UnaryOp {
+            op: UnaryOperator::Minus,
+            expr,
+        } => {
+            if let Expr::Value(Value::Number(v, _)) = expr.as_ref() {
+                v.parse::<i64>().map(|v| v * -1)
+            } else {
+                return Err(CubeError::user(format!("Can't parse int from, {:?}", cell)));
+            }
+        }
+        _ => return Err(CubeError::user(format!("Can't parse int from, {:?}", cell))),
+    };
+    val_int.map_err(|e| CubeError::user(format!("Can't parse int from, {:?}: {}", cell, e)))
+}
+
+fn parse_decimal_i128(cell: &Expr, scale: u8) -> Result<Decimal, CubeError> {
+    match cell {
+        Expr::Value(Value::Number(v, _)) | Expr::Value(Value::SingleQuotedString(v)) => {
+            crate::import::parse_decimal_i128(v, scale)
+        }
+        Expr::UnaryOp {
+            op: UnaryOperator::Minus,
+            expr: box Expr::Value(Value::Number(v, _)),
+        } => Ok(crate::import::parse_decimal_i128(v, scale)?.negate()),
+        _ => Err(CubeError::user(format!(
+            "Can't parse decimal from, {:?}",
+            cell
+        ))),
+    }
+}
+
 fn parse_decimal(cell: &Expr, scale: u8) -> Result<Decimal, CubeError> {
     match cell {
         Expr::Value(Value::Number(v, _)) | Expr::Value(Value::SingleQuotedString(v)) => {
@@ -1287,6 +1667,7 @@ mod tests {
                 TableValue::String("false".to_string()),
                 TableValue::String("true".to_string()),
                 TableValue::String(meta_store.get_table("Foo".to_string(), "Persons".to_string()).await.unwrap().get_row().created_at().as_ref().unwrap().to_string()),
+                TableValue::String("NULL".to_string()),
             ]));
         }
         let _ = DB::destroy(&Options::default(), path);
@@ -1467,6 +1848,63 @@ mod tests {
         }).await;
     }
 
+    #[tokio::test]
+    async fn shuffle_join() {
+        Config::test("shuffle_join").update_config(|mut c| {
+            c.broadcast_join_max_rows = 2;
+            c
+        }).start_test(async move |services| {
+            let service = services.sql_service;
+
+            service.exec_query("CREATE SCHEMA foo").await.unwrap();
+            // Default indices are sorted on `id`, so neither side is sorted on the join key.
+            service.exec_query("CREATE TABLE foo.a1 (id int, k int)").await.unwrap();
+            service.exec_query("CREATE TABLE foo.a2 (id int, k int)").await.unwrap();
+            service.exec_query("CREATE TABLE foo.b (id int, k int, v text)").await.unwrap();
+            service.exec_query(
+                "INSERT INTO foo.a1 (id, k) VALUES (1, 1), (2, 2), (3, 3)"
+            ).await.unwrap();
+            service.exec_query(
+                "INSERT INTO foo.a2 (id, k) VALUES (4, 4), (5, 1)"
+            ).await.unwrap();
+            service.exec_query(
+                "INSERT INTO foo.b (id, k, v) VALUES (10, 1, 'x'), (11, 2, 'y'), (12, 2, 'z'), (13, 7, 'w')"
+            ).await.unwrap();
+
+            // Neither side has at most 2 rows to broadcast and the left one reads two partitions,
+            // so both are shuffled by `k`.
+            let a = "(SELECT * FROM foo.a1 UNION ALL SELECT * FROM foo.a2) a";
+            let result = service.exec_query(&format!(
+                "SELECT a.id, b.v FROM {} JOIN foo.b b ON a.k = b.k ORDER BY 1, 2", a
+            )).await.unwrap();
+            assert_eq!(
+                result.get_rows(),
+                &vec![
+                    Row::new(vec![TableValue::Int(1), TableValue::String("x".to_string())]),
+                    Row::new(vec![TableValue::Int(2), TableValue::String("y".to_string())]),
+                    Row::new(vec![TableValue::Int(2), TableValue::String("z".to_string())]),
+                    Row::new(vec![TableValue::Int(5), TableValue::String("x".to_string())]),
+                ]
+            );
+
+            // Every row of the preserved side is in a single bucket and appears once.
+            let result = service.exec_query(&format!(
+                "SELECT a.id, b.v FROM {} LEFT JOIN foo.b b ON a.k = b.k ORDER BY 1, 2", a
+            )).await.unwrap();
+            assert_eq!(
+                result.get_rows(),
+                &vec![
+                    Row::new(vec![TableValue::Int(1), TableValue::String("x".to_string())]),
+                    Row::new(vec![TableValue::Int(2), TableValue::String("y".to_string())]),
+                    Row::new(vec![TableValue::Int(2), TableValue::String("z".to_string())]),
+                    Row::new(vec![TableValue::Int(3), TableValue::Null]),
+                    Row::new(vec![TableValue::Int(4), TableValue::Null]),
+                    Row::new(vec![TableValue::Int(5), TableValue::String("x".to_string())]),
+                ]
+            );
+        }).await;
+    }
+
     #[tokio::test]
     async fn high_frequency_inserts() {
         Config::test("high_frequency_inserts")
@@ -1738,6 +2176,41 @@ mod tests {
             .await
     }
 
+    #[tokio::test]
+    async fn show_nodes() {
+        Config::test("show_nodes")
+            .update_config(|mut c| {
+                c.select_workers = vec!["127.0.0.1:24406".to_string()];
+                c.metastore_bind_address = Some("127.0.0.1:25406".to_string());
+                c.heart_beat_interval = 1;
+                c.heart_beat_timeout = 1;
+                c
+            })
+            .start_test(async move |services| {
+                let service = services.sql_service;
+                let nodes = service.exec_query("SHOW NODES").await.unwrap();
+                assert_eq!(
+                    nodes.get_rows()[1].values()[..3].to_vec(),
+                    vec![
+                        TableValue::String("127.0.0.1:24406".to_string()),
+                        TableValue::String("worker".to_string()),
+                        TableValue::Boolean(true),
+                    ]
+                );
+
+                // Worker never sends heart beats.
+                Delay::new(Duration::from_millis(2500)).await;
+                let nodes = service.exec_query("SHOW NODES").await.unwrap();
+                assert_eq!(nodes.get_rows().len(), 2);
+                assert_eq!(
+                    nodes.get_rows()[0].values()[1],
+                    TableValue::String("router".to_string())
+                );
+                assert_eq!(nodes.get_rows()[1].values()[3], TableValue::Boolean(false));
+            })
+            .await
+    }
+
     #[tokio::test]
     async fn cluster() {
         Config::test("cluster_router").update_config(|mut config| {
@@ -2003,6 +2476,41 @@ mod tests {
             assert_eq!(result.get_rows(), &vec![Row::new(vec![TableValue::Int(5)])]);
         }).await;
     }
+
+    #[tokio::test]
+    async fn describe_query() {
+        Config::run_test("describe_query", async move |services| {
+            let service = services.sql_service;
+
+            let _ = service.exec_query("CREATE SCHEMA foo").await.unwrap();
+            let _ = service
+                .exec_query("CREATE TABLE foo.orders (id int, city text)")
+                .await
+                .unwrap();
+
+            let columns = service
+                .describe_query(
+                    SqlQueryContext::default(),
+                    "SELECT city, count(*) as cnt FROM foo.orders WHERE city = '' GROUP BY 1",
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                columns
+                    .iter()
+                    .map(|c| (c.get_name().as_str(), c.get_column_type().clone()))
+                    .collect::<Vec<_>>(),
+                vec![("city", ColumnType::String), ("cnt", ColumnType::Int)]
+            );
+
+            let columns = service
+                .describe_query(SqlQueryContext::default(), "CREATE SCHEMA bar")
+                .await
+                .unwrap();
+            assert!(columns.is_empty());
+        })
+        .await;
+    }
 }
 
 impl SqlServiceImpl {
@@ -37,6 +37,16 @@ pub enum Statement {
         schema_name: ObjectName,
         if_not_exists: bool,
     },
+    /// `ALTER TABLE <table_name> SWAP WITH <other_table_name>`.
+    SwapTables {
+        table_name: ObjectName,
+        other_table_name: ObjectName,
+    },
+    Insert {
+        insert: SQLStatement,
+        /// Set by `BATCH ID '<id>'` at the end of the statement.
+        batch_id: Option<String>,
+    },
     Dump(Box<Query>),
 }
 
@@ -61,6 +71,11 @@ impl<'a> CubeStoreParser<'a> {
                     self.parser.next_token();
                     self.parse_create()
                 }
+                Keyword::INSERT => self.parse_insert(),
+                Keyword::ALTER => {
+                    self.parser.next_token();
+                    self.parse_alter()
+                }
                 _ if w.value.eq_ignore_ascii_case("dump") => {
                     self.parser.next_token();
                     let s = self.parser.parse_statement()?;
@@ -80,6 +95,50 @@ impl<'a> CubeStoreParser<'a> {
         }
     }
 
+    pub fn parse_insert(&mut self) -> Result<Statement, ParserError> {
+        let insert = self.parser.parse_statement()?;
+        let batch_id = if self.parse_custom_token("batch") {
+            if !self.parse_custom_token("id") {
+                return Err(ParserError::ParserError(format!(
+                    "Expected ID after BATCH, found: {}",
+                    self.parser.peek_token()
+                )));
+            }
+            Some(self.parser.parse_literal_string()?)
+        } else {
+            None
+        };
+        Ok(Statement::Insert { insert, batch_id })
+    }
+
+    pub fn parse_alter(&mut self) -> Result<Statement, ParserError> {
+        self.parser.expect_keyword(Keyword::TABLE)?;
+        let table_name = self.parser.parse_object_name()?;
+        if !self.parse_custom_token("swap") {
+            return Err(ParserError::ParserError(format!(
+                "Expected SWAP after ALTER TABLE {}, found: {}",
+                table_name,
+                self.parser.peek_token()
+            )));
+        }
+        self.parser.expect_keyword(Keyword::WITH)?;
+        let other_table_name = self.parser.parse_object_name()?;
+        Ok(Statement::SwapTables {
+            table_name,
+            other_table_name,
+        })
+    }
+
+    fn parse_custom_token(&mut self, token: &str) -> bool {
+        if let Token::Word(w) = self.parser.peek_token() {
+            if w.value.eq_ignore_ascii_case(token) {
+                self.parser.next_token();
+                return true;
+            }
+        }
+        false
+    }
+
     pub fn parse_create(&mut self) -> Result<Statement, ParserError> {
         if self.parser.parse_keyword(Keyword::SCHEMA) {
             self.parse_create_schema()
@@ -86,6 +86,24 @@ impl CompactionService for CompactionServiceImpl {
             .get_partition_for_compaction(partition_id)
             .await?;
         let partition_id = partition.get_id();
+        // Sources of an index being built are compacted once they are copied to the index.
+        let is_index_build_source = self
+            .meta_store
+            .get_table_indexes(index.get_row().table_id())
+            .await?
+            .iter()
+            .filter_map(|i| i.get_row().build_sources())
+            .any(|s| {
+                s.partition_ids.contains(&partition_id)
+                    || chunks.iter().any(|c| s.chunk_ids.contains(&c.get_id()))
+            });
+        if is_index_build_source {
+            log::debug!(
+                "Skipping compaction of partition {} used to build an index",
+                partition_id
+            );
+            return Ok(());
+        }
         let chunks_row_count = chunks
             .iter()
             .map(|c| c.get_row().get_row_count())
@@ -446,6 +464,8 @@ mod tests {
                 cols.clone(),
                 None,
                 None,
+                None,
+                None,
                 vec![],
                 true,
             )
@@ -192,6 +192,8 @@ pub trait ChunkDataStore: DIService + Send + Sync {
         columns: &[Column],
     ) -> Result<Vec<ChunkUploadJob>, CubeError>;
     async fn repartition(&self, partition_id: u64) -> Result<(), CubeError>;
+    /// Copies data written before the index was created and makes the index available to queries.
+    async fn build_index(&self, index_id: u64) -> Result<(), CubeError>;
     async fn get_chunk_columns(&self, chunk: IdRow<Chunk>) -> Result<Vec<RecordBatch>, CubeError>;
     async fn delete_remote_chunk(&self, chunk: IdRow<Chunk>) -> Result<(), CubeError>;
 }
@@ -353,6 +355,49 @@ impl ChunkDataStore for ChunkStore {
         Ok(())
     }
 
+    async fn build_index(&self, index_id: u64) -> Result<(), CubeError> {
+        let index = self.meta_store.get_index(index_id).await?;
+        let sources = match index.get_row().build_sources() {
+            Some(sources) => sources.clone(),
+            None => return Ok(()),
+        };
+        let default_index = self
+            .meta_store
+            .get_default_index(index.get_row().table_id())
+            .await?;
+
+        // Sources are read one by one to limit memory usage.
+        let mut new_chunks = Vec::new();
+        for partition_id in sources.partition_ids {
+            let partition = self.meta_store.get_partition(partition_id).await?;
+            if let Some(remote_path) = partition.get_row().get_full_name(partition_id) {
+                let local_file = self.remote_fs.download_file(&remote_path).await?;
+                let parquet =
+                    ParquetTableStore::new(default_index.get_row().clone(), ROW_GROUP_SIZE);
+                let batches =
+                    cube_ext::spawn_blocking(move || parquet.read_columns(&local_file)).await??;
+                self.copy_to_index(batches, &default_index, &index, &mut new_chunks)
+                    .await?;
+            }
+        }
+        for chunk_id in sources.chunk_ids {
+            let chunk = self.meta_store.get_chunk(chunk_id).await?;
+            let batches = self.get_chunk_columns(chunk).await?;
+            self.copy_to_index(batches, &default_index, &index, &mut new_chunks)
+                .await?;
+        }
+        let new_chunk_ids: Result<Vec<u64>, CubeError> = join_all(new_chunks)
+            .await
+            .into_iter()
+            .map(|c| Ok(c??.get_id()))
+            .collect();
+
+        self.meta_store
+            .index_built(index_id, new_chunk_ids?)
+            .await?;
+        Ok(())
+    }
+
     async fn get_chunk_columns(&self, chunk: IdRow<Chunk>) -> Result<Vec<RecordBatch>, CubeError> {
         let (local_file, index) = self.download_chunk(chunk).await?;
         Ok(cube_ext::spawn_blocking(move || -> Result<_, CubeError> {
@@ -458,6 +503,8 @@ mod tests {
                     col.clone(),
                     None,
                     None,
+                    None,
+                    None,
                     Vec::new(),
                     true,
                 )
@@ -533,6 +580,8 @@ mod tests {
                     col.clone(),
                     None,
                     None,
+                    None,
+                    None,
                     vec![],
                     true,
                 )
@@ -672,6 +721,24 @@ impl ChunkStore {
         }))
     }
 
+    async fn copy_to_index(
+        &self,
+        batches: Vec<RecordBatch>,
+        from: &IdRow<Index>,
+        to: &IdRow<Index>,
+        new_chunks: &mut Vec<ChunkUploadJob>,
+    ) -> Result<(), CubeError> {
+        for batch in batches {
+            let columns = remap_columns(
+                batch.columns(),
+                from.get_row().columns(),
+                to.get_row().columns(),
+            )?;
+            new_chunks.append(&mut self.partition_rows(to.get_id(), columns).await?);
+        }
+        Ok(())
+    }
+
     /// Returns a list of newly added chunks.
     async fn build_index_chunks(
         &self,
@@ -0,0 +1,81 @@
+use crate::metastore::StreamSource;
+use crate::streaming::{LogBroker, LogConsumer, LogRecord};
+use crate::CubeError;
+use async_trait::async_trait;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::{ClientConfig, Message, Offset, TopicPartitionList};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+pub struct KafkaLogBroker;
+
+crate::di_service!(KafkaLogBroker, [LogBroker]);
+
+impl KafkaLogBroker {
+    pub fn new() -> Arc<KafkaLogBroker> {
+        Arc::new(KafkaLogBroker)
+    }
+}
+
+impl LogBroker for KafkaLogBroker {
+    fn consumer(
+        &self,
+        source: &StreamSource,
+        group_id: &str,
+    ) -> Result<Arc<dyn LogConsumer>, CubeError> {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", &source.brokers)
+            .set("group.id", group_id)
+            // Offsets are committed only after the data is persisted.
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "earliest")
+            .create()?;
+        consumer.subscribe(&[source.topic.as_str()])?;
+        Ok(Arc::new(KafkaConsumer {
+            consumer: Arc::new(consumer),
+            topic: source.topic.clone(),
+        }))
+    }
+}
+
+struct KafkaConsumer {
+    consumer: Arc<StreamConsumer>,
+    topic: String,
+}
+
+#[async_trait]
+impl LogConsumer for KafkaConsumer {
+    async fn poll(
+        &self,
+        max_records: usize,
+        timeout: Duration,
+    ) -> Result<Vec<LogRecord>, CubeError> {
+        let deadline = Instant::now() + timeout;
+        let mut records = Vec::new();
+        while records.len() < max_records {
+            let m = match tokio::time::timeout_at(deadline, self.consumer.recv()).await {
+                Ok(m) => m?,
+                Err(_) => break,
+            };
+            records.push(LogRecord {
+                partition: m.partition(),
+                offset: m.offset(),
+                payload: m.payload().unwrap_or_default().to_vec(),
+            });
+        }
+        Ok(records)
+    }
+
+    async fn commit(&self, next_offsets: &HashMap<i32, i64>) -> Result<(), CubeError> {
+        let mut offsets = TopicPartitionList::new();
+        for (partition, offset) in next_offsets {
+            offsets.add_partition_offset(&self.topic, *partition, Offset::Offset(*offset))?;
+        }
+        let consumer = self.consumer.clone();
+        // Synchronous commit blocks until the broker responds.
+        tokio::task::spawn_blocking(move || consumer.commit(&offsets, CommitMode::Sync)).await??;
+        Ok(())
+    }
+}
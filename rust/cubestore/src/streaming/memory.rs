@@ -0,0 +1,146 @@
+use crate::metastore::StreamSource;
+use crate::streaming::{LogBroker, LogConsumer, LogRecord};
+use crate::CubeError;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// In-process stand-in for Kafka used in tests.
+pub struct InMemoryLogBroker {
+    state: Arc<Mutex<State>>,
+}
+
+crate::di_service!(InMemoryLogBroker, [LogBroker]);
+
+#[derive(Default)]
+struct State {
+    /// Records of each partition of each topic.
+    topics: HashMap<String, HashMap<i32, Vec<Vec<u8>>>>,
+    /// Next offsets to read by topic, consumer group and partition.
+    committed: HashMap<(String, String, i32), i64>,
+    /// Number of consumers that were not closed yet.
+    open_consumers: usize,
+    /// Number of upcoming polls that will fail.
+    failing_polls: usize,
+}
+
+impl InMemoryLogBroker {
+    pub fn new() -> Arc<InMemoryLogBroker> {
+        Arc::new(InMemoryLogBroker {
+            state: Arc::new(Mutex::new(State::default())),
+        })
+    }
+
+    pub fn produce(&self, topic: &str, partition: i32, payload: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .topics
+            .entry(topic.to_string())
+            .or_default()
+            .entry(partition)
+            .or_default()
+            .push(payload.as_bytes().to_vec());
+    }
+
+    pub fn committed(&self, topic: &str, group_id: &str, partition: i32) -> Option<i64> {
+        self.state
+            .lock()
+            .unwrap()
+            .committed
+            .get(&(topic.to_string(), group_id.to_string(), partition))
+            .cloned()
+    }
+
+    pub fn open_consumers(&self) -> usize {
+        self.state.lock().unwrap().open_consumers
+    }
+
+    pub fn fail_polls(&self, n: usize) {
+        self.state.lock().unwrap().failing_polls = n;
+    }
+}
+
+impl LogBroker for InMemoryLogBroker {
+    fn consumer(
+        &self,
+        source: &StreamSource,
+        group_id: &str,
+    ) -> Result<Arc<dyn LogConsumer>, CubeError> {
+        self.state.lock().unwrap().open_consumers += 1;
+        Ok(Arc::new(InMemoryConsumer {
+            state: self.state.clone(),
+            topic: source.topic.clone(),
+            group_id: group_id.to_string(),
+            positions: Mutex::new(HashMap::new()),
+        }))
+    }
+}
+
+struct InMemoryConsumer {
+    state: Arc<Mutex<State>>,
+    topic: String,
+    group_id: String,
+    /// Next offsets to return, starting from the committed ones.
+    positions: Mutex<HashMap<i32, i64>>,
+}
+
+impl Drop for InMemoryConsumer {
+    fn drop(&mut self) {
+        self.state.lock().unwrap().open_consumers -= 1;
+    }
+}
+
+#[async_trait]
+impl LogConsumer for InMemoryConsumer {
+    async fn poll(
+        &self,
+        max_records: usize,
+        timeout: Duration,
+    ) -> Result<Vec<LogRecord>, CubeError> {
+        let mut records = Vec::new();
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.failing_polls != 0 {
+                state.failing_polls -= 1;
+                return Err(CubeError::internal("Poll failed".to_string()));
+            }
+            let mut positions = self.positions.lock().unwrap();
+            if let Some(partitions) = state.topics.get(&self.topic) {
+                for (partition, data) in partitions {
+                    let position = positions.entry(*partition).or_insert_with(|| {
+                        state
+                            .committed
+                            .get(&(self.topic.clone(), self.group_id.clone(), *partition))
+                            .cloned()
+                            .unwrap_or(0)
+                    });
+                    while (*position as usize) < data.len() && records.len() < max_records {
+                        records.push(LogRecord {
+                            partition: *partition,
+                            offset: *position,
+                            payload: data[*position as usize].clone(),
+                        });
+                        *position += 1;
+                    }
+                }
+            }
+        }
+        if records.is_empty() {
+            tokio::time::sleep(timeout).await;
+        }
+        Ok(records)
+    }
+
+    async fn commit(&self, next_offsets: &HashMap<i32, i64>) -> Result<(), CubeError> {
+        let mut state = self.state.lock().unwrap();
+        for (partition, offset) in next_offsets {
+            state.committed.insert(
+                (self.topic.clone(), self.group_id.clone(), *partition),
+                *offset,
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,567 @@
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(test)]
+mod memory;
+
+use crate::cluster::import_node_name;
+use crate::config::injection::DIService;
+use crate::config::ConfigObj;
+use crate::import::limits::ConcurrencyLimits;
+use crate::import::{parse_table_value, Ingestion};
+use crate::metastore::table::Table;
+use crate::metastore::{Column, IdRow, MetaStore, StreamFormat, StreamSource};
+use crate::store::ChunkDataStore;
+use crate::table::data::{append_row, create_array_builders};
+use crate::table::{Row, TableValue};
+use crate::util::maybe_owned::MaybeOwnedStr;
+use crate::CubeError;
+use async_trait::async_trait;
+use datafusion::cube_ext;
+use itertools::Itertools;
+use mockall::automock;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+/// How long a single poll waits for new records.
+const POLL_TIMEOUT: Duration = Duration::from_secs(1);
+/// How often each node checks which stream tables it should ingest.
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(30);
+/// Upper bound of the delay between retries of a failing table.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Ingests tables that have a [StreamSource]. Each node runs a long-lived ingestion loop for
+/// every stream table assigned to it by [import_node_name].
+#[automock]
+#[async_trait]
+pub trait StreamingService: DIService + Send + Sync {
+    /// Starts ingestion of stream tables assigned to this node and stops ingestion of tables that
+    /// were dropped or assigned to another node.
+    async fn reconcile(&self) -> Result<(), CubeError>;
+    /// Reconciles on startup and periodically after that.
+    async fn wait_processing_loops(&self);
+    async fn stop_processing_loops(&self);
+}
+
+crate::di_service!(MockStreamingService, [StreamingService]);
+
+/// Connects to Kafka-compatible logs.
+pub trait LogBroker: DIService + Send + Sync {
+    fn consumer(
+        &self,
+        source: &StreamSource,
+        group_id: &str,
+    ) -> Result<Arc<dyn LogConsumer>, CubeError>;
+}
+
+/// Used when Cube Store is built without the `kafka` feature.
+#[cfg(not(feature = "kafka"))]
+pub struct DisabledLogBroker;
+
+#[cfg(not(feature = "kafka"))]
+crate::di_service!(DisabledLogBroker, [LogBroker]);
+
+#[cfg(not(feature = "kafka"))]
+impl DisabledLogBroker {
+    pub fn new() -> Arc<DisabledLogBroker> {
+        Arc::new(DisabledLogBroker)
+    }
+}
+
+#[cfg(not(feature = "kafka"))]
+impl LogBroker for DisabledLogBroker {
+    fn consumer(
+        &self,
+        source: &StreamSource,
+        _group_id: &str,
+    ) -> Result<Arc<dyn LogConsumer>, CubeError> {
+        Err(CubeError::user(format!(
+            "Can't stream from '{}': Cube Store is built without the `kafka` feature",
+            source.topic
+        )))
+    }
+}
+
+/// Reads records of a single topic on behalf of a consumer group.
+#[async_trait]
+pub trait LogConsumer: Send + Sync {
+    /// Returns up to [max_records] records following the last returned ones. Waits for at most
+    /// [timeout] if there are no new records.
+    async fn poll(
+        &self,
+        max_records: usize,
+        timeout: Duration,
+    ) -> Result<Vec<LogRecord>, CubeError>;
+    /// Persists the next offset to read for each partition in the consumer group.
+    async fn commit(&self, next_offsets: &HashMap<i32, i64>) -> Result<(), CubeError>;
+}
+
+#[derive(Clone, Debug)]
+pub struct LogRecord {
+    pub partition: i32,
+    pub offset: i64,
+    pub payload: Vec<u8>,
+}
+
+pub struct StreamingServiceImpl {
+    ingestor: Arc<TableIngestor>,
+    /// Cancels the ingestion loop of each table.
+    loops: Mutex<HashMap<u64, CancellationToken>>,
+    stop_token: CancellationToken,
+}
+
+crate::di_service!(StreamingServiceImpl, [StreamingService]);
+
+impl StreamingServiceImpl {
+    pub fn new(
+        meta_store: Arc<dyn MetaStore>,
+        chunk_store: Arc<dyn ChunkDataStore>,
+        limits: Arc<ConcurrencyLimits>,
+        config_obj: Arc<dyn ConfigObj>,
+        broker: Arc<dyn LogBroker>,
+    ) -> Arc<StreamingServiceImpl> {
+        Arc::new(StreamingServiceImpl {
+            ingestor: Arc::new(TableIngestor {
+                meta_store,
+                chunk_store,
+                limits,
+                config_obj,
+                broker,
+            }),
+            loops: Mutex::new(HashMap::new()),
+            stop_token: CancellationToken::new(),
+        })
+    }
+}
+
+#[async_trait]
+impl StreamingService for StreamingServiceImpl {
+    async fn reconcile(&self) -> Result<(), CubeError> {
+        let config = self.ingestor.config_obj.as_ref();
+        let assigned = self
+            .ingestor
+            .meta_store
+            .get_tables()
+            .await?
+            .into_iter()
+            .filter(|t| match t.get_row().stream_source() {
+                Some(source) => {
+                    import_node_name(config, t.get_id(), &source.topic) == *config.server_name()
+                }
+                None => false,
+            })
+            .map(|t| (t.get_id(), t))
+            .collect::<HashMap<_, _>>();
+
+        let mut loops = self.loops.lock().await;
+        loops.retain(|table_id, token| {
+            let keep = assigned.contains_key(table_id);
+            if !keep {
+                log::debug!("Stopping ingestion into table {}", table_id);
+                token.cancel();
+            }
+            keep
+        });
+        if self.stop_token.is_cancelled() {
+            return Ok(());
+        }
+        for (table_id, table) in assigned {
+            if loops.contains_key(&table_id) {
+                continue;
+            }
+            log::debug!("Starting ingestion into table {}", table_id);
+            let token = self.stop_token.child_token();
+            loops.insert(table_id, token.clone());
+            cube_ext::spawn(self.ingestor.clone().run(table, token));
+        }
+        Ok(())
+    }
+
+    async fn wait_processing_loops(&self) {
+        loop {
+            if let Err(e) = self.reconcile().await {
+                log::error!("Error reconciling stream tables: {}", e);
+            }
+            tokio::select! {
+                _ = self.stop_token.cancelled() => return,
+                _ = tokio::time::sleep(RECONCILE_INTERVAL) => {}
+            }
+        }
+    }
+
+    async fn stop_processing_loops(&self) {
+        self.stop_token.cancel();
+        self.loops.lock().await.clear();
+    }
+}
+
+/// Ingests records of a single table.
+struct TableIngestor {
+    meta_store: Arc<dyn MetaStore>,
+    chunk_store: Arc<dyn ChunkDataStore>,
+    limits: Arc<ConcurrencyLimits>,
+    config_obj: Arc<dyn ConfigObj>,
+    broker: Arc<dyn LogBroker>,
+}
+
+impl TableIngestor {
+    /// Runs until [token] is cancelled. The consumer is closed when the loop exits.
+    async fn run(self: Arc<Self>, table: IdRow<Table>, token: CancellationToken) {
+        let table_id = table.get_id();
+        let source = match table.get_row().stream_source() {
+            Some(s) => s,
+            None => return,
+        };
+        let group_id = format!("cubestore-table-{}", table_id);
+        let mut consumer: Option<Arc<dyn LogConsumer>> = None;
+        let mut failures = 0;
+        while !token.is_cancelled() {
+            let res = match &consumer {
+                Some(c) => Ok(c.clone()),
+                None => self.broker.consumer(source, &group_id),
+            };
+            let res = match res {
+                Ok(c) => {
+                    consumer = Some(c.clone());
+                    self.ingest_batch(&table, source, c.as_ref(), &token).await
+                }
+                Err(e) => Err(e),
+            };
+            let e = match res {
+                Ok(()) => {
+                    failures = 0;
+                    continue;
+                }
+                Err(e) => e,
+            };
+            // The consumer has moved past uncommitted records. A new one will start reading
+            // from the last committed offsets.
+            consumer = None;
+            failures += 1;
+            let delay = retry_delay(failures);
+            log::error!(
+                "Error streaming into table {}, retrying in {:?}: {}",
+                table_id,
+                delay,
+                e
+            );
+            tokio::select! {
+                _ = token.cancelled() => {}
+                _ = tokio::time::sleep(delay) => {}
+            }
+        }
+    }
+
+    async fn ingest_batch(
+        &self,
+        table: &IdRow<Table>,
+        source: &StreamSource,
+        consumer: &dyn LogConsumer,
+        token: &CancellationToken,
+    ) -> Result<(), CubeError> {
+        // Nothing is committed while polling, so it is safe to stop here. Once records are
+        // received they are written and committed even if the loop is stopped.
+        let records = tokio::select! {
+            _ = token.cancelled() => return Ok(()),
+            records = consumer.poll(self.config_obj.wal_split_threshold() as usize, POLL_TIMEOUT) => {
+                records?
+            }
+        };
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let columns = table.get_row().get_columns().as_slice();
+        let mut builders = create_array_builders(columns);
+        let mut num_rows = 0;
+        let mut next_offsets = HashMap::new();
+        for r in &records {
+            match parse_record(source.format, &r.payload, columns) {
+                Ok(row) => {
+                    append_row(&mut builders, columns, &row);
+                    num_rows += 1;
+                }
+                // Retrying will not help, so skip the record to keep the stream going.
+                Err(e) => log::error!(
+                    "Skipping malformed record at offset {} of {} partition {}: {}",
+                    r.offset,
+                    source.topic,
+                    r.partition,
+                    e
+                ),
+            }
+            let next = next_offsets.entry(r.partition).or_insert(r.offset + 1);
+            *next = (*next).max(r.offset + 1);
+        }
+
+        if num_rows != 0 {
+            let mut ingestion = Ingestion::new(
+                self.meta_store.clone(),
+                self.chunk_store.clone(),
+                self.limits.clone(),
+                table.clone(),
+                None,
+            );
+            ingestion
+                .queue_data_frame(builders.into_iter().map(|mut b| b.finish()).collect_vec())
+                .await?;
+            // Chunks are activated once this completes, only then it is safe to move the offsets.
+            ingestion.wait_completion().await?;
+        }
+        consumer.commit(&next_offsets).await
+    }
+}
+
+/// Doubles with each consecutive failure, starting from a second.
+fn retry_delay(failures: u32) -> Duration {
+    Duration::from_secs(1 << failures.saturating_sub(1).min(6)).min(MAX_RETRY_DELAY)
+}
+
+fn parse_record(
+    format: StreamFormat,
+    payload: &[u8],
+    columns: &[Column],
+) -> Result<Row, CubeError> {
+    match format {
+        StreamFormat::JSON => {
+            let object = match serde_json::from_slice(payload)? {
+                serde_json::Value::Object(o) => o,
+                v => return Err(CubeError::user(format!("Expected JSON object, got: {}", v))),
+            };
+            let mut row = Vec::with_capacity(columns.len());
+            for c in columns {
+                let value = match object.get(c.get_name()) {
+                    None | Some(serde_json::Value::Null) => TableValue::Null,
+                    Some(serde_json::Value::String(s)) => {
+                        parse_table_value(c, MaybeOwnedStr::Borrowed(s))?
+                    }
+                    Some(v @ (serde_json::Value::Number(_) | serde_json::Value::Bool(_))) => {
+                        parse_table_value(c, MaybeOwnedStr::Owned(v.to_string()))?
+                    }
+                    Some(v) => {
+                        return Err(CubeError::user(format!(
+                            "Unexpected value for column {}: {}",
+                            c.get_name(),
+                            v
+                        )))
+                    }
+                };
+                row.push(value);
+            }
+            Ok(Row::new(row))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::memory::InMemoryLogBroker;
+    use super::*;
+    use crate::config::Config;
+    use crate::metastore::ColumnType;
+    use crate::store::DataFrame;
+
+    #[test]
+    fn json_records() {
+        let columns = vec![
+            Column::new("name".to_string(), ColumnType::String, 0),
+            Column::new("n".to_string(), ColumnType::Int, 1),
+            Column::new("flag".to_string(), ColumnType::Boolean, 2),
+        ];
+        let row = parse_record(
+            StreamFormat::JSON,
+            br#"{"name": "john", "n": 10, "flag": true, "extra": [1]}"#,
+            &columns,
+        )
+        .unwrap();
+        assert_eq!(
+            row,
+            Row::new(vec![
+                TableValue::String("john".to_string()),
+                TableValue::Int(10),
+                TableValue::Boolean(true),
+            ])
+        );
+
+        let row = parse_record(StreamFormat::JSON, br#"{"n": "11"}"#, &columns).unwrap();
+        assert_eq!(
+            row,
+            Row::new(vec![
+                TableValue::Null,
+                TableValue::Int(11),
+                TableValue::Null
+            ])
+        );
+
+        assert!(parse_record(StreamFormat::JSON, b"[1, 2]", &columns).is_err());
+        assert!(parse_record(StreamFormat::JSON, br#"{"name": {}}"#, &columns).is_err());
+        assert!(parse_record(StreamFormat::JSON, b"not json", &columns).is_err());
+    }
+
+    #[tokio::test]
+    async fn stream_into_table() {
+        let broker = InMemoryLogBroker::new();
+        let config = Config::test("stream_into_table");
+        let broker_to_register = broker.clone();
+        config
+            .injector()
+            .register_typed::<dyn LogBroker, _, _, _>(async move |_| broker_to_register)
+            .await;
+
+        config
+            .start_test(async move |services| {
+                let service = services.sql_service;
+                broker.produce("events", 0, r#"{"name": "john", "n": 10}"#);
+                broker.produce("events", 1, r#"{"name": "sara", "n": 7}"#);
+                broker.produce("events", 0, r#"{"name": "john", "n": 5}"#);
+                broker.produce("events", 1, "malformed");
+
+                service.exec_query("CREATE SCHEMA s").await.unwrap();
+                service
+                    .exec_query(
+                        "CREATE TABLE s.Events(name text, n int) \
+                         WITH (source = 'kafka', brokers = 'localhost:9092', topic = 'events', format = 'json')",
+                    )
+                    .await
+                    .unwrap();
+
+                let query = "SELECT name, sum(n) FROM s.Events GROUP BY 1 ORDER BY 1";
+                let expected = vec![
+                    Row::new(vec![
+                        TableValue::String("john".to_string()),
+                        TableValue::Int(15),
+                    ]),
+                    Row::new(vec![
+                        TableValue::String("sara".to_string()),
+                        TableValue::Int(7),
+                    ]),
+                ];
+                wait_for_rows(service.as_ref(), query, &expected).await;
+
+                let table = services
+                    .meta_store
+                    .get_table("s".to_string(), "Events".to_string())
+                    .await
+                    .unwrap();
+                let group = format!("cubestore-table-{}", table.get_id());
+                wait_for_commit(&broker, &group, 0, 2).await;
+                wait_for_commit(&broker, &group, 1, 2).await;
+
+                broker.produce("events", 1, r#"{"name": "sara", "n": 3}"#);
+                let expected = vec![
+                    Row::new(vec![
+                        TableValue::String("john".to_string()),
+                        TableValue::Int(15),
+                    ]),
+                    Row::new(vec![
+                        TableValue::String("sara".to_string()),
+                        TableValue::Int(10),
+                    ]),
+                ];
+                wait_for_rows(service.as_ref(), query, &expected).await;
+                wait_for_commit(&broker, &group, 1, 3).await;
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn stream_retries_and_stops_on_drop() {
+        let broker = InMemoryLogBroker::new();
+        let config = Config::test("stream_retries_and_stops_on_drop");
+        let broker_to_register = broker.clone();
+        config
+            .injector()
+            .register_typed::<dyn LogBroker, _, _, _>(async move |_| broker_to_register)
+            .await;
+
+        config
+            .start_test(async move |services| {
+                let service = services.sql_service;
+                broker.produce("events", 0, r#"{"n": 1}"#);
+                broker.fail_polls(2);
+
+                service.exec_query("CREATE SCHEMA s").await.unwrap();
+                service
+                    .exec_query(
+                        "CREATE TABLE s.Events(n int) \
+                         WITH (source = 'kafka', brokers = 'localhost:9092', topic = 'events', format = 'json')",
+                    )
+                    .await
+                    .unwrap();
+                wait_for_rows(
+                    service.as_ref(),
+                    "SELECT sum(n) FROM s.Events",
+                    &vec![Row::new(vec![TableValue::Int(1)])],
+                )
+                .await;
+                assert_eq!(broker.open_consumers(), 1);
+
+                service.exec_query("DROP TABLE s.Events").await.unwrap();
+                for _ in 0..100 {
+                    if broker.open_consumers() == 0 {
+                        return;
+                    }
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+                panic!("Consumer was not closed after DROP TABLE");
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn stream_source_options() {
+        Config::run_test("stream_source_options", async move |services| {
+            let service = services.sql_service;
+            service.exec_query("CREATE SCHEMA s").await.unwrap();
+            for q in &[
+                "CREATE TABLE s.T(n int) WITH (source = 'kinesis', brokers = 'b', topic = 't')",
+                "CREATE TABLE s.T(n int) WITH (source = 'kafka', topic = 't')",
+                "CREATE TABLE s.T(n int) WITH (source = 'kafka', brokers = 'b')",
+                "CREATE TABLE s.T(n int) WITH (source = 'kafka', brokers = 'b', topic = 't', format = 'csv')",
+                "CREATE TABLE s.T(n int) WITH (source = 'kafka', brokers = 'b', topic = 't', partitions = '3')",
+                "CREATE TABLE s.T(n int) WITH (topic = 't')",
+            ] {
+                assert!(service.exec_query(q).await.is_err(), "{}", q);
+            }
+        })
+        .await;
+    }
+
+    async fn wait_for_commit(
+        broker: &InMemoryLogBroker,
+        group: &str,
+        partition: i32,
+        next_offset: i64,
+    ) {
+        for _ in 0..100 {
+            if broker.committed("events", group, partition) == Some(next_offset) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!(
+            "Offset {} of partition {} was not committed, last committed: {:?}",
+            next_offset,
+            partition,
+            broker.committed("events", group, partition)
+        );
+    }
+
+    async fn wait_for_rows(service: &dyn crate::sql::SqlService, query: &str, expected: &Vec<Row>) {
+        let mut last: Option<Arc<DataFrame>> = None;
+        for _ in 0..100 {
+            let r = service.exec_query(query).await.unwrap();
+            if r.get_rows() == expected {
+                return;
+            }
+            last = Some(r);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!(
+            "Streamed rows did not arrive, last result: {:?}",
+            last.map(|r| r.get_rows().clone())
+        );
+    }
+}
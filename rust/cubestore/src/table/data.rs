@@ -1,5 +1,5 @@
 use crate::metastore::{Column, ColumnType};
-use crate::table::{Row, TableValue, TimestampValue};
+use crate::table::{DateValue, Row, TableValue, TimestampValue};
 use crate::util::decimal::Decimal;
 use crate::util::ordfloat::OrdF64;
 use arrow::array::{Array, ArrayBuilder, ArrayRef, StringArray};
@@ -21,6 +21,7 @@ pub enum TableValueR<'a> {
     Bytes(&'a [u8]),
     Timestamp(TimestampValue),
     Boolean(bool),
+    Date(DateValue),
 }
 
 impl TableValueR<'_> {
@@ -34,6 +35,7 @@ impl TableValueR<'_> {
             TableValue::Bytes(b) => TableValueR::Bytes(&b),
             TableValue::Timestamp(v) => TableValueR::Timestamp(v.clone()),
             TableValue::Boolean(v) => TableValueR::Boolean(*v),
+            TableValue::Date(v) => TableValueR::Date(*v),
         }
     }
 }
@@ -122,6 +124,7 @@ pub fn cmp_same_types(l: &TableValueR, r: &TableValueR) -> Ordering {
         (TableValueR::Bytes(a), TableValueR::Bytes(b)) => a.cmp(b),
         (TableValueR::Timestamp(a), TableValueR::Timestamp(b)) => a.cmp(b),
         (TableValueR::Boolean(a), TableValueR::Boolean(b)) => a.cmp(b),
+        (TableValueR::Date(a), TableValueR::Date(b)) => a.cmp(b),
         (a, b) => panic!("Can't compare {:?} to {:?}", a, b),
     }
 }
@@ -149,12 +152,20 @@ macro_rules! match_column_type {
                 n => panic!("unhandled target scale: {}", n),
             },
             ColumnType::Float => $matcher!(Float, Float64Builder, Float),
+            ColumnType::Int32 => $matcher!(Int32, Int32Builder, Int),
+            ColumnType::Date => $matcher!(Date, Date32Builder, Date),
+            ColumnType::Decimal128 { precision, scale } => {
+                $matcher!(Decimal128, DecimalBuilder, Decimal, precision, scale)
+            }
         }
     }};
 }
 
 pub fn create_array_builder(t: &ColumnType) -> Box<dyn ArrayBuilder> {
     macro_rules! create_builder {
+        (Decimal128, $builder: tt, $tv_enum: tt, $precision: tt, $scale: tt) => {
+            Box::new($builder::new(0, *$precision as usize, *$scale as usize))
+        };
         ($type: tt, $builder: tt $(,$arg: tt)*) => {
             Box::new($builder::new(0))
         };
@@ -182,6 +193,9 @@ pub fn append_value(b: &mut dyn ArrayBuilder, c: &ColumnType, v: &TableValue) {
         (Decimal, $v: expr) => {{
             $v.raw_value()
         }};
+        (Decimal128, $v: expr) => {{
+            $v.raw_value_i128()
+        }};
         (Float, $v: expr) => {{
             $v.0
         }};
@@ -194,7 +208,16 @@ pub fn append_value(b: &mut dyn ArrayBuilder, c: &ColumnType, v: &TableValue) {
         (Bytes, $v: expr) => {{
             $v.as_slice()
         }};
-        ($tv_enum: tt, $v: expr) => {{
+        (HyperLogLog, $v: expr) => {{
+            $v.as_slice()
+        }};
+        (Int32, $v: expr) => {{
+            *$v as i32
+        }};
+        (Date, $v: expr) => {{
+            $v.days_since_epoch()
+        }};
+        ($type: tt, $v: expr) => {{
             *$v
         }};
     }
@@ -206,7 +229,7 @@ pub fn append_value(b: &mut dyn ArrayBuilder, c: &ColumnType, v: &TableValue) {
                 return;
             }
             let v = match v {
-                TableValue::$tv_enum(v) => convert_value!($tv_enum, v),
+                TableValue::$tv_enum(v) => convert_value!($type, v),
                 other => panic!("unexpected value {:?} for type {:?}", other, c),
             };
             b.append_value(v).unwrap();
@@ -2,13 +2,14 @@ use crate::util::decimal::Decimal;
 use crate::util::ordfloat::OrdF64;
 
 use arrow::array::{
-    Array, ArrayRef, BinaryArray, BooleanArray, Float64Array, Int64Array, Int64Decimal0Array,
-    Int64Decimal10Array, Int64Decimal1Array, Int64Decimal2Array, Int64Decimal3Array,
-    Int64Decimal4Array, Int64Decimal5Array, StringArray, TimestampMicrosecondArray,
+    Array, ArrayRef, BinaryArray, BooleanArray, Date32Array, DecimalArray, Float64Array,
+    Int32Array, Int64Array, Int64Decimal0Array, Int64Decimal10Array, Int64Decimal1Array,
+    Int64Decimal2Array, Int64Decimal3Array, Int64Decimal4Array, Int64Decimal5Array, StringArray,
+    TimestampMicrosecondArray,
 };
 use arrow::datatypes::{DataType, TimeUnit};
 
-use chrono::{SecondsFormat, TimeZone, Utc};
+use chrono::{Duration, NaiveDate, SecondsFormat, TimeZone, Utc};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
@@ -29,6 +30,7 @@ pub enum TableValue {
     Bytes(Vec<u8>),
     Timestamp(TimestampValue),
     Boolean(bool),
+    Date(DateValue),
 }
 
 impl TableValue {
@@ -46,6 +48,18 @@ impl TableValue {
             DataType::Int64 => {
                 TableValue::Int(a.as_any().downcast_ref::<Int64Array>().unwrap().value(row))
             }
+            DataType::Int32 => {
+                TableValue::Int(a.as_any().downcast_ref::<Int32Array>().unwrap().value(row) as i64)
+            }
+            DataType::Date32 => TableValue::Date(DateValue::new(
+                a.as_any().downcast_ref::<Date32Array>().unwrap().value(row),
+            )),
+            DataType::Decimal(_, _) => TableValue::Decimal(Decimal::new_i128(
+                a.as_any()
+                    .downcast_ref::<DecimalArray>()
+                    .unwrap()
+                    .value(row),
+            )),
             DataType::Utf8 => TableValue::String(
                 a.as_any()
                     .downcast_ref::<StringArray>()
@@ -166,6 +180,38 @@ impl ToString for TimestampValue {
     }
 }
 
+#[derive(Clone, Copy, Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct DateValue {
+    days_since_epoch: i32,
+}
+
+impl DateValue {
+    pub fn new(days_since_epoch: i32) -> DateValue {
+        DateValue { days_since_epoch }
+    }
+
+    pub fn days_since_epoch(&self) -> i32 {
+        self.days_since_epoch
+    }
+}
+
+impl Debug for DateValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DateValue")
+            .field("days_since_epoch", &self.days_since_epoch)
+            .field("str", &self.to_string())
+            .finish()
+    }
+}
+
+impl ToString for DateValue {
+    fn to_string(&self) -> String {
+        (NaiveDate::from_ymd(1970, 1, 1) + Duration::days(self.days_since_epoch as i64))
+            .format("%Y-%m-%d")
+            .to_string()
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
 pub struct Row {
     values: Vec<TableValue>,
@@ -201,13 +247,14 @@ pub fn cmp_same_types(l: &TableValue, r: &TableValue) -> Ordering {
         (TableValue::Bytes(a), TableValue::Bytes(b)) => a.cmp(b),
         (TableValue::Timestamp(a), TableValue::Timestamp(b)) => a.cmp(b),
         (TableValue::Boolean(a), TableValue::Boolean(b)) => a.cmp(b),
+        (TableValue::Date(a), TableValue::Date(b)) => a.cmp(b),
         (a, b) => panic!("Can't compare {:?} to {:?}", a, b),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::table::{TableValue, TimestampValue};
+    use crate::table::{DateValue, TableValue, TimestampValue};
     use crate::util::decimal::Decimal;
     use serde::{Deserialize, Serialize};
 
@@ -218,10 +265,12 @@ mod tests {
             TableValue::String("foo".into()),
             TableValue::Int(123),
             TableValue::Decimal(Decimal::new(123)),
+            TableValue::Decimal(Decimal::new_i128(-123_000_000_000_000_000_000_000)),
             TableValue::Float(12_f64.into()),
             TableValue::Bytes(vec![1, 2, 3]),
             TableValue::Timestamp(TimestampValue::new(123)),
             TableValue::Boolean(false),
+            TableValue::Date(DateValue::new(18628)),
         ] {
             let b = bincode::serialize(v).expect(&format!("could not serialize {:?}", v));
             let v2: TableValue =
@@ -237,4 +286,11 @@ mod tests {
             assert_eq!(v, &v2);
         }
     }
+
+    #[test]
+    fn date_to_string() {
+        assert_eq!(DateValue::new(0).to_string(), "1970-01-01");
+        assert_eq!(DateValue::new(18628).to_string(), "2021-01-01");
+        assert_eq!(DateValue::new(-1).to_string(), "1969-12-31");
+    }
 }
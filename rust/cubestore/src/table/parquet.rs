@@ -75,7 +75,7 @@ mod tests {
     use crate::store::{compaction, ROW_GROUP_SIZE};
     use crate::table::data::{cmp_row_key_heap, concat_record_batches, rows_to_columns, to_stream};
     use crate::table::parquet::{arrow_schema, ParquetTableStore};
-    use crate::table::{Row, TableValue};
+    use crate::table::{DateValue, Row, TableValue};
     use crate::util::decimal::Decimal;
     use arrow::array::{
         ArrayRef, BooleanArray, Float64Array, Int64Array, Int64Decimal4Array, StringArray,
@@ -370,6 +370,55 @@ mod tests {
         assert_eq_columns!(r.columns(), &data);
     }
 
+    #[test]
+    fn read_int32_dates_and_decimal128() {
+        let index = Index::try_new(
+            "index".into(),
+            0,
+            vec![
+                Column::new("date".into(), ColumnType::Date, 0),
+                Column::new("int32".into(), ColumnType::Int32, 1),
+                Column::new(
+                    "decimal".into(),
+                    ColumnType::Decimal128 {
+                        scale: 10,
+                        precision: 38,
+                    },
+                    2,
+                ),
+            ],
+            1,
+        )
+        .unwrap();
+
+        let file = NamedTempFile::new().unwrap();
+        let file = file.path().to_str().unwrap();
+        let rows = vec![
+            Row::new(vec![
+                TableValue::Date(DateValue::new(-1)),
+                TableValue::Int(-2147483648),
+                TableValue::Decimal(Decimal::new_i128(-12_345_678_901_234_567_890_123_456_789)),
+            ]),
+            Row::new(vec![
+                TableValue::Date(DateValue::new(18628)),
+                TableValue::Null,
+                TableValue::Null,
+            ]),
+            Row::new(vec![
+                TableValue::Null,
+                TableValue::Int(2147483647),
+                TableValue::Decimal(Decimal::new_i128(1)),
+            ]),
+        ];
+
+        let data = rows_to_columns(&index.columns(), &rows);
+
+        let w = ParquetTableStore::new(index.clone(), 8);
+        w.write_data(file, data.clone()).unwrap();
+        let r = concat_record_batches(&w.read_columns(file).unwrap());
+        assert_eq_columns!(r.columns(), &data);
+    }
+
     fn print_min_max_typed<T: DataType>(s: &TypedStatistics<T>) -> String {
         format!("min: {}, max: {}", s.min(), s.max())
     }
@@ -16,17 +16,26 @@ impl Decimal {
         }
     }
 
+    /// Used for columns of [ColumnType::Decimal128](crate::metastore::ColumnType::Decimal128).
+    pub fn new_i128(raw_value: i128) -> Decimal {
+        Decimal { raw_value }
+    }
+
     pub fn raw_value(&self) -> i64 {
         self.raw_value as i64
     }
 
+    pub fn raw_value_i128(&self) -> i128 {
+        self.raw_value
+    }
+
     pub fn negate(&self) -> Decimal {
-        Decimal::new(-self.raw_value())
+        Decimal::new_i128(-self.raw_value)
     }
 
     pub fn to_string(&self, scale: u8) -> String {
-        let n = 10_i64.pow(scale as u32);
-        let v = self.raw_value();
+        let n = 10_i128.pow(scale as u32);
+        let v = self.raw_value;
         let integral = v / n;
         let fractional = (v % n).abs();
         format!("{}.{}", integral, fractional)
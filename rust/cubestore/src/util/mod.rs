@@ -10,6 +10,7 @@ pub mod ordfloat;
 pub mod respawn;
 pub mod strings;
 pub mod time_span;
+pub mod tls;
 
 pub use malloc_trim_loop::spawn_malloc_trim_loop;
 
@@ -108,4 +109,8 @@ impl WorkerLoop {
     pub fn stop(&self) {
         self.stopped_token.cancel()
     }
+
+    pub async fn wait_stopped(&self) {
+        self.stopped_token.cancelled().await
+    }
 }
@@ -0,0 +1,281 @@
+//! TLS for connections between nodes and on the MySQL port.
+//!
+//! Nodes serve [ConfigObj::tls_cert_path] on the worker and metastore ports and verify each
+//! other with the certificates in [ConfigObj::tls_ca_path]. With [ConfigObj::tls_mutual_auth]
+//! nodes also present their certificate when connecting and the ports reject clients without one.
+//! Without [ConfigObj::tls_ca_path] nodes verify each other with the public web PKI roots.
+//! Node addresses must use host names the certificates are issued for.
+//!
+//! The MySQL port negotiates TLS the way MySQL servers do, see [crate::mysql], and never
+//! asks SQL clients for a certificate.
+use crate::config::ConfigObj;
+use crate::CubeError;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+use tokio_rustls::rustls::{
+    AllowAnyAuthenticatedClient, Certificate, ClientConfig, NoClientAuth, PrivateKey,
+    RootCertStore, ServerConfig,
+};
+use tokio_rustls::webpki::DNSNameRef;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+/// Connection between nodes, either plain TCP or TLS.
+pub trait NetworkStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> NetworkStream for T {}
+
+pub struct NodeTls {
+    acceptor: Option<TlsAcceptor>,
+    sql_acceptor: Option<TlsAcceptor>,
+    connector: Option<TlsConnector>,
+}
+
+crate::di_service!(NodeTls, []);
+
+impl NodeTls {
+    pub fn new(config: &dyn ConfigObj) -> Result<Arc<Self>, CubeError> {
+        let (cert_path, key_path) = match (config.tls_cert_path(), config.tls_key_path()) {
+            (Some(cert), Some(key)) => (cert, key),
+            _ => return Ok(Self::disabled()),
+        };
+        let cert_chain = load_certs(cert_path)?;
+        let key = load_private_key(key_path)?;
+        let roots = match config.tls_ca_path() {
+            Some(ca_path) => load_roots(ca_path)?,
+            None if config.tls_mutual_auth() => {
+                return Err(CubeError::user(
+                    "CUBESTORE_TLS_MUTUAL_AUTH requires CUBESTORE_TLS_CA".to_string(),
+                ))
+            }
+            None => {
+                let mut roots = RootCertStore::empty();
+                roots.add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+                roots
+            }
+        };
+
+        let server_config = |client_auth| -> Result<ServerConfig, CubeError> {
+            let mut server_config = ServerConfig::new(client_auth);
+            server_config
+                .set_single_cert(cert_chain.clone(), key.clone())
+                .map_err(|e| CubeError::user(format!("Invalid TLS certificate: {}", e)))?;
+            Ok(server_config)
+        };
+        let acceptor = if config.tls_mutual_auth() {
+            server_config(AllowAnyAuthenticatedClient::new(roots.clone()))?
+        } else {
+            server_config(NoClientAuth::new())?
+        };
+        let sql_acceptor = server_config(NoClientAuth::new())?;
+
+        let mut client_config = ClientConfig::new();
+        client_config.root_store = roots;
+        if config.tls_mutual_auth() {
+            client_config
+                .set_single_client_cert(cert_chain, key)
+                .map_err(|e| CubeError::user(format!("Invalid TLS certificate: {}", e)))?;
+        }
+
+        Ok(Arc::new(Self {
+            acceptor: Some(TlsAcceptor::from(Arc::new(acceptor))),
+            sql_acceptor: Some(TlsAcceptor::from(Arc::new(sql_acceptor))),
+            connector: Some(TlsConnector::from(Arc::new(client_config))),
+        }))
+    }
+
+    pub fn disabled() -> Arc<Self> {
+        Arc::new(Self {
+            acceptor: None,
+            sql_acceptor: None,
+            connector: None,
+        })
+    }
+
+    /// Acceptor for the MySQL port. Doesn't request client certificates even with
+    /// [ConfigObj::tls_mutual_auth].
+    pub fn sql_acceptor(&self) -> Option<&TlsAcceptor> {
+        self.sql_acceptor.as_ref()
+    }
+
+    /// Server side of a connection accepted on the worker or metastore port.
+    pub async fn accept(&self, socket: TcpStream) -> Result<Box<dyn NetworkStream>, CubeError> {
+        match &self.acceptor {
+            Some(acceptor) => {
+                let stream = acceptor
+                    .accept(socket)
+                    .await
+                    .map_err(|e| CubeError::internal(format!("TLS handshake failed: {}", e)))?;
+                Ok(Box::new(stream))
+            }
+            None => Ok(Box::new(socket)),
+        }
+    }
+
+    /// Client side of a connection to another node on `address`.
+    pub async fn connect(
+        &self,
+        address: &str,
+        socket: TcpStream,
+    ) -> Result<Box<dyn NetworkStream>, CubeError> {
+        match &self.connector {
+            Some(connector) => {
+                let host = host_name(address);
+                let dns_name = DNSNameRef::try_from_ascii_str(host).map_err(|_| {
+                    CubeError::user(format!(
+                        "TLS requires a host name in node address, found '{}'",
+                        address
+                    ))
+                })?;
+                let stream = connector.connect(dns_name, socket).await.map_err(|e| {
+                    CubeError::internal(format!("TLS handshake with {} failed: {}", address, e))
+                })?;
+                Ok(Box::new(stream))
+            }
+            None => Ok(Box::new(socket)),
+        }
+    }
+}
+
+fn host_name(address: &str) -> &str {
+    match address.rsplit_once(':') {
+        Some((host, port)) if port.parse::<u16>().is_ok() => host,
+        _ => address,
+    }
+}
+
+fn open(path: &str) -> Result<BufReader<File>, CubeError> {
+    Ok(BufReader::new(File::open(path).map_err(|e| {
+        CubeError::user(format!("Can't open '{}': {}", path, e))
+    })?))
+}
+
+fn load_certs(path: &str) -> Result<Vec<Certificate>, CubeError> {
+    let certs = certs(&mut open(path)?)
+        .map_err(|_| CubeError::user(format!("Can't parse certificates in '{}'", path)))?;
+    if certs.is_empty() {
+        return Err(CubeError::user(format!("No certificates in '{}'", path)));
+    }
+    Ok(certs)
+}
+
+fn load_private_key(path: &str) -> Result<PrivateKey, CubeError> {
+    let parse_error = || CubeError::user(format!("Can't parse private key in '{}'", path));
+    let mut keys = pkcs8_private_keys(&mut open(path)?).map_err(|_| parse_error())?;
+    if keys.is_empty() {
+        keys = rsa_private_keys(&mut open(path)?).map_err(|_| parse_error())?;
+    }
+    keys.into_iter()
+        .next()
+        .ok_or_else(|| CubeError::user(format!("No private key in '{}'", path)))
+}
+
+fn load_roots(path: &str) -> Result<RootCertStore, CubeError> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots
+            .add(&cert)
+            .map_err(|e| CubeError::user(format!("Invalid CA certificate in '{}': {}", path, e)))?;
+    }
+    Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{validate_config, Config};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn tls_config(ca: bool, mutual_auth: bool) -> Arc<dyn ConfigObj> {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/testing/tls");
+        Config::test("tls")
+            .update_config(|mut c| {
+                c.tls_cert_path = Some(format!("{}/node.pem", dir));
+                c.tls_key_path = Some(format!("{}/node.key", dir));
+                c.tls_ca_path = if ca {
+                    Some(format!("{}/ca.pem", dir))
+                } else {
+                    None
+                };
+                c.tls_mutual_auth = mutual_auth;
+                c
+            })
+            .config_obj()
+    }
+
+    async fn handshake(server: &NodeTls, client: &NodeTls) -> Result<(), CubeError> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = format!("localhost:{}", listener.local_addr()?.port());
+        let (accepted, connected) = tokio::join!(
+            async {
+                let (socket, _) = listener.accept().await?;
+                let mut stream = server.accept(socket).await?;
+                stream.write_all(b"ping").await?;
+                stream.flush().await?;
+                Ok::<_, CubeError>(())
+            },
+            async {
+                let socket = TcpStream::connect(&address).await?;
+                let mut stream = client.connect(&address, socket).await?;
+                let mut buf = [0; 4];
+                stream.read_exact(&mut buf).await?;
+                assert_eq!(&buf, b"ping");
+                Ok::<_, CubeError>(())
+            }
+        );
+        accepted.and(connected)
+    }
+
+    #[tokio::test]
+    async fn one_way_handshake() {
+        let node = NodeTls::new(tls_config(true, false).as_ref()).unwrap();
+        handshake(&node, &node).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn mutual_handshake() {
+        let node = NodeTls::new(tls_config(true, true).as_ref()).unwrap();
+        handshake(&node, &node).await.unwrap();
+
+        let without_client_cert = NodeTls::new(tls_config(true, false).as_ref()).unwrap();
+        assert!(handshake(&node, &without_client_cert).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn handshake_without_ca_uses_web_roots() {
+        let server = NodeTls::new(tls_config(true, false).as_ref()).unwrap();
+        let client = NodeTls::new(tls_config(false, false).as_ref()).unwrap();
+        // Test certificates aren't issued by a public CA, so the client must not trust them.
+        assert!(handshake(&server, &client).await.is_err());
+        assert!(NodeTls::new(tls_config(false, true).as_ref()).is_err());
+    }
+
+    #[tokio::test]
+    async fn invalid_certificates_fail_startup() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/testing/tls");
+        let config = Config::test("tls_invalid").update_config(|mut c| {
+            c.tls_cert_path = Some(format!("{}/node.key", dir));
+            c.tls_key_path = Some(format!("{}/node.key", dir));
+            c
+        });
+        let expected = format!("No certificates in '{}/node.key'", dir);
+
+        let messages = validate_config(config.config_obj().as_ref());
+        assert!(messages.errors.contains(&expected), "{:?}", messages.errors);
+        assert_eq!(
+            config.configure_injector().await.unwrap_err().message,
+            expected
+        );
+    }
+
+    #[test]
+    fn host_names() {
+        assert_eq!(host_name("worker-1:10001"), "worker-1");
+        assert_eq!(host_name("worker-1"), "worker-1");
+        assert_eq!(host_name("router.local:9999"), "router.local");
+    }
+}